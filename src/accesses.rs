@@ -0,0 +1,148 @@
+/***********************************************************/
+//
+// Heap Accesses
+// =============
+// Records every heap address a run reads and writes, and
+// when, for run --heap-map, so authors of programs that keep
+// arrays in the heap can see how they go through them:
+// whether accesses stay close together, how far apart the
+// addresses they use are, and so which heap suits them.
+//
+// The record is written out either as CSV, a row per access,
+// for spreadsheets and plotting tools to take in, or as a
+// heatmap in the PPM image format most image viewers open.
+// The heatmap runs from the lowest address used on the left
+// to the highest on the right and from the first step at the
+// top to the last at the bottom, with reads drawn in green
+// and writes in red, brighter where there were more of them.
+// The stores made by reads and the retrieves made by GetEnv
+// count like anything else.
+//
+// enum Format <- Which format a record is written in
+//
+// struct Accesses <- Every heap access of a run so far
+//
+/***********************************************************/
+use std::fmt::Write;
+
+
+/********************************************/
+// Structures
+/********************************************/
+// Which format a record is written in
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Format {
+	Csv, // A row per access
+	Ppm, // A heatmap of address against time
+}
+
+// Every heap access of a run so far
+#[derive(Debug, Clone, Default)]
+pub struct Accesses {
+	accesses: Vec<(u64, i64, bool)>, // The step, the address, and whether it was a write, in order
+}
+
+
+/********************************************/
+// Constants
+/********************************************/
+// The most pixels a heatmap is across and down. Larger ranges of
+// addresses or steps share pixels.
+const SIZE: u64 = 512;
+
+
+/********************************************/
+// Methods
+/********************************************/
+impl Format {
+	// The format with the name used on the command line
+	pub fn from_name(name: &str) -> Option<Format> {
+		match name {
+			"csv" => Some(Format::Csv),
+			"ppm" => Some(Format::Ppm),
+			_     => None,
+		}
+	}
+}
+
+impl Accesses {
+	// Record a read of an address at a step
+	pub fn read(&mut self, step: u64, address: i64) {
+		self.accesses.push((step, address, false));
+	}
+
+	// Record a write to an address at a step
+	pub fn write(&mut self, step: u64, address: i64) {
+		self.accesses.push((step, address, true));
+	}
+
+	// Describe the record in a line, for choosing a heap: how many reads and
+	// writes there were, and the range of addresses they went to
+	pub fn summary(&self) -> String {
+		let writes = self.accesses.iter().filter(|access| access.2).count();
+		let reads  = self.accesses.len() - writes;
+		match self.addresses() {
+			Some((lowest, highest)) => format!("Heap map: {} read(s) and {} write(s), at addresses {} to {}\n", reads, writes, lowest, highest),
+			None                    => "Heap map: the heap was never used\n".to_string(),
+		}
+	}
+
+	// Write the record out in a format
+	pub fn render(&self, format: Format) -> Vec<u8> {
+		match format {
+			Format::Csv => self.render_csv().into_bytes(),
+			Format::Ppm => self.render_ppm(),
+		}
+	}
+
+	// Write the record as CSV, with a header row
+	fn render_csv(&self) -> String {
+		let mut text = String::from("step,address,access\n");
+		for &(step, address, write) in &self.accesses {
+			let _ = writeln!(text, "{},{},{}", step, address, if write {"write"} else {"read"});
+		}
+		text
+	}
+
+	// Draw the record as a binary PPM heatmap. Counts go on a log scale, so
+	// a few busy addresses don't leave every other one dark.
+	fn render_ppm(&self) -> Vec<u8> {
+		let (lowest, highest) = self.addresses().unwrap_or((0, 0));
+		let first = self.accesses.first().map_or(0, |access| access.0);
+		let last  = self.accesses.last().map_or(0, |access| access.0);
+
+		// Every address but one fits in a span as wide as a u64 can count
+		let across = ((highest as i128 - lowest as i128) as u64).saturating_add(1);
+		let down   = last - first + 1;
+		let (width, height) = (across.min(SIZE), down.min(SIZE));
+
+		// Each pixel counts reads, then writes
+		let mut counts = vec![(0u64, 0u64); (width * height) as usize];
+		for &(step, address, write) in &self.accesses {
+			let x = (((address as i128 - lowest as i128) as u128 * width as u128 / across as u128) as u64).min(width - 1);
+			let y = ((step - first) as u128 * height as u128 / down as u128) as u64;
+			let count = &mut counts[(y * width + x) as usize];
+			if write {
+				count.1 += 1;
+			} else {
+				count.0 += 1;
+			}
+		}
+
+		let most = counts.iter().map(|&(reads, writes)| reads.max(writes)).max().unwrap_or(0);
+		let shade = |count: u64| if count == 0 {0} else {(64.0 + 191.0 * ((count as f64).ln_1p() / (most as f64).ln_1p())) as u8};
+
+		let mut image = format!("P6\n{} {}\n255\n", width, height).into_bytes();
+		for &(reads, writes) in &counts {
+			image.extend_from_slice(&[shade(writes), shade(reads), 0]);
+		}
+		image
+	}
+
+	// The lowest and highest addresses accessed, if any were
+	fn addresses(&self) -> Option<(i64, i64)> {
+		let lowest  = self.accesses.iter().map(|access| access.1).min()?;
+		let highest = self.accesses.iter().map(|access| access.1).max()?;
+		Some((lowest, highest))
+	}
+}
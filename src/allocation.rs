@@ -0,0 +1,86 @@
+/***********************************************************/
+//
+// Allocation Tuning
+// =================
+// How the vm's own storage (the stack, the call stack and
+// the heap) allocates. Programs that embed the vm to run
+// thousands of short programs can give it room ahead of
+// time, reuse one vm for each program instead of making a
+// new one, and count how often the storage still had to
+// grow to see whether the room they gave was enough.
+//
+// struct Capacity <- The room the vm makes ahead of time, and what it
+//                    keeps from one program to the next
+//
+// enum Shrink <- What a recycled vm gives back of its storage
+//
+// struct Allocations <- How often the vm's storage allocated or grew
+//
+// parse(text: &str) -> Option<Capacity>
+// -- Read capacity hints written as <stack>,<call stack>,<heap>
+//
+/***********************************************************/
+use std::fmt::Write;
+
+
+/********************************************/
+// Structures
+/********************************************/
+// The room the vm makes ahead of time, as a number of values for each
+// kind of storage, and what it keeps from one program to the next
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub struct Capacity {
+	pub stack:      usize,
+	pub call_stack: usize,
+	pub heap:       usize,
+	pub shrink:     Shrink,
+}
+
+// What a recycled vm gives back of its storage
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub enum Shrink {
+	#[default]
+	Keep,    // Keep everything the last program grew it to, for the next
+	ToHints, // Give back anything past the capacity hints
+}
+
+// How often the vm's storage allocated or grew during a run, counting
+// the heap's only for storage that counts them
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub struct Allocations {
+	pub stack:      u64,
+	pub call_stack: u64,
+	pub heap:       u64,
+}
+
+
+/********************************************/
+// Methods
+/********************************************/
+impl Allocations {
+	// Describe the allocations in a few lines of text
+	pub fn render(&self) -> String {
+		let mut text = String::new();
+
+		let _ = writeln!(text, "Allocations:");
+		let _ = writeln!(text, "  {:<20} {}", "stack", self.stack);
+		let _ = writeln!(text, "  {:<20} {}", "call stack", self.call_stack);
+		let _ = writeln!(text, "  {:<20} {}", "heap", self.heap);
+
+		text
+	}
+}
+
+
+/********************************************/
+// Public Functions
+/********************************************/
+// Read capacity hints written as <stack>,<call stack>,<heap>, each a
+// number of values, keeping what the last program grew the storage to
+pub fn parse(text: &str) -> Option<Capacity> {
+	let hints = text.split(',').map(str::parse).collect::<Result<Vec<usize>, _>>().ok()?;
+	match hints[..] {
+		[stack, call_stack, heap] => Some(Capacity {stack, call_stack, heap, shrink: Shrink::Keep}),
+		_                         => None,
+	}
+}
@@ -0,0 +1,539 @@
+/***********************************************************/
+//
+// Analysis
+// ========
+// Static analysis routines that work on a parsed (not
+// yet reduced) whitespace program.
+//
+// basic_blocks(program: &[Action]) -> Vec<BasicBlock>
+// -- Splits a program into straight-line runs of actions
+//
+// cost(action: &Action) -> u64
+// -- The estimated relative cost of executing an action
+//
+// stack_effect(action: &Action) -> StackEffect
+// -- How many values an action pops and pushes
+//
+// reachable_blocks(program: &[Action], blocks: &[BasicBlock]) -> Vec<bool>
+// -- Which basic blocks can be reached from the start of the program
+//
+// stack_underflows(program: &[Action], blocks: &[BasicBlock]) -> Vec<Underflow>
+// -- Actions that may pop more values than the stack holds
+//
+// certainties(program: &[Action], blocks: &[BasicBlock]) -> Vec<(usize, Certainty)>
+// -- What constant propagation shows always happens at some actions
+//
+// call_graph(program: &[Action], blocks: &[BasicBlock]) -> Vec<Routine>
+// -- The subroutines each part of the program calls
+//
+// ends_block(action: &Action) -> bool
+// -- Whether control can leave an action other than to the next one
+//
+/***********************************************************/
+use std::collections::{HashMap, HashSet};
+use labels::LabelId;
+use symbols::Action;
+use symbols::Action::*;
+
+
+/********************************************/
+// Structures
+/********************************************/
+// A straight-line run of actions. Control only enters
+// at the first action and only leaves after the last.
+#[derive(Debug, Copy, Clone)]
+pub struct BasicBlock {
+	pub start: usize, // Index of the first action in the block
+	pub end:   usize, // Index one past the last action in the block
+}
+
+impl BasicBlock {
+	// The estimated cost of executing every action in the block once
+	pub fn cost(&self, program: &[Action]) -> u64 {
+		program[self.start..self.end].iter().map(cost).sum()
+	}
+}
+
+
+// The number of values an action needs on the stack,
+// and the number of values it leaves in their place
+#[derive(Debug, Copy, Clone)]
+pub struct StackEffect {
+	pub pops:   usize,
+	pub pushes: usize,
+}
+
+
+// An action that may run with fewer values on the stack than it needs
+#[derive(Debug, Copy, Clone)]
+pub struct Underflow {
+	pub index:  usize, // Index of the action in the program
+	pub depth:  usize, // Smallest stack depth the action may see
+	pub needed: usize, // Number of values the action pops
+}
+
+
+// Something constant propagation shows always happens at an action
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Certainty {
+	DivisionByZero,      // Divide or Modulo divides by zero
+	UnsetRetrieve(i64),  // HeapRetrieve reads an address nothing stores to
+	Branch(bool, i64),   // A conditional jump is always taken, or never, testing this value
+}
+
+
+// The main program or a subroutine, and the subroutines it calls
+#[derive(Debug, Clone)]
+pub struct Routine {
+	pub label: Option<LabelId>, // The label the subroutine starts at, or None for the main program
+	pub calls: Vec<LabelId>,    // The labels it calls, in the order they first appear
+}
+
+
+// The stack depth known on entry to a block during the underflow analysis
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Depth {
+	Known(usize), // At least this many values are on the stack
+	Unknown,      // The block is entered with an unknown stack (e.g. a subroutine)
+}
+
+
+// A value on the stack during constant propagation
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Value {
+	Known(i64),
+	Unknown,
+}
+
+// What constant propagation knows about the top of the stack, topmost
+// last. Every value below them is unknown.
+type Constants = Vec<Value>;
+
+// The most values at the top of the stack constant propagation keeps track of
+const CONSTANTS: usize = 64;
+
+
+/********************************************/
+// Public Functions
+/********************************************/
+// Split a program into basic blocks. A new block starts
+// at every label and after every flow control action.
+pub fn basic_blocks(program: &[Action]) -> Vec<BasicBlock> {
+	let mut blocks = Vec::new();
+	let mut start  = 0;
+
+	for (index, action) in program.iter().enumerate() {
+		if let Label(_) = *action {
+			if index > start {
+				blocks.push(BasicBlock {start, end: index});
+				start = index;
+			}
+		}
+
+		if ends_block(action) {
+			blocks.push(BasicBlock {start, end: index + 1});
+			start = index + 1;
+		}
+	}
+
+	if start < program.len() {
+		blocks.push(BasicBlock {start, end: program.len()});
+	}
+
+	blocks
+}
+
+
+// Determine which blocks can be reached from the first block
+// by following jumps, calls, and fall-through
+pub fn reachable_blocks(program: &[Action], blocks: &[BasicBlock]) -> Vec<bool> {
+	let label_blocks = label_blocks(program, blocks);
+	let mut reachable = vec![false; blocks.len()];
+	let mut pending   = if blocks.is_empty() {Vec::new()} else {vec![0]};
+
+	while let Some(block) = pending.pop() {
+		if reachable[block] {
+			continue;
+		}
+
+		reachable[block] = true;
+		pending.extend(successors(program, blocks, &label_blocks, block));
+	}
+
+	reachable
+}
+
+
+// Find the actions that may pop more values than the stack holds. The
+// depth of the stack is tracked from the start of the program; blocks
+// entered by a call, or after returning from one, are assumed to start
+// with an unknown stack, so only underflows in the main line of the
+// program are found.
+pub fn stack_underflows(program: &[Action], blocks: &[BasicBlock]) -> Vec<Underflow> {
+	let label_blocks = label_blocks(program, blocks);
+	let mut entry: Vec<Option<Depth>> = vec![None; blocks.len()];
+	let mut pending = Vec::new();
+
+	if !blocks.is_empty() {
+		entry[0] = Some(Depth::Known(0));
+		pending.push(0);
+	}
+
+	// Propagate the smallest possible depth through the blocks until nothing changes
+	while let Some(block) = pending.pop() {
+		let exit = match entry[block] {
+			Some(Depth::Known(depth)) => simulate(program, blocks[block], depth, &mut Vec::new()),
+			_                         => Depth::Unknown,
+		};
+
+		let last = program[blocks[block].end - 1];
+		for successor in successors(program, blocks, &label_blocks, block) {
+			// Subroutines and the code after a call start with an unknown stack
+			let incoming = match last {
+				Call(_) => Depth::Unknown,
+				_       => exit,
+			};
+
+			let merged = match (entry[successor], incoming) {
+				(None, incoming)                                      => incoming,
+				(Some(Depth::Known(old)), Depth::Known(new))          => Depth::Known(old.min(new)),
+				_                                                     => Depth::Unknown,
+			};
+
+			if entry[successor] != Some(merged) {
+				entry[successor] = Some(merged);
+				pending.push(successor);
+			}
+		}
+	}
+
+	// Walk each block once more with its final entry depth, collecting underflows
+	let mut underflows = Vec::new();
+	for (block, depth) in entry.iter().enumerate() {
+		if let Some(Depth::Known(depth)) = *depth {
+			simulate(program, blocks[block], depth, &mut underflows);
+		}
+	}
+
+	underflows
+}
+
+
+// Find what always happens at some actions, by tracking the values known
+// to be on the stack from the start of the program: divisions by zero,
+// conditional jumps that go the same way every time, and reads of heap
+// addresses that nothing stores to. Like the underflow analysis, nothing
+// is known about the stack where a subroutine starts or after a call.
+// The heap isn't tracked, so what's read from it is never known.
+pub fn certainties(program: &[Action], blocks: &[BasicBlock]) -> Vec<(usize, Certainty)> {
+	let label_blocks = label_blocks(program, blocks);
+	let mut entry: Vec<Option<Constants>> = vec![None; blocks.len()];
+	let mut pending = Vec::new();
+
+	if !blocks.is_empty() {
+		entry[0] = Some(Vec::new());
+		pending.push(0);
+	}
+
+	// Propagate what's known through the blocks until nothing changes,
+	// keeping only what every way into a block agrees on
+	while let Some(block) = pending.pop() {
+		let mut exit = entry[block].clone().unwrap_or_default();
+		propagate(program, blocks[block], &mut exit, &mut Vec::new(), &mut Stores::default());
+		if let Call(_) = program[blocks[block].end - 1] {
+			exit.clear();
+		}
+
+		for successor in successors(program, blocks, &label_blocks, block) {
+			let merged = match entry[successor] {
+				None          => exit.clone(),
+				Some(ref old) => merge(old, &exit),
+			};
+
+			if entry[successor].as_ref() != Some(&merged) {
+				entry[successor] = Some(merged);
+				pending.push(successor);
+			}
+		}
+	}
+
+	// Walk each block once more with what's known on entry, collecting what's certain
+	let mut found  = Vec::new();
+	let mut stores = Stores::default();
+	for (block, constants) in entry.iter().enumerate() {
+		if let Some(ref constants) = *constants {
+			propagate(program, blocks[block], &mut constants.clone(), &mut found, &mut stores);
+		}
+	}
+
+	// A read is only certain to find nothing once every store has been seen
+	found.retain(|&(_, certainty)| match certainty {
+		Certainty::UnsetRetrieve(address) => !stores.anywhere && !stores.addresses.contains(&address),
+		_                                 => true,
+	});
+	found.sort_by_key(|&(index, _)| index);
+	found
+}
+
+
+// Find the subroutines called by the main program and by each
+// subroutine. A routine is made of the blocks reachable from its
+// start without following calls, so the calls a subroutine makes are
+// credited to it even when its code is spread around the program.
+pub fn call_graph(program: &[Action], blocks: &[BasicBlock]) -> Vec<Routine> {
+	let label_blocks = label_blocks(program, blocks);
+
+	// Every label that's called starts a subroutine, in program order
+	let mut called: Vec<LabelId> = program.iter().filter_map(|action| match *action {
+		Call(label) if label_blocks.contains_key(&label) => Some(label),
+		_                                                => None,
+	}).collect();
+	called.sort_by_key(|label| label_blocks[label]);
+	called.dedup();
+
+	let mut entries: Vec<(Option<LabelId>, usize)> = called.into_iter().map(|label| (Some(label), label_blocks[&label])).collect();
+	if !blocks.is_empty() {
+		entries.insert(0, (None, 0));
+	}
+
+	entries.into_iter().map(|(label, entry)| {
+		let mut visited = vec![false; blocks.len()];
+		let mut pending = vec![entry];
+		let mut calls   = Vec::new();
+
+		while let Some(block) = pending.pop() {
+			if visited[block] {
+				continue;
+			}
+			visited[block] = true;
+
+			// Control comes back from a call to the next block, but the
+			// subroutine itself isn't part of this routine
+			match program[blocks[block].end - 1] {
+				Call(callee) => {
+					if label_blocks.contains_key(&callee) && !calls.contains(&callee) {
+						calls.push(callee);
+					}
+					pending.extend(Some(block + 1).filter(|&next| next < blocks.len()));
+				},
+				_            => pending.extend(successors(program, blocks, &label_blocks, block)),
+			}
+		}
+
+		Routine {label, calls}
+	}).collect()
+}
+
+
+// Get the number of values an action pops and pushes
+pub fn stack_effect(action: &Action) -> StackEffect {
+	let (pops, pushes) = match *action {
+		StackPush(_)                            => (0, 1),
+		StackDuplicate                          => (1, 2),
+		StackSwap                               => (2, 2),
+		StackDiscard                            => (1, 0),
+		Add | Subtract | Multiply               => (2, 1),
+		Divide | Modulo                         => (2, 1),
+		HeapStore                               => (2, 0),
+		HeapRetrieve                            => (1, 1),
+		JumpIfZero(_) | JumpIfNegative(_)       => (1, 0),
+		OutputChar | OutputNumber               => (1, 0),
+		ReadChar | ReadNumber                   => (1, 0),
+		GetEnv                                  => (1, 1),
+		DumpHeap                                => (0, 0),
+		Assert                                  => (2, 0),
+		CallDepth                               => (0, 1),
+		PeekReturn                              => (1, 1),
+		OutputFormatted                         => (3, 0),
+		Clock                                   => (0, 1),
+		Random                                  => (1, 1),
+		Label(_) | Call(_) | Jump(_)            => (0, 0),
+		EndSubroutine | Halt                    => (0, 0),
+	};
+
+	StackEffect {pops, pushes}
+}
+
+
+// Estimate the relative cost of executing an action. The
+// numbers are rough weights rather than measured timings:
+// stack shuffling is cheapest, heap access goes through a
+// hash map, and I/O dominates everything else.
+pub fn cost(action: &Action) -> u64 {
+	match *action {
+		StackPush(_) | StackDuplicate | StackSwap | StackDiscard => 1,
+		CallDepth | PeekReturn                                   => 1,
+		Add | Subtract | Multiply | Assert                       => 2,
+		Divide | Modulo | Random                                 => 4,
+		HeapStore | HeapRetrieve                                 => 3,
+		Label(_)                                                 => 0,
+		Jump(_) | Halt                                           => 1,
+		JumpIfZero(_) | JumpIfNegative(_) | EndSubroutine        => 2,
+		Call(_)                                                  => 3,
+		OutputChar | OutputNumber | OutputFormatted              => 10,
+		ReadChar | ReadNumber | GetEnv | DumpHeap | Clock        => 20,
+	}
+}
+
+
+/********************************************/
+// Private Functions
+/********************************************/
+// Map each label to the index of the block it starts
+fn label_blocks(program: &[Action], blocks: &[BasicBlock]) -> HashMap<LabelId, usize> {
+	let mut labels = HashMap::new();
+
+	for (block_index, block) in blocks.iter().enumerate() {
+		if let Label(label) = program[block.start] {
+			labels.insert(label, block_index);
+		}
+	}
+
+	labels
+}
+
+
+// Get the blocks that control may pass to after the given block. A
+// call may return, so the block after it is a successor as well.
+fn successors(program: &[Action], blocks: &[BasicBlock], labels: &HashMap<LabelId, usize>, block: usize) -> Vec<usize> {
+	let next = if block + 1 < blocks.len() {Some(block + 1)} else {None};
+	let mut found = Vec::new();
+
+	match program[blocks[block].end - 1] {
+		Jump(label) => found.extend(labels.get(&label)),
+
+		Call(label) | JumpIfZero(label) | JumpIfNegative(label) => {
+			found.extend(labels.get(&label));
+			found.extend(next);
+		},
+
+		EndSubroutine | Halt => {},
+
+		_ => found.extend(next),
+	}
+
+	found
+}
+
+
+// Run through a block tracking the stack depth, recording any
+// underflows, and returning the depth when the block is left
+fn simulate(program: &[Action], block: BasicBlock, mut depth: usize, underflows: &mut Vec<Underflow>) -> Depth {
+	for (index, action) in program.iter().enumerate().take(block.end).skip(block.start) {
+		let effect = stack_effect(action);
+
+		if effect.pops > depth {
+			underflows.push(Underflow {index, depth, needed: effect.pops});
+			depth = effect.pops;
+		}
+
+		depth = depth - effect.pops + effect.pushes;
+	}
+
+	Depth::Known(depth)
+}
+
+
+// The heap addresses stored to during constant propagation
+#[derive(Debug, Default)]
+struct Stores {
+	addresses: HashSet<i64>,
+	anywhere:  bool, // Something stores to an address that isn't known
+}
+
+impl Stores {
+	fn add(&mut self, address: Value) {
+		match address {
+			Value::Known(address) => {self.addresses.insert(address);},
+			Value::Unknown        => self.anywhere = true,
+		}
+	}
+}
+
+
+// Run through a block tracking the values known to be on the stack,
+// recording what's certain and where the heap is stored to. Reads from
+// the heap are recorded as reading nothing whenever their address is
+// known, for the caller to check against every store.
+fn propagate(program: &[Action], block: BasicBlock, stack: &mut Constants, found: &mut Vec<(usize, Certainty)>, stores: &mut Stores) {
+	for (index, action) in program.iter().enumerate().take(block.end).skip(block.start) {
+		let mut pop = || stack.pop().unwrap_or(Value::Unknown);
+		let pushed = match *action {
+			StackPush(value) => Some(Value::Known(value)),
+			StackDuplicate   => {let top = pop(); stack.push(top); Some(top)},
+			StackSwap        => {let top = pop(); let below = pop(); stack.push(top); Some(below)},
+			StackDiscard     => {pop(); None},
+
+			Add | Subtract | Multiply | Divide | Modulo => {
+				let right = pop();
+				let left  = pop();
+				if right == Value::Known(0) && matches!(*action, Divide | Modulo) {
+					found.push((index, Certainty::DivisionByZero));
+				}
+
+				// Division only rounds the same way in every mode when neither side is negative
+				Some(match (left, right) {
+					(Value::Known(left), Value::Known(right)) => {
+						let value = match *action {
+							Add                        => left.checked_add(right),
+							Subtract                   => left.checked_sub(right),
+							Multiply                   => left.checked_mul(right),
+							_ if left < 0 || right < 0 => None,
+							Divide                     => left.checked_div(right),
+							_                          => left.checked_rem(right),
+						};
+						value.map_or(Value::Unknown, Value::Known)
+					},
+					_ => Value::Unknown,
+				})
+			},
+
+			HeapStore => {pop(); stores.add(pop()); None},
+			HeapRetrieve => {
+				if let Value::Known(address) = pop() {
+					found.push((index, Certainty::UnsetRetrieve(address)));
+				}
+				Some(Value::Unknown)
+			},
+
+			JumpIfZero(_) | JumpIfNegative(_) => {
+				if let Value::Known(value) = pop() {
+					let taken = if let JumpIfZero(_) = *action {value == 0} else {value < 0};
+					found.push((index, Certainty::Branch(taken, value)));
+				}
+				None
+			},
+
+			// Reads store where they're told, and GetEnv stores its value anywhere after that
+			ReadChar | ReadNumber => {stores.add(pop()); None},
+			GetEnv                => {pop(); stores.add(Value::Unknown); Some(Value::Unknown)},
+
+			OutputChar | OutputNumber => {pop(); None},
+			OutputFormatted           => {pop(); pop(); pop(); None},
+			Assert                    => {pop(); pop(); None},
+			CallDepth | Clock         => Some(Value::Unknown),
+			PeekReturn | Random       => {pop(); Some(Value::Unknown)},
+			DumpHeap | Label(_) | Call(_) | Jump(_) | EndSubroutine | Halt => None,
+		};
+
+		stack.extend(pushed);
+		if stack.len() > CONSTANTS {
+			stack.remove(0);
+		}
+	}
+}
+
+
+// What two ways into a block agree is known about the top of the stack
+fn merge(old: &[Value], new: &[Value]) -> Constants {
+	let common = old.len().min(new.len());
+	let (old, new) = (&old[old.len() - common..], &new[new.len() - common..]);
+	old.iter().zip(new).map(|(&old, &new)| if old == new {old} else {Value::Unknown}).collect()
+}
+
+
+// Check if control can leave the program's straight-line
+// flow after the given action
+pub fn ends_block(action: &Action) -> bool {
+	matches!(*action, Call(_) | Jump(_) | JumpIfZero(_) | JumpIfNegative(_) | EndSubroutine | Halt)
+}
@@ -0,0 +1,207 @@
+/***********************************************************/
+//
+// Assembler
+// =========
+// Reads programs written as mnemonics, for the assemble
+// command, since writing whitespace's spaces and tabs by
+// hand is next to impossible. Each line holds an
+// instruction and its operand, and anything after a ; is a
+// comment:
+//
+//    ; Count down from 5
+//       push 5
+//    loop:
+//       dup
+//       outn
+//       push 1
+//       sub
+//       dup
+//       jz end
+//       jump loop
+//    end:
+//       halt
+//
+// Every instruction also goes by the name list and pretty
+// give it, like StackPush or JumpIfZero, in any case, and
+// operands can be written in brackets after it the way they
+// show them, so StackPush(5) and Call(loop) assemble too.
+// `label loop` defines a label the same as `loop:` does.
+// Numbers are decimal, or a character in quotes like 'a' or
+// '\n'. Labels are named by anything without whitespace, and
+// written as the bytes of their names, a bit at a time, with
+// S for a zero and T for a one. A % followed by S and T
+// gives a label's tokens themselves, so %STS is the label
+// STS and % alone is the empty label.
+//
+// struct Assembly <- An assembled program and the labels it uses
+//
+// assemble(source: &str) -> Result<Assembly, String>
+// -- Assemble a program from its mnemonics
+//
+/***********************************************************/
+use labels::{LabelId, SymbolTable};
+use symbols::{Action, Token};
+use symbols::Action::*;
+
+
+/********************************************/
+// Structures
+/********************************************/
+// An assembled program and the labels it uses, as they're spelled
+#[derive(Debug, Clone, Default)]
+pub struct Assembly {
+	pub program: Vec<Action>,
+	pub labels:  SymbolTable,
+}
+
+// An instruction, waiting for the operand it takes
+enum Instruction {
+	Bare(Action),                 // Takes no operand
+	Number(fn(i64) -> Action),    // Takes a number
+	Label(fn(LabelId) -> Action), // Takes a label
+}
+
+
+/********************************************/
+// Public Functions
+/********************************************/
+// Assemble a program from its mnemonics, an instruction to a line, giving
+// each label an id in the order they first appear
+pub fn assemble(source: &str) -> Result<Assembly, String> {
+	let mut assembly = Assembly::default();
+
+	for (number, line) in source.lines().enumerate() {
+		let line = strip_comment(line).trim();
+		if line.is_empty() {
+			continue;
+		}
+
+		// A name and a colon define a label
+		let (mnemonic, operand) = match line.strip_suffix(':') {
+			Some(name) if !name.is_empty() && !name.contains(char::is_whitespace) => ("label", name),
+			_                                                                    => split(line),
+		};
+
+		let action = match (instruction(mnemonic), operand) {
+			(None, _)                                  => Err(format!("{} isn't an instruction", mnemonic)),
+			(Some(Instruction::Bare(action)), "")      => Ok(action),
+			(Some(Instruction::Bare(_)), _)            => Err(format!("{} doesn't take an operand", mnemonic)),
+			(Some(_), "")                              => Err(format!("{} needs an operand", mnemonic)),
+			(Some(Instruction::Number(make)), operand) => value(operand).map(make).ok_or_else(|| format!("{} isn't a number or a character in quotes", operand)),
+			(Some(Instruction::Label(_)), name) if name.contains(char::is_whitespace) => Err(format!("the label {} has whitespace in it", name)),
+			(Some(Instruction::Label(make)), name) => Ok(make(assembly.labels.intern(&tokens(name)))),
+		};
+		assembly.program.push(action.map_err(|message| format!("line {}: {}", number + 1, message))?);
+	}
+
+	Ok(assembly)
+}
+
+
+/********************************************/
+// Private Functions
+/********************************************/
+// The instruction a mnemonic stands for, by its short name or the name
+// list gives it, in any case
+fn instruction(mnemonic: &str) -> Option<Instruction> {
+	let instruction = match mnemonic.to_lowercase().as_str() {
+		"push"     | "stackpush"       => Instruction::Number(StackPush),
+		"dup"      | "stackduplicate"  => Instruction::Bare(StackDuplicate),
+		"swap"     | "stackswap"       => Instruction::Bare(StackSwap),
+		"drop"     | "stackdiscard"    => Instruction::Bare(StackDiscard),
+		"add"                          => Instruction::Bare(Add),
+		"sub"      | "subtract"        => Instruction::Bare(Subtract),
+		"mul"      | "multiply"        => Instruction::Bare(Multiply),
+		"div"      | "divide"          => Instruction::Bare(Divide),
+		"mod"      | "modulo"          => Instruction::Bare(Modulo),
+		"store"    | "heapstore"       => Instruction::Bare(HeapStore),
+		"retrieve" | "heapretrieve"    => Instruction::Bare(HeapRetrieve),
+		"label"                        => Instruction::Label(Label),
+		"call"                         => Instruction::Label(Call),
+		"jump"     | "jmp"             => Instruction::Label(Jump),
+		"jz"       | "jumpifzero"      => Instruction::Label(JumpIfZero),
+		"jn"       | "jumpifnegative"  => Instruction::Label(JumpIfNegative),
+		"ret"      | "endsubroutine"   => Instruction::Bare(EndSubroutine),
+		"halt"     | "end"             => Instruction::Bare(Halt),
+		"outc"     | "outputchar"      => Instruction::Bare(OutputChar),
+		"outn"     | "outputnumber"    => Instruction::Bare(OutputNumber),
+		"readc"    | "readchar"        => Instruction::Bare(ReadChar),
+		"readn"    | "readnumber"      => Instruction::Bare(ReadNumber),
+		"getenv"                       => Instruction::Bare(GetEnv),
+		"dumpheap"                     => Instruction::Bare(DumpHeap),
+		"assert"                       => Instruction::Bare(Assert),
+		"calldepth"                    => Instruction::Bare(CallDepth),
+		"peekreturn"                   => Instruction::Bare(PeekReturn),
+		"outf"     | "outputformatted" => Instruction::Bare(OutputFormatted),
+		"clock"                        => Instruction::Bare(Clock),
+		"random"                       => Instruction::Bare(Random),
+		_                              => return None,
+	};
+	Some(instruction)
+}
+
+
+// The tokens of a label with a name, which are its bytes a bit at a time
+// unless it's a % and the tokens themselves
+fn tokens(name: &str) -> Vec<Token> {
+	let bit = |one: bool| if one {Token::Tab} else {Token::Space};
+	match name.strip_prefix('%') {
+		Some(spelling) if spelling.chars().all(|token| token == 'S' || token == 'T') => spelling.chars().map(|token| bit(token == 'T')).collect(),
+		_ => name.bytes().flat_map(|byte| (0..8).rev().map(move |index| bit(byte >> index & 1 == 1))).collect(),
+	}
+}
+
+
+// Split a line into its mnemonic and operand, which is either after
+// whitespace or in brackets straight after the mnemonic
+fn split(line: &str) -> (&str, &str) {
+	let end = line.find(|character: char| character.is_whitespace() || character == '(').unwrap_or(line.len());
+	let (mnemonic, rest) = line.split_at(end);
+	match rest.strip_prefix('(').and_then(|rest| rest.strip_suffix(')')) {
+		Some(operand) => (mnemonic, operand.trim()),
+		None          => (mnemonic, rest.trim()),
+	}
+}
+
+
+// A line without its comment, a ; that isn't in quotes and what follows
+fn strip_comment(line: &str) -> &str {
+	let mut quoted  = false;
+	let mut escaped = false;
+	for (index, character) in line.char_indices() {
+		match character {
+			_ if escaped   => escaped = false,
+			'\\' if quoted => escaped = true,
+			'\''           => quoted = !quoted,
+			';' if !quoted => return &line[..index],
+			_              => {},
+		}
+	}
+	line
+}
+
+
+// The value of a number, or of a character in quotes
+fn value(operand: &str) -> Option<i64> {
+	let quoted = match operand.strip_prefix('\'').and_then(|rest| rest.strip_suffix('\'')) {
+		Some(quoted) => quoted,
+		None         => return operand.parse().ok(),
+	};
+
+	let character = match quoted {
+		"\\n"  => '\n',
+		"\\t"  => '\t',
+		"\\r"  => '\r',
+		"\\0"  => '\0',
+		"\\\\" => '\\',
+		"\\'"  => '\'',
+		_      => {
+			let mut characters = quoted.chars();
+			match (characters.next(), characters.next()) {
+				(Some(character), None) => character,
+				_                       => return None,
+			}
+		},
+	};
+	Some(character as i64)
+}
@@ -0,0 +1,118 @@
+/***********************************************************/
+//
+// Determinism Audits
+// ==================
+// Keeps track of everything a run depends on besides its
+// program and options, for run --audit: reading input,
+// reading environment variables, sharing the heap with
+// another program's thread, and stopping at a time limit.
+// A run that touched none of them gives the same output and
+// ends the same way every time, so its result can be kept
+// and reused.
+//
+// A strict audit, for run --deterministic, fails the run at
+// the first of them instead.
+//
+// enum Source <- Something a run can depend on besides its program
+//
+// struct Audit <- The sources a run has touched so far
+//
+/***********************************************************/
+use std::fmt::Write;
+
+
+/********************************************/
+// Structures
+/********************************************/
+// Something a run can depend on besides its program
+#[derive(Debug, Clone, PartialEq)]
+pub enum Source {
+	Input,               // Reading a character or a number
+	Environment(String), // Reading an environment variable the program may read
+	SharedHeap,          // Sharing the heap with a program on another thread
+	Clock,               // Stopping at the time limit
+	Time,                // Reading the time with Clock, unless the clock is virtual
+	Entropy,             // Drawing a random number with Random, unless there's a seed
+}
+
+// The sources a run has touched so far
+#[derive(Debug, Clone, Default)]
+pub struct Audit {
+	strict:  bool,
+	touched: Vec<Touch>,
+}
+
+// A source a run has touched, and where it first did
+#[derive(Debug, Clone)]
+struct Touch {
+	source:  Source,
+	count:   u64,
+	step:    u64,
+	pointer: usize,
+}
+
+
+/********************************************/
+// Methods
+/********************************************/
+impl Source {
+	// What the run did, for reports and errors
+	pub fn description(&self) -> String {
+		match *self {
+			Source::Input                 => "reading input".to_string(),
+			Source::Environment(ref name) => format!("reading the environment variable {}", name),
+			Source::SharedHeap            => "sharing the heap with the --alongside program".to_string(),
+			Source::Clock                 => "stopping at the time limit".to_string(),
+			Source::Time                  => "reading the time".to_string(),
+			Source::Entropy               => "drawing a random number without a --seed".to_string(),
+		}
+	}
+}
+
+
+impl Audit {
+	// Constructor, an audit that only records, or a strict one that fails
+	// at the first source touched
+	pub fn new(strict: bool) -> Audit {
+		Audit {strict, touched: Vec::new()}
+	}
+
+	// Record that the run touched a source at the given step and action,
+	// failing with why the run isn't deterministic if the audit is strict
+	pub fn touch(&mut self, source: Source, step: u64, pointer: usize) -> Result<(), String> {
+		if self.strict {
+			return Err(format!("The run isn't deterministic: it depends on {}.", source.description()));
+		}
+
+		self.record(source, step, pointer);
+		Ok(())
+	}
+
+	// Record that the run touched a source, even in a strict audit, for
+	// sources that stop the run anyway
+	pub fn record(&mut self, source: Source, step: u64, pointer: usize) {
+		match self.touched.iter_mut().find(|touch| touch.source == source) {
+			Some(touch) => touch.count += 1,
+			None        => self.touched.push(Touch {source, count: 1, step, pointer}),
+		}
+	}
+
+	// Whether the run has touched nothing but its program
+	pub fn deterministic(&self) -> bool {
+		self.touched.is_empty()
+	}
+
+	// Describe every source touched, how often, and when first
+	pub fn render(&self) -> String {
+		if self.deterministic() {
+			return "Determinism audit: the run was deterministic\n".to_string();
+		}
+
+		let mut text = format!("Determinism audit: the run depended on {} source(s) besides its program\n", self.touched.len());
+		for touch in &self.touched {
+			let times = if touch.count == 1 {"once".to_string()} else {format!("{} times", touch.count)};
+			let _ = writeln!(text, "    {}, {}, first at action {} (step {})", touch.source.description(), times, touch.pointer, touch.step);
+		}
+		text
+	}
+}
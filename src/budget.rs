@@ -0,0 +1,157 @@
+/***********************************************************/
+//
+// Execution Budgets
+// =================
+// Attributes the actions a run executes to the subroutines
+// they ran in, for run --budget, the way perf report does by
+// function. An action counts against the subroutine of the
+// innermost call being executed when it ran, its exclusive
+// count, and against every subroutine with a call being
+// executed at the time, its inclusive count. A subroutine
+// that calls itself counts each action once. Actions outside
+// every call count against main.
+//
+// A subroutine is known by where its calls go, and is shown
+// with the label there, as the parser read it, or as the
+// text it spells when it's printable ASCII a byte at a time,
+// the way many programs name their labels. Like taint
+// tracking, the calls are followed alongside the vm by
+// looking at each action before it's executed.
+//
+// struct Budget <- Where a run's actions have been executed so far
+//
+// names(program: &[Action], labels: &SymbolTable) -> HashMap<usize, String>
+// -- The name of the label at each program pointer of a parsed program
+//
+/***********************************************************/
+use std::collections::HashMap;
+use std::fmt::Write;
+use labels::SymbolTable;
+use symbols::Action;
+use symbols::Action::*;
+
+
+/********************************************/
+// Structures
+/********************************************/
+// Where a run's actions have been executed so far
+#[derive(Debug, Clone, Default)]
+pub struct Budget {
+	names:    HashMap<usize, String>,   // The label at each program pointer
+	frames:   Vec<(usize, u64)>,        // Each call being executed: where it went, and the step of the call
+	routines: HashMap<usize, Routine>,  // The counts for each subroutine, by where its calls go
+	main:     u64,                      // Actions executed outside every call
+	last:     u64,                      // The last step seen, since a read that pauses is seen again when it's repeated
+}
+
+// The counts for a subroutine
+#[derive(Debug, Clone, Default)]
+struct Routine {
+	calls:     u64,
+	inclusive: u64, // Actions executed in calls that have returned, counting each once
+	exclusive: u64, // Actions executed while it was the innermost call
+}
+
+
+/********************************************/
+// Methods
+/********************************************/
+impl Budget {
+	// Constructor, with the label at each program pointer of the resolved
+	// program, which subroutines are shown with
+	pub fn new(names: HashMap<usize, String>) -> Budget {
+		Budget {names, ..Budget::default()}
+	}
+
+	// Count an action about to be executed against the call it's in, and
+	// follow it into or out of a subroutine
+	pub fn before(&mut self, step: u64, action: &Action) {
+		if step <= self.last {
+			return;
+		}
+		self.last = step;
+
+		match self.frames.last() {
+			Some(&(routine, _)) => self.routines.entry(routine).or_default().exclusive += 1,
+			None                => self.main += 1,
+		}
+
+		match *action {
+			Call(target) => {
+				let routine = target.0 as usize;
+				self.routines.entry(routine).or_default().calls += 1;
+				self.frames.push((routine, step));
+			},
+
+			// A return counts in the call it ends, until the very next step
+			EndSubroutine => if let Some((routine, called)) = self.frames.pop() {
+				if !self.frames.iter().any(|&(outer, _)| outer == routine) {
+					self.routines.entry(routine).or_default().inclusive += step - called;
+				}
+			},
+
+			_ => {},
+		}
+	}
+
+	// A table of the actions executed in each subroutine, the most
+	// inclusive first
+	pub fn render(&self) -> String {
+		// Calls still being executed count up to the last step, once per subroutine
+		let mut inclusive: HashMap<usize, u64> = self.routines.iter().map(|(&routine, counts)| (routine, counts.inclusive)).collect();
+		for (index, &(routine, called)) in self.frames.iter().enumerate() {
+			if !self.frames[..index].iter().any(|&(outer, _)| outer == routine) {
+				*inclusive.entry(routine).or_insert(0) += self.last - called;
+			}
+		}
+
+		let mut rows: Vec<(String, u64, u64, u64)> = self.routines.iter()
+			.map(|(routine, counts)| (self.name(*routine), counts.calls, inclusive[routine], counts.exclusive))
+			.collect();
+		rows.sort_by(|a, b| b.2.cmp(&a.2).then_with(|| a.0.cmp(&b.0)));
+		// A resumed run counts from where it resumed, rather than from step one
+		let executed = self.main + self.routines.values().map(|counts| counts.exclusive).sum::<u64>();
+		rows.insert(0, ("main".to_string(), 1, executed, self.main));
+
+		let total = executed.max(1) as f64;
+		let width = rows.iter().map(|row| row.0.len()).max().unwrap_or(0).max(20);
+		let mut text = format!("Budget report: {} action(s) executed\n", executed);
+		let _ = writeln!(text, "    {:<width$} {:>8} {:>12} {:>7} {:>12} {:>7}", "routine", "calls", "inclusive", "%", "exclusive", "%", width = width);
+		for (name, calls, inclusive, exclusive) in rows {
+			let _ = writeln!(
+				text, "    {:<width$} {:>8} {:>12} {:>6.1}% {:>12} {:>6.1}%",
+				name, calls, inclusive, inclusive as f64 * 100.0 / total, exclusive, exclusive as f64 * 100.0 / total, width = width,
+			);
+		}
+		text
+	}
+
+	// The name a subroutine is shown with: its label, or where it starts
+	// when the label isn't known
+	fn name(&self, routine: usize) -> String {
+		match self.names.get(&routine) {
+			Some(name) => format!("label {}", name),
+			None       => format!("action {}", routine),
+		}
+	}
+}
+
+
+/********************************************/
+// Public Functions
+/********************************************/
+// The name of the label at each program pointer a parsed program's labels
+// resolve to, as the text it spells when it spells one. Where several
+// labels mark the same place, the first one is used.
+pub fn names(program: &[Action], labels: &SymbolTable) -> HashMap<usize, String> {
+	let mut names   = HashMap::new();
+	let mut pointer = 0;
+	for action in program {
+		match *action {
+			Label(label) => {names.entry(pointer).or_insert_with(|| labels.text(label).map_or_else(|| labels.name(label), |text| format!("{:?}", text)));},
+			_            => pointer += 1,
+		}
+	}
+	names
+}
+
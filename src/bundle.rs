@@ -0,0 +1,176 @@
+/***********************************************************/
+//
+// Bundles
+// =======
+// Self-running executables, for the bundle command, so a
+// program can be handed to people who don't have the
+// interpreter. A bundle is a copy of the interpreter with
+// the program appended to it, along with the options it was
+// bundled with and the configuration file that applied, and
+// then a trailer saying how long they are.
+//
+// When the interpreter starts, it looks for a trailer at the
+// end of its own executable, and if there's one runs the
+// program in it with those options instead of reading its
+// own, handing every argument it was given to the program.
+// The program is kept in STL notation, so it's run the way
+// --eval runs one.
+//
+// struct Bundle <- A program and the options it's run with
+//
+// embedded() -> Option<Bundle>
+// -- The bundle at the end of the running executable, if it's one
+//
+/***********************************************************/
+use std::env;
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+use options::Options;
+use parser;
+use symbols::Token;
+
+
+/********************************************/
+// Structures
+/********************************************/
+// A program and the options it's run with
+#[derive(Debug, Clone, PartialEq)]
+pub struct Bundle {
+	pub config: Option<String>, // The configuration file that applied when it was bundled
+	flags:      Vec<String>,    // The options it was bundled with
+	notation:   String,         // The program, in STL notation
+}
+
+
+/********************************************/
+// Constants
+/********************************************/
+// The end of every bundle, changed whenever the format does. The eight
+// bytes before it are the length of the rest, little-endian.
+static MAGIC: &[u8; 8] = b"WSBUNDL1";
+
+
+/********************************************/
+// Methods
+/********************************************/
+impl Bundle {
+	// Constructor, from the configuration and options a program is run with
+	// and its source, read with the options it's bundled with
+	pub fn new(config: Option<String>, flags: Vec<String>, program: &str, options: &Options) -> Bundle {
+		let notation = program.chars().filter_map(|character| match parser::token(character, options)? {
+			Token::Space  => Some('S'),
+			Token::Tab    => Some('T'),
+			Token::Return => Some('L'),
+		}).collect();
+		Bundle {config, flags, notation}
+	}
+
+	// The command line the program is run with as a bundle, passing on
+	// the arguments it was given
+	pub fn arguments(&self, given: &[String]) -> Vec<String> {
+		let mut arguments = vec!["run".to_string()];
+		arguments.extend(self.flags.iter().cloned());
+		arguments.push("--eval".to_string());
+		arguments.push(self.notation.clone());
+		if !given.is_empty() {
+			arguments.push("--".to_string());
+			arguments.extend(given.iter().cloned());
+		}
+		arguments
+	}
+
+	// Write a copy of the running interpreter with the bundle appended to
+	// it, ready to be run
+	pub fn write(&self, path: &Path) -> io::Result<()> {
+		let mut executable = read_executable()?;
+
+		let mut fields = vec![self.config.clone().unwrap_or_default()];
+		fields.extend(self.flags.iter().cloned());
+		fields.push(self.notation.clone());
+		let payload = fields.join("\0");
+
+		executable.extend_from_slice(payload.as_bytes());
+		executable.extend_from_slice(&(payload.len() as u64).to_le_bytes());
+		executable.extend_from_slice(MAGIC);
+		fs::write(path, executable)?;
+		executable_permissions(path)
+	}
+}
+
+
+/********************************************/
+// Public Functions
+/********************************************/
+// The bundle at the end of the running executable, if it's one. Only
+// the end is read, so starting an interpreter that isn't a bundle
+// costs next to nothing.
+pub fn embedded() -> Option<Bundle> {
+	let mut file = File::open(env::current_exe().ok()?).ok()?;
+	let length   = trailer(&mut file)?;
+
+	let mut payload = vec![0; length as usize];
+	file.seek(SeekFrom::End(-16 - length as i64)).ok()?;
+	file.read_exact(&mut payload).ok()?;
+
+	let payload    = String::from_utf8(payload).ok()?;
+	let mut fields = payload.split('\0').map(str::to_string).collect::<Vec<String>>();
+	let notation   = fields.pop()?;
+	if fields.is_empty() {
+		return None;
+	}
+	let config = Some(fields.remove(0)).filter(|config| !config.is_empty());
+	Some(Bundle {config, flags: fields, notation})
+}
+
+
+/********************************************/
+// Private Functions
+/********************************************/
+// The length of the bundle before the trailer at the end of a file, if
+// it ends in one
+fn trailer(file: &mut File) -> Option<u64> {
+	let mut trailer = [0; 16];
+	file.seek(SeekFrom::End(-16)).ok()?;
+	file.read_exact(&mut trailer).ok()?;
+	if &trailer[8..] != MAGIC {
+		return None;
+	}
+
+	let mut length = [0; 8];
+	length.copy_from_slice(&trailer[..8]);
+	let length = u64::from_le_bytes(length);
+	let size   = file.metadata().ok()?.len();
+	Some(length).filter(|&length| length.checked_add(16).is_some_and(|bundled| bundled <= size))
+}
+
+
+// The running interpreter's executable, without any bundle at its end
+fn read_executable() -> io::Result<Vec<u8>> {
+	let path = env::current_exe()?;
+	let mut file = File::open(path)?;
+	let bundled  = trailer(&mut file);
+
+	let mut executable = Vec::new();
+	file.seek(SeekFrom::Start(0))?;
+	file.read_to_end(&mut executable)?;
+	if let Some(length) = bundled {
+		executable.truncate(executable.len() - 16 - length as usize);
+	}
+	Ok(executable)
+}
+
+
+// Let everyone run a written bundle, where files have permissions
+#[cfg(unix)]
+fn executable_permissions(path: &Path) -> io::Result<()> {
+	use std::os::unix::fs::PermissionsExt;
+	fs::set_permissions(path, fs::Permissions::from_mode(0o755))
+}
+
+#[cfg(not(unix))]
+fn executable_permissions(_path: &Path) -> io::Result<()> {
+	Ok(())
+}
@@ -0,0 +1,239 @@
+/***********************************************************/
+//
+// Program Cache
+// =============
+// Keeps reduced programs on disk, keyed by a hash of their
+// source and the options they were parsed with, so running
+// the same large program again skips parsing entirely.
+// The warnings the parser gave are kept with the program,
+// so they can be shown again without parsing it.
+//
+// The cache lives in $XDG_CACHE_HOME/whitespace, or in
+// ~/.cache/whitespace, with one file per program holding
+// a warning or an action per line. The cache is only an
+// optimization: failing to read or write it just means
+// parsing again.
+//
+// key(source: &str, options: &Options, passes: &[&str]) -> String
+// -- The name a program is cached under
+//
+// load(key: &str) -> Option<(Vec<Action>, Vec<ParseError>)>
+// -- Get a cached program and its warnings, if there is one
+//
+// store(key: &str, program: &[Action], warnings: &[ParseError])
+// -- Cache a reduced program and the warnings parsing it gave
+//
+// binary(key: &str) -> Option<PathBuf>
+// -- Where the native binary of a program is cached
+//
+// clear() -> io::Result<usize>
+// -- Remove every cached program and binary, returning how
+//    many there were
+//
+/***********************************************************/
+use std::env;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use error::ParseError;
+use labels::LabelId;
+use options::Options;
+use symbols::Action;
+use symbols::Action::*;
+
+
+/********************************************/
+// Constants
+/********************************************/
+// The first line of every cache file, changed whenever the format does
+static HEADER: &str = "whitespace-cache 2";
+
+// What the lines holding warnings start with, ahead of the actions
+static WARNING: &str = "Warning ";
+
+// The extension of cache files, so clear only removes what the cache wrote
+static EXTENSION: &str = "wsc";
+
+// The extension of native binaries built by run --native
+static BINARY_EXTENSION: &str = "wsx";
+
+
+/********************************************/
+// Public Functions
+/********************************************/
+// The name a program is cached under: a hash of its source, the options
+// that change how it's parsed, the rewrites run over it after parsing,
+// and the version of the interpreter
+pub fn key(source: &str, options: &Options, passes: &[&str]) -> String {
+	let described = format!("{}\0{:?}\0{:?}\0{}", env!("CARGO_PKG_VERSION"), options, passes, source);
+	format!("{:016x}", fnv1a(described.as_bytes()))
+}
+
+
+// Get a cached program and the warnings parsing it gave, if there is one
+// and it can be read
+pub fn load(key: &str) -> Option<(Vec<Action>, Vec<ParseError>)> {
+	let text = fs::read_to_string(path(key)?).ok()?;
+	let mut lines = text.lines().peekable();
+	if lines.next() != Some(HEADER) {
+		return None;
+	}
+
+	let mut warnings = Vec::new();
+	while let Some(warning) = lines.next_if(|line| line.starts_with(WARNING)) {
+		warnings.push(parse_warning(&warning[WARNING.len()..])?);
+	}
+
+	Some((lines.map(parse_action).collect::<Option<_>>()?, warnings))
+}
+
+
+// Cache a reduced program and the warnings parsing it gave, ignoring any
+// failure to do so
+pub fn store(key: &str, program: &[Action], warnings: &[ParseError]) {
+	let path = match path(key) {
+		Some(path) => path,
+		None       => return,
+	};
+
+	let mut text = format!("{}\n", HEADER);
+	for warning in warnings {
+		text.push_str(&write_warning(warning));
+		text.push('\n');
+	}
+	for action in program {
+		text.push_str(&write_action(action));
+		text.push('\n');
+	}
+
+	// Write to a temporary file first so a reader never sees half a program
+	let temporary = path.with_extension("tmp");
+	let written = path.parent().map_or(Ok(()), fs::create_dir_all)
+		.and_then(|()| fs::write(&temporary, text))
+		.and_then(|()| fs::rename(&temporary, &path));
+	if written.is_err() {
+		let _ = fs::remove_file(&temporary);
+	}
+}
+
+
+// Where the native binary of a program is cached, if there's anywhere to put it
+pub fn binary(key: &str) -> Option<PathBuf> {
+	directory().map(|directory| directory.join(key).with_extension(BINARY_EXTENSION))
+}
+
+
+// Remove every cached program and binary, returning how many there were
+pub fn clear() -> io::Result<usize> {
+	let directory = match directory() {
+		Some(directory) if directory.is_dir() => directory,
+		_                                     => return Ok(0),
+	};
+
+	let mut removed = 0;
+	for entry in fs::read_dir(directory)? {
+		let path = entry?.path();
+		if path.extension().is_some_and(|extension| extension == EXTENSION || extension == BINARY_EXTENSION) {
+			fs::remove_file(path)?;
+			removed += 1;
+		}
+	}
+
+	Ok(removed)
+}
+
+
+/********************************************/
+// Private Functions
+/********************************************/
+// The directory the cache lives in, if there's anywhere to put it
+fn directory() -> Option<PathBuf> {
+	let base = match env::var_os("XDG_CACHE_HOME").filter(|base| !base.is_empty()) {
+		Some(base) => PathBuf::from(base),
+		None       => PathBuf::from(env::var_os("HOME")?).join(".cache"),
+	};
+
+	Some(base.join("whitespace"))
+}
+
+
+// The file a program with the given key is cached in
+fn path(key: &str) -> Option<PathBuf> {
+	directory().map(|directory| directory.join(key).with_extension(EXTENSION))
+}
+
+
+// The 64 bit FNV-1a hash, which unlike the standard library's hasher
+// gives the same result from one build to the next
+fn fnv1a(bytes: &[u8]) -> u64 {
+	bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| (hash ^ byte as u64).wrapping_mul(0x0000_0100_0000_01b3))
+}
+
+
+// Write a warning as where it points, followed by its message
+fn write_warning(warning: &ParseError) -> String {
+	format!("{}{} {} {} {} {} {}", WARNING, warning.offset, warning.line, warning.column, warning.token, warning.position, warning.message)
+}
+
+
+// Read a warning written by write_warning, without the start of its line
+fn parse_warning(text: &str) -> Option<ParseError> {
+	let mut parts = text.splitn(6, ' ');
+	let mut place = || parts.next().and_then(|part| part.parse().ok());
+	let (offset, line, column, token, position) = (place()?, place()?, place()?, place()?, place()?);
+
+	Some(ParseError {message: parts.next()?.to_string(), token, position, offset, line, column})
+}
+
+
+// Write an action as its name, followed by its operand if it has one
+fn write_action(action: &Action) -> String {
+	match *action {
+		StackPush(value) => format!("{} {}", action.name(), value),
+		Label(label) | Call(label) | Jump(label) | JumpIfZero(label) | JumpIfNegative(label) => format!("{} {}", action.name(), label.0),
+		_ => action.name().to_string(),
+	}
+}
+
+
+// Read an action written by write_action
+fn parse_action(line: &str) -> Option<Action> {
+	let mut parts = line.split(' ');
+	let name      = parts.next()?;
+	let operand   = parts.next();
+	let label     = || operand.and_then(|operand| operand.parse().ok()).map(LabelId);
+
+	Some(match name {
+		"StackPush"       => StackPush(operand?.parse().ok()?),
+		"StackDuplicate"  => StackDuplicate,
+		"StackSwap"       => StackSwap,
+		"StackDiscard"    => StackDiscard,
+		"Add"             => Add,
+		"Subtract"        => Subtract,
+		"Multiply"        => Multiply,
+		"Divide"          => Divide,
+		"Modulo"          => Modulo,
+		"HeapStore"       => HeapStore,
+		"HeapRetrieve"    => HeapRetrieve,
+		"Label"           => Label(label()?),
+		"Call"            => Call(label()?),
+		"Jump"            => Jump(label()?),
+		"JumpIfZero"      => JumpIfZero(label()?),
+		"JumpIfNegative"  => JumpIfNegative(label()?),
+		"EndSubroutine"   => EndSubroutine,
+		"Halt"            => Halt,
+		"OutputChar"      => OutputChar,
+		"OutputNumber"    => OutputNumber,
+		"ReadChar"        => ReadChar,
+		"ReadNumber"      => ReadNumber,
+		"GetEnv"          => GetEnv,
+		"DumpHeap"        => DumpHeap,
+		"Assert"          => Assert,
+		"CallDepth"       => CallDepth,
+		"PeekReturn"      => PeekReturn,
+		"OutputFormatted" => OutputFormatted,
+		"Clock"           => Clock,
+		"Random"          => Random,
+		_                 => return None,
+	})
+}
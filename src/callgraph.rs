@@ -0,0 +1,68 @@
+/***********************************************************/
+//
+// Call Graphs
+// ===========
+// Writes out which subroutines call which, as found by
+// analysis::call_graph, for viewing with Graphviz or for
+// other tools to read.
+//
+// render_dot(routines: &[Routine], labels: &SymbolTable) -> String
+// -- Write a call graph in Graphviz's DOT language
+//
+// render_json(routines: &[Routine], labels: &SymbolTable) -> String
+// -- Write a call graph as JSON
+//
+// Labels are named as the table they were interned in
+// names them.
+//
+/***********************************************************/
+use analysis::Routine;
+use json;
+use labels::{LabelId, SymbolTable};
+
+
+/********************************************/
+// Public Functions
+/********************************************/
+// Write a call graph in Graphviz's DOT language, with a node for
+// the main program and each subroutine
+pub fn render_dot(routines: &[Routine], labels: &SymbolTable) -> String {
+	let mut dot = String::from("digraph calls {\n");
+
+	for routine in routines {
+		dot.push_str(&format!("\t{};\n", json::string(&name(routine.label, labels))));
+	}
+	for routine in routines {
+		for &callee in &routine.calls {
+			dot.push_str(&format!("\t{} -> {};\n", json::string(&name(routine.label, labels)), json::string(&name(Some(callee), labels))));
+		}
+	}
+
+	dot.push_str("}\n");
+	dot
+}
+
+
+// Write a call graph as JSON: a list of the routines, where the main
+// program's label is null
+pub fn render_json(routines: &[Routine], labels: &SymbolTable) -> String {
+	let entries: Vec<String> = routines.iter().map(|routine| format!(
+		"{{\"label\":{},\"calls\":[{}]}}",
+		routine.label.map_or("null".to_string(), |label| json::string(&labels.name(label))),
+		routine.calls.iter().map(|&label| json::string(&labels.name(label))).collect::<Vec<String>>().join(","),
+	)).collect();
+
+	format!("[{}]\n", entries.join(","))
+}
+
+
+/********************************************/
+// Private Functions
+/********************************************/
+// The name a routine is shown with
+fn name(label: Option<LabelId>, labels: &SymbolTable) -> String {
+	match label {
+		Some(label) => format!("label {}", labels.name(label)),
+		None        => "main".to_string(),
+	}
+}
@@ -0,0 +1,59 @@
+/***********************************************************/
+//
+// Cancellation
+// ============
+// A token an application embedding the interpreter keeps to
+// stop a run from another thread, say when its user gives
+// up on a program that never halts, without killing the
+// process or giving the vm a timeout up front.
+//
+// The vm only looks at the token at safe points, where
+// stopping leaves it exactly as it was between two actions:
+// before a read or a write, and after a jump, call or return
+// going back to an earlier action, which is how every loop
+// carries on. A program that doesn't halt has to go back
+// round somewhere, so it's stopped promptly without putting
+// a check on every action. A cancelled run stops with a
+// limit error, like one that reached its limits, and can be
+// executed again from where it stopped once the token has
+// been reset.
+//
+// struct Cancel <- A token that cancels the runs it's given to
+//
+/***********************************************************/
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+
+/********************************************/
+// Structures
+/********************************************/
+// A token that cancels the runs it's given to. Clones share the token,
+// so one can be handed to the vm and another kept by the thread that
+// cancels it.
+#[derive(Debug, Clone, Default)]
+pub struct Cancel {
+	cancelled: Arc<AtomicBool>,
+}
+
+
+/********************************************/
+// Methods
+/********************************************/
+impl Cancel {
+	// Stop the runs given the token at their next safe point, and any
+	// executed after until the token is reset
+	pub fn cancel(&self) {
+		self.cancelled.store(true, Ordering::Relaxed);
+	}
+
+	// Let runs given the token execute again
+	pub fn reset(&self) {
+		self.cancelled.store(false, Ordering::Relaxed);
+	}
+
+	// Whether the token has been cancelled since it was last reset
+	pub fn is_cancelled(&self) -> bool {
+		self.cancelled.load(Ordering::Relaxed)
+	}
+}
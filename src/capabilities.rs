@@ -0,0 +1,210 @@
+/***********************************************************/
+//
+// Capabilities
+// ============
+// Describes what this build of the interpreter understands:
+// the instructions it parses, the extensions it offers, the
+// semantic options and their values, and the features it
+// was built with. Editors, playgrounds and test harnesses
+// read it to adapt to the interpreter instead of guessing
+// from its version.
+//
+// struct Instruction <- An instruction the parser understands
+//
+// render_text() -> String
+// -- Describe the build for a person to read
+//
+// render_json() -> String
+// -- Describe the build as a JSON object
+//
+/***********************************************************/
+use std::env::consts;
+use json;
+use labels::LabelId;
+use options;
+use symbols::{Action, Category};
+use symbols::Action::*;
+
+
+/********************************************/
+// Structures
+/********************************************/
+// An instruction the parser understands
+pub struct Instruction {
+	pub action:  Action,       // The action it parses to, with a placeholder operand
+	pub tokens:  &'static str, // The tokens it starts with, in STL notation
+	pub operand: &'static str, // What follows the tokens: a number, a label, or nothing
+}
+
+// Every instruction, in the order the language groups them
+pub static INSTRUCTIONS: &[Instruction] = &[
+	Instruction {action: StackPush(0),               tokens: "SS",        operand: "number"},
+	Instruction {action: StackDuplicate,             tokens: "SLS",       operand: ""},
+	Instruction {action: StackSwap,                  tokens: "SLT",       operand: ""},
+	Instruction {action: StackDiscard,               tokens: "SLL",       operand: ""},
+	Instruction {action: Add,                        tokens: "TSSS",      operand: ""},
+	Instruction {action: Subtract,                   tokens: "TSST",      operand: ""},
+	Instruction {action: Multiply,                   tokens: "TSSL",      operand: ""},
+	Instruction {action: Divide,                     tokens: "TSTS",      operand: ""},
+	Instruction {action: Modulo,                     tokens: "TSTT",      operand: ""},
+	Instruction {action: HeapStore,                  tokens: "TTS",       operand: ""},
+	Instruction {action: HeapRetrieve,               tokens: "TTT",       operand: ""},
+	Instruction {action: Label(LabelId(0)),          tokens: "LSS",       operand: "label"},
+	Instruction {action: Call(LabelId(0)),           tokens: "LST",       operand: "label"},
+	Instruction {action: Jump(LabelId(0)),           tokens: "LSL",       operand: "label"},
+	Instruction {action: JumpIfZero(LabelId(0)),     tokens: "LTS",       operand: "label"},
+	Instruction {action: JumpIfNegative(LabelId(0)), tokens: "LTT",       operand: "label"},
+	Instruction {action: EndSubroutine,              tokens: "LTL",       operand: ""},
+	Instruction {action: Halt,                       tokens: "LLL",       operand: ""},
+	Instruction {action: OutputChar,                 tokens: "TLSS",      operand: ""},
+	Instruction {action: OutputNumber,               tokens: "TLST",      operand: ""},
+	Instruction {action: ReadChar,                   tokens: "TLTS",      operand: ""},
+	Instruction {action: ReadNumber,                 tokens: "TLTT",      operand: ""},
+	Instruction {action: GetEnv,                     tokens: "LLSSTL",    operand: ""},
+	Instruction {action: DumpHeap,                   tokens: "LLSSTSL",   operand: ""},
+	Instruction {action: Assert,                     tokens: "LLSSTTL",   operand: ""},
+	Instruction {action: CallDepth,                  tokens: "LLSSTSSL",  operand: ""},
+	Instruction {action: PeekReturn,                 tokens: "LLSSTSTL",  operand: ""},
+	Instruction {action: OutputFormatted,            tokens: "LLSSTTSL",  operand: ""},
+	Instruction {action: Clock,                      tokens: "LLSSTTTL",  operand: ""},
+	Instruction {action: Random,                     tokens: "LLSSTSSSL", operand: ""},
+];
+
+// The extensions, as the name --extensions enables them by, the number
+// they're written with, and the instruction they add
+static EXTENSIONS: &[(&str, u64, Action)] = &[
+	("env",       1, GetEnv),
+	("debug",     2, DumpHeap),
+	("assert",    3, Assert),
+	("callstack", 4, CallDepth),
+	("callstack", 5, PeekReturn),
+	("format",    6, OutputFormatted),
+	("clock",     7, Clock),
+	("random",    8, Random),
+];
+
+// The semantic options, the values each accepts, and whether it takes
+// a comma separated list of them
+static OPTIONS: &[(&str, &[&str], bool)] = &[
+	("--eof",               &["error", "-1", "0", "unchanged"],                                            false),
+	("--end-of-program",    &["error", "halt"],                                                            false),
+	("--read-char",         &["heap", "stack"],                                                            false),
+	("--division",          &["floored", "truncated", "euclidean"],                                        false),
+	("--labels",            &["eager", "lazy"],                                                            false),
+	("--invalid-number",    &["reprompt", "error"],                                                        false),
+	("--unset-heap",        &["error", "zero"],                                                            false),
+	("--char-encoding",     &["byte", "unicode"],                                                          false),
+	("--invalid-char",      &["lossy", "error"],                                                           false),
+	("--empty-number",      &["zero", "warn", "error"],                                                    false),
+	("--other-whitespace",  &["ignore", "warn", "error"],                                                  false),
+	("--whitespace-tokens", &["vt=<token>", "ff=<token>", "nbsp=<token>", "none"],                         true),
+	("--empty-line",        &["invalid", "zero", "skip"],                                                  false),
+	("--number-input",      &["whitespace", "plus", "hex", "none"],                                        true),
+	("--extensions",        &["env", "debug", "assert", "callstack", "format", "clock", "random", "none"], true),
+];
+
+// The profiles --profile accepts, and the heaps --heap does
+static PROFILES: &[&str] = &["default", "strict", "lenient", "reference"];
+static HEAPS:    &[&str] = &["hash", "dense", "paged"];
+
+// The optional features of the build, and whether this one has them
+static FEATURES: &[(&str, bool)] = &[
+	("hooks",    cfg!(feature = "hooks")),
+	("llvm",     cfg!(feature = "llvm")),
+	("symbolic", cfg!(feature = "symbolic")),
+];
+
+
+/********************************************/
+// Public Functions
+/********************************************/
+// Describe the build for a person to read
+pub fn render_text() -> String {
+	let mut text = format!("whitespace {} ({} {})\n", env!("CARGO_PKG_VERSION"), consts::OS, consts::ARCH);
+
+	let features: Vec<String> = FEATURES.iter().map(|&(name, enabled)| format!("{} ({})", name, if enabled {"enabled"} else {"disabled"})).collect();
+	text.push_str(&format!("Features: {}\n", features.join(", ")));
+
+	text.push_str("Instructions:\n");
+	for instruction in INSTRUCTIONS {
+		let needs = extension(instruction.action).map_or(String::new(), |name| format!("  (--extensions {})", name));
+		let line = format!("  {:<16}{:<10}{:<8}{}{}", instruction.action.name(), instruction.tokens, instruction.operand, category(instruction.action), needs);
+		text.push_str(line.trim_end());
+		text.push('\n');
+	}
+
+	let extensions: Vec<String> = EXTENSIONS.iter().map(|&(name, number, action)| format!("{} (number {}, adds {})", name, number, action.name())).collect();
+	text.push_str(&format!("Extensions: {}\n", extensions.join(", ")));
+
+	text.push_str("Options:\n");
+	for &(flag, values, list) in OPTIONS {
+		let values = if list {format!("any of {}", values.join(","))} else {values.join("|")};
+		text.push_str(&format!("  {:<18}{}\n", flag, values));
+	}
+
+	text.push_str(&format!("Profiles: {}\n", PROFILES.join(", ")));
+	text.push_str(&format!("Heaps:    {}\n", HEAPS.join(", ")));
+	text.push_str(&format!("Limits:   {}\n", options::LIMIT_FLAGS.join(", ")));
+	text
+}
+
+
+// Describe the build as a JSON object
+pub fn render_json() -> String {
+	let strings = |values: &[&str]| format!("[{}]", values.iter().map(|value| json::string(value)).collect::<Vec<String>>().join(","));
+
+	let features: Vec<String> = FEATURES.iter().map(|&(name, enabled)| format!("{}:{}", json::string(name), enabled)).collect();
+
+	let instructions: Vec<String> = INSTRUCTIONS.iter().map(|instruction| format!(
+		"{{\"name\":{},\"tokens\":{},\"operand\":{},\"category\":{},\"extension\":{}}}",
+		json::string(instruction.action.name()),
+		json::string(instruction.tokens),
+		if instruction.operand.is_empty() {"null".to_string()} else {json::string(instruction.operand)},
+		json::string(category(instruction.action)),
+		extension(instruction.action).map_or("null".to_string(), json::string),
+	)).collect();
+
+	let extensions: Vec<String> = EXTENSIONS.iter().map(|&(name, number, action)| format!(
+		"{{\"name\":{},\"number\":{},\"instruction\":{}}}", json::string(name), number, json::string(action.name())
+	)).collect();
+
+	let options: Vec<String> = OPTIONS.iter().map(|&(flag, values, list)| format!(
+		"{{\"flag\":{},\"values\":{},\"list\":{}}}", json::string(flag), strings(values), list
+	)).collect();
+
+	format!(
+		"{{\"version\":{},\"target\":{{\"os\":{},\"arch\":{}}},\"features\":{{{}}},\"instructions\":[{}],\"extensions\":[{}],\"options\":[{}],\"profiles\":{},\"heaps\":{},\"limits\":{}}}\n",
+		json::string(env!("CARGO_PKG_VERSION")),
+		json::string(consts::OS),
+		json::string(consts::ARCH),
+		features.join(","),
+		instructions.join(","),
+		extensions.join(","),
+		options.join(","),
+		strings(PROFILES),
+		strings(HEAPS),
+		strings(options::LIMIT_FLAGS),
+	)
+}
+
+
+/********************************************/
+// Private Functions
+/********************************************/
+// The name of the group an instruction falls into
+fn category(action: Action) -> &'static str {
+	match action.category() {
+		Category::Stack      => "stack",
+		Category::Arithmetic => "arithmetic",
+		Category::Heap       => "heap",
+		Category::Flow       => "flow",
+		Category::Io         => "io",
+		Category::Extension  => "extension",
+	}
+}
+
+
+// The extension an instruction needs enabled, if it's one
+fn extension(action: Action) -> Option<&'static str> {
+	EXTENSIONS.iter().find(|&&(_, _, added)| added.name() == action.name()).map(|&(name, _, _)| name)
+}
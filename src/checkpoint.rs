@@ -0,0 +1,169 @@
+/***********************************************************/
+//
+// Checkpoints
+// ===========
+// Snapshots of a long run, for run --checkpoint-every, so a
+// run of hours can carry on from the last one after a crash
+// or a reboot with the resume command. A snapshot holds what
+// the vm needs to carry on: where it was in the program, its
+// stack, call stack and heap, the actions executed and how
+// much input it had read, along with the key the program and
+// its options are cached under, so it can't be resumed with
+// a different program. It also holds where the virtual clock
+// and Random's generator had got to, so a run with a seed
+// draws the same numbers whether it was resumed or not.
+//
+// Only the latest snapshot is kept. Each is written to a
+// temporary file and then renamed over the last, so a crash
+// while one is being written leaves the one before it.
+//
+// struct Checkpoints <- How often snapshots are taken and where they go
+//
+// struct Checkpoint <- A snapshot of a run
+//
+// load(path: &Path, key: &str) -> Result<Checkpoint, String>
+// -- Read the snapshot at a path, checking it's of the program with the key
+//
+// remove(path: &Path) -> io::Result<()>
+// -- Remove the snapshot at a path, if there is one
+//
+/***********************************************************/
+use std::fmt::Write;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+
+/********************************************/
+// Structures
+/********************************************/
+// How often snapshots are taken and where they go
+#[derive(Debug, Clone)]
+pub struct Checkpoints {
+	every: u64,
+	next:  u64,      // The actions executed when the next snapshot is due
+	path:  PathBuf,
+	key:   String,   // The key of the program being run
+}
+
+// A snapshot of a run
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Checkpoint {
+	pub pointer:    usize,
+	pub steps:      u64,
+	pub read:       u64,             // The bytes of input read
+	pub stack:      Vec<i64>,        // Bottom first
+	pub call_stack: Vec<usize>,      // Bottom first
+	pub heap:       Vec<(i64, i64)>, // Each address and value, in address order
+	pub clock:      i64,             // The milliseconds the virtual clock has reached
+	pub random:     Option<u64>,     // Random's generator, if it's been seeded
+}
+
+// The first line of every checkpoint, changed whenever the format does
+static HEADER: &str = "whitespace-checkpoint 2";
+
+// Where a checkpoint is written when no path is given
+pub static DEFAULT_PATH: &str = "whitespace.checkpoint";
+
+
+/********************************************/
+// Methods
+/********************************************/
+impl Checkpoints {
+	// Constructor, snapshot a run of the program with the key every so many
+	// actions, counting from the actions it has executed already
+	pub fn new(every: u64, path: PathBuf, key: String, steps: u64) -> Checkpoints {
+		Checkpoints {every, next: steps + every, path, key}
+	}
+
+	// Whether the next snapshot is due. Straight-line stretches run as a
+	// batch, so it can be a few actions late.
+	#[inline(always)]
+	pub fn due(&self, steps: u64) -> bool {
+		steps >= self.next
+	}
+
+	// Write a snapshot over the last one
+	pub fn save(&mut self, checkpoint: &Checkpoint) -> io::Result<()> {
+		self.next = checkpoint.steps + self.every;
+
+		let mut temporary = self.path.clone().into_os_string();
+		temporary.push(".tmp");
+		fs::write(&temporary, checkpoint.render(&self.key))?;
+		fs::rename(&temporary, &self.path)
+	}
+}
+
+impl Checkpoint {
+	// Write out the snapshot of a run of the program with the key
+	pub fn render(&self, key: &str) -> String {
+		let join = |values: Vec<String>| values.iter().map(|value| format!(" {}", value)).collect::<String>();
+
+		let mut text = String::new();
+		let _ = writeln!(text, "{}", HEADER);
+		let _ = writeln!(text, "key {}", key);
+		let _ = writeln!(text, "pointer {}", self.pointer);
+		let _ = writeln!(text, "steps {}", self.steps);
+		let _ = writeln!(text, "read {}", self.read);
+		let _ = writeln!(text, "stack{}", join(self.stack.iter().map(i64::to_string).collect()));
+		let _ = writeln!(text, "calls{}", join(self.call_stack.iter().map(usize::to_string).collect()));
+		let _ = writeln!(text, "clock {}", self.clock);
+		let _ = writeln!(text, "random {}", self.random.map_or("none".to_string(), |state| state.to_string()));
+		let _ = writeln!(text, "heap");
+		for &(address, value) in &self.heap {
+			let _ = writeln!(text, "{} {}", address, value);
+		}
+		text
+	}
+}
+
+
+/********************************************/
+// Public Functions
+/********************************************/
+// Read the snapshot at a path, checking it's of a run of the program with the key
+pub fn load(path: &Path, key: &str) -> Result<Checkpoint, String> {
+	let text  = fs::read_to_string(path).map_err(|error| format!("{}: {}", path.display(), error))?;
+	let wrong = |line: &str| format!("{}: Expected {}", path.display(), line);
+
+	let mut lines = text.lines();
+	if lines.next() != Some(HEADER) {
+		return Err(format!("{}: Not a checkpoint this version can read", path.display()));
+	}
+	if lines.next().and_then(|line| line.strip_prefix("key ")) != Some(key) {
+		return Err(format!("{}: The checkpoint is of a different program, or the same one with different options", path.display()));
+	}
+
+	let mut field = |name: &str| lines.next().and_then(|line| line.strip_prefix(name)).map(|values| values.split_whitespace().map(str::to_string).collect::<Vec<String>>());
+	let pointer    = field("pointer ").and_then(|values| values.first()?.parse().ok()).ok_or_else(|| wrong("`pointer <index>`"))?;
+	let steps      = field("steps ").and_then(|values| values.first()?.parse().ok()).ok_or_else(|| wrong("`steps <count>`"))?;
+	let read       = field("read ").and_then(|values| values.first()?.parse().ok()).ok_or_else(|| wrong("`read <bytes>`"))?;
+	let stack      = field("stack").and_then(|values| values.iter().map(|value| value.parse().ok()).collect()).ok_or_else(|| wrong("`stack <values>`"))?;
+	let call_stack = field("calls").and_then(|values| values.iter().map(|value| value.parse().ok()).collect()).ok_or_else(|| wrong("`calls <pointers>`"))?;
+	let clock      = field("clock ").and_then(|values| values.first()?.parse().ok()).ok_or_else(|| wrong("`clock <milliseconds>`"))?;
+	let random     = field("random ").and_then(|values| match values.first()?.as_str() {"none" => Some(None), state => state.parse().ok().map(Some)}).ok_or_else(|| wrong("`random <state>`"))?;
+	if field("heap").is_none() {
+		return Err(wrong("`heap`"));
+	}
+
+	let mut heap = Vec::new();
+	for line in lines {
+		let mut fields = line.split_whitespace().map(str::parse);
+		match (fields.next(), fields.next(), fields.next()) {
+			(Some(Ok(address)), Some(Ok(value)), None) => heap.push((address, value)),
+			_                                          => return Err(wrong("`address value` in the heap")),
+		}
+	}
+
+	Ok(Checkpoint {pointer, steps, read, stack, call_stack, heap, clock, random})
+}
+
+
+// Remove the snapshot at a path once it won't be needed, as when the
+// program halts, if there is one
+pub fn remove(path: &Path) -> io::Result<()> {
+	match fs::remove_file(path) {
+		Err(ref error) if error.kind() == io::ErrorKind::NotFound => Ok(()),
+		removed                                                   => removed,
+	}
+}
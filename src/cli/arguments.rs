@@ -0,0 +1,580 @@
+/***********************************************************/
+//
+// Arguments
+// =========
+// Works out which command the command line asks for, and
+// reads the options after it into Flags, with the
+// configuration file applied first so the options can
+// override it. A command line that makes no sense exits with
+// the usage instructions.
+//
+// struct Arguments <- The command and the arguments after it
+//
+// struct Flags <- What the options given to a command chose
+//
+/***********************************************************/
+use std::fs;
+use std::fs::File;
+use std::io::Read;
+use std::ops::Range;
+use std::path::Path;
+use std::rc::Rc;
+use std::slice;
+use std::time::Duration;
+use accesses;
+use allocation;
+use allocation::Capacity;
+use bundle;
+use bundle::Bundle;
+use checkpoint;
+use codec;
+use codec::Codec;
+use compile;
+use config;
+use coverage;
+use heap;
+use lint::{Level, LintConfig};
+use options;
+use options::{FlushPolicy, IoOptions, Limits, Options, Profile};
+use report::ErrorFormat;
+use status;
+use trace::TraceFilter;
+use super::usage::exit_with_usage;
+
+
+/********************************************/
+// Structures
+/********************************************/
+// The command the command line asks for, and the arguments after it
+pub struct Arguments {
+	pub command:   &'static str,
+	pub resume:    bool,           // Whether the run carries on from a checkpoint
+	pub bundle:    bool,           // Whether the run is bundled instead
+	pub embedded:  Option<Bundle>, // The bundle being run, when this is one
+	pub arguments: Vec<String>,
+}
+
+// What the options given to a command chose
+pub struct Flags {
+	pub command: &'static str,
+	pub resume:  bool,
+	pub bundle:  bool,
+
+	// How programs are read and run
+	pub vm_options:  Options,
+	pub io_options:  IoOptions,
+	pub limits:      Limits,
+	pub capacity:    Capacity,
+	pub errors:      ErrorFormat,
+	pub config_text: Option<(String, String)>, // The configuration file that applied, and where it came from
+
+	// Where the program and its input come from
+	pub file_paths:        Vec<String>,
+	pub eval:              Option<String>,
+	pub input_path:        Option<String>,
+	pub program_arguments: Option<Vec<String>>,
+
+	// list, disasm, pretty, explain, check, callgraph and highlight
+	pub show_costs:  bool,
+	pub show_idioms: bool,
+	pub name_labels: bool,
+	pub label_map:   Option<String>,
+	pub json_output: bool,
+	pub verbose:     bool,
+	pub comments:    bool,
+	pub targets:     bool,
+	pub lints:       LintConfig,
+
+	// Rewriting the program first
+	pub optimize:    bool,
+	pub strip_debug: bool,
+
+	// compile, bundle, verify, server, explore, fuzz and debug
+	pub target:      Option<String>,
+	pub bundle_path: Option<String>,
+	pub bundled:     Vec<String>, // The options a bundle is run with
+	pub golden:      Option<String>,
+	pub listen:      Option<String>,
+	pub alphabet:    Vec<String>,
+	pub depth:       usize,
+	pub symbolic:    bool,
+	pub reach:       Option<String>,
+	pub spec:        Option<String>,
+	pub runs:        u64,
+	pub seed:        Option<u64>,
+	pub commands:    Option<String>,
+
+	// How a run is carried out
+	pub heap_kind:   String,
+	pub load_heap:   Option<String>,
+	pub save_heap:   Option<String>,
+	pub alongside:   Option<String>,
+	pub shared_heap: Option<Range<i64>>,
+	pub codec:       Option<Rc<dyn Codec>>,
+	pub hooks:       Option<String>,
+	pub raw_input:   bool,
+	pub use_cache:   bool,
+	pub native:      bool,
+	pub watch:       bool,
+	pub watch_stack: bool,
+	pub stream:      bool,
+	pub force:       bool,
+	pub every:       Option<u64>,
+	pub checkpoint:  String,
+	pub record_io:   Option<String>,
+	pub replay_io:   Option<String>,
+
+	// What's reported about a run
+	pub show_stats:  bool,
+	pub metrics:     bool,
+	pub metrics_to:  Option<String>,
+	pub coverage:    Option<String>,
+	pub coverage_as: coverage::Format,
+	pub heap_map:    Option<String>,
+	pub heap_map_as: accesses::Format,
+	pub audit:       Option<bool>,
+	pub taint:       bool,
+	pub budget:      bool,
+	pub trace:       Option<TraceFilter>,
+	pub status:      Option<Duration>,
+}
+
+
+/********************************************/
+// Constants
+/********************************************/
+// The commands that can rewrite the program with --optimize and --strip-debug
+static REWRITTEN: &[&str] = &["run", "debug", "list", "explain", "disasm", "decompile", "minify", "pretty", "obfuscate", "compile"];
+
+
+/********************************************/
+// Methods
+/********************************************/
+impl Arguments {
+	// The arguments the interpreter was started with. A bundle runs its
+	// own program instead, with every argument handed to the program.
+	pub fn from_env() -> Arguments {
+		let embedded  = bundle::embedded();
+		let arguments: Vec<String> = std::env::args().skip(1).collect();
+		let arguments = match embedded {
+			Some(ref embedded) => embedded.arguments(&arguments),
+			None               => arguments,
+		};
+
+		// The command is optional and defaults to run. Resuming a run is running
+		// it from a checkpoint, and bundling one is running it somewhere else.
+		let resume = arguments.first().is_some_and(|argument| argument == "resume");
+		let bundle = arguments.first().is_some_and(|argument| argument == "bundle");
+		let command = match arguments.first().map(|argument| argument.as_str()) {
+			Some("run")          => "run",
+			Some("resume")       => "run",
+			Some("bundle")       => "run",
+			Some("list")         => "list",
+			Some("check")        => "check",
+			Some("callgraph")    => "callgraph",
+			Some("decompile")    => "decompile",
+			Some("debug")        => "debug",
+			Some("obfuscate")    => "obfuscate",
+			Some("minify")       => "minify",
+			Some("pretty")       => "pretty",
+			Some("highlight")    => "highlight",
+			Some("explain")      => "explain",
+			Some("disasm")       => "disasm",
+			Some("assemble")     => "assemble",
+			Some("asm")          => "assemble",
+			Some("compile")      => "compile",
+			Some("verify")       => "verify",
+			Some("explore")      => "explore",
+			Some("fuzz")         => "fuzz",
+			Some("cache")        => "cache",
+			Some("conformance")  => "conformance",
+			Some("run-dir")      => "run-dir",
+			Some("server")       => "server",
+			Some("jupyter")      => "jupyter",
+			Some("repl")         => "repl",
+			Some("capabilities") => "capabilities",
+			_                    => return Arguments {command: "run", resume, bundle, embedded, arguments},
+		};
+
+		Arguments {command, resume, bundle, embedded, arguments: arguments[1..].to_vec()}
+	}
+}
+
+impl Flags {
+	// Read the options given to a command, exiting with the usage
+	// instructions when they don't make sense
+	pub fn parse(given: &Arguments) -> Flags {
+		let (command, bundle) = (given.command, given.bundle);
+		let mut flags = Flags {
+			command,
+			resume: given.resume,
+			bundle,
+			vm_options:  Options::default(),
+			io_options:  IoOptions {flush: FlushPolicy::detect(), ..IoOptions::default()},
+			limits:      Limits::default(),
+			capacity:    Capacity::default(),
+			errors:      ErrorFormat::detect(),
+			config_text: None,
+			file_paths:        Vec::new(),
+			eval:              None,
+			input_path:        None,
+			program_arguments: None,
+			show_costs:  false,
+			show_idioms: false,
+			name_labels: false,
+			label_map:   None,
+			json_output: false,
+			verbose:     false,
+			comments:    true,
+			targets:     false,
+			lints:       LintConfig::default(),
+			optimize:    false,
+			strip_debug: false,
+			target:      None,
+			bundle_path: None,
+			bundled:     Vec::new(),
+			golden:      None,
+			listen:      None,
+			alphabet:    vec!["0".to_string(), "1".to_string(), "-1".to_string()],
+			depth:       4,
+			symbolic:    false,
+			reach:       None,
+			spec:        None,
+			runs:        100,
+			seed:        None,
+			commands:    None,
+			heap_kind:   "hash".to_string(),
+			load_heap:   None,
+			save_heap:   None,
+			alongside:   None,
+			shared_heap: None,
+			codec:       None,
+			hooks:       None,
+			raw_input:   false,
+			use_cache:   true,
+			native:      false,
+			watch:       false,
+			watch_stack: false,
+			stream:      false,
+			force:       false,
+			every:       None,
+			checkpoint:  checkpoint::DEFAULT_PATH.to_string(),
+			record_io:   None,
+			replay_io:   None,
+			show_stats:  false,
+			metrics:     false,
+			metrics_to:  None,
+			coverage:    None,
+			coverage_as: coverage::Format::Lcov,
+			heap_map:    None,
+			heap_map_as: accesses::Format::Csv,
+			audit:       None,
+			taint:       false,
+			budget:      false,
+			trace:       None,
+			status:      None,
+		};
+
+		// Read the configuration file first, so the flags can override it. A
+		// bundle keeps the one that applied when it was made. Only the flags
+		// before -- count, since the rest are the program's own arguments.
+		let arguments = &given.arguments[..];
+		let before    = arguments.iter().position(|argument| argument == "--").map_or(arguments, |end| &arguments[..end]);
+		let config_path = match before.iter().position(|argument| argument == "--config") {
+			_ if given.embedded.is_some() => None,
+			Some(index)                   => Some(before.get(index + 1).cloned().unwrap_or_else(|| exit_with_usage())),
+			None                          => Some(config::DEFAULT_PATH.to_string()).filter(|path| Path::new(path).exists()),
+		};
+		flags.config_text = given.embedded.as_ref().and_then(|embedded| embedded.config.clone()).map(|text| ("<bundle>".to_string(), text));
+		if let Some(path) = config_path {
+			let text = handle_err!(fs::read_to_string(&path).map_err(|error| format!("{}: {}", path, error)));
+			flags.config_text = Some((path, text));
+		}
+		if let Some((ref path, ref text)) = flags.config_text {
+			let settings = config::Settings {options: &mut flags.vm_options, io: &mut flags.io_options, limits: &mut flags.limits, lints: &mut flags.lints};
+			handle_err!(config::apply(text, settings).map_err(|error| format!("{}: {}", path, error)));
+		}
+
+		let mut arguments = arguments.iter();
+		while let Some(argument) = arguments.next() {
+			let files = flags.file_paths.len();
+			let rest  = arguments.as_slice();
+			match argument.as_str() {
+				"--output" if bundle => flags.bundle_path = Some(value(&mut arguments).clone()),
+
+				"--costs"  if command == "list" => flags.show_costs = true,
+				"--idioms" if command == "list" => flags.show_idioms = true,
+				"--name-labels" if command == "disasm" => flags.name_labels = true,
+				"--label-map" if command == "disasm" => flags.label_map = Some(value(&mut arguments).clone()),
+				"--no-comments" if command == "pretty" || command == "assemble" => flags.comments = false,
+				"--targets" if command == "explain" => flags.targets = true,
+
+				"--spec" if command == "fuzz" => flags.spec = Some(value(&mut arguments).clone()),
+				"--runs" if command == "fuzz" => match arguments.next().map(|runs| runs.parse()) {
+					Some(Ok(value)) => flags.runs = value,
+					_               => exit_with_usage(),
+				},
+
+				"--seed" if command == "obfuscate" || command == "fuzz" => match arguments.next().map(|seed| seed.parse()) {
+					Some(Ok(value)) => flags.seed = Some(value),
+					_               => exit_with_usage(),
+				},
+
+				"--config" => {arguments.next();},
+
+				"--strict"    => flags.vm_options = Profile::Strict.options(),
+				"--lenient"   => flags.vm_options = Profile::Lenient.options(),
+				"--reference" => flags.vm_options = Profile::Reference.options(),
+
+				// Safe settings for running other people's programs, stored in the hash heap so
+				// a program can't make the vm allocate up to whatever address it stores to
+				"--untrusted" => {
+					options::untrusted(&mut flags.vm_options, &mut flags.io_options, &mut flags.limits);
+					flags.heap_kind = "hash".to_string();
+				},
+
+				"--profile" => match Profile::from_name(value(&mut arguments)) {
+					Some(profile) => flags.vm_options = profile.options(),
+					None          => exit_with_usage(),
+				},
+
+				flag if options::FLAGS.contains(&flag) => {
+					let value = value(&mut arguments);
+					if !options::set(&mut flags.vm_options, argument, value) {
+						exit_with_usage();
+					}
+				},
+
+				flag if options::IO_FLAGS.contains(&flag) => {
+					let value = value(&mut arguments);
+					if !options::set_io(&mut flags.io_options, argument, value) {
+						exit_with_usage();
+					}
+				},
+
+				flag if options::LIMIT_FLAGS.contains(&flag) => {
+					let value = value(&mut arguments);
+					if !options::set_limit(&mut flags.limits, argument, value) {
+						exit_with_usage();
+					}
+				},
+
+				"--heap" => match arguments.next() {
+					Some(kind) if heap::from_name(kind).is_some() => flags.heap_kind = kind.clone(),
+					_                                             => exit_with_usage(),
+				},
+
+				"--load-heap" => flags.load_heap = Some(value(&mut arguments).clone()),
+				"--save-heap" => flags.save_heap = Some(value(&mut arguments).clone()),
+				"--raw-input" => flags.raw_input = true,
+				"--stats" if command == "run" => flags.show_stats = true,
+				"--metrics" if command == "run" => match arguments.next().map(|format| format.as_str()) {
+					Some("json") => flags.metrics = true,
+					_            => exit_with_usage(),
+				},
+				"--metrics-file" if command == "run" => {flags.metrics = true; flags.metrics_to = Some(value(&mut arguments).clone())},
+				"--coverage" if command == "run" => flags.coverage = Some(value(&mut arguments).clone()),
+				"--coverage-format" if command == "run" => match arguments.next().and_then(|format| coverage::Format::from_name(format)) {
+					Some(format) => flags.coverage_as = format,
+					None         => exit_with_usage(),
+				},
+				"--heap-map" if command == "run" => flags.heap_map = Some(value(&mut arguments).clone()),
+				"--codec" if command == "run" => match arguments.next().and_then(|name| codec::from_name(name)) {
+					Some(chosen) => flags.codec = Some(chosen),
+					None         => exit_with_usage(),
+				},
+				"--heap-map-format" if command == "run" => match arguments.next().and_then(|format| accesses::Format::from_name(format)) {
+					Some(format) => flags.heap_map_as = format,
+					None         => exit_with_usage(),
+				},
+				"--audit" if command == "run" => flags.audit = Some(false),
+				"--deterministic" if command == "run" => flags.audit = Some(true),
+				"--taint" if command == "run" => flags.taint = true,
+				"--budget" if command == "run" => flags.budget = true,
+				"--virtual-time" if command == "run" => flags.io_options.virtual_time = true,
+				"--no-cache"                  => flags.use_cache = false,
+				"--native" if command == "run" => flags.native = true,
+				"--optimize" if REWRITTEN.contains(&command) => flags.optimize = true,
+				"--strip-debug" if REWRITTEN.contains(&command) => flags.strip_debug = true,
+				"--watch" if command == "run" => flags.watch = true,
+				"--stream" if command == "run" => flags.stream = true,
+				"--force" if command == "run" => flags.force = true,
+				"--checkpoint-every" if command == "run" => match arguments.next().map(|every| every.parse()) {
+					Some(Ok(steps)) if steps > 0 => flags.every = Some(steps),
+					_                            => exit_with_usage(),
+				},
+				"--checkpoint" if command == "run" => match arguments.next() {
+					Some(path) => flags.checkpoint = path.clone(),
+					None       => exit_with_usage(),
+				},
+				"--watch-stack" if command == "run" => {flags.watch = true; flags.watch_stack = true},
+				"--trace" if command == "run" => flags.trace = Some(TraceFilter::all()),
+
+				// Only trace some categories of action
+				_ if command == "run" && argument.starts_with("--trace=") => match TraceFilter::parse(&argument["--trace=".len()..]) {
+					Some(filter) => flags.trace = Some(filter),
+					None         => exit_with_usage(),
+				},
+				"--status-interval" if command == "run" => match arguments.next().and_then(|interval| status::parse_interval(interval)) {
+					Some(interval) => flags.status = Some(interval),
+					None           => exit_with_usage(),
+				},
+				"--alongside" if command == "run" => flags.alongside = Some(value(&mut arguments).clone()),
+				"--hooks"     if command == "run" => flags.hooks = Some(value(&mut arguments).clone()),
+
+				"--shared-heap" => match arguments.next().and_then(|region| parse_region(region)) {
+					Some(region) => flags.shared_heap = Some(region),
+					None         => exit_with_usage(),
+				},
+
+				"--error-format" => match arguments.next().and_then(|format| ErrorFormat::from_name(format)) {
+					Some(format) => flags.errors = format,
+					None         => exit_with_usage(),
+				},
+
+				"--eval" if command != "conformance" && command != "run-dir" => flags.eval = Some(value(&mut arguments).clone()),
+
+				"--input"     => flags.input_path = Some(value(&mut arguments).clone()),
+				"--stdin"  if command == "verify" => flags.input_path = Some(value(&mut arguments).clone()),
+				"--expect" if command == "verify" => flags.golden = Some(value(&mut arguments).clone()),
+				"--listen" if command == "server" => flags.listen = Some(value(&mut arguments).clone()),
+				"--capacity" if command == "run" || command == "server" => match arguments.next().and_then(|hints| allocation::parse(hints)) {
+					Some(hints) => flags.capacity = hints,
+					None        => exit_with_usage(),
+				},
+				"--alphabet" if command == "explore" => match arguments.next() {
+					Some(lines) => flags.alphabet = lines.split(',').map(str::to_string).collect(),
+					None        => exit_with_usage(),
+				},
+				"--symbolic" if command == "explore" => flags.symbolic = true,
+				"--reach"    if command == "explore" => {flags.symbolic = true; flags.reach = Some(value(&mut arguments).clone())},
+				"--depth" if command == "explore" => match arguments.next().map(|depth| depth.parse()) {
+					Some(Ok(value)) => flags.depth = value,
+					_               => exit_with_usage(),
+				},
+
+				"--target" if command == "compile" => match arguments.next() {
+					Some(name) if compile::TARGETS.contains(&name.as_str()) => flags.target = Some(name.clone()),
+					_                                                       => exit_with_usage(),
+				},
+				"--record-io" => flags.record_io = Some(value(&mut arguments).clone()),
+				"--replay-io" => flags.replay_io = Some(value(&mut arguments).clone()),
+				"--script" if command == "debug" => flags.commands = Some(value(&mut arguments).clone()),
+
+				"--allow" | "--warn" | "--deny" if command == "check" => {
+					let level = Level::from_name(&argument[2..]).unwrap();
+					let name  = value(&mut arguments);
+					handle_err!(flags.lints.set(name, level));
+				},
+
+				"--lints" if command == "check" => {
+					let mut manifest = String::new();
+					let mut file = handle_err!(File::open(value(&mut arguments)));
+					handle_err!(file.read_to_string(&mut manifest));
+					handle_err!(flags.lints.load_manifest(&manifest));
+				},
+
+				"--format" if ["check", "callgraph", "list", "highlight"].contains(&command) => match arguments.next().map(|format| format.as_str()) {
+					Some("text") if command != "callgraph" => {flags.json_output = false; flags.verbose = false},
+					Some("dot")  if command == "callgraph" => flags.json_output = false,
+					Some("json") if command != "list"      => flags.json_output = true,
+					Some("verbose") if command == "list"   => flags.verbose = true,
+					_                                      => exit_with_usage(),
+				},
+
+				// Everything after -- belongs to the program
+				"--" if command == "run" => flags.program_arguments = Some(arguments.by_ref().cloned().collect()),
+
+				_ if argument.starts_with("--") => exit_with_usage(),
+				_                               => flags.file_paths.push(argument.clone()),
+			}
+
+			// A bundle is run with the options it's made with, but not told where it's written or where its program was
+			if bundle && flags.file_paths.len() == files && !["--output", "--config", "--eval"].contains(&argument.as_str()) {
+				let taken = rest.len() - arguments.as_slice().len();
+				flags.bundled.push(argument.clone());
+				flags.bundled.extend(rest[..taken].iter().cloned());
+			}
+		}
+
+		flags.check();
+		flags
+	}
+
+
+	// Exit with the usage instructions if the options given to a run can't
+	// be used together
+	fn check(&self) {
+		// Only a single file can be watched for changes
+		if self.watch && (self.file_paths.len() != 1 || self.from_stdin() > 0) {
+			exit_with_usage();
+		}
+
+		// A program executed as it's parsed can't be rewritten or looked over first
+		if self.stream && (self.watch || self.optimize || self.strip_debug || self.show_stats || self.native) {
+			exit_with_usage();
+		}
+
+		// Only a program parsed all at once can be cut short where it stops parsing
+		if self.force && (self.stream || self.watch) {
+			exit_with_usage();
+		}
+
+		// A bundle needs somewhere to go, and takes its arguments when it's run
+		if self.bundle && (self.bundle_path.is_none() || self.watch || self.program_arguments.is_some()) {
+			exit_with_usage();
+		}
+
+		// Coverage is of the source, which rewrites lose track of
+		if self.coverage.is_some() && (self.watch || self.optimize || self.strip_debug) {
+			exit_with_usage();
+		}
+
+		// A budget is shown with the labels of the program as it was parsed
+		if self.budget && (self.watch || self.stream) {
+			exit_with_usage();
+		}
+
+		// A checkpoint is only good for the program that was running when it was taken
+		if (self.resume || self.every.is_some()) && (self.watch || self.stream) {
+			exit_with_usage();
+		}
+	}
+
+
+	// How many of the program's paths are -, for reading it from stdin
+	pub fn from_stdin(&self) -> usize {
+		self.file_paths.iter().filter(|path| *path == "-").count()
+	}
+
+
+	// Whether a run needs the interpreter, because something asked for
+	// watches it, changes what it reads and writes, or limits it, rather
+	// than being able to run as a native binary
+	pub fn interpreted(&self) -> bool {
+		self.watch || self.raw_input || self.show_stats || self.metrics || self.coverage.is_some() || self.resume || self.every.is_some()
+			|| self.trace.is_some() || self.status.is_some() || self.audit.is_some() || self.taint || self.budget || self.heap_map.is_some()
+			|| self.codec.is_some() || self.hooks.is_some() || self.alongside.is_some() || self.load_heap.is_some() || self.save_heap.is_some()
+			|| self.record_io.is_some() || self.replay_io.is_some() || self.program_arguments.is_some()
+			|| self.limits != Limits::default() || !self.io_options.prompt.is_empty() || !self.io_options.input_messages
+	}
+}
+
+
+/********************************************/
+// Private Functions
+/********************************************/
+// Take the value given after a flag, exiting with the usage instructions
+// when the flag is the last argument
+fn value<'a>(arguments: &mut slice::Iter<'a, String>) -> &'a String {
+	arguments.next().unwrap_or_else(|| exit_with_usage())
+}
+
+
+// Parse a range of heap addresses written as start..end
+fn parse_region(region: &str) -> Option<Range<i64>> {
+	let mut bounds = region.splitn(2, "..");
+	let start = bounds.next()?.parse().ok()?;
+	let end   = bounds.next()?.parse().ok()?;
+	Some(start..end)
+}
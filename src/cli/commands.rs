@@ -0,0 +1,445 @@
+/***********************************************************/
+//
+// Commands
+// ========
+// Everything the command line does besides running a
+// program: the commands that take no program, the ones that
+// take a directory of them, and the ones that look a program
+// over, rewrite it or translate it. Each prints what it
+// gives to stdout, and exits with the status for what went
+// wrong when something does.
+//
+/***********************************************************/
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::io::{BufReader, IsTerminal, Read, Write};
+use std::net::TcpListener;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+use analysis;
+use assembler;
+use bundle::Bundle;
+use cache;
+use callgraph;
+use capabilities;
+use compile;
+use conformance;
+use decompile;
+use diff;
+use disassembler;
+use emit;
+use explain;
+use explore;
+use fuzz;
+use highlight;
+use idioms;
+#[cfg(feature = "jupyter")]
+use jupyter;
+use labels::SymbolTable;
+use lint;
+use lint::Level;
+use obfuscate;
+use options::{IoOptions, Limits, Options};
+use parser::Span;
+use repl;
+use server;
+#[cfg(feature = "symbolic")]
+use symbolic;
+use symbols::Action;
+use super::arguments::{Arguments, Flags};
+use super::program::{Parsed, Program};
+use super::usage::exit_with_usage;
+
+
+/********************************************/
+// Public Functions
+/********************************************/
+// Look after the cache, which has its own subcommands and takes no program
+pub fn cache(arguments: &Arguments) {
+	match arguments.arguments.iter().map(|argument| argument.as_str()).collect::<Vec<&str>>().as_slice() {
+		["clear"] => println!("Removed {} cached programs", handle_err!(cache::clear())),
+		_         => exit_with_usage(),
+	}
+}
+
+
+// Describe the build, which takes no program either
+pub fn capabilities(arguments: &Arguments) {
+	match arguments.arguments.iter().map(|argument| argument.as_str()).collect::<Vec<&str>>().as_slice() {
+		[]         => print!("{}", capabilities::render_text()),
+		["--json"] => print!("{}", capabilities::render_json()),
+		_          => exit_with_usage(),
+	}
+}
+
+
+// Answer requests from editors and the like until they stop coming, on
+// stdin or from each connection to an address in turn
+pub fn server(flags: &Flags) {
+	if !flags.file_paths.is_empty() || flags.eval.is_some() {
+		exit_with_usage();
+	}
+
+	match flags.listen {
+		Some(ref address) => {
+			for connection in handle_err!(TcpListener::bind(address)).incoming() {
+				let mut responses = handle_err!(connection);
+				let requests      = Box::new(BufReader::new(handle_err!(responses.try_clone())));
+				if let Err(error) = server::serve(requests, &mut responses, flags.vm_options, flags.io_options.clone(), flags.limits, flags.capacity) {
+					eprintln!("Error: {}", error);
+				}
+			}
+		},
+		None => handle_err!(server::serve(Box::new(BufReader::new(io::stdin())), &mut io::stdout(), flags.vm_options, flags.io_options.clone(), flags.limits, flags.capacity)),
+	}
+}
+
+
+// Run as a Jupyter kernel, which Jupyter starts with the connection file it wrote
+pub fn jupyter(flags: &Flags) {
+	if flags.file_paths.len() != 1 || flags.eval.is_some() {
+		exit_with_usage();
+	}
+	handle_err!(run_kernel(&flags.file_paths[0], flags.vm_options, flags.io_options.clone(), flags.limits));
+}
+
+
+// Turn mnemonics into whitespace, read from each file in turn, or from
+// stdin when a path is -
+pub fn assemble(flags: &Flags) {
+	if flags.file_paths.is_empty() || flags.eval.is_some() {
+		exit_with_usage();
+	}
+
+	let mut source = String::new();
+	for path in &flags.file_paths {
+		match path.as_str() {
+			"-"  => {handle_err!(io::stdin().read_to_string(&mut source));},
+			path => source.push_str(&handle_err!(fs::read_to_string(path).map_err(|error| format!("{}: {}", path, error)))),
+		}
+		source.push('\n');
+	}
+
+	let assembly = handle_err!(assembler::assemble(&source), |_| 4);
+	print!("{}", emit::named(&assembly.program, &assembly.labels, flags.comments));
+}
+
+
+// Take lines of code and commands interactively, starting with a program
+// loaded when a file is given
+pub fn repl(flags: &Flags) {
+	if flags.file_paths.len() > 1 || flags.eval.is_some() {
+		exit_with_usage();
+	}
+
+	let loaded = flags.file_paths.first().map(|path| handle_err!(fs::read_to_string(path).map_err(|error| format!("{}: {}", path, error))));
+	handle_err!(repl::repl(flags.vm_options, flags.io_options.clone(), flags.limits, loaded, &mut io::stdin().lock(), &mut io::stdout()));
+}
+
+
+// Run a directory of test programs, reporting a score for conformance
+// and a table for run-dir
+pub fn conformance(flags: &Flags) {
+	if flags.file_paths.len() != 1 {
+		exit_with_usage();
+	}
+
+	let results = handle_err!(conformance::run_suite(Path::new(&flags.file_paths[0]), flags.vm_options, flags.limits));
+	if flags.command == "conformance" {
+		print!("{}", conformance::render_report(&results));
+	} else {
+		print!("{}", conformance::render_table(&results));
+	}
+
+	if results.iter().any(|result| result.failure.is_some()) {
+		std::process::exit(1);
+	}
+}
+
+
+// Classify the tokens for an editor, even of a program that doesn't parse yet
+pub fn highlight(flags: &Flags, program: Program) {
+	if program.files.len() != 1 {
+		exit_with_usage();
+	}
+
+	let regions = highlight::classify(&program.text, &flags.vm_options);
+	if flags.json_output {
+		print!("{}", highlight::render_json(&regions));
+	} else {
+		print!("{}", highlight::render_text(&program.text, &regions, io::stdout().is_terminal()));
+	}
+}
+
+
+// Run the program against a file of the output it should write
+pub fn verify(flags: &Flags, program: Program) {
+	let mut expected = Vec::new();
+	handle_err!(handle_err!(fs::File::open(flags.golden.as_ref().unwrap_or_else(|| exit_with_usage()))).read_to_end(&mut expected));
+
+	let mut input = Vec::new();
+	if let Some(ref path) = flags.input_path {
+		handle_err!(handle_err!(fs::File::open(path)).read_to_end(&mut input));
+	}
+
+	let run      = conformance::run_captured(program.text, flags.vm_options, flags.limits, input);
+	let expected = String::from_utf8_lossy(&expected);
+	let actual   = String::from_utf8_lossy(&run.output);
+	let mut passed = true;
+
+	if let Err(ref error) = run.result {
+		eprintln!("Error: {}", error);
+		passed = false;
+	}
+	if actual != expected {
+		print!("{}", diff::render(&expected, &actual, io::stdout().is_terminal()));
+		passed = false;
+	}
+
+	std::process::exit(if passed {0} else {1});
+}
+
+
+// Write a self-running executable of the program as it was read, with the
+// options it was given. Only a program that resolves is bundled, so it
+// fails here rather than for whoever runs it.
+pub fn bundle(flags: &Flags, program: Program) {
+	let bundled = Bundle::new(flags.config_text.as_ref().map(|(_, text)| text.clone()), flags.bundled.clone(), &program.text, &flags.vm_options);
+	Parsed::parse(flags, program).reduce(&flags.vm_options);
+
+	let path = flags.bundle_path.clone().unwrap_or_default();
+	handle_err!(bundled.write(Path::new(&path)).map_err(|error| format!("{}: {}", path, error)));
+}
+
+
+// Run the lints over the program, failing if one set to deny finds something
+pub fn check(flags: &Flags, parsed: &Parsed) {
+	let diagnostics = lint::check(&parsed.parsed, &parsed.labels, &flags.lints);
+	if flags.json_output {
+		print!("{}", lint::render_json(&diagnostics));
+	} else {
+		print!("{}", lint::render_text(&diagnostics));
+	}
+
+	if diagnostics.iter().any(|diagnostic| diagnostic.level == Level::Deny) {
+		std::process::exit(1);
+	}
+}
+
+
+// Print which subroutines call which
+pub fn callgraph(flags: &Flags, parsed: &Parsed) {
+	let routines = analysis::call_graph(&parsed.parsed, &analysis::basic_blocks(&parsed.parsed));
+	if flags.json_output {
+		print!("{}", callgraph::render_json(&routines, &parsed.labels));
+	} else {
+		print!("{}", callgraph::render_dot(&routines, &parsed.labels));
+	}
+}
+
+
+// Print the program translated to the language of the target
+pub fn compile(flags: &Flags, parsed: &Parsed) {
+	let backend = match flags.target.as_ref().and_then(|target| compile::backend(target)) {
+		Some(backend) => backend,
+		None          => exit_with_usage(),
+	};
+	let reduced = parsed.reduce(&flags.vm_options);
+	handle_err!(io::stdout().write_all(&handle_err!(backend.emit(&reduced, &flags.vm_options))));
+}
+
+
+// Look for inputs that make the program fail, or reach a label, failing
+// if there's a problem or the label can be reached
+pub fn explore(flags: &Flags, parsed: &Parsed) {
+	// Labels are gone once they're resolved, so find where the one to reach leads first
+	let target = flags.reach.as_ref().map(|label| match find_label(&parsed.parsed, &parsed.labels, label) {
+		Some(pointer) => pointer,
+		None          => {eprintln!("Error: The program has no label {}", label); std::process::exit(1)},
+	});
+	let reduced = parsed.reduce(&flags.vm_options);
+
+	// Either way, finding something means the program has a problem or the label is reachable
+	let found = if flags.symbolic {
+		handle_err!(explore_symbolically(reduced, flags.vm_options, flags.io_options.clone(), flags.limits, flags.depth, target))
+	} else {
+		let exploration = explore::explore(reduced, flags.vm_options, flags.io_options.clone(), flags.limits, &flags.alphabet, flags.depth);
+		print!("{}", explore::render(&exploration));
+		!exploration.failures.is_empty()
+	};
+
+	if found != target.is_some() {
+		std::process::exit(1);
+	}
+}
+
+
+// Run the program on random inputs made up from a spec, failing if any of
+// them make it fail
+pub fn fuzz(flags: &Flags, parsed: &Parsed) {
+	let text = handle_err!(fs::read_to_string(flags.spec.clone().unwrap_or_else(|| exit_with_usage())));
+	let spec = handle_err!(fuzz::Spec::parse(&text));
+
+	// Without a seed every run draws different inputs, but says which it drew from
+	let seed    = flags.seed.unwrap_or_else(random_seed);
+	let reduced = parsed.reduce(&flags.vm_options);
+	let fuzzing = fuzz::fuzz(reduced, flags.vm_options, flags.io_options.clone(), flags.limits, &spec, flags.runs, seed);
+	print!("{}", fuzz::render(&fuzzing));
+	if !fuzzing.failures.is_empty() {
+		std::process::exit(1);
+	}
+}
+
+
+// Print the program in as few bytes as possible, reporting the savings on
+// stderr so the program can be redirected to a file
+pub fn minify(parsed: &Parsed) {
+	let minified = emit::emit(&emit::shorten_labels(&parsed.parsed));
+	print!("{}", minified);
+	eprintln!("Saved {} bytes ({} down to {})", parsed.original_size.saturating_sub(minified.len()), parsed.original_size, minified.len());
+}
+
+
+// Print the program with a comment naming each instruction
+pub fn pretty(flags: &Flags, parsed: &Parsed) {
+	print!("{}", emit::pretty(&parsed.parsed, flags.comments));
+}
+
+
+// Print an equivalent program that's harder to follow, which without a
+// seed is a different one every run
+pub fn obfuscate(flags: &Flags, parsed: &Parsed) {
+	let seed = flags.seed.unwrap_or_else(random_seed);
+	print!("{}", emit::emit(&obfuscate::obfuscate(&parsed.parsed, seed)));
+}
+
+
+// Print the program as structured pseudocode
+pub fn decompile(flags: &Flags, parsed: &Parsed) {
+	print!("{}", decompile::decompile(&parsed.parsed, &flags.vm_options));
+}
+
+
+// Print the program as mnemonics. Names already in the label map are kept,
+// and the ones given now added to it.
+pub fn disasm(flags: &Flags, parsed: &Parsed) {
+	let mut map = match flags.label_map {
+		Some(ref path) if Path::new(path).exists() => {
+			let text = handle_err!(fs::read_to_string(path).map_err(|error| format!("{}: {}", path, error)));
+			handle_err!(disassembler::LabelMap::parse(&text).map_err(|error| format!("{}: {}", path, error)))
+		},
+		_ => disassembler::LabelMap::default(),
+	};
+	let names = if flags.name_labels || flags.label_map.is_some() {disassembler::name_labels(&parsed.parsed, &parsed.labels, &mut map)} else {HashMap::new()};
+	print!("{}", disassembler::disassemble(&parsed.parsed, &parsed.labels, &names));
+	if let Some(ref path) = flags.label_map {
+		handle_err!(fs::write(path, map.render()));
+	}
+}
+
+
+// List each instruction with what it does and its stack effect
+pub fn explain(flags: &Flags, parsed: &Parsed) {
+	print!("{}", explain::explain(&parsed.parsed, &parsed.labels, &flags.vm_options, flags.targets));
+}
+
+
+// List the actions in the program, with their costs, their source, or the
+// idioms they make up
+pub fn list(flags: &Flags, parsed: &Parsed) {
+	if flags.verbose {
+		list_with_spans(&parsed.spanned, &parsed.labels);
+	} else if flags.show_costs {
+		list_with_costs(&parsed.parsed);
+	} else {
+		// Say what each recognized stretch of code does before it starts
+		let idioms = if flags.show_idioms {idioms::find(&parsed.parsed)} else {Vec::new()};
+		for (index, action) in parsed.parsed.iter().enumerate() {
+			for idiom in idioms.iter().filter(|idiom| idiom.start == index) {
+				println!("; {} (actions {}-{})", idiom.description, idiom.start, idiom.end - 1);
+			}
+			println!("{:?}", action);
+		}
+	}
+}
+
+
+/********************************************/
+// Private Functions
+/********************************************/
+// A seed that's different every run
+fn random_seed() -> u64 {
+	SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |time| time.as_nanos() as u64)
+}
+
+
+// The pointer a label leads to once labels are resolved, given its tokens as S and T
+fn find_label(actions: &[Action], labels: &SymbolTable, name: &str) -> Option<usize> {
+	let index = actions.iter().position(|action| matches!(*action, Action::Label(label) if labels.name(label) == name))?;
+	Some(actions[..index].iter().filter(|action| !matches!(**action, Action::Label(_))).count())
+}
+
+
+// Look for inputs that make the program fail or reach a label by executing it
+// symbolically, printing what was found and returning whether anything was
+#[cfg(feature = "symbolic")]
+fn explore_symbolically(program: Vec<Action>, options: Options, io_options: IoOptions, limits: Limits, depth: usize, target: Option<usize>) -> Result<bool, String> {
+	let exploration = symbolic::explore(program, options, io_options, limits, depth, target);
+	print!("{}", symbolic::render(&exploration));
+	Ok(exploration.findings.iter().any(|finding| finding.error.is_some() == target.is_none()))
+}
+
+#[cfg(not(feature = "symbolic"))]
+fn explore_symbolically(_program: Vec<Action>, _options: Options, _io_options: IoOptions, _limits: Limits, _depth: usize, _target: Option<usize>) -> Result<bool, String> {
+	Err("--symbolic needs the symbolic feature: build with cargo build --features symbolic".to_string())
+}
+
+
+// Run a Jupyter kernel on the ports a connection file gives
+#[cfg(feature = "jupyter")]
+fn run_kernel(path: &str, options: Options, io_options: IoOptions, limits: Limits) -> Result<(), String> {
+	let text = fs::read_to_string(path).map_err(|error| format!("{}: {}", path, error))?;
+	let connection = jupyter::Connection::parse(&text).map_err(|error| format!("{}: {}", path, error))?;
+	jupyter::serve(&connection, options, io_options, limits)
+}
+
+#[cfg(not(feature = "jupyter"))]
+fn run_kernel(_path: &str, _options: Options, _io_options: IoOptions, _limits: Limits) -> Result<(), String> {
+	Err("jupyter needs the jupyter feature: build with cargo build --features jupyter".to_string())
+}
+
+
+// List the program annotated with the estimated cost of each
+// action and the total estimated cost of each basic block
+fn list_with_costs(program: &[Action]) {
+	let mut total = 0;
+
+	for (number, block) in analysis::basic_blocks(program).iter().enumerate() {
+		let block_cost = block.cost(program);
+		total += block_cost;
+
+		println!("; block {} (actions {}-{}): cost {}", number, block.start, block.end - 1, block_cost);
+		for action in &program[block.start..block.end] {
+			println!("{:>6}  {:?}", analysis::cost(action), action);
+		}
+	}
+
+	println!("; total cost of a single pass: {}", total);
+}
+
+
+// List the program as parsed, with each action's index, mnemonic,
+// operand (labels by their tokens), the bytes of the source it came
+// from, and its tokens
+fn list_with_spans(program: &[(Action, Span)], labels: &SymbolTable) {
+	println!("{:>6}  {:<14} {:>20}  {:<13} {:<9} tokens", "index", "mnemonic", "operand", "bytes", "line:col");
+	for (index, &(action, ref span)) in program.iter().enumerate() {
+		let operand = match action {
+			Action::StackPush(value) => value.to_string(),
+			Action::Label(label) | Action::Call(label) | Action::Jump(label) | Action::JumpIfZero(label) | Action::JumpIfNegative(label) => labels.name(label),
+			_ => String::new(),
+		};
+		println!("{:>6}  {:<14} {:>20}  {:<13} {:<9} {}", index, action.name(), operand, format!("{}..{}", span.start, span.end), format!("{}:{}", span.line, span.column), span.tokens);
+	}
+}
@@ -0,0 +1,66 @@
+/***********************************************************/
+//
+// Command Line
+// ============
+// The whitespace command, built on the rest of the crate.
+// The binary reads the arguments, and then picks the
+// function for the command they ask for; everything else,
+// from reading the options to reporting how a run went, is
+// here. Like the modules it uses, it's free to change
+// between versions.
+//
+// A command that fails prints why to stderr and exits with
+// the status for the failure, and one given options that
+// make no sense exits with the usage instructions.
+//
+// struct Arguments <- The command and the arguments after it
+//
+// struct Flags <- What the options given to a command chose
+//
+// struct Program <- The source of the program a command is
+//                   given
+//
+// struct Parsed <- A program as parsed for a command
+//
+// run(flags: Flags, parsed: Parsed)
+// -- Run the program, or debug it, as the run, resume and
+//    debug commands do
+//
+// The other commands each have a function of the same name,
+// like check(flags: &Flags, parsed: &Parsed).
+//
+/***********************************************************/
+
+
+/********************************************/
+// Macros
+/********************************************/
+// Return the value of a result, or print the error message to stderr and
+// exit with the status given for the error, or 1 when none is given
+macro_rules! handle_err {
+    ($expression:expr) => (handle_err!($expression, |_| 1));
+    ($expression:expr, $status:expr) => (match $expression {
+    	Ok(val)  => val,
+    	Err(err) => {eprintln!("Error: {}", err); std::process::exit($status(&err));},
+    })
+}
+
+// Return the value of a result, or show the error against the program's
+// source on stderr and exit with the status for the error
+macro_rules! handle_program_err {
+    ($expression:expr, $reporter:expr) => (match $expression {
+    	Ok(val)  => val,
+    	Err(err) => {eprint!("{}", $reporter.render(&err)); std::process::exit(err.exit_code());},
+    })
+}
+
+mod arguments;
+mod commands;
+mod program;
+mod run;
+mod usage;
+
+pub use self::arguments::{Arguments, Flags};
+pub use self::commands::{assemble, bundle, cache, callgraph, capabilities, check, compile, conformance, decompile, disasm, explain, explore, fuzz, highlight, jupyter, list, minify, obfuscate, pretty, repl, server, verify};
+pub use self::program::{Parsed, Program};
+pub use self::run::run;
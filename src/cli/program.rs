@@ -0,0 +1,182 @@
+/***********************************************************/
+//
+// Programs
+// ========
+// Reads the program a command is given, from its files, from
+// stdin, or inline in STL notation, and parses it the way the
+// command needs: with the source of every action for list
+// --format verbose, cut short at a parse error for run
+// --force, left to be parsed as it runs for run --stream, or
+// not at all when an earlier run cached it.
+//
+// struct Program <- The source of a program, and the files it
+//                   was read from
+//
+// struct Parsed <- A program as parsed for a command, with
+//                  what's needed to report errors against it
+//
+/***********************************************************/
+use std::fs::File;
+use std::io;
+use std::io::Read;
+use cache;
+use error::ParseError;
+use labels::SymbolTable;
+use metrics;
+use metrics::Metrics;
+use options::Options;
+use parser;
+use parser::Span;
+use passes::{Pipeline, StripDebug};
+use report::Reporter;
+use symbols::Action;
+use super::arguments::Flags;
+use super::usage::exit_with_usage;
+
+
+/********************************************/
+// Structures
+/********************************************/
+// The source of a program, and the files it was read from
+pub struct Program {
+	pub text:  String,
+	pub files: Vec<(String, usize)>, // Each file's name, and where in the text it starts
+}
+
+// A program as parsed for a command, with what's needed to report errors
+// against it
+pub struct Parsed {
+	pub reporter:      Reporter,
+	pub pipeline:      Pipeline,
+	pub parsed:        Vec<Action>,
+	pub spanned:       Vec<(Action, Span)>, // The actions with their source, for list --format verbose
+	pub labels:        SymbolTable,
+	pub warnings:      Vec<ParseError>,
+	pub warned:        Vec<ParseError>,     // The warnings already shown, kept with the program when it's cached
+	pub cached:        Option<Vec<Action>>, // The program as an earlier run resolved it, instead of parsing it
+	pub cache_key:     Option<String>,
+	pub snapshot_key:  Option<String>,      // What a checkpoint of the run is kept under
+	pub source:        Option<String>,      // The source of a program to be parsed as it runs
+	pub original_size: usize,
+	pub measured:      Metrics,
+}
+
+
+/********************************************/
+// Methods
+/********************************************/
+impl Program {
+	// Read the program from its files one after another, or from stdin when
+	// a path is -. A program is given either inline or as paths, but not
+	// both, and stdin can only be read once.
+	pub fn read(flags: &Flags) -> Program {
+		if flags.file_paths.is_empty() == flags.eval.is_none() || flags.from_stdin() > 1 {
+			exit_with_usage();
+		}
+
+		let mut text  = String::new();
+		let mut files = Vec::new();
+		if let Some(ref notation) = flags.eval {
+			text = parser::from_stl(notation);
+			files.push(("<eval>".to_string(), 0));
+		}
+		for file_path in &flags.file_paths {
+			files.push((if file_path == "-" {"<stdin>".to_string()} else {file_path.clone()}, text.len()));
+			if file_path == "-" {
+				handle_err!(io::stdin().read_to_string(&mut text));
+			} else {
+				handle_err!(handle_err!(File::open(file_path)).read_to_string(&mut text));
+			}
+		}
+
+		Program {text, files}
+	}
+}
+
+impl Parsed {
+	// Parse and optimize the program, unless an earlier run of the same
+	// program cached it, exiting with the error if it doesn't parse
+	pub fn parse(flags: &Flags, program: Program) -> Parsed {
+		let (command, options) = (flags.command, &flags.vm_options);
+
+		// Errors from here on point into the program, though rewrites lose track of where runtime errors came from
+		let reporter = Reporter::new(flags.errors, program.text.clone(), program.files, *options, !flags.optimize && !flags.strip_debug && !flags.watch);
+		let program  = program.text;
+
+		let mut pipeline  = if flags.optimize {Pipeline::optimizer()} else {Pipeline::default()};
+		if flags.strip_debug {
+			pipeline.add(Box::new(StripDebug));
+		}
+		let original_size = program.len();
+		let cache_key     = if command == "run" && (flags.use_cache || flags.native) && !flags.watch && !flags.stream && !flags.force && !flags.bundle && !flags.budget {Some(cache::key(&program, options, &pipeline.names()))} else {None};
+		let cached        = cache_key.as_ref().filter(|_| flags.use_cache).and_then(|key| cache::load(key));
+		let snapshot_key  = if flags.resume || flags.every.is_some() {Some(cache::key(&program, options, &pipeline.names()))} else {None};
+		let mut labels    = SymbolTable::default();
+		let mut warnings  = Vec::new();
+		let mut warned    = Vec::new();
+		let spanned       = if flags.verbose {
+			let spanned = parser::parse_with_symbols(program.clone(), options, &mut labels, &mut warnings);
+			warn(&reporter, &mut warnings);
+			handle_program_err!(spanned, reporter)
+		} else {
+			Vec::new()
+		};
+		let mut measured  = Metrics {cached: cached.is_some(), ..Metrics::default()};
+		let mut source    = None;
+		let parsed        = match cached {
+			None if flags.stream   => {source = Some(program); Vec::new()},
+			None if !flags.verbose => {
+				let parsed = metrics::time(&mut measured.parse, || if flags.force {
+					parse_forced(program, options, &reporter)
+				} else {
+					let parsed = parser::parse_with_symbols(program, options, &mut labels, &mut warnings);
+					warned = warnings.clone();
+					warn(&reporter, &mut warnings);
+					handle_program_err!(parsed, reporter)
+				});
+				metrics::time(&mut measured.optimize, || pipeline.run(parsed.into_iter().map(|(action, _)| action).collect()))
+			},
+
+			// The program wasn't parsed this time, but what parsing it warned about still applies
+			Some((_, ref kept)) => {warn(&reporter, &mut kept.clone()); Vec::new()},
+			None                => Vec::new(),
+		};
+
+		let cached = cached.map(|(cached, _)| cached);
+		Parsed {reporter, pipeline, parsed, spanned, labels, warnings, warned, cached, cache_key, snapshot_key, source, original_size, measured}
+	}
+
+
+	// Resolve the program's labels, exiting with the error if some don't exist
+	pub fn reduce(&self, options: &Options) -> Vec<Action> {
+		handle_program_err!(parser::reduce_labels(self.parsed.clone(), &self.labels, options.labels), self.reporter)
+	}
+}
+
+
+/********************************************/
+// Public Functions
+/********************************************/
+// Show the warnings the parser gave on stderr, leaving none to show again
+pub fn warn(reporter: &Reporter, warnings: &mut Vec<ParseError>) {
+	for warning in warnings.drain(..) {
+		eprint!("{}", reporter.render_warning(&warning));
+	}
+}
+
+
+/********************************************/
+// Private Functions
+/********************************************/
+// Parse the program for run --force, reporting where it stops parsing
+// as a warning and keeping the actions before it to be run anyway
+fn parse_forced(program: String, options: &Options, reporter: &Reporter) -> Vec<(Action, Span)> {
+	let mut warnings = Vec::new();
+	let (parsed, result) = parser::parse_partial(program, options, &mut warnings);
+	warn(reporter, &mut warnings);
+	if let Err(error) = result {
+		eprint!("{}", reporter.render(&error));
+		eprintln!("Warning: --force was given, so running the {} actions parsed before the error", parsed.len());
+	}
+	parsed
+}
@@ -0,0 +1,420 @@
+/***********************************************************/
+//
+// Runs
+// ====
+// Runs a program for the run, resume and debug commands. A
+// run is set up from its flags: where it reads and writes,
+// recording or replaying a transcript, the heap it starts
+// with and shares with a program alongside it, and whatever
+// watches it as it goes. Once it stops, what was watched is
+// reported on stderr, so it stays apart from the program's
+// output, and the exit status says how it stopped.
+//
+// A cached program with nothing asking for the interpreter
+// runs as a native binary instead, when there's one.
+//
+/***********************************************************/
+use std::collections::HashMap;
+use std::collections::hash_map::RandomState;
+use std::fs;
+use std::fs::File;
+use std::hash::{BuildHasher, Hasher};
+use std::io;
+use std::io::{BufRead, BufReader, Cursor, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::thread;
+use std::time::{Duration, Instant};
+use accesses::Accesses;
+use audit::{Audit, Source};
+use budget;
+use budget::Budget;
+use cache;
+use checkpoint;
+use checkpoint::Checkpoints;
+use coverage::Coverage;
+use debugger;
+use error::{Fault, Limit, LimitError, RuntimeError, WhitespaceError};
+use heap;
+use heap::{Heap, RegionHeap, SharedHeap};
+#[cfg(feature = "hooks")]
+use hooks;
+use labels::SymbolTable;
+use metrics;
+use metrics::Metrics;
+use native;
+use options::Limits;
+use parser;
+use reload;
+use status::Status;
+use streaming;
+use streams::{RawTerminal, SharedBuffer, TeeOutput};
+use symbols::Action;
+use taint::Taint;
+use trace::Trace;
+use transcript::{Recorder, RecordingInput, RecordingOutput, ReplayOutput, Transcript};
+use virtual_machine::WhitespaceVM;
+use super::arguments::Flags;
+use super::program::{warn, Parsed};
+
+
+/********************************************/
+// Public Functions
+/********************************************/
+// Run the program, or hand it to the debugger, exiting with the status
+// for how it stopped
+pub fn run(mut flags: Flags, parsed: Parsed) {
+	let Parsed {reporter, mut pipeline, parsed, mut labels, mut warnings, warned, cached, cache_key, snapshot_key, source, mut measured, ..} = parsed;
+	let (command, options, limits) = (flags.command, flags.vm_options, flags.limits);
+
+	// Subroutines are known by their labels, which are gone once they're resolved
+	let names = if flags.budget || command == "debug" {budget::names(&parsed, &labels)} else {HashMap::new()};
+
+	// Reduce the routine labels to program pointers, caching the result and what parsing
+	// it warned about for next time.
+	// A streamed program is reduced as it's loaded instead.
+	let reduced = match cached {
+		Some(reduced)        => reduced,
+		None if flags.stream => Vec::new(),
+		None                 => {
+			let reduced = metrics::time(&mut measured.resolve, || handle_program_err!(parser::reduce_labels(parsed.clone(), &labels, options.labels), reporter));
+			if let Some(key) = cache_key.as_ref().filter(|_| flags.use_cache) {
+				cache::store(key, &reduced, &warned);
+			}
+			reduced
+		},
+	};
+
+	// Run a native binary of the program instead, when nothing needs the interpreter
+	if let (true, false, Some(ref key)) = (flags.native, flags.interpreted(), &cache_key) {
+		let stdin = match flags.input_path {
+			Some(ref path)                 => Stdio::from(handle_err!(File::open(path))),
+			None if flags.from_stdin() > 0 => Stdio::null(),
+			None                           => Stdio::inherit(),
+		};
+		match native::run(key, &reduced, &options, stdin, !flags.use_cache) {
+			Ok(status) => std::process::exit(status),
+			Err(error) => eprintln!("Warning: can't run natively, interpreting instead: {}", error),
+		}
+	}
+
+	// The program reads stdin, unless it was given another input or stdin held the program itself
+	let given_input = flags.input_path.is_some();
+	let mut input: Box<dyn BufRead> = match flags.input_path {
+		Some(ref path)                 => Box::new(BufReader::new(handle_err!(File::open(path)))),
+		None if flags.from_stdin() > 0 => Box::new(io::empty()),
+		None if command == "debug"     => Box::new(io::empty()),
+		None                           => Box::new(io::stdin().lock()),
+	};
+	let mut output: Box<dyn Write> = Box::new(io::stdout());
+
+	// Carry on from an earlier run's last snapshot, skipping the input it
+	// read when it's the same file again
+	let resumed = match snapshot_key {
+		Some(ref key) if flags.resume => Some(handle_err!(checkpoint::load(Path::new(&flags.checkpoint), key))),
+		_                             => None,
+	};
+	if let (Some(ref resumed), true) = (&resumed, given_input) {
+		handle_err!(io::copy(&mut input.by_ref().take(resumed.read), &mut io::sink()));
+	}
+
+	// Replay the input of a transcript, checking the output against it
+	let mut replay = None;
+
+	if let Some(ref path) = flags.replay_io {
+		let transcript = handle_err!(Transcript::load(path));
+		let checker    = ReplayOutput::new(transcript.output().to_vec(), output);
+		input  = Box::new(Cursor::new(transcript.input().to_vec()));
+		output = Box::new(checker.clone());
+		replay = Some(checker);
+
+		// Random and Clock draw from what they did, unless told otherwise
+		flags.io_options.seed = flags.io_options.seed.or(transcript.seed());
+		flags.io_options.virtual_time |= transcript.virtual_time();
+	}
+
+	// Record everything read and written to a transcript, along with a
+	// seed for Random, so a replay draws the same numbers
+	if let Some(ref path) = flags.record_io {
+		let recorder = handle_err!(Recorder::create(path));
+		let seed     = flags.io_options.seed.unwrap_or_else(|| RandomState::new().build_hasher().finish());
+		flags.io_options.seed = Some(seed);
+		handle_err!(recorder.source(&format!("seed {}", seed)));
+		if flags.io_options.virtual_time {
+			handle_err!(recorder.source("virtual-time"));
+		}
+		input  = Box::new(RecordingInput::new(input, recorder.clone()));
+		output = Box::new(RecordingOutput::new(output, recorder));
+	}
+
+	// Keep a copy of what the program writes for the debugger to look through
+	let written = SharedBuffer::default();
+	if command == "debug" {
+		output = Box::new(TeeOutput::new(output, written.clone()));
+	}
+
+	// Create the vm and execute the program
+	let program = if flags.show_stats {reduced.clone()} else {Vec::new()};
+	let mut vm  = WhitespaceVM::with_io(reduced, options, input, output);
+	vm.set_io_options(flags.io_options.clone());
+	vm.set_limits(limits);
+
+	// Audit what the run depends on, which includes the other program's thread when there is one
+	if let Some(strict) = flags.audit {
+		let mut audit = Audit::new(strict);
+		if flags.alongside.is_some() {
+			let shared = audit.touch(Source::SharedHeap, 0, 0).map_err(|message| WhitespaceError::Runtime(RuntimeError {fault: Fault::Denied, message, program_pointer: 0}));
+			handle_err!(shared, WhitespaceError::exit_code);
+		}
+		vm.set_audit(audit);
+	}
+	if flags.taint {
+		vm.set_taint(Taint::new(options.read_char));
+	}
+	if flags.budget {
+		vm.set_budget(Budget::new(names.clone()));
+	}
+	if flags.heap_map.is_some() {
+		vm.set_accesses(Accesses::default());
+	}
+	if let Some(codec) = flags.codec.take() {
+		vm.set_codec(codec);
+	}
+
+	// Run a second program on its own thread, sharing the heap (or part of it) with this one
+	let mut storage: Box<dyn Heap + Send> = heap::from_name(&flags.heap_kind).unwrap();
+	let mut companion = None;
+
+	if let Some(path) = flags.alongside.clone() {
+		let mut source = String::new();
+		handle_err!(handle_err!(File::open(&path)).read_to_string(&mut source));
+		let mut labels   = SymbolTable::default();
+		let mut warnings = Vec::new();
+		let parsed = parser::parse_with_symbols(source, &options, &mut labels, &mut warnings);
+		for warning in &warnings {
+			eprintln!("Warning: {} in {}", warning, path);
+		}
+		let parsed  = handle_err!(parsed, WhitespaceError::exit_code).into_iter().map(|(action, _)| action).collect();
+		let reduced = handle_err!(parser::reduce_labels(parsed, &labels, options.labels), WhitespaceError::exit_code);
+
+		let shared = SharedHeap::new(heap::from_name(&flags.heap_kind).unwrap());
+		let theirs: Box<dyn Heap + Send> = match flags.shared_heap {
+			Some(ref region) => Box::new(RegionHeap::new(heap::from_name(&flags.heap_kind).unwrap(), shared.clone(), region.clone())),
+			None             => Box::new(shared.clone()),
+		};
+		storage = match flags.shared_heap.clone() {
+			Some(region) => Box::new(RegionHeap::new(storage, shared, region)),
+			None         => Box::new(shared),
+		};
+
+		// The companion reads nothing and writes to the same stdout. Some
+		// platforms, like WASI, can't start threads at all.
+		let io_options = flags.io_options.clone();
+		companion = Some(handle_err!(thread::Builder::new().spawn(move || {
+			let mut vm = WhitespaceVM::with_io(reduced, options, Box::new(io::empty()), Box::new(io::stdout()));
+			vm.set_io_options(io_options);
+			vm.set_limits(limits);
+			vm.set_heap(theirs);
+			vm.execute().map_err(|error| (path, error))
+		})));
+	}
+
+	// Start from the heap a previous run saved
+	if let Some(ref path) = flags.load_heap {
+		let mut text = String::new();
+		handle_err!(handle_err!(File::open(path)).read_to_string(&mut text));
+		handle_err!(heap::load(&mut *storage, &text).map_err(|error| format!("{}: {}", path, error)));
+	}
+	vm.set_heap(storage);
+	vm.set_capacity(flags.capacity);
+	if let Some(filter) = flags.trace {
+		vm.set_trace(Trace::new(filter, Box::new(io::stderr())));
+	}
+	if let Some(interval) = flags.status {
+		vm.set_status(Status::new(interval, Box::new(io::stderr())));
+	}
+	if let Some(ref path) = flags.hooks {
+		handle_err!(attach_hooks(&mut vm, path));
+	}
+	if let Some(ref program_arguments) = flags.program_arguments {
+		vm.push_arguments(program_arguments);
+	}
+	if let Some(ref resumed) = resumed {
+		vm.restore(resumed);
+	}
+	if let (Some(every), Some(ref key)) = (flags.every, &snapshot_key) {
+		vm.set_checkpoints(Checkpoints::new(every, PathBuf::from(&flags.checkpoint), key.clone(), vm.steps()));
+	}
+
+	// Hand control to the user, who reads commands from stdin instead of the program,
+	// or to a file of commands
+	if command == "debug" {
+		let echo = flags.commands.is_some();
+		let mut commands: Box<dyn BufRead> = match flags.commands {
+			Some(ref path) => Box::new(BufReader::new(handle_err!(File::open(path)))),
+			None           => Box::new(io::stdin().lock()),
+		};
+		handle_err!(debugger::debug(&mut vm, &names, limits, &mut *commands, &mut io::stdout(), &written, echo));
+		return;
+	}
+
+	// Hand keypresses straight to the program until it finishes
+	let terminal = if flags.raw_input {Some(handle_err!(RawTerminal::enable()))} else {None};
+	let mut parse_time = measured.parse;
+	let result   = metrics::time(&mut measured.execute, || if flags.watch {
+		// Parse each version into the same table, so the labels it keeps keep their ids
+		// The reporter has the first version's source, so warnings about later ones go on a line
+		let mut reparse = |source| {
+			let mut warnings = Vec::new();
+			let edited = parser::parse_with_symbols(source, &options, &mut labels, &mut warnings);
+			for warning in &warnings {
+				eprintln!("Warning: {}", warning);
+			}
+			let edited = pipeline.run(edited?.into_iter().map(|(action, _)| action).collect());
+			parser::reduce_labels(edited.clone(), &labels, options.labels).map(|reduced| (edited, reduced))
+		};
+		execute_watched(&mut vm, &flags.file_paths[0], parsed, &mut reparse, limits, flags.watch_stack)
+	} else if let Some(source) = source {
+		let streamed = streaming::execute(&mut vm, source, options, &mut parse_time, &mut warnings);
+		warn(&reporter, &mut warnings);
+		streamed
+	} else {
+		vm.execute()
+	});
+	measured.parse = parse_time;
+	drop(terminal);
+
+	// Describe the run on stderr, so it stays apart from the program's output
+	if flags.show_stats {
+		eprint!("{}", vm.statistics().render(&program));
+		eprint!("{}", vm.allocations().render());
+	}
+	if let (Some(false), Some(audit)) = (flags.audit, vm.audit()) {
+		eprint!("{}", audit.render());
+	}
+	if let Some(taint) = vm.taint() {
+		eprint!("{}", taint.render());
+	}
+	if let Some(budget) = vm.budget() {
+		eprint!("{}", budget.render());
+	}
+	// A run that halted has no need of its snapshot
+	if result.is_ok() && snapshot_key.is_some() {
+		handle_err!(checkpoint::remove(Path::new(&flags.checkpoint)));
+	}
+	if let Some(ref path) = flags.coverage {
+		let report = Coverage::new(&reporter.lines(), &vm.statistics().executions).render(flags.coverage_as);
+		match path.as_str() {
+			"-"  => print!("{}", report),
+			path => handle_err!(fs::write(path, report)),
+		}
+	}
+	if let (Some(ref path), Some(accesses)) = (&flags.heap_map, vm.accesses()) {
+		eprint!("{}", accesses.summary());
+		let map = accesses.render(flags.heap_map_as);
+		match path.as_str() {
+			"-"  => handle_err!(io::stdout().write_all(&map)),
+			path => handle_err!(fs::write(path, map)),
+		}
+	}
+	if flags.metrics {
+		let measured = Metrics {steps: vm.steps(), stack: vm.stack_capacity(), call_stack: vm.call_stack_capacity(), heap: vm.heap().len(), ..measured};
+		match flags.metrics_to {
+			Some(ref path) if path == "-" => print!("{}", measured.render_json()),
+			Some(ref path)                => handle_err!(fs::write(path, measured.render_json())),
+			None                          => eprint!("{}", measured.render_json()),
+		}
+	}
+	handle_program_err!(result, reporter);
+
+	// Both programs have to finish for the run to have succeeded
+	if let Some(companion) = companion {
+		if let Err((path, error)) = companion.join().expect("the companion program's thread panicked") {
+			eprintln!("Error: {}: {}", path, error);
+			std::process::exit(error.exit_code());
+		}
+	}
+
+	if let Some(replay) = replay {
+		handle_err!(replay.finish());
+	}
+
+	// Keep the heap the program halted with for a later run
+	if let Some(ref path) = flags.save_heap {
+		handle_err!(handle_err!(File::create(path)).write_all(heap::save(vm.heap()).as_bytes()));
+	}
+}
+
+
+/********************************************/
+// Private Functions
+/********************************************/
+// Execute a program, switching to the new version of it whenever its
+// file changes. The vm runs a slice of actions at a time, and the file
+// is checked between slices, so a program waiting for input doesn't
+// see changes until it reads something. Each version is reparsed into
+// both its actions and their reduction, and one that doesn't parse or
+// resolve is reported and skipped until it's fixed.
+fn execute_watched(vm: &mut WhitespaceVM, path: &str, mut parsed: Vec<Action>, reparse: &mut dyn FnMut(String) -> Result<(Vec<Action>, Vec<Action>), WhitespaceError>, limits: Limits, keep_stack: bool) -> Result<(), WhitespaceError> {
+	const SLICE:  u64      = 100_000;
+	const SETTLE: Duration = Duration::from_millis(200);
+
+	let modified = || fs::metadata(path).and_then(|metadata| metadata.modified()).ok();
+	let started  = Instant::now();
+	let mut seen    = modified();
+	let mut pending = None;
+
+	loop {
+		// The user's limits still cover the whole run, not each slice
+		let slice = vm.steps() + SLICE;
+		let steps = limits.steps.map_or(slice, |steps| steps.min(slice));
+		let time  = limits.time.map(|time| time.saturating_sub(started.elapsed()));
+		vm.set_limits(Limits {steps: Some(steps), time, memory: limits.memory});
+
+		match vm.execute() {
+			Err(WhitespaceError::Limit(LimitError {limit: Limit::Steps(_), ..})) if limits.steps != Some(steps) => {},
+			Err(WhitespaceError::Limit(LimitError {limit: Limit::Time(_), program_pointer})) => {
+				return Err(WhitespaceError::Limit(LimitError {limit: Limit::Time(limits.time.unwrap()), program_pointer}));
+			},
+			result => return result,
+		}
+
+		// Wait for the file to stay the same for a moment, so a save that's
+		// still being written isn't read half finished
+		let changed = modified();
+		if changed != seen {
+			seen    = changed;
+			pending = Some(Instant::now());
+			continue;
+		}
+		match pending {
+			Some(since) if since.elapsed() >= SETTLE => pending = None,
+			_                                        => continue,
+		}
+
+		let edited = fs::read_to_string(path).map_err(WhitespaceError::from).and_then(&mut *reparse);
+
+		match edited {
+			Ok((edited, reduced)) => {
+				vm.reload(reduced, &|pointer| reload::remap(&parsed, &edited, pointer), keep_stack);
+				parsed = edited;
+				eprintln!("Reloaded {}", path);
+			},
+			Err(error) => eprintln!("Not reloading {}: {}", path, error),
+		}
+	}
+}
+
+
+// Attach the hooks in a file to the vm, with what they print going to stderr
+#[cfg(feature = "hooks")]
+fn attach_hooks(vm: &mut WhitespaceVM, path: &str) -> Result<(), String> {
+	let text = fs::read_to_string(path).map_err(|error| format!("{}: {}", path, error))?;
+	vm.set_hooks(hooks::Hooks::parse(&text, Box::new(io::stderr())).map_err(|error| format!("{}: {}", path, error))?);
+	Ok(())
+}
+
+#[cfg(not(feature = "hooks"))]
+fn attach_hooks(_vm: &mut WhitespaceVM, _path: &str) -> Result<(), String> {
+	Err("--hooks needs the hooks feature: build with cargo build --features hooks".to_string())
+}
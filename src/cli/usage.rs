@@ -0,0 +1,166 @@
+/***********************************************************/
+//
+// Usage
+// =====
+// The usage instructions, printed when the command line
+// makes no sense. The readme's Usage section is a copy of
+// them.
+//
+// exit_with_usage() -> !
+// -- Print the usage instructions and exit with the status
+//    for a usage error
+//
+/***********************************************************/
+use lint;
+
+
+/********************************************/
+// Public Functions
+/********************************************/
+// Print the program's usage instructions and exit with the status for a usage error
+pub fn exit_with_usage() -> ! {
+	println!("Usage:    whitespace [command] [options] <files, directory, or - for stdin>");
+	println!("Commands: run   - (default) run the program");
+	println!("          resume - carry on a run from the last snapshot --checkpoint-every took");
+	println!("          list  - list the commands that the file contains");
+	println!("          explain - list each instruction with what it does and its stack effect");
+	println!("          check - run the lints over the program");
+	println!("          callgraph - print which subroutines call which");
+	println!("          decompile - print the program as structured pseudocode");
+	println!("          debug - run the program a step at a time, with breakpoints, looking at the stack and heap in between");
+	println!("          obfuscate - print an equivalent program that's harder to follow");
+	println!("          minify - print the program in as few bytes as possible");
+	println!("          pretty - print the program with a comment naming each instruction, for reviews and diffs");
+	println!("          highlight - print the kind of every token, drawn over the source or as JSON for editors");
+	println!("          disasm - print the program as mnemonics, which assemble turns back into the same program");
+	println!("          assemble (or asm) - write mnemonics like push 5 and jz end out as a whitespace program");
+	println!("          compile --target js|py|jvm|llvm - print the program translated to another language");
+	println!("          bundle --output <file> - write a self-running executable of the program, for people without the interpreter");
+	println!("          verify - run the program and compare its output with a golden file");
+	println!("          explore - run the program on every short sequence of inputs, reporting the errors they reach");
+	println!("          fuzz --spec <file> - run the program on random inputs made up from a spec, reporting the errors they reach");
+	println!("          cache clear - remove every program cached by earlier runs");
+	println!("          conformance - run a directory of test programs and report a compliance score");
+	println!("          run-dir - run a directory of programs and print a table of how each one went");
+	println!("          repl - run lines of code as they're typed, building up subroutines and a program to save");
+	println!("          server - answer requests to load and run programs, as lines of JSON on stdin");
+	println!("          jupyter <connection file> - run as a Jupyter kernel, as Jupyter starts it");
+	println!("          capabilities [--json] - describe the instructions, extensions and options this build supports");
+	println!("          whitespace [command] [options] --eval <program in STL notation>");
+	println!("          whitespace run [options] <files> -- <arguments for the program>");
+	println!("Options:  --config <file>              - read settings from a TOML file instead of ./whitespace.toml");
+	println!("          --costs                      - (list) annotate each action with its estimated cost");
+	println!("          --idioms                     - (list) point out common shapes of code and what they do");
+	println!("          --name-labels                - (disasm) name labels by how they're used, like loop_1 and func_2");
+	println!("          --label-map <file>           - (disasm) name labels as a file says, adding the names given to it");
+	println!("          --no-comments                - (pretty, assemble) write the tokens alone, without naming the instructions");
+	println!("          --targets                    - (explain) show the instruction each call and jump goes to");
+	println!("          --seed <n>                   - (obfuscate, fuzz) choose the random choices, so they can be repeated");
+	println!("          --target js|py|jvm|llvm      - (compile) the language to translate the program to: JavaScript, Python,");
+	println!("                                         a JVM class file, or LLVM IR with the llvm feature");
+	println!("          --output <file>              - (bundle) where the executable goes; the other options are kept for its runs");
+	println!("          --expect <file>              - (verify) the output the program should write");
+	println!("          --script <file>              - (debug) read the debugger's commands from a file instead of stdin");
+	println!("          --stdin <file>               - (verify) give the program a file as its input");
+	println!("          --listen <address>           - (server) take requests from connections to an address instead");
+	println!("          --alphabet <lines>           - (explore) the comma separated lines of input to try at each read,");
+	println!("                                         besides the end of the input: 0,1,-1 by default");
+	println!("          --depth <n>                  - (explore) the most reads to try each input at, 4 by default");
+	println!("          --symbolic                   - (explore) work out the inputs instead of trying each, with the");
+	println!("                                         symbolic feature");
+	println!("          --reach <label>              - (explore) work out an input that reaches a label, given as S and T");
+	println!("          --spec <file>                - (fuzz) how the inputs are made up, a stretch of lines to a line");
+	println!("          --runs <n>                   - (fuzz) how many inputs to try, 100 by default");
+	println!("          --allow/--warn/--deny <lint> - (check) set the level of a lint");
+	println!("          --lints <file>               - (check) read lint levels from a manifest");
+	println!("          --format text|json           - (check, highlight) choose the report format");
+	println!("          --format dot|json            - (callgraph) choose the graph format");
+	println!("          --format text|verbose        - (list) show where in the source each action came from");
+	println!("          --strict                     - follow the published specification exactly");
+	println!("          --lenient                    - keep running wherever possible");
+	println!("          --reference                  - behave like the original Haskell interpreter");
+	println!("          --profile <profile>          - choose a profile: default, strict, lenient, reference");
+	println!("          --eof error|-1|0|unchanged   - (run) what reads produce at the end of the input");
+	println!("          --end-of-program error|halt  - (run) what happens when execution runs off the end");
+	println!("          --read-char heap|stack       - (run) where ReadChar stores the character");
+	println!("          --division floored|truncated|euclidean");
+	println!("                                       - (run) how Divide and Modulo round");
+	println!("          --labels eager|lazy          - (run) report missing labels before running or when jumped to");
+	println!("          --invalid-number reprompt|error");
+	println!("                                       - (run) what ReadNumber does with a line that isn't a number");
+	println!("          --unset-heap error|zero      - (run) what HeapRetrieve gives for addresses never stored to");
+	println!("          --char-encoding byte|unicode - (run) whether OutputChar writes a value as a byte or a Unicode code point");
+	println!("          --invalid-char lossy|error   - (run) what OutputChar does with a value that isn't a character");
+	println!("          --empty-number zero|warn|error");
+	println!("                                       - how number literals without digits are parsed");
+	println!("          --other-whitespace ignore|warn|error");
+	println!("                                       - what happens to whitespace that isn't a token, like form feeds");
+	println!("          --whitespace-tokens <list>   - read vt, ff or nbsp as a token, like vt=tab,nbsp=space, or none");
+	println!("          --number-input <list>        - (run) what ReadNumber accepts: none, or any of whitespace,plus,hex");
+	println!("          --empty-line invalid|zero|skip");
+	println!("                                       - (run) what ReadNumber does with an empty line");
+	println!("          --extensions <list>          - enable extension instructions: none, or any of env,debug,assert,");
+	println!("                                         callstack,format,clock,random");
+	println!("          --allow-env <names>          - (run) let GetEnv read these comma separated environment variables");
+	println!("          --flush auto|always|newline|input|exit");
+	println!("                                       - (run) when buffered output is written out");
+	println!("          --prompt <text>              - (run) write a prompt before every read");
+	println!("          --input-messages show|hide   - (run) whether ReadNumber explains why it's asking again");
+	println!("          --virtual-time               - (run) Clock reads a clock that starts at zero and moves 1ms a read");
+	println!("          --seed <n>                   - (run) Random draws the same numbers every run, from this seed");
+	println!("          --timeout <seconds>          - stop a program with an error after it runs this long");
+	println!("          --max-steps <n>              - stop a program with an error after it executes n actions");
+	println!("          --max-memory <n>             - stop a program with an error once it holds more than n values");
+	println!("          --untrusted                  - no extensions or environment, and limits on steps, time and memory");
+	println!("          --error-format plain|rich|json");
+	println!("                                       - how errors are shown: one line, with the source marked, or as JSON");
+	println!("          --input <file>               - (run) give the program a file as its input instead of stdin");
+	println!("          --heap hash|dense|paged      - (run) how the heap is stored: a hash map, a vector, or pages");
+	println!("          --load-heap <file>           - (run) start with the heap saved by --save-heap");
+	println!("          --save-heap <file>           - (run) save the heap to a file when the program halts");
+	println!("          --alongside <file>           - (run) run another program at the same time, sharing the heap");
+	println!("          --shared-heap <start>..<end> - (run) share only these heap addresses with the --alongside program");
+	println!("          --watch                      - (run) switch to the new version of the file whenever it's saved");
+	println!("          --watch-stack                - (run) like --watch, but keep the stack as well as the heap");
+	println!("          --stream                     - (run) start executing while the rest of the program is parsed");
+	println!("          --force                      - (run) run the actions before a parse error instead of refusing to");
+	println!("          --checkpoint-every <n>       - (run) snapshot the run every n actions, for resume to carry on from");
+	println!("          --checkpoint <file>          - (run) where the snapshot goes, ./whitespace.checkpoint by default");
+	println!("          --raw-input                  - (run) read each keypress immediately, without waiting for Enter");
+	println!("          --no-cache                   - (run) parse the program again instead of using the cache");
+	println!("          --native                     - (run) run a cached native binary of the program, built with the");
+	println!("                                         llvm feature and clang, falling back to the interpreter");
+	println!("          --optimize                   - simplify the program before running, listing, or printing it");
+	println!("          --strip-debug                - remove DumpHeap and other development-only instructions first");
+	println!("          --stats                      - (run) print counts of what the program did to stderr when it stops");
+	println!("          --capacity <s>,<c>,<h>       - (run, server) make room for this many values on the stack, on");
+	println!("                                         the call stack and in the heap before running");
+	println!("          --metrics json               - (run) print how long parsing, optimizing, resolving and running took,");
+	println!("                                         and the memory used, as JSON to stderr when it stops");
+	println!("          --metrics-file <file>        - (run) write the metrics to a file instead, or stdout for -");
+	println!("          --coverage <file>            - (run) write how often each line of the source ran, or to stdout for -");
+	println!("          --coverage-format lcov|cobertura");
+	println!("                                       - (run) write the coverage as an LCOV tracefile or Cobertura XML");
+	println!("          --heap-map <file>            - (run) write every heap address read and written, and when, or to stdout for -");
+	println!("          --heap-map-format csv|ppm    - (run) write the heap map as CSV or as a PPM heatmap of address against time");
+	println!("          --codec bytes|utf8|ebcdic    - (run) write and read characters as raw bytes, UTF-8 or EBCDIC");
+	println!("          --status-interval <interval> - (run) report progress on stderr every interval, like 5s or 500ms");
+	println!("          --audit                      - (run) list what the run depended on besides the program to stderr");
+	println!("          --deterministic              - (run) fail as soon as the run depends on anything but the program");
+	println!("          --budget                     - (run) print how many actions each subroutine executed to stderr");
+	println!("          --taint                      - (run) list where values read from the input were used to stderr");
+	println!("          --trace[=<categories>]       - (run) log each action to stderr, or only those in any of");
+	println!("                                         stack,arithmetic,heap,flow,io,ext");
+	println!("          --hooks <file>               - (run) run the Rhai hook scripts in a file as the program runs");
+	println!("          --record-io <file>           - (run) record everything read and written to a transcript");
+	println!("          --replay-io <file>           - (run) feed a transcript's input back and check the output matches");
+	println!("Lints:");
+	for lint in lint::LINTS {
+		println!("          {:<28} - ({}) {}", lint.name, lint.default.name(), lint.description);
+	}
+	println!("Exit status: 0 halted, 1 failed (or check/conformance/explore/fuzz found problems), 2 usage error,");
+	println!("             3 I/O error, 4 parse error, 5 undefined label, 6 runtime error,");
+	println!("             7 step limit reached, 8 timed out");
+	println!("\n");
+	std::process::exit(2);
+}
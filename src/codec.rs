@@ -0,0 +1,208 @@
+/***********************************************************/
+//
+// Codecs
+// ======
+// Turn the values OutputChar writes and ReadChar reads into
+// the bytes that go out and come in, so programs written
+// for an unusual character encoding can run unmodified, and
+// applications embedding the interpreter can plug in an
+// encoding of their own. A vm without a codec writes
+// characters the way the char encoding option says and reads
+// a byte at a time.
+//
+// Reading a character can take more than a byte. A codec
+// takes a whole character at once when the input has it
+// buffered, so an input that pauses the vm between pieces
+// doesn't split one, but an input that pauses partway
+// through a character it hasn't buffered loses what was
+// read of it.
+//
+// trait Codec <- Turns values into characters and back
+//
+// struct Bytes <- Each value is a byte, written and read as is
+//
+// struct Utf8 <- Each value is a Unicode code point, written
+//                and read as UTF-8
+//
+// struct Table <- Each value is the index of a character in a
+//                 table, written and read as UTF-8
+//
+// from_name(name: &str) -> Option<Rc<dyn Codec>>
+// -- The codec with the name used on the command line
+//
+/***********************************************************/
+use std::convert::TryFrom;
+use std::io;
+use std::io::BufRead;
+use std::rc::Rc;
+
+
+/********************************************/
+// Structures
+/********************************************/
+// Turns the values a program writes and reads as characters into bytes
+// and back
+pub trait Codec {
+	// Add the bytes a value is written as to the end of bytes, giving false
+	// if the value doesn't stand for a character
+	fn encode(&self, value: i64, bytes: &mut Vec<u8>) -> bool;
+
+	// Read the next character from the input, giving its value, or None at
+	// the end of the input
+	fn decode(&self, input: &mut dyn BufRead) -> io::Result<Option<i64>>;
+}
+
+// Each value is a byte, written and read as it is without any encoding
+#[derive(Debug, Copy, Clone, Default)]
+pub struct Bytes;
+
+// Each value is a Unicode code point, written and read as UTF-8. Input
+// that isn't UTF-8 reads as the replacement character.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct Utf8;
+
+// Each value is the index of a character in a table, written and read
+// as UTF-8. Reading a character the table doesn't have fails.
+#[derive(Debug, Clone)]
+pub struct Table {
+	characters: Vec<char>,
+}
+
+
+/********************************************/
+// Constants
+/********************************************/
+// EBCDIC code page 037, as used on IBM mainframes in the US, by byte
+const EBCDIC: [char; 256] = [
+	'\u{0}', '\u{1}', '\u{2}', '\u{3}', '\u{9c}', '\u{9}', '\u{86}', '\u{7f}', '\u{97}', '\u{8d}', '\u{8e}', '\u{b}', '\u{c}', '\u{d}', '\u{e}', '\u{f}',
+	'\u{10}', '\u{11}', '\u{12}', '\u{13}', '\u{9d}', '\u{85}', '\u{8}', '\u{87}', '\u{18}', '\u{19}', '\u{92}', '\u{8f}', '\u{1c}', '\u{1d}', '\u{1e}', '\u{1f}',
+	'\u{80}', '\u{81}', '\u{82}', '\u{83}', '\u{84}', '\u{a}', '\u{17}', '\u{1b}', '\u{88}', '\u{89}', '\u{8a}', '\u{8b}', '\u{8c}', '\u{5}', '\u{6}', '\u{7}',
+	'\u{90}', '\u{91}', '\u{16}', '\u{93}', '\u{94}', '\u{95}', '\u{96}', '\u{4}', '\u{98}', '\u{99}', '\u{9a}', '\u{9b}', '\u{14}', '\u{15}', '\u{9e}', '\u{1a}',
+	' ', '\u{a0}', 'â', 'ä', 'à', 'á', 'ã', 'å', 'ç', 'ñ', '¢', '.', '<', '(', '+', '|',
+	'&', 'é', 'ê', 'ë', 'è', 'í', 'î', 'ï', 'ì', 'ß', '!', '$', '*', ')', ';', '¬',
+	'-', '/', 'Â', 'Ä', 'À', 'Á', 'Ã', 'Å', 'Ç', 'Ñ', '¦', ',', '%', '_', '>', '?',
+	'ø', 'É', 'Ê', 'Ë', 'È', 'Í', 'Î', 'Ï', 'Ì', '`', ':', '#', '@', '\'', '=', '"',
+	'Ø', 'a', 'b', 'c', 'd', 'e', 'f', 'g', 'h', 'i', '«', '»', 'ð', 'ý', 'þ', '±',
+	'°', 'j', 'k', 'l', 'm', 'n', 'o', 'p', 'q', 'r', 'ª', 'º', 'æ', '¸', 'Æ', '¤',
+	'µ', '~', 's', 't', 'u', 'v', 'w', 'x', 'y', 'z', '¡', '¿', 'Ð', 'Ý', 'Þ', '®',
+	'^', '£', '¥', '·', '©', '§', '¶', '¼', '½', '¾', '[', ']', '¯', '¨', '´', '×',
+	'{', 'A', 'B', 'C', 'D', 'E', 'F', 'G', 'H', 'I', '\u{ad}', 'ô', 'ö', 'ò', 'ó', 'õ',
+	'}', 'J', 'K', 'L', 'M', 'N', 'O', 'P', 'Q', 'R', '¹', 'û', 'ü', 'ù', 'ú', 'ÿ',
+	'\\', '÷', 'S', 'T', 'U', 'V', 'W', 'X', 'Y', 'Z', '²', 'Ô', 'Ö', 'Ò', 'Ó', 'Õ',
+	'0', '1', '2', '3', '4', '5', '6', '7', '8', '9', '³', 'Û', 'Ü', 'Ù', 'Ú', '\u{9f}',
+];
+
+
+/********************************************/
+// Methods
+/********************************************/
+impl Codec for Bytes {
+	fn encode(&self, value: i64, bytes: &mut Vec<u8>) -> bool {
+		match u8::try_from(value) {
+			Ok(byte) => {bytes.push(byte); true},
+			Err(_)   => false,
+		}
+	}
+
+	fn decode(&self, input: &mut dyn BufRead) -> io::Result<Option<i64>> {
+		let byte = input.fill_buf()?.first().copied();
+		if byte.is_some() {
+			input.consume(1);
+		}
+		Ok(byte.map(i64::from))
+	}
+}
+
+impl Codec for Utf8 {
+	fn encode(&self, value: i64, bytes: &mut Vec<u8>) -> bool {
+		match u32::try_from(value).ok().and_then(char::from_u32) {
+			Some(character) => {bytes.extend_from_slice(character.encode_utf8(&mut [0; 4]).as_bytes()); true},
+			None            => false,
+		}
+	}
+
+	fn decode(&self, input: &mut dyn BufRead) -> io::Result<Option<i64>> {
+		Ok(read_character(input)?.map(|character| character as i64))
+	}
+}
+
+impl Table {
+	// Constructor, a table giving each value from zero up the character
+	// at that index
+	pub fn new(characters: Vec<char>) -> Table {
+		Table {characters}
+	}
+
+	// The table for EBCDIC code page 037
+	pub fn ebcdic() -> Table {
+		Table::new(EBCDIC.to_vec())
+	}
+}
+
+impl Codec for Table {
+	fn encode(&self, value: i64, bytes: &mut Vec<u8>) -> bool {
+		match usize::try_from(value).ok().and_then(|index| self.characters.get(index)) {
+			Some(character) => {bytes.extend_from_slice(character.encode_utf8(&mut [0; 4]).as_bytes()); true},
+			None            => false,
+		}
+	}
+
+	fn decode(&self, input: &mut dyn BufRead) -> io::Result<Option<i64>> {
+		let character = match read_character(input)? {
+			Some(character) => character,
+			None            => return Ok(None),
+		};
+		match self.characters.iter().position(|&entry| entry == character) {
+			Some(index) => Ok(Some(index as i64)),
+			None        => Err(io::Error::new(io::ErrorKind::InvalidData, format!("read {:?}, which the character table doesn't have", character))),
+		}
+	}
+}
+
+
+/********************************************/
+// Public Functions
+/********************************************/
+// The codec with the name used on the command line
+pub fn from_name(name: &str) -> Option<Rc<dyn Codec>> {
+	match name {
+		"bytes"  => Some(Rc::new(Bytes)),
+		"utf8"   => Some(Rc::new(Utf8)),
+		"ebcdic" => Some(Rc::new(Table::ebcdic())),
+		_        => None,
+	}
+}
+
+
+/********************************************/
+// Private Functions
+/********************************************/
+// Read a character encoded as UTF-8, or the replacement character for
+// bytes that aren't UTF-8
+fn read_character(input: &mut dyn BufRead) -> io::Result<Option<char>> {
+	let buffered = input.fill_buf()?;
+	let length = match buffered.first() {
+		None              => return Ok(None),
+		Some(0x00..=0x7f) => 1,
+		Some(0xc2..=0xdf) => 2,
+		Some(0xe0..=0xef) => 3,
+		Some(0xf0..=0xf4) => 4,
+		Some(_)           => {input.consume(1); return Ok(Some(char::REPLACEMENT_CHARACTER))},
+	};
+
+	// Take the whole character at once when it's buffered, skipping only its
+	// first byte if the rest doesn't follow on from it
+	if buffered.len() >= length {
+		let character = std::str::from_utf8(&buffered[..length]).ok().and_then(|text| text.chars().next());
+		input.consume(if character.is_some() {length} else {1});
+		return Ok(Some(character.unwrap_or(char::REPLACEMENT_CHARACTER)));
+	}
+
+	let mut bytes = [0u8; 4];
+	match input.read_exact(&mut bytes[..length]) {
+		Ok(())                                                         => {},
+		Err(ref error) if error.kind() == io::ErrorKind::UnexpectedEof => return Ok(Some(char::REPLACEMENT_CHARACTER)),
+		Err(error)                                                     => return Err(error),
+	}
+	Ok(Some(std::str::from_utf8(&bytes[..length]).ok().and_then(|text| text.chars().next()).unwrap_or(char::REPLACEMENT_CHARACTER)))
+}
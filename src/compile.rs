@@ -0,0 +1,118 @@
+/***********************************************************/
+//
+// Compiling
+// =========
+// Translates a program into source code for another
+// language, so it runs wherever that language does without
+// the interpreter. Each language is a backend that writes a
+// resolved program, where calls and jumps already hold the
+// index of the action they go to, following the options
+// that decide what the program means.
+//
+// Backends compile the program's semantics, not the
+// interpreter around it: limits, prompts and tracing are
+// left to whoever runs the output.
+//
+// trait Backend <- Writes resolved programs in another language
+//
+// backend(target: &str) -> Option<Box<dyn Backend>>
+// -- The backend for a target, like "js"
+//
+// leaders(program: &[Action]) -> Vec<bool>
+// -- Which actions control can arrive at other than from
+//    the action before
+//
+// unsupported(backend: &dyn Backend, action: &Action) -> String
+// -- The error for an action a backend can't translate
+//
+/***********************************************************/
+use javascript::JavaScript;
+use jvm::Jvm;
+#[cfg(feature = "llvm")]
+use llvm::Llvm;
+use python::Python;
+use options::Options;
+use symbols::Action;
+use symbols::Action::*;
+
+
+/********************************************/
+// Structures
+/********************************************/
+// Writes resolved programs in another language
+pub trait Backend {
+	// The name the backend is chosen by, as given to --target
+	fn name(&self) -> &'static str;
+
+	// Write the program as the complete file it compiles to, usually
+	// source code, or say why it can't be
+	fn emit(&self, program: &[Action], options: &Options) -> Result<Vec<u8>, String>;
+}
+
+// The names of every backend, as given to --target
+pub static TARGETS: &[&str] = &["js", "py", "jvm", "llvm"];
+
+// Stands in for the LLVM backend when it isn't built, to say how to get it
+#[cfg(not(feature = "llvm"))]
+struct Llvm;
+
+#[cfg(not(feature = "llvm"))]
+impl Backend for Llvm {
+	fn name(&self) -> &'static str {
+		"llvm"
+	}
+
+	fn emit(&self, _program: &[Action], _options: &Options) -> Result<Vec<u8>, String> {
+		Err("--target llvm needs the llvm feature: build with cargo build --features llvm".to_string())
+	}
+}
+
+
+/********************************************/
+// Public Functions
+/********************************************/
+// The backend for a target, if there is one
+pub fn backend(target: &str) -> Option<Box<dyn Backend>> {
+	match target {
+		"js"   => Some(Box::new(JavaScript)),
+		"py"   => Some(Box::new(Python)),
+		"jvm"  => Some(Box::new(Jvm)),
+		"llvm" => Some(Box::new(Llvm)),
+		_      => None,
+	}
+}
+
+
+// Which actions control can arrive at other than by running the action
+// before: the start, the targets of calls and jumps, the actions calls
+// return to, and the end of the program, which has an entry of its own
+pub fn leaders(program: &[Action]) -> Vec<bool> {
+	let mut leaders = vec![false; program.len() + 1];
+	leaders[0] = true;
+	leaders[program.len()] = true;
+
+	for (index, action) in program.iter().enumerate() {
+		match *action {
+			Call(target) => {
+				leaders[index + 1] = true;
+				if let Some(leader) = leaders.get_mut(target.0 as usize) {
+					*leader = true;
+				}
+			},
+			Jump(target) | JumpIfZero(target) | JumpIfNegative(target) => {
+				if let Some(leader) = leaders.get_mut(target.0 as usize) {
+					*leader = true;
+				}
+			},
+			_ => {},
+		}
+	}
+
+	leaders
+}
+
+
+// The error for an action a backend has no translation for
+pub fn unsupported(backend: &dyn Backend, action: &Action) -> String {
+	format!("{} can't be compiled to {}", action.name(), backend.name())
+}
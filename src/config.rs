@@ -0,0 +1,164 @@
+/***********************************************************/
+//
+// Configuration Files
+// ===================
+// Reads the interpreter's settings from a TOML file, so a
+// project can keep its profile, options, and limits in a
+// whitespace.toml instead of repeating them as flags.
+//
+// Only the part of TOML the settings need is understood:
+// `key = value` lines under optional [table] headers, where
+// a value is a string, an integer, or an array of strings.
+//
+//    profile = "lenient"
+//
+//    [semantics]
+//    division     = "truncated"
+//    number-input = ["whitespace", "hex"]
+//
+//    [io]
+//    flush = "newline"
+//
+//    [limits]
+//    max-steps = 1000000
+//
+//    [lints]
+//    unused-label = "allow"
+//
+// Keys are the names of the matching command line flags.
+//
+// apply(text: &str, settings: Settings) -> Result<(), String>
+// -- Apply the settings in a configuration file
+//
+/***********************************************************/
+use lint::{Level, LintConfig};
+use options;
+use options::{IoOptions, Limits, Options, Profile};
+
+
+/********************************************/
+// Structures
+/********************************************/
+// Everything a configuration file can set
+pub struct Settings<'a> {
+	pub options: &'a mut Options,
+	pub io:      &'a mut IoOptions,
+	pub limits:  &'a mut Limits,
+	pub lints:   &'a mut LintConfig,
+}
+
+
+/********************************************/
+// Public Functions
+/********************************************/
+// The file read from the current directory when no --config is given
+pub static DEFAULT_PATH: &str = "whitespace.toml";
+
+
+// Apply the settings in a configuration file, stopping at the first
+// line that can't be understood
+pub fn apply(text: &str, settings: Settings) -> Result<(), String> {
+	let mut table = String::new();
+
+	for (number, line) in text.lines().enumerate() {
+		let error = |message: &str| format!("Line {}: {}", number + 1, message);
+
+		let line = strip_comment(line).trim();
+		if line.is_empty() {
+			continue;
+		}
+
+		if line.starts_with('[') {
+			table = line.strip_prefix('[').and_then(|line| line.strip_suffix(']')).ok_or_else(|| error("Expected `[table]`"))?.trim().to_string();
+			continue;
+		}
+
+		let mut parts = line.splitn(2, '=');
+		let key   = parts.next().unwrap_or("").trim();
+		let value = parse_value(parts.next().unwrap_or("").trim()).ok_or_else(|| error("Expected `key = value`"))?;
+		let flag  = format!("--{}", key);
+
+		let applied = match table.as_str() {
+			"" if key == "profile" => Profile::from_name(&value).map(|profile| *settings.options = profile.options()).is_some(),
+			""                     => false,
+			"semantics"            => options::FLAGS.contains(&flag.as_str()) && options::set(settings.options, &flag, &value),
+			"io"                   => options::IO_FLAGS.contains(&flag.as_str()) && options::set_io(settings.io, &flag, &value),
+			"limits"               => options::LIMIT_FLAGS.contains(&flag.as_str()) && options::set_limit(settings.limits, &flag, &value),
+			"lints"                => match Level::from_name(&value) {
+				Some(level) => settings.lints.set(key, level).map(|()| true).map_err(|message| error(&message))?,
+				None        => false,
+			},
+			_                      => return Err(error(&format!("Unknown table `[{}]`", table))),
+		};
+
+		if !applied {
+			return Err(error(&format!("`{}` isn't a valid setting", line)));
+		}
+	}
+
+	Ok(())
+}
+
+
+/********************************************/
+// Private Functions
+/********************************************/
+// Remove a trailing comment from a line, leaving any # inside a string
+fn strip_comment(line: &str) -> &str {
+	let mut quoted  = false;
+	let mut escaped = false;
+
+	for (index, character) in line.char_indices() {
+		match character {
+			_ if escaped   => escaped = false,
+			'\\' if quoted => escaped = true,
+			'"'            => quoted = !quoted,
+			'#' if !quoted => return &line[..index],
+			_              => {},
+		}
+	}
+
+	line
+}
+
+
+// Parse a value into the text its command line flag would be given.
+// Arrays become comma separated lists, and an empty array becomes none.
+fn parse_value(value: &str) -> Option<String> {
+	if let Some(items) = value.strip_prefix('[').and_then(|value| value.strip_suffix(']')) {
+		let items = items.split(',').map(str::trim).filter(|item| !item.is_empty());
+		let items = items.map(parse_string).collect::<Option<Vec<String>>>()?;
+		return Some(if items.is_empty() {"none".to_string()} else {items.join(",")});
+	}
+
+	if value.starts_with('"') {
+		return parse_string(value);
+	}
+
+	value.parse::<i64>().ok().map(|number| number.to_string())
+}
+
+
+// Parse a double quoted string with escapes
+fn parse_string(value: &str) -> Option<String> {
+	let value = value.strip_prefix('"')?.strip_suffix('"')?;
+	let mut string = String::new();
+	let mut characters = value.chars();
+
+	while let Some(character) = characters.next() {
+		string.push(match character {
+			'\\' => match characters.next()? {
+				'n'  => '\n',
+				't'  => '\t',
+				'r'  => '\r',
+				'\\' => '\\',
+				'"'  => '"',
+				_    => return None,
+			},
+			'"'  => return None,
+			_    => character,
+		});
+	}
+
+	Some(string)
+}
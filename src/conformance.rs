@@ -0,0 +1,386 @@
+/***********************************************************/
+//
+// Conformance
+// ===========
+// Runs a directory of test programs laid out like the
+// community whitespace test corpus and scores how many
+// of them this interpreter's configuration passes.
+//
+// Each case is a program `name.ws` with optional fixtures
+// next to it:
+//    name.in     - bytes fed to the program's input
+//    name.script - an interaction script to play against the
+//                  program instead of name.in (see script.rs)
+//    name.out    - the exact output expected
+//    name.exit   - the expected termination: halt (the
+//                  default), parse-error, resolve-error,
+//                  runtime-error, io-error, step-limit,
+//                  timeout, or memory-limit
+//    name.options - command line flags the case runs with on
+//                  top of the suite's, like --division
+//                  truncated, --codec ebcdic or --max-steps 10
+//
+// tests/conformance holds cases for this interpreter's own
+// options, which the tests run.
+//
+// run_suite(directory: &Path, options: Options, limits: Limits) -> io::Result<Vec<CaseResult>>
+// -- Run every case found under a directory
+//
+// run_captured(source: String, options: Options, limits: Limits, input: Vec<u8>) -> Run
+// -- Run a program against in-memory input, capturing its output
+//
+// render_report(results: &[CaseResult]) -> String
+// -- Describe the results as a list of passes and failures
+//    with a compliance score
+//
+// render_table(results: &[CaseResult]) -> String
+// -- Describe the results as a table of how each program
+//    ended, how many steps it took, and how long it ran
+//
+/***********************************************************/
+use std::fs;
+use std::io;
+use std::io::{BufRead, BufReader, Cursor};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+use codec;
+use codec::Codec;
+use error::{Limit, LimitError, WhitespaceError};
+use options;
+use options::{Limits, Options};
+use parser;
+use script::ScriptedInput;
+use streams::SharedBuffer;
+use virtual_machine::WhitespaceVM;
+
+
+/********************************************/
+// Structures
+/********************************************/
+// The way a run of a program ended
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Termination {
+	Halt,
+	ParseError,
+	ResolveError,
+	RuntimeError,
+	IoError,
+	StepLimit,
+	Timeout,
+	MemoryLimit,
+	Cancelled,
+}
+
+impl Termination {
+	// Get the termination with the given name
+	pub fn from_name(name: &str) -> Option<Termination> {
+		match name {
+			"halt"          => Some(Termination::Halt),
+			"parse-error"   => Some(Termination::ParseError),
+			"resolve-error" => Some(Termination::ResolveError),
+			"runtime-error" => Some(Termination::RuntimeError),
+			"io-error"      => Some(Termination::IoError),
+			"step-limit"    => Some(Termination::StepLimit),
+			"timeout"       => Some(Termination::Timeout),
+			"memory-limit"  => Some(Termination::MemoryLimit),
+			"cancelled"     => Some(Termination::Cancelled),
+			_               => None,
+		}
+	}
+
+	// The name of the termination, as used in fixtures and reports
+	pub fn name(&self) -> &'static str {
+		match *self {
+			Termination::Halt         => "halt",
+			Termination::ParseError   => "parse-error",
+			Termination::ResolveError => "resolve-error",
+			Termination::RuntimeError => "runtime-error",
+			Termination::IoError      => "io-error",
+			Termination::StepLimit    => "step-limit",
+			Termination::Timeout      => "timeout",
+			Termination::MemoryLimit  => "memory-limit",
+			Termination::Cancelled    => "cancelled",
+		}
+	}
+
+	// Classify the result of a run
+	pub fn of(result: &Result<(), WhitespaceError>) -> Termination {
+		match *result {
+			Ok(())                                                               => Termination::Halt,
+			Err(WhitespaceError::Parse(_))                                       => Termination::ParseError,
+			Err(WhitespaceError::Resolve(_))                                     => Termination::ResolveError,
+			Err(WhitespaceError::Runtime(_))                                     => Termination::RuntimeError,
+			Err(WhitespaceError::Io(_))                                          => Termination::IoError,
+			Err(WhitespaceError::Limit(LimitError {limit: Limit::Steps(_), ..})) => Termination::StepLimit,
+			Err(WhitespaceError::Limit(LimitError {limit: Limit::Time(_), ..}))  => Termination::Timeout,
+			Err(WhitespaceError::Limit(LimitError {limit: Limit::Memory(_), ..})) => Termination::MemoryLimit,
+			Err(WhitespaceError::Limit(LimitError {limit: Limit::Cancelled, ..})) => Termination::Cancelled,
+		}
+	}
+}
+
+
+// A finished run of a program
+#[derive(Debug)]
+pub struct Run {
+	pub output: Vec<u8>,
+	pub result: Result<(), WhitespaceError>,
+	pub steps:  u64, // How many actions were executed
+}
+
+
+// The outcome of a single conformance case
+#[derive(Debug)]
+pub struct CaseResult {
+	pub name:        String,              // Path of the program relative to the suite directory
+	pub termination: Option<Termination>, // How the program ended, if its fixtures let it run
+	pub steps:       u64,                 // How many actions were executed
+	pub elapsed:     Duration,            // How long the program ran
+	pub failure:     Option<String>,      // Why the case failed, if it did
+}
+
+
+/********************************************/
+// Public Functions
+/********************************************/
+// Run every case found under a directory, in path order
+pub fn run_suite(directory: &Path, options: Options, limits: Limits) -> io::Result<Vec<CaseResult>> {
+	let mut programs = Vec::new();
+	find_programs(directory, &mut programs)?;
+	programs.sort();
+
+	programs.iter().map(|program| {
+		let name = program.strip_prefix(directory).unwrap_or(program).with_extension("");
+		run_case(name.to_string_lossy().into_owned(), program, options, limits)
+	}).collect()
+}
+
+
+// Parse, resolve, and execute a program against in-memory input
+pub fn run_captured(source: String, options: Options, limits: Limits, input: Vec<u8>) -> Run {
+	run_with_io(source, options, limits, None, Box::new(Cursor::new(input)), SharedBuffer::default())
+}
+
+
+// Render the results of a suite as a report ending with the overall score
+pub fn render_report(results: &[CaseResult]) -> String {
+	let mut report = String::new();
+
+	for result in results {
+		match result.failure {
+			None              => report.push_str(&format!("PASS  {}\n", result.name)),
+			Some(ref failure) => report.push_str(&format!("FAIL  {}: {}\n", result.name, failure)),
+		}
+	}
+
+	let passed = results.iter().filter(|result| result.failure.is_none()).count();
+	let score  = if results.is_empty() {100.0} else {100.0 * passed as f64 / results.len() as f64};
+	report.push_str(&format!("Compliance: {}/{} cases passed ({:.1}%)\n", passed, results.len(), score));
+	report
+}
+
+
+// Render the results of a suite as a table, one program per row
+pub fn render_table(results: &[CaseResult]) -> String {
+	let width = results.iter().map(|result| result.name.len()).chain(Some("PROGRAM".len())).max().unwrap_or(0);
+	let mut table = format!("{:<4}  {:<width$}  {:<13}  {:>12}  {:>10}\n", "", "PROGRAM", "ENDED", "STEPS", "TIME", width = width);
+
+	for result in results {
+		let status = if result.failure.is_none() {"ok"} else {"FAIL"};
+		let ended  = result.termination.map(|termination| termination.name()).unwrap_or("-");
+		let time   = format!("{:.1}ms", result.elapsed.as_secs_f64() * 1000.0);
+		table.push_str(&format!("{:<4}  {:<width$}  {:<13}  {:>12}  {:>10}", status, result.name, ended, result.steps, time, width = width));
+
+		if let Some(ref failure) = result.failure {
+			table.push_str(&format!("  {}", failure));
+		}
+		table.push('\n');
+	}
+
+	let passed = results.iter().filter(|result| result.failure.is_none()).count();
+	table.push_str(&format!("{}/{} programs passed\n", passed, results.len()));
+	table
+}
+
+
+/********************************************/
+// Private Functions
+/********************************************/
+// Collect every .ws file under a directory
+fn find_programs(directory: &Path, programs: &mut Vec<PathBuf>) -> io::Result<()> {
+	for entry in fs::read_dir(directory)? {
+		let path = entry?.path();
+
+		if path.is_dir() {
+			find_programs(&path, programs)?;
+		} else if path.extension().map(|extension| extension == "ws").unwrap_or(false) {
+			programs.push(path);
+		}
+	}
+
+	Ok(())
+}
+
+
+// Run a single case, timing it and checking it against its fixtures
+fn run_case(name: String, program: &Path, mut options: Options, mut limits: Limits) -> io::Result<CaseResult> {
+	let source   = fs::read_to_string(program)?;
+	let input    = read_fixture(program, "in")?.unwrap_or_default();
+	let script   = read_fixture(program, "script")?;
+	let expected = read_fixture(program, "out")?;
+	let exit     = read_fixture(program, "exit")?;
+	let flags    = read_fixture(program, "options")?;
+
+	let mut case = CaseResult {name, termination: None, steps: 0, elapsed: Duration::default(), failure: None};
+
+	let codec = match flags.map(|flags| set_flags(&String::from_utf8_lossy(&flags), &mut options, &mut limits)) {
+		None               => None,
+		Some(Ok(codec))    => codec,
+		Some(Err(message)) => {
+			case.failure = Some(format!("the .options fixture is invalid: {}", message));
+			return Ok(case);
+		},
+	};
+
+	let expected_termination = match exit {
+		None       => Termination::Halt,
+		Some(exit) => match Termination::from_name(String::from_utf8_lossy(&exit).trim()) {
+			Some(termination) => termination,
+			None              => {
+				case.failure = Some("the .exit fixture doesn't name a termination".to_string());
+				return Ok(case);
+			},
+		},
+	};
+
+	let output = SharedBuffer::default();
+	let script = match script.map(|script| ScriptedInput::parse(&String::from_utf8_lossy(&script), output.clone())) {
+		None               => None,
+		Some(Ok(script))   => Some(script),
+		Some(Err(message)) => {
+			case.failure = Some(format!("the .script fixture is invalid: {}", message));
+			return Ok(case);
+		},
+	};
+
+	let started = Instant::now();
+	let run = match script {
+		None             => run_with_io(source, options, limits, codec, Box::new(Cursor::new(input)), output),
+		Some(ref script) => run_with_io(source, options, limits, codec, Box::new(BufReader::new(script.clone())), output),
+	};
+
+	case.elapsed     = started.elapsed();
+	case.steps       = run.steps;
+	case.termination = Some(Termination::of(&run.result));
+	case.failure     = script.and_then(|script| script.finish()).or_else(|| judge(&run, expected_termination, expected));
+	Ok(case)
+}
+
+
+// Check a run against the termination and output it was expected to
+// have, returning why it doesn't match, if it doesn't
+fn judge(run: &Run, expected_termination: Termination, expected: Option<Vec<u8>>) -> Option<String> {
+	let termination = Termination::of(&run.result);
+
+	if termination != expected_termination {
+		let detail = match run.result {
+			Err(ref error) => format!(" ({})", error),
+			Ok(())         => String::new(),
+		};
+		return Some(format!("expected {}, got {}{}", expected_termination.name(), termination.name(), detail));
+	}
+
+	if let Some(expected) = expected {
+		if run.output != expected {
+			let difference = run.output.iter().zip(&expected).take_while(|&(actual, expected)| actual == expected).count();
+			return Some(format!(
+				"output differs at byte {} (expected {} bytes, got {})",
+				difference, expected.len(), run.output.len(),
+			));
+		}
+	}
+
+	None
+}
+
+
+// Apply a case's flags to the options and limits it runs with, giving
+// the codec they name, if any
+fn set_flags(flags: &str, options: &mut Options, limits: &mut Limits) -> Result<Option<Rc<dyn Codec>>, String> {
+	let mut codec = None;
+	let mut flags = flags.split_whitespace();
+
+	while let Some(flag) = flags.next() {
+		let value = flags.next().ok_or_else(|| format!("{} needs a value", flag))?;
+		let known = match flag {
+			"--codec" => codec::from_name(value).map(|named| codec = Some(named)).is_some(),
+			_         => options::set(options, flag, value) || options::set_limit(limits, flag, value),
+		};
+		if !known {
+			return Err(format!("{} {} isn't an option this interpreter has", flag, value));
+		}
+	}
+
+	Ok(codec)
+}
+
+
+// Parse, resolve, and execute a program with the given streams
+fn run_with_io(source: String, options: Options, limits: Limits, codec: Option<Rc<dyn Codec>>, input: Box<dyn BufRead>, output: SharedBuffer) -> Run {
+	let program = match parser::parse_and_reduce(source, &options) {
+		Ok(program) => program,
+		Err(error)  => return Run {output: Vec::new(), result: Err(error), steps: 0},
+	};
+
+	let mut vm = WhitespaceVM::with_io(program, options, input, Box::new(output.clone()));
+	vm.set_limits(limits);
+	if let Some(codec) = codec {
+		vm.set_codec(codec);
+	}
+	let result = vm.execute();
+
+	Run {output: output.contents(), result, steps: vm.steps()}
+}
+
+
+// Read the fixture with the given extension next to a program, if it exists
+fn read_fixture(program: &Path, extension: &str) -> io::Result<Option<Vec<u8>>> {
+	let path = program.with_extension(extension);
+
+	if path.exists() {
+		fs::read(path).map(Some)
+	} else {
+		Ok(None)
+	}
+}
+
+
+/********************************************/
+// Tests
+/********************************************/
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn fixtures_pass() {
+		let directory = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("conformance");
+		let results   = run_suite(&directory, Options::default(), Limits::default()).unwrap();
+		assert!(results.len() >= 15, "found only {} cases", results.len());
+
+		let failures: Vec<String> = results.iter().filter_map(|result| result.failure.as_ref().map(|failure| format!("{}: {}", result.name, failure))).collect();
+		assert!(failures.is_empty(), "{}", failures.join("\n"));
+	}
+
+	#[test]
+	fn invalid_options_fail_the_case() {
+		let mut options = Options::default();
+		let mut limits  = Limits::default();
+		assert!(set_flags("--division truncated\n--max-steps 5 --codec utf8", &mut options, &mut limits).unwrap().is_some());
+		assert_eq!(limits.steps, Some(5));
+		assert!(set_flags("--division", &mut options, &mut limits).is_err());
+		assert!(set_flags("--codec latin", &mut options, &mut limits).is_err());
+		assert!(set_flags("--colour red", &mut options, &mut limits).is_err());
+	}
+}
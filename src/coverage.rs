@@ -0,0 +1,159 @@
+/***********************************************************/
+//
+// Coverage
+// ========
+// Reports how often each line of a program's source was
+// executed, for run --coverage, in a format the usual
+// coverage viewers read: an LCOV tracefile, which genhtml and
+// most editors show, or Cobertura XML, which CI services
+// take. A program read from several files is reported per
+// file.
+//
+// Whitespace source has no statements of its own, so a line
+// counts as executed as often as the most executed action
+// that starts on it, and lines where no action starts, like
+// comments, aren't counted at all.
+//
+// enum Format <- Which format a report is written in
+//
+// struct Coverage <- How often each line of a run's source was executed
+//
+/***********************************************************/
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+
+/********************************************/
+// Structures
+/********************************************/
+// Which format a report is written in
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Format {
+	Lcov,      // An LCOV tracefile
+	Cobertura, // Cobertura's XML
+}
+
+// How often each line of a run's source was executed
+#[derive(Debug, Clone, Default)]
+pub struct Coverage {
+	files: Vec<(String, BTreeMap<usize, u64>)>, // Each file, in the order it was read, and the count for each line with an action on it
+}
+
+
+/********************************************/
+// Methods
+/********************************************/
+impl Format {
+	// The format with the name used on the command line
+	pub fn from_name(name: &str) -> Option<Format> {
+		match name {
+			"lcov"      => Some(Format::Lcov),
+			"cobertura" => Some(Format::Cobertura),
+			_           => None,
+		}
+	}
+}
+
+impl Coverage {
+	// Constructor, from the file and line each action of the program starts
+	// on and how many times each was executed
+	pub fn new(lines: &[(&str, usize)], executions: &[u64]) -> Coverage {
+		let mut coverage = Coverage::default();
+		for (&(name, line), &count) in lines.iter().zip(executions) {
+			if coverage.files.last().is_none_or(|(last, _)| last != name) {
+				coverage.files.push((name.to_string(), BTreeMap::new()));
+			}
+			let (_, counts) = coverage.files.last_mut().unwrap();
+			let counted = counts.entry(line).or_insert(0);
+			*counted = (*counted).max(count);
+		}
+		coverage
+	}
+
+	// Write the report out in a format
+	pub fn render(&self, format: Format) -> String {
+		match format {
+			Format::Lcov      => self.render_lcov(),
+			Format::Cobertura => self.render_cobertura(),
+		}
+	}
+
+	// Write the report as an LCOV tracefile, a record per file
+	fn render_lcov(&self) -> String {
+		let mut text = String::new();
+		for (name, counts) in &self.files {
+			let _ = writeln!(text, "TN:");
+			let _ = writeln!(text, "SF:{}", name);
+			for (line, count) in counts {
+				let _ = writeln!(text, "DA:{},{}", line, count);
+			}
+			let _ = writeln!(text, "LF:{}", counts.len());
+			let _ = writeln!(text, "LH:{}", hit(counts));
+			let _ = writeln!(text, "end_of_record");
+		}
+		text
+	}
+
+	// Write the report as Cobertura XML, a class per file. Whitespace has
+	// no branches to report apart, so each jump is only its line.
+	fn render_cobertura(&self) -> String {
+		let valid:   usize = self.files.iter().map(|(_, counts)| counts.len()).sum();
+		let covered: usize = self.files.iter().map(|(_, counts)| hit(counts)).sum();
+		let seconds = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |time| time.as_secs());
+
+		let mut text = String::new();
+		let _ = writeln!(text, "<?xml version=\"1.0\" ?>");
+		let _ = writeln!(text, "<coverage line-rate=\"{}\" branch-rate=\"0\" lines-covered=\"{}\" lines-valid=\"{}\" branches-covered=\"0\" branches-valid=\"0\" complexity=\"0\" version=\"{}\" timestamp=\"{}\">", rate(covered, valid), covered, valid, env!("CARGO_PKG_VERSION"), seconds);
+		let _ = writeln!(text, "\t<sources><source>.</source></sources>");
+		let _ = writeln!(text, "\t<packages>");
+		let _ = writeln!(text, "\t\t<package name=\"whitespace\" line-rate=\"{}\" branch-rate=\"0\" complexity=\"0\">", rate(covered, valid));
+		let _ = writeln!(text, "\t\t\t<classes>");
+		for (name, counts) in &self.files {
+			let _ = writeln!(text, "\t\t\t\t<class name=\"{0}\" filename=\"{0}\" line-rate=\"{1}\" branch-rate=\"0\" complexity=\"0\">", escape(name), rate(hit(counts), counts.len()));
+			let _ = writeln!(text, "\t\t\t\t\t<methods/>");
+			let _ = writeln!(text, "\t\t\t\t\t<lines>");
+			for (line, count) in counts {
+				let _ = writeln!(text, "\t\t\t\t\t\t<line number=\"{}\" hits=\"{}\"/>", line, count);
+			}
+			let _ = writeln!(text, "\t\t\t\t\t</lines>");
+			let _ = writeln!(text, "\t\t\t\t</class>");
+		}
+		let _ = writeln!(text, "\t\t\t</classes>");
+		let _ = writeln!(text, "\t\t</package>");
+		let _ = writeln!(text, "\t</packages>");
+		let _ = writeln!(text, "</coverage>");
+		text
+	}
+}
+
+
+/********************************************/
+// Private Functions
+/********************************************/
+// How many lines were executed at all
+fn hit(counts: &BTreeMap<usize, u64>) -> usize {
+	counts.values().filter(|&&count| count > 0).count()
+}
+
+
+// The fraction of lines executed, which is all of none
+fn rate(covered: usize, valid: usize) -> f64 {
+	if valid == 0 {1.0} else {covered as f64 / valid as f64}
+}
+
+
+// Escape text for an XML attribute
+fn escape(text: &str) -> String {
+	let mut escaped = String::new();
+	for character in text.chars() {
+		match character {
+			'&' => escaped.push_str("&amp;"),
+			'<' => escaped.push_str("&lt;"),
+			'>' => escaped.push_str("&gt;"),
+			'"' => escaped.push_str("&quot;"),
+			_   => escaped.push(character),
+		}
+	}
+	escaped
+}
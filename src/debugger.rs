@@ -0,0 +1,389 @@
+/***********************************************************/
+//
+// Debugger
+// ========
+// Runs a program a few actions at a time under the user's
+// control, with commands for looking at memory in between:
+//    step [n]       - execute one action, or n
+//    continue       - execute until the program stops
+//    run-until pc == <n>
+//                   - execute until the next action is action n
+//    run-until output-contains "text"
+//                   - execute until the program writes the text
+//    x/<n> <start>  - show n heap addresses from start
+//    stack [n]      - show the top n values on the stack
+//    break <n|label>
+//                   - stop before action n, or the label
+//    delete [n|label]
+//                   - remove a breakpoint, or all of them
+//    breakpoints    - list the breakpoints
+//    backtrace, bt  - show the subroutines being executed
+//    help, quit
+// Continuing and run-until stop at a breakpoint once they've
+// executed an action, so from one they carry on past it.
+// Labels are known by the text they spell, or their tokens
+// as S and T, the names run --budget shows them with.
+//
+// Values are shown in decimal, in hex, and as the character
+// they stand for, in columns, rather than as one long dump.
+//
+// Commands can also come from a file, which makes a session
+// repeatable. Each one is then echoed after the prompt, so
+// the output reads like the session it replays.
+//
+// debug(vm: &mut WhitespaceVM, names: &HashMap<usize, String>, limits: Limits, commands: &mut dyn BufRead, output: &mut dyn Write, written: &SharedBuffer, echo: bool) -> io::Result<()>
+// -- Take commands until the user quits or runs out of them
+//
+// examine_heap(heap: &dyn Heap, start: i64, count: usize) -> String
+// -- Show a range of heap addresses in columns
+//
+// examine_stack(stack: &[i64], count: usize) -> String
+// -- Show the top of the stack in columns
+//
+// backtrace(vm: &WhitespaceVM, names: &HashMap<usize, String>) -> String
+// -- Show the subroutines being executed, innermost first
+//
+/***********************************************************/
+use std::collections::{BTreeSet, HashMap};
+use std::io;
+use std::io::{BufRead, Write};
+use error::WhitespaceError;
+use heap::Heap;
+use options::Limits;
+use script;
+use streams::SharedBuffer;
+use symbols::Action;
+use virtual_machine::WhitespaceVM;
+
+
+/********************************************/
+// Structures
+/********************************************/
+// A debugger command
+#[derive(Debug, Clone, PartialEq)]
+enum Command {
+	Step(u64),
+	Continue,
+	RunUntil(Condition),
+	Examine(usize, i64), // How many addresses, and the first
+	Stack(usize),
+	Break(String),          // An action's index or a label
+	Delete(Option<String>), // The same, or every breakpoint
+	Breakpoints,
+	Backtrace,
+	Help,
+	Quit,
+}
+
+// What run-until executes until
+#[derive(Debug, Clone, PartialEq)]
+enum Condition {
+	Pointer(usize),  // The next action is the one at this index
+	Output(Vec<u8>), // The program has written this since run-until started
+}
+
+// The number of stack values shown when no count is given
+const STACK_SHOWN: usize = 10;
+
+// The headings of the columns a value is shown in
+static HEADINGS: &str = "             decimal                  hex  char";
+
+static HELP: &str = "\
+step [n]       - execute one action, or n
+continue       - execute until the program stops
+run-until pc == <n>
+               - execute until the next action is action n
+run-until output-contains \"text\"
+               - execute until the program writes the text
+x/<n> <start>  - show n heap addresses from start
+stack [n]      - show the top n values on the stack
+break <n|label>
+               - stop before action n, or the label, when running
+delete [n|label]
+               - remove a breakpoint, or all of them
+breakpoints    - list the breakpoints
+backtrace, bt  - show the subroutines being executed
+quit           - stop debugging
+";
+
+
+/********************************************/
+// Public Functions
+/********************************************/
+// Take commands until the user quits or runs out of them. Names are the
+// labels at each program pointer, for breakpoints and backtraces. Limits
+// apply to continuing, and are counted from the start of the run. Written
+// is where the vm's output is copied, so run-until can look at it.
+pub fn debug(vm: &mut WhitespaceVM, names: &HashMap<usize, String>, limits: Limits, commands: &mut dyn BufRead, output: &mut dyn Write, written: &SharedBuffer, echo: bool) -> io::Result<()> {
+	let mut finished    = false;
+	let mut breakpoints = BTreeSet::new();
+
+	loop {
+		write!(output, "(debug) ")?;
+		output.flush()?;
+
+		let mut line = String::new();
+		if commands.read_line(&mut line)? == 0 {
+			writeln!(output)?;
+			return Ok(());
+		}
+		if echo {
+			writeln!(output, "{}", line.trim_end())?;
+		}
+		if line.trim().is_empty() {
+			continue;
+		}
+
+		let command = match parse_command(line.trim()) {
+			Some(command) => command,
+			None          => {
+				writeln!(output, "Unknown command, try help")?;
+				continue;
+			},
+		};
+
+		match command {
+			Command::Step(_) | Command::Continue | Command::RunUntil(_) if finished => writeln!(output, "The program has stopped.")?,
+
+			Command::Step(count) => {
+				vm.set_limits(Limits {steps: Some(vm.steps() + count), time: None, memory: limits.memory});
+				finished = report(vm.execute(), vm, false, output)?;
+			},
+
+			// Without breakpoints to look out for, the program can run at full speed
+			Command::Continue if breakpoints.is_empty() => {
+				vm.set_limits(limits);
+				finished = report(vm.execute(), vm, true, output)?;
+			},
+			Command::Continue => {
+				vm.set_limits(limits);
+				finished = run_until(vm, None, &breakpoints, names, written, output)?;
+			},
+			Command::RunUntil(condition) => {
+				vm.set_limits(limits);
+				finished = run_until(vm, Some(&condition), &breakpoints, names, written, output)?;
+			},
+
+			Command::Break(place) => match pointer(vm, names, &place) {
+				Some(pointer) => {
+					breakpoints.insert(pointer);
+					writeln!(output, "Breakpoint at action {}{}.", pointer, label(names, pointer))?;
+				},
+				None => writeln!(output, "There's no action or label {}.", place)?,
+			},
+			Command::Delete(None) => {
+				writeln!(output, "Deleted {} breakpoint(s).", breakpoints.len())?;
+				breakpoints.clear();
+			},
+			Command::Delete(Some(place)) => match pointer(vm, names, &place) {
+				Some(pointer) if breakpoints.remove(&pointer) => writeln!(output, "Deleted the breakpoint at action {}{}.", pointer, label(names, pointer))?,
+				_                                             => writeln!(output, "There's no breakpoint at {}.", place)?,
+			},
+			Command::Breakpoints if breakpoints.is_empty() => writeln!(output, "There are no breakpoints.")?,
+			Command::Breakpoints => for &pointer in &breakpoints {
+				writeln!(output, "Breakpoint at action {}{}", pointer, label(names, pointer))?;
+			},
+
+			Command::Examine(count, start) => write!(output, "{}", examine_heap(vm.heap(), start, count))?,
+			Command::Stack(count)          => write!(output, "{}", examine_stack(vm.stack(), count))?,
+			Command::Backtrace             => write!(output, "{}", backtrace(vm, names))?,
+			Command::Help                  => write!(output, "{}", HELP)?,
+			Command::Quit                  => return Ok(()),
+		}
+	}
+}
+
+
+// Show a range of heap addresses in columns, marking those never stored to
+pub fn examine_heap(heap: &dyn Heap, start: i64, count: usize) -> String {
+	let mut text = format!("{:>12}  {}\n", "address", HEADINGS);
+
+	for address in (start..).take(count) {
+		let shown = match heap.get(address) {
+			Some(value) => columns(value),
+			None        => format!("{:>20}", "-"),
+		};
+		text.push_str(&format!("{:>12}  {}\n", address, shown));
+	}
+
+	text
+}
+
+
+// Show the top of the stack in columns, counting depth from the top
+pub fn examine_stack(stack: &[i64], count: usize) -> String {
+	if stack.is_empty() {
+		return "The stack is empty.\n".to_string();
+	}
+
+	let mut text = format!("{:>6}  {}\n", "depth", HEADINGS);
+	for (depth, &value) in stack.iter().rev().take(count).enumerate() {
+		text.push_str(&format!("{:>6}  {}\n", depth, columns(value)));
+	}
+	if count < stack.len() {
+		text.push_str(&format!("({} more below)\n", stack.len() - count));
+	}
+
+	text
+}
+
+
+// Show the subroutines being executed, innermost first, each with the
+// action it's at: the next action for the innermost, and the call being
+// executed for the rest
+pub fn backtrace(vm: &WhitespaceVM, names: &HashMap<usize, String>) -> String {
+	let calls = vm.call_stack();
+	let mut text = String::new();
+
+	for depth in 0..=calls.len() {
+		let at = match depth {
+			0 => vm.program_pointer(),
+			_ => calls[calls.len() - depth] - 1,
+		};
+
+		// A frame is in the subroutine the call outside it went to
+		let routine = match calls.len().checked_sub(depth + 1).and_then(|index| vm.action(calls[index] - 1)) {
+			Some(Action::Call(target)) => match names.get(&(target.0 as usize)) {
+				Some(name) => format!("label {}", name),
+				None       => format!("action {}", target.0),
+			},
+			_ => "main".to_string(),
+		};
+		text.push_str(&format!("#{:<2} action {} in {}\n", depth, at, routine));
+	}
+
+	text
+}
+
+
+/********************************************/
+// Private Functions
+/********************************************/
+// A value in decimal, hex, and as a character
+fn columns(value: i64) -> String {
+	let hex = if value < 0 {format!("-{:#x}", value.unsigned_abs())} else {format!("{:#x}", value)};
+	format!("{:>20} {:>20}  {}", value, hex, character(value)).trim_end().to_string()
+}
+
+
+// The character a value stands for, if it's one worth showing
+fn character(value: i64) -> String {
+	match value {
+		0x0a        => "'\\n'".to_string(),
+		0x09        => "'\\t'".to_string(),
+		0x0d        => "'\\r'".to_string(),
+		0x20..=0x7e => format!("'{}'", value as u8 as char),
+		_           => String::new(),
+	}
+}
+
+
+// Execute an action at a time until the program stops, reaches a
+// breakpoint, or meets the condition, returning whether it's done
+fn run_until(vm: &mut WhitespaceVM, condition: Option<&Condition>, breakpoints: &BTreeSet<usize>, names: &HashMap<usize, String>, written: &SharedBuffer, output: &mut dyn Write) -> io::Result<bool> {
+	written.take();
+	let mut since = Vec::new();
+
+	loop {
+		let result = vm.step();
+		since.extend(written.take());
+		match result {
+			Ok(false)  => {},
+			Ok(true)   => return report(Ok(()), vm, true, output),
+			Err(error) => return report(Err(error), vm, true, output),
+		}
+
+		let reached = match condition {
+			Some(&Condition::Pointer(pointer)) => vm.program_pointer() == pointer,
+			Some(Condition::Output(text))      => since.windows(text.len()).any(|window| window == &text[..]),
+			None                               => false,
+		};
+
+		let pointer = vm.program_pointer();
+		if breakpoints.contains(&pointer) {
+			writeln!(output, "Breakpoint at action {}{} after {} steps.", pointer, label(names, pointer), vm.steps())?;
+			return Ok(false);
+		}
+		if reached {
+			writeln!(output, "Stopped before action {} after {} steps.", pointer, vm.steps())?;
+			return Ok(false);
+		}
+	}
+}
+
+
+// The program pointer of an action's index or of a label, by the name
+// it's shown with or the text it spells
+fn pointer(vm: &WhitespaceVM, names: &HashMap<usize, String>, place: &str) -> Option<usize> {
+	if let Ok(pointer) = place.parse::<usize>() {
+		return vm.action(pointer).map(|_| pointer);
+	}
+
+	let quoted = format!("{:?}", place);
+	names.iter().filter(|&(_, name)| *name == place || *name == quoted).map(|(&pointer, _)| pointer).min()
+}
+
+
+// Where an action is marked by a label, which one, to follow its index
+fn label(names: &HashMap<usize, String>, pointer: usize) -> String {
+	match names.get(&pointer) {
+		Some(name) => format!(" (label {})", name),
+		None       => String::new(),
+	}
+}
+
+
+// Say how an execution stopped, returning whether the program is done.
+// Reaching a limit leaves it able to carry on, but when continuing that
+// means one of the user's limits, so it's worth pointing out.
+fn report(result: Result<(), WhitespaceError>, vm: &WhitespaceVM, continuing: bool, output: &mut dyn Write) -> io::Result<bool> {
+	match result {
+		Ok(()) => {
+			writeln!(output, "The program halted after {} steps.", vm.steps())?;
+			Ok(true)
+		},
+		Err(error @ WhitespaceError::Limit(_)) => {
+			if continuing {
+				writeln!(output, "{}", error)?;
+			}
+			writeln!(output, "Stopped before action {} after {} steps.", vm.program_pointer(), vm.steps())?;
+			Ok(false)
+		},
+		Err(error) => {
+			writeln!(output, "Error: {}", error)?;
+			Ok(true)
+		},
+	}
+}
+
+
+// Parse a debugger command
+fn parse_command(line: &str) -> Option<Command> {
+	let words: Vec<&str> = line.split_whitespace().collect();
+
+	Some(match words.as_slice() {
+		["step"] | ["s"]               => Command::Step(1),
+		["step", count] | ["s", count] => Command::Step(count.parse().ok()?),
+		["continue"] | ["c"]           => Command::Continue,
+		["run-until", "pc", "==", pointer] => Command::RunUntil(Condition::Pointer(pointer.parse().ok()?)),
+		["run-until", "output-contains", ..] => {
+			let text = line.trim_start_matches("run-until").trim_start().trim_start_matches("output-contains").trim();
+			Command::RunUntil(Condition::Output(script::unquote(text).filter(|text| !text.is_empty())?))
+		},
+		["stack"]                      => Command::Stack(STACK_SHOWN),
+		["stack", count]               => Command::Stack(count.parse().ok()?),
+		["break", place] | ["b", place] => Command::Break(place.to_string()),
+		["delete"]                     => Command::Delete(None),
+		["delete", place]              => Command::Delete(Some(place.to_string())),
+		["breakpoints"]                => Command::Breakpoints,
+		["backtrace"] | ["bt"]         => Command::Backtrace,
+		["help"] | ["h"]               => Command::Help,
+		["quit"] | ["q"]               => Command::Quit,
+		["x", start]                   => Command::Examine(1, start.parse().ok()?),
+
+		// x/16 1000 shows 16 addresses from 1000
+		[examine, start] if examine.starts_with("x/") => Command::Examine(examine[2..].parse().ok()?, start.parse().ok()?),
+
+		_ => return None,
+	})
+}
@@ -0,0 +1,536 @@
+/***********************************************************/
+//
+// Decompiler
+// ==========
+// Lifts a parsed program into pseudocode that's easier to
+// read than its raw actions. Values are tracked through the
+// stack so arithmetic and heap accesses become expressions,
+// and the jump patterns that compilers and people use for
+// loops and conditionals become while, do-while, and if
+// blocks. Anything that doesn't fit a pattern is left as
+// labels and gotos.
+//
+// Values still on the stack when control flow happens are
+// written out as push(...), and values the code pops without
+// having pushed them itself are read with pop().
+//
+// decompile(program: &[Action], options: &Options) -> String
+// -- Lift a parsed program into pseudocode
+//
+/***********************************************************/
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use labels::LabelId;
+use options::{Options, ReadCharDestination};
+use symbols::Action;
+use symbols::Action::*;
+
+
+/********************************************/
+// Structures
+/********************************************/
+// A value computed by the program
+#[derive(Debug, Clone)]
+enum Expression {
+	Constant(i64),
+	Temporary(usize),                                       // A value saved earlier
+	Heap(Box<Expression>),                                  // The value stored at an address
+	Binary(&'static str, Box<Expression>, Box<Expression>), // An arithmetic operation
+}
+
+impl Expression {
+	// Whether the value can be copied without saving it first
+	fn is_simple(&self) -> bool {
+		matches!(*self, Expression::Constant(_) | Expression::Temporary(_))
+	}
+
+	// Whether the value depends on what's in the heap
+	fn reads_heap(&self) -> bool {
+		match *self {
+			Expression::Heap(_)                        => true,
+			Expression::Binary(_, ref left, ref right) => left.reads_heap() || right.reads_heap(),
+			_                                          => false,
+		}
+	}
+
+	// The expression without parentheses around it
+	fn bare(&self) -> String {
+		match *self {
+			Expression::Binary(operator, ref left, ref right) => format!("{} {} {}", left, operator, right),
+			_                                                 => self.to_string(),
+		}
+	}
+}
+
+
+// What a conditional jump tests the value it pops for
+#[derive(Debug, Copy, Clone)]
+enum Test {
+	Zero,
+	Negative,
+}
+
+
+// A line of pseudocode, or a block of them
+#[derive(Debug, Clone)]
+enum Statement {
+	Line(String),                       // Anything that doesn't transfer control
+	Label(LabelId),
+	Goto(LabelId),
+	Branch(Test, Expression, LabelId),  // Jump to the label if the test passes
+	While {
+		test:  Test,                    // Leave the loop when the test passes
+		value: Expression,
+		setup: Vec<Statement>,          // Run before the value is tested
+		body:  Vec<Statement>,
+	},
+	DoWhile {
+		body:  Vec<Statement>,
+		test:  Test,                    // Repeat the loop while the test passes
+		value: Expression,
+	},
+	If {
+		test:      Test,                // Skip to the else block when the test passes
+		value:     Expression,
+		then:      Vec<Statement>,
+		otherwise: Vec<Statement>,
+	},
+}
+
+
+// Turns actions into flat statements, tracking the values on the stack
+struct Lifter {
+	statements:  Vec<Statement>,
+	stack:       Vec<Expression>,
+	temporaries: usize,
+}
+
+impl Lifter {
+	// Take the top value off the stack, reading it from the real stack
+	// if the code being lifted didn't push it
+	fn pop(&mut self) -> Expression {
+		match self.stack.pop() {
+			Some(value) => value,
+			None        => self.assign("pop()".to_string()),
+		}
+	}
+
+	// Save a value in a new temporary, returning it
+	fn assign(&mut self, value: String) -> Expression {
+		let temporary = Expression::Temporary(self.temporaries);
+		self.temporaries += 1;
+		self.line(format!("{} = {}", temporary, value));
+		temporary
+	}
+
+	// Save the values on the stack that read the heap before the heap changes
+	fn spill(&mut self) {
+		for index in 0..self.stack.len() {
+			if self.stack[index].reads_heap() {
+				let value = self.stack[index].bare();
+				self.stack[index] = self.assign(value);
+			}
+		}
+	}
+
+	// Write out the values left on the stack before control flow happens
+	fn flush(&mut self) {
+		for value in std::mem::take(&mut self.stack) {
+			self.line(format!("push({})", value.bare()));
+		}
+	}
+
+	fn line(&mut self, line: String) {
+		self.statements.push(Statement::Line(line));
+	}
+}
+
+
+/********************************************/
+// Trait Implementations
+/********************************************/
+impl fmt::Display for Expression {
+	fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+		match *self {
+			Expression::Constant(value)                       => write!(formatter, "{}", value),
+			Expression::Temporary(number)                     => write!(formatter, "t{}", number),
+			Expression::Heap(ref address)                     => write!(formatter, "heap[{}]", address.bare()),
+			Expression::Binary(operator, ref left, ref right) => write!(formatter, "({} {} {})", left, operator, right),
+		}
+	}
+}
+
+
+/********************************************/
+// Public Functions
+/********************************************/
+// Lift a parsed program into pseudocode. The options decide where
+// ReadChar puts the character it reads.
+pub fn decompile(program: &[Action], options: &Options) -> String {
+	let mut lifter = Lifter {statements: Vec::new(), stack: Vec::new(), temporaries: 0};
+	for action in program {
+		lift(&mut lifter, action, options);
+	}
+	lifter.flush();
+
+	// Count the references to each label, so only labels used just once
+	// by the pattern being matched are folded into a block
+	let mut references = HashMap::new();
+	let mut called     = HashSet::new();
+	for action in program {
+		if let Call(label) | Jump(label) | JumpIfZero(label) | JumpIfNegative(label) = *action {
+			*references.entry(label).or_insert(0) += 1;
+		}
+		if let Call(label) = *action {
+			called.insert(label);
+		}
+	}
+
+	let mut text = String::new();
+	render(&structure(&lifter.statements, &references), 0, &called, &mut text);
+	text
+}
+
+
+/********************************************/
+// Private Functions
+/********************************************/
+// Lift a single action onto the statements
+fn lift(lifter: &mut Lifter, action: &Action, options: &Options) {
+	match *action {
+		StackPush(value) => lifter.stack.push(Expression::Constant(value)),
+
+		StackDuplicate => {
+			let value = lifter.pop();
+			let value = if value.is_simple() {value} else {lifter.assign(value.bare())};
+			lifter.stack.push(value.clone());
+			lifter.stack.push(value);
+		},
+
+		StackSwap => {
+			let top    = lifter.pop();
+			let second = lifter.pop();
+			lifter.stack.push(top);
+			lifter.stack.push(second);
+		},
+
+		// Expressions don't have side effects, so a discarded one can be forgotten
+		StackDiscard => {
+			lifter.pop();
+		},
+
+		Add | Subtract | Multiply | Divide | Modulo => {
+			let operator = match *action {
+				Add      => "+",
+				Subtract => "-",
+				Multiply => "*",
+				Divide   => "/",
+				_        => "%",
+			};
+			let right = lifter.pop();
+			let left  = lifter.pop();
+			lifter.stack.push(Expression::Binary(operator, Box::new(left), Box::new(right)));
+		},
+
+		HeapStore => {
+			let value   = lifter.pop();
+			let address = lifter.pop();
+			lifter.spill();
+			lifter.line(format!("heap[{}] = {}", address.bare(), value.bare()));
+		},
+
+		HeapRetrieve => {
+			let address = lifter.pop();
+			lifter.stack.push(Expression::Heap(Box::new(address)));
+		},
+
+		Label(label) => {
+			lifter.flush();
+			lifter.statements.push(Statement::Label(label));
+		},
+
+		Call(label) => {
+			lifter.flush();
+			lifter.line(format!("call {}", label_name(label)));
+		},
+
+		Jump(label) => {
+			lifter.flush();
+			lifter.statements.push(Statement::Goto(label));
+		},
+
+		JumpIfZero(label) | JumpIfNegative(label) => {
+			let test  = if let JumpIfZero(_) = *action {Test::Zero} else {Test::Negative};
+			let value = lifter.pop();
+			lifter.flush();
+			lifter.statements.push(Statement::Branch(test, value, label));
+		},
+
+		EndSubroutine => {
+			lifter.flush();
+			lifter.line("return".to_string());
+		},
+
+		Halt => {
+			lifter.flush();
+			lifter.line("halt".to_string());
+		},
+
+		OutputChar => {
+			let value = lifter.pop();
+			let shown = match value {
+				Expression::Constant(code @ 0x20..=0x7e) if code != 0x27 => format!("'{}'", code as u8 as char),
+				_                                                       => value.bare(),
+			};
+			lifter.line(format!("print_char({})", shown));
+		},
+
+		OutputNumber => {
+			let value = lifter.pop();
+			lifter.line(format!("print_number({})", value.bare()));
+		},
+
+		ReadChar => match options.read_char {
+			ReadCharDestination::Heap  => {
+				let address = lifter.pop();
+				lifter.spill();
+				lifter.line(format!("heap[{}] = read_char()", address.bare()));
+			},
+			ReadCharDestination::Stack => {
+				let character = lifter.assign("read_char()".to_string());
+				lifter.stack.push(character);
+			},
+		},
+
+		ReadNumber => {
+			let address = lifter.pop();
+			lifter.spill();
+			lifter.line(format!("heap[{}] = read_number()", address.bare()));
+		},
+
+		GetEnv => {
+			let address = lifter.pop();
+			lifter.spill();
+			let length = lifter.assign(format!("getenv({})", address.bare()));
+			lifter.stack.push(length);
+		},
+
+		DumpHeap => lifter.line("dump_heap()".to_string()),
+
+		Assert => {
+			let expected = lifter.pop();
+			let actual   = lifter.pop();
+			lifter.line(format!("assert_equal({}, {})", actual.bare(), expected.bare()));
+		},
+
+		CallDepth => {
+			let depth = lifter.assign("call_depth()".to_string());
+			lifter.stack.push(depth);
+		},
+
+		PeekReturn => {
+			let depth   = lifter.pop();
+			let address = lifter.assign(format!("return_address({})", depth.bare()));
+			lifter.stack.push(address);
+		},
+
+		OutputFormatted => {
+			let width = lifter.pop();
+			let base  = lifter.pop();
+			let value = lifter.pop();
+			lifter.line(format!("print_formatted({}, {}, {})", value.bare(), base.bare(), width.bare()));
+		},
+
+		Clock => {
+			let time = lifter.assign("clock()".to_string());
+			lifter.stack.push(time);
+		},
+
+		Random => {
+			let bound  = lifter.pop();
+			let number = lifter.assign(format!("random({})", bound.bare()));
+			lifter.stack.push(number);
+		},
+	}
+}
+
+
+// Fold the jump patterns in a run of flat statements into blocks
+fn structure(flat: &[Statement], references: &HashMap<LabelId, usize>) -> Vec<Statement> {
+	let mut structured = Vec::new();
+	let mut index = 0;
+
+	while index < flat.len() {
+		let matched = match_while(flat, index, references)
+			.or_else(|| match_do_while(flat, index, references))
+			.or_else(|| match_if(flat, index, references));
+
+		match matched {
+			Some((statement, next)) => {
+				structured.push(statement);
+				index = next;
+			},
+			None => {
+				// Labels nothing refers to don't need to be shown
+				match flat[index] {
+					Statement::Label(label) if !references.contains_key(&label) => {},
+					ref statement                                              => structured.push(statement.clone()),
+				}
+				index += 1;
+			},
+		}
+	}
+
+	structured
+}
+
+
+// Match a loop that tests its condition at the top:
+//    start: setup; if value passes goto end; body; goto start; end:
+fn match_while(flat: &[Statement], index: usize, references: &HashMap<LabelId, usize>) -> Option<(Statement, usize)> {
+	let start = match flat[index] {
+		Statement::Label(label) if references.get(&label) == Some(&1) => label,
+		_                                                         => return None,
+	};
+
+	let back = (index + 1..flat.len()).find(|&at| matches!(flat[at], Statement::Goto(label) if label == start))?;
+	let end  = match flat.get(back + 1) {
+		Some(&Statement::Label(label)) if references.get(&label) == Some(&1) => label,
+		_                                                                => return None,
+	};
+
+	// The setup can't transfer control, or the test wouldn't always run
+	let exit = (index + 1..back).find(|&at| !matches!(flat[at], Statement::Line(_)))?;
+	match flat[exit] {
+		Statement::Branch(test, ref value, label) if label == end => Some((Statement::While {
+			test,
+			value: value.clone(),
+			setup: flat[index + 1..exit].to_vec(),
+			body:  structure(&flat[exit + 1..back], references),
+		}, back + 2)),
+		_ => None,
+	}
+}
+
+
+// Match a loop that tests its condition at the bottom:
+//    start: body; if value passes goto start
+fn match_do_while(flat: &[Statement], index: usize, references: &HashMap<LabelId, usize>) -> Option<(Statement, usize)> {
+	let start = match flat[index] {
+		Statement::Label(label) if references.get(&label) == Some(&1) => label,
+		_                                                         => return None,
+	};
+
+	let back = (index + 1..flat.len()).find(|&at| matches!(flat[at], Statement::Branch(_, _, label) if label == start))?;
+	match flat[back] {
+		Statement::Branch(test, ref value, _) => Some((Statement::DoWhile {
+			body:  structure(&flat[index + 1..back], references),
+			test,
+			value: value.clone(),
+		}, back + 1)),
+		_ => None,
+	}
+}
+
+
+// Match a conditional, with or without an else block:
+//    if value passes goto skip; then; skip:
+//    if value passes goto skip; then; goto end; skip: otherwise; end:
+fn match_if(flat: &[Statement], index: usize, references: &HashMap<LabelId, usize>) -> Option<(Statement, usize)> {
+	let (test, value, skip) = match flat[index] {
+		Statement::Branch(test, ref value, label) if references.get(&label) == Some(&1) => (test, value.clone(), label),
+		_                                                                           => return None,
+	};
+
+	let target = (index + 1..flat.len()).find(|&at| matches!(flat[at], Statement::Label(label) if label == skip))?;
+
+	// A then block ending in a jump past an else block
+	if let Statement::Goto(end) = flat[target - 1] {
+		let found = (target + 1..flat.len()).find(|&at| matches!(flat[at], Statement::Label(label) if label == end));
+		if let (true, Some(after)) = (target - 1 > index && references.get(&end) == Some(&1), found) {
+			return Some((Statement::If {
+				test,
+				value,
+				then:      structure(&flat[index + 1..target - 1], references),
+				otherwise: structure(&flat[target + 1..after], references),
+			}, after + 1));
+		}
+	}
+
+	Some((Statement::If {
+		test,
+		value,
+		then:      structure(&flat[index + 1..target], references),
+		otherwise: Vec::new(),
+	}, target + 1))
+}
+
+
+// Write out statements indented to a depth
+fn render(statements: &[Statement], depth: usize, called: &HashSet<LabelId>, text: &mut String) {
+	let indent = "    ".repeat(depth);
+
+	for statement in statements {
+		match *statement {
+			Statement::Line(ref line) => text.push_str(&format!("{}{}\n", indent, line)),
+
+			// Subroutines are set apart from the code before them
+			Statement::Label(label) if called.contains(&label) => text.push_str(&format!("\n{}subroutine {}:\n", indent, label_name(label))),
+			Statement::Label(label)                            => text.push_str(&format!("{}{}:\n", indent, label_name(label))),
+
+			Statement::Goto(label) => text.push_str(&format!("{}goto {}\n", indent, label_name(label))),
+
+			Statement::Branch(test, ref value, label) => {
+				text.push_str(&format!("{}if {} goto {}\n", indent, condition(test, value, true), label_name(label)));
+			},
+
+			Statement::While {test, ref value, ref setup, ref body} if setup.is_empty() => {
+				text.push_str(&format!("{}while {} {{\n", indent, condition(test, value, false)));
+				render(body, depth + 1, called, text);
+				text.push_str(&format!("{}}}\n", indent));
+			},
+
+			// A loop that has to do some work before it can test its condition
+			Statement::While {test, ref value, ref setup, ref body} => {
+				text.push_str(&format!("{}loop {{\n", indent));
+				render(setup, depth + 1, called, text);
+				text.push_str(&format!("{}    if {} break\n", indent, condition(test, value, true)));
+				render(body, depth + 1, called, text);
+				text.push_str(&format!("{}}}\n", indent));
+			},
+
+			Statement::DoWhile {ref body, test, ref value} => {
+				text.push_str(&format!("{}do {{\n", indent));
+				render(body, depth + 1, called, text);
+				text.push_str(&format!("{}}} while {}\n", indent, condition(test, value, true)));
+			},
+
+			Statement::If {test, ref value, ref then, ref otherwise} => {
+				text.push_str(&format!("{}if {} {{\n", indent, condition(test, value, false)));
+				render(then, depth + 1, called, text);
+				if !otherwise.is_empty() {
+					text.push_str(&format!("{}}} else {{\n", indent));
+					render(otherwise, depth + 1, called, text);
+				}
+				text.push_str(&format!("{}}}\n", indent));
+			},
+		}
+	}
+}
+
+
+// The condition under which a test passes, or fails
+fn condition(test: Test, value: &Expression, passes: bool) -> String {
+	match (test, passes) {
+		(Test::Zero,     true)  => format!("{} == 0", value),
+		(Test::Zero,     false) => format!("{} != 0", value),
+		(Test::Negative, true)  => format!("{} < 0", value),
+		(Test::Negative, false) => format!("{} >= 0", value),
+	}
+}
+
+
+// The name a label is shown with
+fn label_name(label: LabelId) -> String {
+	format!("label_{}", label.0)
+}
@@ -0,0 +1,127 @@
+/***********************************************************/
+//
+// Diffs
+// =====
+// Line by line comparison of expected and actual output,
+// shown the way diff -u shows it, so a failed check points
+// straight at the lines that differ.
+//
+// render(expected: &str, actual: &str, color: bool) -> String
+// -- Show the lines that differ between two texts, with a
+//    little context around them
+//
+/***********************************************************/
+
+
+/********************************************/
+// Structures
+/********************************************/
+// A line of the comparison
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Change<'a> {
+	Same(&'a str),
+	Removed(&'a str), // Only in the expected text
+	Added(&'a str),   // Only in the actual text
+}
+
+// The number of unchanged lines shown around each change
+const CONTEXT: usize = 2;
+
+
+/********************************************/
+// Public Functions
+/********************************************/
+// Show the lines that differ between two texts, marking lines only
+// expected with - and lines only in the actual text with +, and
+// coloring them red and green when asked to
+pub fn render(expected: &str, actual: &str, color: bool) -> String {
+	let changes = compare(&lines(expected), &lines(actual));
+	let mut text = String::new();
+
+	// Keep the lines within CONTEXT of a change, and mark the gaps
+	let near = |index: usize| {
+		let low  = index.saturating_sub(CONTEXT);
+		let high = (index + CONTEXT + 1).min(changes.len());
+		changes[low..high].iter().any(|change| !matches!(change, Change::Same(_)))
+	};
+
+	let mut skipped = false;
+	for (index, change) in changes.iter().enumerate() {
+		if !near(index) {
+			skipped = true;
+			continue;
+		}
+		if skipped {
+			text.push_str("...\n");
+			skipped = false;
+		}
+
+		let (marker, line, paint) = match *change {
+			Change::Same(line)    => (' ', line, ""),
+			Change::Removed(line) => ('-', line, "\x1b[31m"),
+			Change::Added(line)   => ('+', line, "\x1b[32m"),
+		};
+
+		// Show lines without their newline, noting when one is missing
+		let shown = match line.strip_suffix('\n') {
+			Some(shown) => shown.to_string(),
+			None        => format!("{} (no newline at end)", line),
+		};
+
+		if color && !paint.is_empty() {
+			text.push_str(&format!("{}{} {}\x1b[0m\n", paint, marker, shown));
+		} else {
+			text.push_str(&format!("{} {}\n", marker, shown));
+		}
+	}
+	if skipped {
+		text.push_str("...\n");
+	}
+
+	text
+}
+
+
+/********************************************/
+// Private Functions
+/********************************************/
+// Split a text into lines, keeping each line's newline
+fn lines(text: &str) -> Vec<&str> {
+	text.split_inclusive('\n').collect()
+}
+
+
+// Find the changes that turn the expected lines into the actual ones,
+// using the longest common subsequence of lines
+fn compare<'a>(expected: &[&'a str], actual: &[&'a str]) -> Vec<Change<'a>> {
+	// common[i][j] is the length of the longest common subsequence of
+	// expected[i..] and actual[j..]
+	let mut common = vec![vec![0usize; actual.len() + 1]; expected.len() + 1];
+	for i in (0..expected.len()).rev() {
+		for j in (0..actual.len()).rev() {
+			common[i][j] = if expected[i] == actual[j] {
+				common[i + 1][j + 1] + 1
+			} else {
+				common[i + 1][j].max(common[i][j + 1])
+			};
+		}
+	}
+
+	let mut changes = Vec::new();
+	let (mut i, mut j) = (0, 0);
+	while i < expected.len() || j < actual.len() {
+		if i < expected.len() && j < actual.len() && expected[i] == actual[j] {
+			changes.push(Change::Same(expected[i]));
+			i += 1;
+			j += 1;
+		} else if i < expected.len() && (j == actual.len() || common[i + 1][j] >= common[i][j + 1]) {
+			changes.push(Change::Removed(expected[i]));
+			i += 1;
+		} else {
+			changes.push(Change::Added(actual[j]));
+			j += 1;
+		}
+	}
+
+	changes
+}
@@ -0,0 +1,256 @@
+/***********************************************************/
+//
+// Disassembler
+// ============
+// Writes a parsed program out as mnemonics, for the disasm
+// command, in the syntax the assembler reads, so that
+// assembling the listing gives the same program back:
+//
+//    whitespace disasm prog.ws | whitespace asm - > again.ws
+//
+// Labels are named by the text they spell, the way many
+// programs name them, and written as their tokens after a %
+// when they don't spell a name the assembler can read back.
+// Pushes of printable characters say which in a comment.
+//
+// Programs from elsewhere rarely name their labels, so the
+// labels can instead be named by how they're used: func_1
+// for a subroutine, loop_1 for a label jumped back to, skip_1
+// for one only jumped forward to, exit_1 for one that halts
+// and unused_1 for one nothing goes to. Subroutines and loops
+// that look like a known idiom are named for it, like
+// func_print_number. A label map keeps the names given, by
+// the label's tokens, so disassembling a program again, or
+// an edited version of it, names its labels the same way
+// and the listings can be diffed.
+//
+// struct LabelMap <- The names given to labels, by their tokens
+//
+// disassemble(program: &[Action], labels: &SymbolTable, names: &HashMap<LabelId, String>) -> String
+// -- Write a parsed program as mnemonics
+//
+// name_labels(program: &[Action], labels: &SymbolTable, map: &mut LabelMap) -> HashMap<LabelId, String>
+// -- Name the labels of a program by how they're used
+//
+/***********************************************************/
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write;
+use idioms;
+use labels::{LabelId, SymbolTable};
+use symbols::Action;
+use symbols::Action::*;
+
+
+/********************************************/
+// Structures
+/********************************************/
+// The names given to labels, by their tokens as S and T, in the order
+// they were given. Names stay in the map once given, even for labels a
+// program no longer has, so they aren't handed to another.
+#[derive(Debug, Clone, Default)]
+pub struct LabelMap {
+	names: Vec<(String, String)>,
+}
+
+
+/********************************************/
+// Methods
+/********************************************/
+impl LabelMap {
+	// Read a map written by render, a label to a line, as its tokens after
+	// a % and then its name
+	pub fn parse(text: &str) -> Result<LabelMap, String> {
+		let mut map = LabelMap::default();
+		for (number, line) in text.lines().enumerate() {
+			let fields: Vec<&str> = line.split_whitespace().collect();
+			let entry = match fields[..] {
+				[]             => continue,
+				[label, name] => label.strip_prefix('%').filter(|spelling| spelling.chars().all(|token| token == 'S' || token == 'T')).map(|spelling| (spelling, name)),
+				_              => None,
+			};
+			match entry {
+				Some((spelling, name)) if readable(name) => map.names.push((spelling.to_string(), name.to_string())),
+				Some((_, name))                           => return Err(format!("line {}: {} can't be read back as a label name", number + 1, name)),
+				None                                      => return Err(format!("line {}: expected a label's tokens after a % and its name", number + 1)),
+			}
+		}
+		Ok(map)
+	}
+
+	// Write the map out, a label to a line
+	pub fn render(&self) -> String {
+		self.names.iter().map(|(spelling, name)| format!("%{} {}\n", spelling, name)).collect()
+	}
+
+	// The name given to the label with some tokens
+	fn get(&self, spelling: &str) -> Option<&str> {
+		self.names.iter().find(|entry| entry.0 == spelling).map(|entry| entry.1.as_str())
+	}
+}
+
+
+/********************************************/
+// Public Functions
+/********************************************/
+// Write a parsed program as mnemonics, an instruction to a line, with
+// each label's definition at the start of its own. Labels are written
+// with the names given, if any.
+pub fn disassemble(program: &[Action], labels: &SymbolTable, names: &HashMap<LabelId, String>) -> String {
+	let name = |label: LabelId| names.get(&label).cloned().unwrap_or_else(|| name(labels, label));
+	let mut text = String::new();
+	for action in program {
+		let _ = match *action {
+			Label(label)     => writeln!(text, "{}:", name(label)),
+			StackPush(value) if (32..127).contains(&value) => writeln!(text, "    {:<24} ; {:?}", format!("push {}", value), value as u8 as char),
+			StackPush(value) => writeln!(text, "    push {}", value),
+			Call(label) | Jump(label) | JumpIfZero(label) | JumpIfNegative(label) => writeln!(text, "    {} {}", mnemonic(action), name(label)),
+			_ => writeln!(text, "    {}", mnemonic(action)),
+		};
+	}
+	text
+}
+
+
+// Name the labels of a program by how they're used, taking the names
+// the map already gives and adding the ones given now. Labels that spell
+// a name keep it.
+pub fn name_labels(program: &[Action], labels: &SymbolTable, map: &mut LabelMap) -> HashMap<LabelId, String> {
+	// Every label the program has, in the order they first appear
+	let mut order = Vec::new();
+	for action in program {
+		if let Label(label) | Call(label) | Jump(label) | JumpIfZero(label) | JumpIfNegative(label) = *action {
+			if !order.contains(&label) {
+				order.push(label);
+			}
+		}
+	}
+
+	// The map's names come first, then the names labels spell
+	let mut names = HashMap::new();
+	let mut taken = map.names.iter().map(|entry| entry.1.clone()).collect::<HashSet<String>>();
+	for &label in &order {
+		if let Some(name) = map.get(&spelling(labels, label)) {
+			names.insert(label, name.to_string());
+		}
+	}
+	for &label in &order {
+		if let Some(text) = labels.text(label).filter(|text| readable(text) && !names.contains_key(&label) && !taken.contains(text)) {
+			taken.insert(text.clone());
+			names.insert(label, text);
+		}
+	}
+
+	let idioms = idioms::find(program);
+	let mut counts = HashMap::new();
+	for &label in &order {
+		if names.contains_key(&label) {
+			continue;
+		}
+
+		let defined = program.iter().position(|action| matches!(*action, Label(target) if target == label));
+		let idiom   = defined.and_then(|start| idioms.iter().find(|idiom| idiom.start == start)).map(|idiom| idiom.name);
+		let called  = program.iter().any(|action| matches!(*action, Call(target) if target == label));
+		let back    = defined.is_some_and(|start| program[start..].iter().any(|action| matches!(*action, Jump(target) | JumpIfZero(target) | JumpIfNegative(target) if target == label)));
+		let jumped  = program.iter().any(|action| matches!(*action, Jump(target) | JumpIfZero(target) | JumpIfNegative(target) if target == label));
+		let halts   = defined.is_some_and(|start| matches!(program[start..].iter().find(|action| !matches!(action, Label(_))), Some(Halt)));
+
+		let kind = match (called, back) {
+			_ if defined.is_none() => "missing",
+			(true, _)              => "func",
+			(_, true)              => "loop",
+			_ if halts             => "exit",
+			_ if jumped            => "skip",
+			_                      => "unused",
+		};
+
+		// Idioms give a name of their own, numbered from the second time
+		let name = match idiom.filter(|_| kind == "func" || kind == "loop") {
+			Some(idiom) => {
+				let base = format!("{}_{}", kind, idiom);
+				(1..).map(|count| if count == 1 {base.clone()} else {format!("{}_{}", base, count)}).find(|name| !taken.contains(name)).unwrap()
+			},
+			None => {
+				let count = counts.entry(kind).or_insert(0);
+				loop {
+					*count += 1;
+					let name = format!("{}_{}", kind, count);
+					if !taken.contains(&name) {
+						break name;
+					}
+				}
+			},
+		};
+
+		taken.insert(name.clone());
+		map.names.push((spelling(labels, label), name.clone()));
+		names.insert(label, name);
+	}
+
+	names
+}
+
+
+/********************************************/
+// Private Functions
+/********************************************/
+// The short mnemonic the assembler reads an action by
+fn mnemonic(action: &Action) -> &'static str {
+	match *action {
+		StackPush(_)      => "push",
+		StackDuplicate    => "dup",
+		StackSwap         => "swap",
+		StackDiscard      => "drop",
+		Add               => "add",
+		Subtract          => "sub",
+		Multiply          => "mul",
+		Divide            => "div",
+		Modulo            => "mod",
+		HeapStore         => "store",
+		HeapRetrieve      => "retrieve",
+		Label(_)          => "label",
+		Call(_)           => "call",
+		Jump(_)           => "jump",
+		JumpIfZero(_)     => "jz",
+		JumpIfNegative(_) => "jn",
+		EndSubroutine     => "ret",
+		Halt              => "halt",
+		OutputChar        => "outc",
+		OutputNumber      => "outn",
+		ReadChar          => "readc",
+		ReadNumber        => "readn",
+		GetEnv            => "getenv",
+		DumpHeap          => "dumpheap",
+		Assert            => "assert",
+		CallDepth         => "calldepth",
+		PeekReturn        => "peekreturn",
+		OutputFormatted   => "outf",
+		Clock             => "clock",
+		Random            => "random",
+	}
+}
+
+
+// The name a label is written with: the text it spells, unless the
+// assembler would read that as something else, or its tokens after a %
+fn name(labels: &SymbolTable, label: LabelId) -> String {
+	match labels.text(label).filter(|text| readable(text)) {
+		Some(text) => text,
+		None       => format!("%{}", spelling(labels, label)),
+	}
+}
+
+
+// A label's tokens as S and T. Labels the parser never saw are spelled
+// the way the emitter writes their ids.
+fn spelling(labels: &SymbolTable, label: LabelId) -> String {
+	match labels.spelling(label) {
+		Some(spelling) => spelling.to_string(),
+		None           => format!("{:b}", label.0)[1..].chars().map(|bit| if bit == '1' {'S'} else {'T'}).collect(),
+	}
+}
+
+
+// Whether the assembler reads a name back as the label it names
+fn readable(name: &str) -> bool {
+	!name.contains(char::is_whitespace) && !name.contains(';') && !name.starts_with('%')
+}
@@ -0,0 +1,188 @@
+/***********************************************************/
+//
+// Emitter
+// =======
+// Writes parsed programs back out as whitespace, the inverse
+// of the parser, so transformations of a program can be
+// saved and run like any other.
+//
+// Programs can also be written out for reading and review,
+// with a comment naming each instruction just before its
+// tokens. Comments can't hold whitespace, so each is a name
+// and operand like StackPush(42) or Call(3). Line feeds are
+// tokens, so lines can only end at the instructions' own:
+// most instructions start a line, and those ending in one,
+// like pushes, labels and jumps, have it to themselves.
+// Diffs of two versions then name the instructions that
+// changed.
+//
+// emit(program: &[Action]) -> String
+// -- Write a parsed program as whitespace
+//
+// pretty(program: &[Action], comments: bool) -> String
+// -- Write a parsed program as whitespace laid out for reading
+//
+// named(program: &[Action], labels: &SymbolTable, comments: bool) -> String
+// -- Write a program laid out for reading, with its labels as they're spelled
+//
+// shorten_labels(program: &[Action]) -> Vec<Action>
+// -- Rename the labels to the shortest encodings available
+//
+/***********************************************************/
+use std::collections::HashMap;
+use labels::{LabelId, SymbolTable};
+use passes;
+use symbols::Action;
+use symbols::Action::*;
+
+
+/********************************************/
+// Public Functions
+/********************************************/
+// Write a parsed program as whitespace
+pub fn emit(program: &[Action]) -> String {
+	let mut text = String::new();
+	for action in program {
+		tokens(&mut text, action, &label_bits);
+	}
+	text
+}
+
+
+// Write a parsed program as whitespace with a comment naming each
+// instruction, unless comments are left out. Labels are written from
+// their ids, so they keep the names the comments give them.
+pub fn pretty(program: &[Action], comments: bool) -> String {
+	laid_out(program, comments, &label_bits, &|label| label.0.to_string())
+}
+
+
+// Write a program laid out for reading, with each label written the way
+// the table spells it, and named in the comments by the text it spells
+// when it spells one that has no whitespace, since comments can't.
+// Labels the table doesn't have are written from their ids.
+pub fn named(program: &[Action], labels: &SymbolTable, comments: bool) -> String {
+	let bits = |label: LabelId| match labels.spelling(label) {
+		Some(spelling) => spelling.chars().map(|token| if token == 'T' {'\t'} else {' '}).chain(Some('\n')).collect(),
+		None           => label_bits(label),
+	};
+	let name = |label: LabelId| labels.text(label).filter(|text| !text.contains(' ')).unwrap_or_else(|| labels.name(label));
+	laid_out(program, comments, &bits, &name)
+}
+
+
+// Rename the labels so the ones referred to most often get the shortest
+// encodings. Number literals are already written as short as they can be.
+pub fn shorten_labels(program: &[Action]) -> Vec<Action> {
+	let mut uses: HashMap<LabelId, usize> = HashMap::new();
+	let mut order = Vec::new();
+
+	for action in program {
+		if let Label(label) | Call(label) | Jump(label) | JumpIfZero(label) | JumpIfNegative(label) = *action {
+			if !uses.contains_key(&label) {
+				order.push(label);
+			}
+			*uses.entry(label).or_insert(0) += 1;
+		}
+	}
+
+	// Label 1 is the empty encoding, 2 and 3 take a token, 4 to 7 take two,
+	// and so on, so handing out names in order hands out the shortest first.
+	// Ties keep the order the labels first appear in.
+	order.sort_by_key(|label| std::cmp::Reverse(uses[label]));
+	let names: HashMap<LabelId, LabelId> = order.into_iter().zip((1..).map(LabelId)).collect();
+	passes::map_labels(program, &|label| names[&label])
+}
+
+
+/********************************************/
+// Private Functions
+/********************************************/
+// Write a program with each instruction starting a line, and a comment
+// naming it unless comments are left out. An instruction starting with a
+// line feed gets its comment after it, at the start of the line the rest
+// of its tokens are on.
+fn laid_out(program: &[Action], comments: bool, bits: &dyn Fn(LabelId) -> String, name: &dyn Fn(LabelId) -> String) -> String {
+	let mut text    = String::new();
+	let mut written = String::new();
+	for action in program {
+		written.clear();
+		tokens(&mut written, action, bits);
+
+		let start = if written.starts_with('\n') {1} else {0};
+		text.push_str(&written[..start]);
+		if comments {
+			text.push_str(&comment(action, name));
+		}
+		text.push_str(&written[start..]);
+	}
+	text
+}
+
+
+// Write the tokens of an action, with its label encoded by bits
+fn tokens(text: &mut String, action: &Action, bits: &dyn Fn(LabelId) -> String) {
+	match *action {
+		StackPush(value)      => {text.push_str("  "); text.push_str(&number(value));},
+		StackDuplicate        => text.push_str(" \n "),
+		StackSwap             => text.push_str(" \n\t"),
+		StackDiscard          => text.push_str(" \n\n"),
+		Add                   => text.push_str("\t   "),
+		Subtract              => text.push_str("\t  \t"),
+		Multiply              => text.push_str("\t  \n"),
+		Divide                => text.push_str("\t \t "),
+		Modulo                => text.push_str("\t \t\t"),
+		HeapStore             => text.push_str("\t\t "),
+		HeapRetrieve          => text.push_str("\t\t\t"),
+		Label(label)          => {text.push_str("\n  ");   text.push_str(&bits(label));},
+		Call(label)           => {text.push_str("\n \t");  text.push_str(&bits(label));},
+		Jump(label)           => {text.push_str("\n \n");  text.push_str(&bits(label));},
+		JumpIfZero(label)     => {text.push_str("\n\t ");  text.push_str(&bits(label));},
+		JumpIfNegative(label) => {text.push_str("\n\t\t"); text.push_str(&bits(label));},
+		EndSubroutine         => text.push_str("\n\t\n"),
+		Halt                  => text.push_str("\n\n\n"),
+		OutputChar            => text.push_str("\t\n  "),
+		OutputNumber          => text.push_str("\t\n \t"),
+		ReadChar              => text.push_str("\t\n\t "),
+		ReadNumber            => text.push_str("\t\n\t\t"),
+		GetEnv                => {text.push_str("\n\n "); text.push_str(&number(1));},
+		DumpHeap              => {text.push_str("\n\n "); text.push_str(&number(2));},
+		Assert                => {text.push_str("\n\n "); text.push_str(&number(3));},
+		CallDepth             => {text.push_str("\n\n "); text.push_str(&number(4));},
+		PeekReturn            => {text.push_str("\n\n "); text.push_str(&number(5));},
+		OutputFormatted       => {text.push_str("\n\n "); text.push_str(&number(6));},
+		Clock                 => {text.push_str("\n\n "); text.push_str(&number(7));},
+		Random                => {text.push_str("\n\n "); text.push_str(&number(8));},
+	}
+}
+
+
+// Name an action and its operand in a comment, which can't hold whitespace
+fn comment(action: &Action, name: &dyn Fn(LabelId) -> String) -> String {
+	match *action {
+		StackPush(value) => format!("{}({})", action.name(), value),
+		Label(label) | Call(label) | Jump(label) | JumpIfZero(label) | JumpIfNegative(label) => format!("{}({})", action.name(), name(label)),
+		_ => action.name().to_string(),
+	}
+}
+
+
+// Encode a number literal: its sign, then its binary digits from the
+// most significant, then a line feed. Zero gets a single digit, since
+// a literal without digits is ambiguous between interpreters.
+fn number(value: i64) -> String {
+	let sign      = if value < 0 {'\t'} else {' '};
+	let magnitude = value.unsigned_abs();
+	let digits    = format!("{:b}", magnitude).chars().map(|digit| if digit == '1' {'\t'} else {' '}).collect::<String>();
+	format!("{}{}\n", sign, digits)
+}
+
+
+// Encode a label from its id: the bits after the leading one, with a
+// space for each one and a tab for each zero, so every id gets its own
+// encoding and id 1 gets the empty one
+fn label_bits(label: LabelId) -> String {
+	let bits = format!("{:b}", label.0);
+	let bits = bits[1..].chars().map(|bit| if bit == '1' {' '} else {'\t'}).collect::<String>();
+	format!("{}\n", bits)
+}
@@ -0,0 +1,217 @@
+/***********************************************************/
+//
+// Errors
+// ======
+// The errors that can occur while loading, parsing,
+// resolving, or executing a whitespace program.
+//
+// enum WhitespaceError <- Any error produced by the interpreter
+//
+// struct ParseError <- A malformed instruction and where it was found
+//
+// struct UnresolvedLabel <- A call or jump to a label that doesn't exist
+//
+// struct RuntimeError <- A failure while the program was executing
+//
+// enum Fault <- What kind of failure stopped a program
+//
+// struct LimitError <- A program stopped for going past one of its limits,
+//                      or cancelled
+//
+/***********************************************************/
+use std::error;
+use std::fmt;
+use std::io;
+use std::time::Duration;
+use labels::LabelId;
+use symbols::Action;
+
+
+/********************************************/
+// Structures
+/********************************************/
+// Any error produced by the interpreter
+#[derive(Debug)]
+pub enum WhitespaceError {
+	Io(io::Error),                 // Reading the program or performing its I/O failed
+	Parse(ParseError),             // The program isn't valid whitespace
+	Resolve(Vec<UnresolvedLabel>), // Calls or jumps refer to labels that don't exist
+	Runtime(RuntimeError),         // The program failed while executing
+	Limit(LimitError),             // The program was stopped for going past a limit
+}
+
+
+// A malformed instruction. Whitespace files are mostly invisible, so the
+// location is given in whitespace tokens as well as lines and columns.
+#[derive(Debug, Clone)]
+pub struct ParseError {
+	pub message:  String, // What went wrong
+	pub token:    usize,  // Index of the offending token among all the tokens in the program
+	pub position: usize,  // Index of the offending token within its instruction
+	pub offset:   usize,  // Byte offset of the offending token in the source, or of the end
+	pub line:     usize,  // Line of the offending token, counting from one
+	pub column:   usize,  // Column of the offending token in characters, counting from one
+}
+
+
+// A reference to a label that doesn't exist
+#[derive(Debug, Clone)]
+pub struct UnresolvedLabel {
+	pub label:  LabelId, // The missing label
+	pub name:   String,  // The name the missing label is shown with, from the table it was interned in
+	pub index:  usize,   // Index of the referring action in the parsed program
+	pub action: Action,  // The referring action
+}
+
+
+// A failure while executing a program
+#[derive(Debug, Clone)]
+pub struct RuntimeError {
+	pub fault:           Fault,  // What kind of failure it was
+	pub message:         String, // What went wrong
+	pub program_pointer: usize,  // Index of the action that failed
+}
+
+
+// What kind of failure stopped a program, so code embedding the vm can
+// handle some differently from others without reading the message
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Fault {
+	StackUnderflow,    // An action needed more values than the stack held
+	DivisionByZero,    // Divide or Modulo by zero
+	Overflow,          // Arithmetic, or a heap address, went past what 64 bits hold
+	UnknownLabel,      // A call or jump to a label that doesn't exist, when labels are resolved lazily
+	UnsetHeap,         // A heap address was read before anything was stored there
+	ReturnWithoutCall, // EndSubroutine outside every subroutine
+	EndOfProgram,      // Execution ran off the end of the program
+	EndOfInput,        // A read found the end of the input
+	InvalidInput,      // ReadNumber read a line that isn't a number
+	InvalidOperand,    // An action was given a value it can't use, like a base of 40 or a character that doesn't exist
+	AssertionFailed,   // Assert's values weren't equal
+	Denied,            // The run depended on something it isn't allowed to, as with --deterministic
+	Hook,              // A hook stopped the program
+}
+
+
+// A program going past one of its limits
+#[derive(Debug, Copy, Clone)]
+pub struct LimitError {
+	pub limit:           Limit, // The limit that was reached
+	pub program_pointer: usize, // Index of the action that was about to execute
+}
+
+
+// One of the limits a program runs under
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Limit {
+	Steps(u64),     // The most actions to execute
+	Time(Duration), // The longest to run
+	Memory(usize),  // The most values to hold at once
+	Cancelled,      // The run's cancellation token, which only applications embedding the vm hold
+}
+
+
+impl WhitespaceError {
+	// The status the command line exits with when it stops because of this error
+	pub fn exit_code(&self) -> i32 {
+		match *self {
+			WhitespaceError::Io(_)                                          => 3,
+			WhitespaceError::Parse(_)                                       => 4,
+			WhitespaceError::Resolve(_)                                     => 5,
+			WhitespaceError::Runtime(_)                                     => 6,
+			WhitespaceError::Limit(LimitError {limit: Limit::Steps(_), ..}) => 7,
+			WhitespaceError::Limit(LimitError {limit: Limit::Time(_), ..})  => 8,
+			WhitespaceError::Limit(LimitError {limit: Limit::Memory(_), ..}) => 9,
+			WhitespaceError::Limit(LimitError {limit: Limit::Cancelled, ..}) => 10,
+		}
+	}
+}
+
+
+/********************************************/
+// Trait Implementations
+/********************************************/
+impl fmt::Display for WhitespaceError {
+	fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+		match *self {
+			WhitespaceError::Io(ref error)       => write!(formatter, "I/O Error: {}", error),
+			WhitespaceError::Parse(ref error)    => write!(formatter, "Parse Error: {}", error),
+			WhitespaceError::Resolve(ref labels) => {
+				write!(formatter, "Resolve Error: {} reference(s) to undefined labels", labels.len())?;
+				for label in labels {
+					write!(formatter, "\n    {}", label)?;
+				}
+				Ok(())
+			},
+			WhitespaceError::Runtime(ref error)  => write!(formatter, "Runtime Error: {}", error),
+			WhitespaceError::Limit(ref error)    => write!(formatter, "Limit Error: {}", error),
+		}
+	}
+}
+
+impl fmt::Display for ParseError {
+	fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+		write!(formatter, "{} at line {}, column {} (token {}, token {} of the instruction)", self.message, self.line, self.column, self.token, self.position)
+	}
+}
+
+impl fmt::Display for UnresolvedLabel {
+	fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+		write!(formatter, "label {} referenced by {} at action {}", self.name, self.action.name(), self.index)
+	}
+}
+
+impl fmt::Display for RuntimeError {
+	fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+		write!(formatter, "{} (at action {})", self.message, self.program_pointer)
+	}
+}
+
+impl fmt::Display for LimitError {
+	fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+		match self.limit {
+			Limit::Steps(steps) => write!(formatter, "Reached the limit of {} steps", steps)?,
+			Limit::Time(time)   => write!(formatter, "Ran past the time limit of {:.3}s", time.as_secs_f64())?,
+			Limit::Memory(most) => write!(formatter, "Held more than the limit of {} values", most)?,
+			Limit::Cancelled    => write!(formatter, "The run was cancelled")?,
+		}
+		write!(formatter, " (at action {})", self.program_pointer)
+	}
+}
+
+impl error::Error for WhitespaceError {
+	fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+		match *self {
+			WhitespaceError::Io(ref error) => Some(error),
+			_                              => None,
+		}
+	}
+}
+
+impl error::Error for ParseError {}
+impl error::Error for RuntimeError {}
+impl error::Error for LimitError {}
+
+impl From<io::Error> for WhitespaceError {
+	fn from(error: io::Error) -> WhitespaceError {
+		WhitespaceError::Io(error)
+	}
+}
+
+impl From<ParseError> for WhitespaceError {
+	fn from(error: ParseError) -> WhitespaceError {
+		WhitespaceError::Parse(error)
+	}
+}
+
+impl From<RuntimeError> for WhitespaceError {
+	fn from(error: RuntimeError) -> WhitespaceError {
+		WhitespaceError::Runtime(error)
+	}
+}
+
+impl From<LimitError> for WhitespaceError {
+	fn from(error: LimitError) -> WhitespaceError {
+		WhitespaceError::Limit(error)
+	}
+}
@@ -0,0 +1,133 @@
+/***********************************************************/
+//
+// Explanations
+// ============
+// Describes each instruction of a program in plain English,
+// for the explain command, for people learning the language
+// or reviewing a program in it. Each instruction is listed
+// with what it does and its stack effect, like
+// `pops b, a; pushes a-b`, naming the values it pops from
+// the top down. Calls and jumps can be listed with the
+// instruction their label resolves to as well.
+//
+// explain(program: &[Action], labels: &SymbolTable, options: &Options, targets: bool) -> String
+// -- List every instruction of a program with what it does
+//
+/***********************************************************/
+use std::collections::HashMap;
+use std::fmt::Write;
+use labels::SymbolTable;
+use options::{Options, ReadCharDestination};
+use symbols::Action;
+use symbols::Action::*;
+
+
+/********************************************/
+// Public Functions
+/********************************************/
+// List every instruction of a program with its index, its stack effect,
+// and what it does, with where each call and jump goes when there are
+// targets
+pub fn explain(program: &[Action], labels: &SymbolTable, options: &Options, targets: bool) -> String {
+	// Like resolving them, the last definition of a label is the one jumped to
+	let mut defined = HashMap::new();
+	for (index, action) in program.iter().enumerate() {
+		if let Label(label) = *action {
+			defined.insert(label, index);
+		}
+	}
+
+	let mut text = String::new();
+	let _ = writeln!(text, "{:>6}  {:<24} {:<24} description", "index", "instruction", "effect");
+	for (index, action) in program.iter().enumerate() {
+		let instruction = match *action {
+			StackPush(value) => format!("{} {}", action.name(), value),
+			Label(label) | Call(label) | Jump(label) | JumpIfZero(label) | JumpIfNegative(label) => format!("{} {}", action.name(), labels.name(label)),
+			_ => action.name().to_string(),
+		};
+		let _ = write!(text, "{:>6}  {:<24} {:<24} {}", index, instruction, effect(action, options), describe(action));
+
+		match *action {
+			Call(label) | Jump(label) | JumpIfZero(label) | JumpIfNegative(label) if targets => match defined.get(&label) {
+				Some(target) => {let _ = write!(text, " (goes to {})", target);},
+				None         => text.push_str(" (the label is never defined)"),
+			},
+			_ => {},
+		}
+		text.push('\n');
+	}
+	text
+}
+
+
+/********************************************/
+// Private Functions
+/********************************************/
+// What an action pops and pushes, naming what it pops from the top of
+// the stack down
+fn effect(action: &Action, options: &Options) -> String {
+	let effect = match *action {
+		StackPush(value)                  => return format!("pushes {}", value),
+		StackDuplicate                    => "pops a; pushes a, a",
+		StackSwap                         => "pops b, a; pushes b, a",
+		StackDiscard                      => "pops a",
+		Add                               => "pops b, a; pushes a+b",
+		Subtract                          => "pops b, a; pushes a-b",
+		Multiply                          => "pops b, a; pushes a*b",
+		Divide                            => "pops b, a; pushes a/b",
+		Modulo                            => "pops b, a; pushes a%b",
+		HeapStore                         => "pops v, a",
+		HeapRetrieve                      => "pops a; pushes heap[a]",
+		JumpIfZero(_) | JumpIfNegative(_) => "pops a",
+		OutputChar | OutputNumber         => "pops a",
+		ReadChar if options.read_char == ReadCharDestination::Stack => "pushes a character",
+		ReadChar | ReadNumber             => "pops a",
+		GetEnv                            => "pops a; pushes its length",
+		Assert                            => "pops e, a",
+		CallDepth                         => "pushes the depth",
+		PeekReturn                        => "pops d; pushes a pointer",
+		OutputFormatted                   => "pops w, b, n",
+		Clock                             => "pushes the time",
+		Random                            => "pops b; pushes a number",
+		Label(_) | Call(_) | Jump(_)      => "none",
+		DumpHeap | EndSubroutine | Halt   => "none",
+	};
+	effect.to_string()
+}
+
+
+// What an action does, in the names its stack effect uses
+fn describe(action: &Action) -> &'static str {
+	match *action {
+		StackPush(_)      => "Put a number on the stack",
+		StackDuplicate    => "Copy the top value",
+		StackSwap         => "Swap the top two values",
+		StackDiscard      => "Throw the top value away",
+		Add               => "Add the top two values",
+		Subtract          => "Subtract the top value from the one under it",
+		Multiply          => "Multiply the top two values",
+		Divide            => "Divide the value under the top by the top one",
+		Modulo            => "Take the remainder of dividing the value under the top by the top one",
+		HeapStore         => "Store v in the heap at address a",
+		HeapRetrieve      => "Fetch the value in the heap at address a",
+		Label(_)          => "Mark this place for calls and jumps to go to",
+		Call(_)           => "Call the subroutine at the label, coming back here when it ends",
+		Jump(_)           => "Go to the label",
+		JumpIfZero(_)     => "Go to the label if a is zero",
+		JumpIfNegative(_) => "Go to the label if a is negative",
+		EndSubroutine     => "Return to just after the call that started this subroutine",
+		Halt              => "Stop the program",
+		OutputChar        => "Write a as a character",
+		OutputNumber      => "Write a as a number",
+		ReadChar          => "Read a character, into the heap at address a unless it goes on the stack",
+		ReadNumber        => "Read a line as a number into the heap at address a",
+		GetEnv            => "Read the environment variable named in the heap at address a over its name",
+		DumpHeap          => "Write the heap out to stderr",
+		Assert            => "Fail unless a equals the expected value e",
+		CallDepth         => "Count the subroutines being executed",
+		PeekReturn        => "Find where the subroutine d calls down returns to, or -1",
+		OutputFormatted   => "Write n in base b, padded to width w",
+		Clock             => "Read the milliseconds since the Unix epoch, or the virtual clock",
+		Random            => "Draw a random number from 0 up to b",
+	}
+}
@@ -0,0 +1,141 @@
+/***********************************************************/
+//
+// Exploration
+// ===========
+// Looks for the ways a program can fail, for the explore
+// command, by running it on every sequence of inputs drawn
+// from a small alphabet, up to a number of reads. Whenever a
+// run stops at a read it's forked, once for each line of the
+// alphabet and once for the end of the input, so what the
+// runs have in common is only executed once. Shorter
+// sequences are tried first, so the input given for each
+// error is one of the shortest that reach it.
+//
+// Every run stops at a step limit, so a program that loops
+// forever on some input doesn't hold up the rest.
+//
+// struct Exploration <- What running a program on every input found
+//
+// explore(program: Vec<Action>, options: Options, io_options: IoOptions, limits: Limits, alphabet: &[String], depth: usize) -> Exploration
+// -- Run a program on every sequence of inputs up to a depth
+//
+// render(exploration: &Exploration) -> String
+// -- Describe what exploring a program found
+//
+/***********************************************************/
+use std::collections::VecDeque;
+use std::fmt::Write;
+use std::io;
+use error::WhitespaceError;
+use options::{IoOptions, Limits, Options};
+use streams::PendingInput;
+use symbols::Action;
+use virtual_machine::WhitespaceVM;
+
+
+/********************************************/
+// Structures
+/********************************************/
+// What running a program on every input found
+#[derive(Debug, Clone, Default)]
+pub struct Exploration {
+	pub alphabet: usize,        // The number of inputs tried at each read, counting the end of the input
+	pub depth:    usize,
+	pub halted:   u64,          // Runs that halted
+	pub reading:  u64,          // Runs still reading when they'd been given depth inputs
+	pub limited:  u64,          // Runs stopped by the limits
+	pub failures: Vec<Failure>, // Each way the program failed, in the order found
+}
+
+// A way the program failed, and the input that first made it
+#[derive(Debug, Clone)]
+pub struct Failure {
+	pub error: String,
+	pub input: Vec<Choice>,
+	pub runs:  u64, // How many runs failed this way
+}
+
+// What a run was given at a read
+#[derive(Debug, Clone, PartialEq)]
+pub enum Choice {
+	Line(String), // A line of input
+	End,          // The end of the input
+}
+
+// The steps each run gets when no limit is given
+const STEPS: u64 = 100_000;
+
+
+/********************************************/
+// Public Functions
+/********************************************/
+// Run a resolved program on every sequence of inputs from the alphabet,
+// each a line, up to depth of them
+pub fn explore(program: Vec<Action>, options: Options, io_options: IoOptions, limits: Limits, alphabet: &[String], depth: usize) -> Exploration {
+	let choices: Vec<Choice> = alphabet.iter().map(|line| Choice::Line(line.clone())).chain(Some(Choice::End)).collect();
+	let mut exploration = Exploration {alphabet: choices.len(), depth, ..Exploration::default()};
+
+	let mut vm = WhitespaceVM::with_io(program, options, Box::new(PendingInput::default()), Box::new(io::sink()));
+	vm.set_io_options(io_options);
+	vm.set_limits(Limits {steps: Some(limits.steps.unwrap_or(STEPS)), ..limits});
+
+	// Run each sequence as far as its next read, then branch on what it reads
+	let mut runs = VecDeque::from(vec![(vm, Vec::new())]);
+	while let Some((mut vm, input)) = runs.pop_front() {
+		match vm.execute() {
+			Ok(())                         => exploration.halted  += 1,
+			Err(WhitespaceError::Limit(_)) => exploration.limited += 1,
+			Err(WhitespaceError::Io(ref error)) if error.kind() == io::ErrorKind::WouldBlock => {
+				if input.len() == depth {
+					exploration.reading += 1;
+					continue;
+				}
+
+				for choice in &choices {
+					let stream = PendingInput::default();
+					match *choice {
+						Choice::Line(ref line) => stream.feed(format!("{}\n", line).as_bytes()),
+						Choice::End            => stream.close(),
+					}
+
+					let mut given = input.clone();
+					given.push(choice.clone());
+					runs.push_back((vm.fork(Box::new(stream), Box::new(io::sink())), given));
+				}
+			},
+			Err(error) => {
+				let error = error.to_string();
+				match exploration.failures.iter_mut().find(|failure| failure.error == error) {
+					Some(failure) => failure.runs += 1,
+					None          => exploration.failures.push(Failure {error, input, runs: 1}),
+				}
+			},
+		}
+	}
+
+	exploration
+}
+
+
+// Describe what exploring a program found
+pub fn render(exploration: &Exploration) -> String {
+	let runs = exploration.halted + exploration.reading + exploration.limited + exploration.failures.iter().map(|failure| failure.runs).sum::<u64>();
+	let mut text = format!(
+		"Explored {} run(s), trying {} inputs at each of up to {} reads: {} halted, {} still reading, {} stopped by the limits, {} failed\n",
+		runs, exploration.alphabet, exploration.depth, exploration.halted, exploration.reading, exploration.limited, runs - exploration.halted - exploration.reading - exploration.limited,
+	);
+
+	for failure in &exploration.failures {
+		let input = match failure.input.len() {
+			0 => "no input".to_string(),
+			_ => failure.input.iter().map(|choice| match *choice {
+				Choice::Line(ref line) => format!("{:?}", line),
+				Choice::End            => "the end of the input".to_string(),
+			}).collect::<Vec<String>>().join(", "),
+		};
+
+		let times = if failure.runs == 1 {"1 run".to_string()} else {format!("{} runs", failure.runs)};
+		let _ = writeln!(text, "\nError: {}\n    {} failed this way, first given {}", failure.error, times, input);
+	}
+	text
+}
@@ -0,0 +1,258 @@
+/***********************************************************/
+//
+// Fuzzing
+// =======
+// Looks for the ways a program can fail on realistic input,
+// for the fuzz command, by running it on many inputs made up
+// from a small spec. Where explore tries every sequence of a
+// few chosen lines, fuzzing draws longer inputs at random:
+// so many numbers from a range, lines of text from an
+// alphabet, and fixed lines, in the order the spec gives
+// them. A spec is a line per stretch of input:
+//
+//    # Lines starting with # are comments
+//    1 number 1..10                  - one number from 1 to 10
+//    0..20 number -1000..1000        - up to twenty numbers
+//    2 text 1..8 abcdefghijklmnop    - two lines of 1 to 8 letters
+//    1 line -1                       - the line -1
+//
+// Every run stops at a step limit, so a program that loops
+// forever on some input doesn't hold up the rest, and the
+// same seed draws the same inputs, so a failure can be
+// reproduced.
+//
+// struct Spec <- The inputs a spec makes up
+//
+// struct Fuzzing <- What running a program on the inputs found
+//
+// fuzz(program: Vec<Action>, options: Options, io_options: IoOptions, limits: Limits, spec: &Spec, runs: u64, seed: u64) -> Fuzzing
+// -- Run a program on inputs made up from a spec
+//
+// render(fuzzing: &Fuzzing) -> String
+// -- Describe what fuzzing a program found
+//
+/***********************************************************/
+use std::fmt::Write;
+use std::io;
+use std::io::Cursor;
+use error::WhitespaceError;
+use options::{IoOptions, Limits, Options};
+use symbols::Action;
+use virtual_machine::WhitespaceVM;
+
+
+/********************************************/
+// Structures
+/********************************************/
+// The inputs a spec makes up, a stretch of lines at a time
+#[derive(Debug, Clone, PartialEq)]
+pub struct Spec {
+	stretches: Vec<Stretch>,
+}
+
+// Some lines of input, and how many of them there are
+#[derive(Debug, Clone, PartialEq)]
+struct Stretch {
+	fewest: u64,
+	most:   u64,
+	line:   Line,
+}
+
+// How a line of input is made up
+#[derive(Debug, Clone, PartialEq)]
+enum Line {
+	Number(i64, i64),              // A number from the first to the second
+	Text(usize, usize, Vec<char>), // Between so many characters from an alphabet
+	Fixed(String),                 // Always the same
+}
+
+// What running a program on the inputs found
+#[derive(Debug, Clone, Default)]
+pub struct Fuzzing {
+	pub seed:     u64,
+	pub halted:   u64,          // Runs that halted
+	pub limited:  u64,          // Runs stopped by the limits
+	pub failures: Vec<Failure>, // Each way the program failed, in the order found
+}
+
+// A way the program failed, and the input that first made it
+#[derive(Debug, Clone)]
+pub struct Failure {
+	pub error: String,
+	pub input: Vec<String>,
+	pub runs:  u64, // How many runs failed this way
+}
+
+// A small xorshift generator. Fuzzing needs variety, not
+// statistical quality, and this keeps runs reproducible.
+struct Random {
+	state: u64,
+}
+
+impl Random {
+	// Constructor, the state must never be zero
+	fn new(seed: u64) -> Random {
+		Random {state: (seed ^ 0x9e37_79b9_7f4a_7c15) | 1}
+	}
+
+	// The next number in the sequence
+	fn next(&mut self) -> u64 {
+		self.state ^= self.state << 13;
+		self.state ^= self.state >> 7;
+		self.state ^= self.state << 17;
+		self.state
+	}
+
+	// A number from the first to the second, including both
+	fn between(&mut self, low: i128, high: i128) -> i128 {
+		low + (self.next() as u128 % (high - low + 1) as u128) as i128
+	}
+}
+
+// The steps each run gets when no limit is given
+const STEPS: u64 = 100_000;
+
+
+/********************************************/
+// Methods
+/********************************************/
+impl Spec {
+	// Read a spec, a stretch of input to a line
+	pub fn parse(text: &str) -> Result<Spec, String> {
+		let mut stretches = Vec::new();
+
+		for (number, line) in text.lines().enumerate() {
+			let line = line.trim();
+			if line.is_empty() || line.starts_with('#') {
+				continue;
+			}
+
+			match parse_stretch(line) {
+				Some(stretch) => stretches.push(stretch),
+				None          => return Err(format!("line {} of the spec isn't a count, then number <low>..<high>, text <shortest>..<longest> <alphabet> or line <text>", number + 1)),
+			}
+		}
+
+		Ok(Spec {stretches})
+	}
+
+	// Make up an input, a line at a time
+	fn generate(&self, random: &mut Random) -> Vec<String> {
+		let mut lines = Vec::new();
+		for stretch in &self.stretches {
+			let count = random.between(stretch.fewest as i128, stretch.most as i128);
+			for _ in 0..count {
+				lines.push(match stretch.line {
+					Line::Number(low, high)                     => random.between(low as i128, high as i128).to_string(),
+					Line::Text(shortest, longest, ref alphabet) => {
+						let length = random.between(shortest as i128, longest as i128);
+						(0..length).map(|_| alphabet[random.between(0, alphabet.len() as i128 - 1) as usize]).collect()
+					},
+					Line::Fixed(ref text)                       => text.clone(),
+				});
+			}
+		}
+		lines
+	}
+}
+
+
+/********************************************/
+// Public Functions
+/********************************************/
+// Run a resolved program on so many inputs made up from a spec, drawn
+// from a seed
+pub fn fuzz(program: Vec<Action>, options: Options, io_options: IoOptions, limits: Limits, spec: &Spec, runs: u64, seed: u64) -> Fuzzing {
+	let mut fuzzing = Fuzzing {seed, ..Fuzzing::default()};
+	let mut random  = Random::new(seed);
+
+	// Every run starts from a fork of the same vm, so the program is only loaded once
+	let mut vm = WhitespaceVM::with_io(program, options, Box::new(io::empty()), Box::new(io::sink()));
+	vm.set_io_options(io_options);
+	vm.set_limits(Limits {steps: Some(limits.steps.unwrap_or(STEPS)), ..limits});
+
+	for _ in 0..runs {
+		let input = spec.generate(&mut random);
+		let text: String = input.iter().map(|line| format!("{}\n", line)).collect();
+
+		match vm.fork(Box::new(Cursor::new(text.into_bytes())), Box::new(io::sink())).execute() {
+			Ok(())                         => fuzzing.halted  += 1,
+			Err(WhitespaceError::Limit(_)) => fuzzing.limited += 1,
+			Err(error) => {
+				let error = error.to_string();
+				match fuzzing.failures.iter_mut().find(|failure| failure.error == error) {
+					Some(failure) => failure.runs += 1,
+					None          => fuzzing.failures.push(Failure {error, input, runs: 1}),
+				}
+			},
+		}
+	}
+
+	fuzzing
+}
+
+
+// Describe what fuzzing a program found
+pub fn render(fuzzing: &Fuzzing) -> String {
+	let failed = fuzzing.failures.iter().map(|failure| failure.runs).sum::<u64>();
+	let mut text = format!(
+		"Fuzzed {} run(s) from seed {}: {} halted, {} stopped by the limits, {} failed\n",
+		fuzzing.halted + fuzzing.limited + failed, fuzzing.seed, fuzzing.halted, fuzzing.limited, failed,
+	);
+
+	for failure in &fuzzing.failures {
+		let input = match failure.input.len() {
+			0 => "no input".to_string(),
+			_ => failure.input.iter().map(|line| format!("{:?}", line)).collect::<Vec<String>>().join(", "),
+		};
+
+		let times = if failure.runs == 1 {"1 run".to_string()} else {format!("{} runs", failure.runs)};
+		let _ = writeln!(text, "\nError: {}\n    {} failed this way, first given {}", failure.error, times, input);
+	}
+	text
+}
+
+
+/********************************************/
+// Private Functions
+/********************************************/
+// Read a line of a spec: a count, then how each line is made up
+fn parse_stretch(line: &str) -> Option<Stretch> {
+	let mut words = line.splitn(3, ' ');
+	let (fewest, most) = range(words.next()?)?;
+	let kind = words.next()?;
+	let rest = words.next().unwrap_or("").trim();
+
+	let line = match kind {
+		"number" => {
+			let (low, high) = range(rest)?;
+			Line::Number(low, high)
+		},
+		"text" => {
+			let (lengths, alphabet) = rest.split_once(' ')?;
+			let (shortest, longest) = range(lengths)?;
+			let alphabet: Vec<char> = alphabet.trim().chars().collect();
+			if shortest < 0 || alphabet.is_empty() {
+				return None;
+			}
+			Line::Text(shortest as usize, longest as usize, alphabet)
+		},
+		"line" => Line::Fixed(rest.to_string()),
+		_      => return None,
+	};
+
+	if fewest < 0 {
+		return None;
+	}
+	Some(Stretch {fewest: fewest as u64, most: most as u64, line})
+}
+
+
+// A number, or a range of them written as low..high, including both
+fn range(text: &str) -> Option<(i64, i64)> {
+	let (low, high) = match text.split_once("..") {
+		Some((low, high)) => (low.parse().ok()?, high.parse().ok()?),
+		None              => {let number = text.parse().ok()?; (number, number)},
+	};
+	Some((low, high)).filter(|&(low, high)| low <= high)
+}
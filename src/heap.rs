@@ -0,0 +1,693 @@
+/***********************************************************/
+//
+// Heaps
+// =====
+// The storage behind the vm's heap. Programs that keep
+// arrays at small addresses are best served by a vector,
+// while ones that scatter values across the whole range
+// of addresses need a map, so the storage is pluggable.
+//
+// trait Heap <- Storage for heap values
+//
+// struct HashHeap <- Every value in a hash map (the default)
+//
+// struct DenseHeap <- Small non-negative addresses in a vector
+//
+// struct PagedHeap <- Values in fixed size pages allocated on demand
+//
+// struct SharedHeap <- A heap several vms can use at once, even from
+//                      different threads
+//
+// struct RegionHeap <- A heap that shares one range of addresses and
+//                      keeps the rest private
+//
+// struct ForkedHeap <- A heap that copies on write, so forks of a vm
+//                      share whatever neither has changed
+//
+// from_name(name: &str) -> Option<Box<dyn Heap + Send>>
+// -- Create an empty heap of the kind with the given name
+//
+// save(heap: &dyn Heap) -> String
+// -- Write out a heap's contents, one `address value` per line
+//
+// load(heap: &mut dyn Heap, text: &str) -> Result<(), String>
+// -- Store the values written out by save in a heap
+//
+/***********************************************************/
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::mem;
+use std::ops::Range;
+use std::rc::Rc;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+
+/********************************************/
+// Structures
+/********************************************/
+// Storage for heap values, mapping addresses to the values stored there
+pub trait Heap {
+	// Get the value stored at an address, if one has been
+	fn get(&self, address: i64) -> Option<i64>;
+
+	// Store a value at an address
+	fn set(&mut self, address: i64, value: i64);
+
+	// The number of addresses that hold a value
+	fn len(&self) -> usize;
+
+	// Whether no address holds a value
+	fn is_empty(&self) -> bool {
+		self.len() == 0
+	}
+
+	// Every address that holds a value along with the value, in address order
+	fn iter(&self) -> Box<dyn Iterator<Item = (i64, i64)> + '_>;
+
+	// A copy of the heap that shares its storage until either changes it,
+	// if the storage can be shared that way
+	fn fork(&mut self) -> Option<Box<dyn Heap>> {
+		None
+	}
+
+	// How many times the storage has allocated or grown since it was
+	// created, for storage that counts them
+	fn allocations(&self) -> u64 {
+		0
+	}
+
+	// Make room for a number of values more than the heap holds, for
+	// storage that can
+	fn reserve(&mut self, _additional: usize) {}
+
+	// Give back the room kept past a number of values, for storage that can
+	fn shrink_to(&mut self, _capacity: usize) {}
+
+	// Remove every value, keeping the room they took, returning false if
+	// the storage can't be cleared, like storage shared with other vms
+	fn clear(&mut self) -> bool {
+		false
+	}
+}
+
+
+// Keeps every value in a hash map, which suits any pattern of addresses
+#[derive(Debug, Default)]
+pub struct HashHeap {
+	values:      HashMap<i64, i64>,
+	allocations: u64,
+}
+
+
+// Keeps the values at addresses from 0 up to a limit in a vector that
+// grows to fit the highest one, and any others in a hash map
+#[derive(Debug, Default)]
+pub struct DenseHeap {
+	dense:       Vec<Option<i64>>,
+	sparse:      HashMap<i64, i64>,
+	occupied:    usize, // The number of cells of the vector holding a value
+	allocations: u64,
+}
+
+// The highest address the dense heap keeps in its vector
+const DENSE_LIMIT: i64 = 1 << 20;
+
+
+// Splits the address space into pages of PAGE_SIZE values, allocating a
+// page the first time a value is stored in it
+#[derive(Debug, Default)]
+pub struct PagedHeap {
+	pages:       HashMap<i64, Vec<Option<i64>>>,
+	len:         usize,
+	allocations: u64,
+}
+
+// The number of values in each page of a paged heap
+const PAGE_SIZE: i64 = 1024;
+
+
+// A heap that several vms can use at once, even from different threads.
+// Clones share the same storage, and each get or set happens atomically.
+#[derive(Clone)]
+pub struct SharedHeap {
+	storage: Arc<Mutex<Box<dyn Heap + Send>>>,
+}
+
+impl SharedHeap {
+	// Constructor, share a heap's storage
+	pub fn new(storage: Box<dyn Heap + Send>) -> SharedHeap {
+		SharedHeap {storage: Arc::new(Mutex::new(storage))}
+	}
+
+	// Lock the storage. A vm that panicked while holding the lock can't
+	// have left a single get or set half done, so poisoning is ignored.
+	fn lock(&self) -> MutexGuard<'_, Box<dyn Heap + Send>> {
+		self.storage.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+	}
+}
+
+
+// A heap that keeps the addresses in one region in a shared heap and
+// every other address in private storage
+pub struct RegionHeap {
+	private: Box<dyn Heap + Send>,
+	shared:  SharedHeap,
+	region:  Range<i64>,
+}
+
+impl RegionHeap {
+	// Constructor, share the addresses in a region and keep the rest private
+	pub fn new(private: Box<dyn Heap + Send>, shared: SharedHeap, region: Range<i64>) -> RegionHeap {
+		RegionHeap {private, shared, region}
+	}
+}
+
+
+
+// Keeps the values as layers of maps that never change once they're
+// shared, and a map of the values stored since. Forking freezes those
+// into a new layer that both heaps read through, so a fork costs as
+// much as the values stored since the last one rather than the whole
+// heap.
+#[derive(Debug, Default)]
+pub struct ForkedHeap {
+	layers:      Vec<Rc<HashMap<i64, i64>>>, // Values shared with other forks, oldest first
+	changes:     HashMap<i64, i64>,          // Values stored since the last fork
+	len:         usize,
+	allocations: u64,
+}
+
+// The most layers a forked heap reads through before they're merged
+const MAX_LAYERS: usize = 8;
+
+impl ForkedHeap {
+	// Constructor, copy the values of any heap
+	pub fn new(heap: &dyn Heap) -> ForkedHeap {
+		let changes: HashMap<i64, i64> = heap.iter().collect();
+		ForkedHeap {layers: Vec::new(), len: changes.len(), changes, allocations: 0}
+	}
+
+	// Freeze the values stored so far, and get a heap that shares them
+	pub fn split(&mut self) -> ForkedHeap {
+		if !self.changes.is_empty() {
+			self.layers.push(Rc::new(mem::take(&mut self.changes)));
+		}
+
+		// Reading through many layers is slow, so merge them now and then
+		if self.layers.len() > MAX_LAYERS {
+			self.allocations += 1;
+			let mut merged = HashMap::with_capacity(self.len);
+			for layer in &self.layers {
+				merged.extend(layer.iter().map(|(&address, &value)| (address, value)));
+			}
+			self.layers = vec![Rc::new(merged)];
+		}
+
+		ForkedHeap {layers: self.layers.clone(), changes: HashMap::new(), len: self.len, allocations: 0}
+	}
+}
+
+
+/********************************************/
+// Trait Implementations
+/********************************************/
+// Show the values in address order, whatever the storage
+impl fmt::Debug for dyn Heap {
+	fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+		write!(formatter, "{} values ", self.len())?;
+		formatter.debug_map().entries(self.iter()).finish()
+	}
+}
+
+impl fmt::Debug for SharedHeap {
+	fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+		formatter.debug_map().entries(self.iter()).finish()
+	}
+}
+
+impl Heap for HashHeap {
+	fn get(&self, address: i64) -> Option<i64> {
+		self.values.get(&address).cloned()
+	}
+
+	fn set(&mut self, address: i64, value: i64) {
+		self.allocations += grows(&self.values, address);
+		self.values.insert(address, value);
+	}
+
+	fn len(&self) -> usize {
+		self.values.len()
+	}
+
+	fn iter(&self) -> Box<dyn Iterator<Item = (i64, i64)> + '_> {
+		let mut values: Vec<(i64, i64)> = self.values.iter().map(|(&address, &value)| (address, value)).collect();
+		values.sort();
+		Box::new(values.into_iter())
+	}
+
+	fn allocations(&self) -> u64 {
+		self.allocations
+	}
+
+	fn reserve(&mut self, additional: usize) {
+		self.values.reserve(additional);
+	}
+
+	fn shrink_to(&mut self, capacity: usize) {
+		self.values.shrink_to(capacity);
+	}
+
+	fn clear(&mut self) -> bool {
+		self.values.clear();
+		true
+	}
+}
+
+impl Heap for DenseHeap {
+	fn get(&self, address: i64) -> Option<i64> {
+		if (0..DENSE_LIMIT).contains(&address) {
+			self.dense.get(address as usize).cloned().unwrap_or(None)
+		} else {
+			self.sparse.get(&address).cloned()
+		}
+	}
+
+	fn set(&mut self, address: i64, value: i64) {
+		if !(0..DENSE_LIMIT).contains(&address) {
+			self.allocations += grows(&self.sparse, address);
+			self.sparse.insert(address, value);
+			return;
+		}
+
+		let index = address as usize;
+		if index >= self.dense.len() {
+			self.allocations += (index >= self.dense.capacity()) as u64;
+			self.dense.resize(index + 1, None);
+		}
+		if self.dense[index].is_none() {
+			self.occupied += 1;
+		}
+		self.dense[index] = Some(value);
+	}
+
+	fn len(&self) -> usize {
+		self.occupied + self.sparse.len()
+	}
+
+	fn iter(&self) -> Box<dyn Iterator<Item = (i64, i64)> + '_> {
+		let mut sparse: Vec<(i64, i64)> = self.sparse.iter().map(|(&address, &value)| (address, value)).collect();
+		sparse.sort();
+
+		// Negative addresses come before the vector and large ones after it
+		let split = sparse.iter().position(|&(address, _)| address >= 0).unwrap_or(sparse.len());
+		let above = sparse.split_off(split);
+		let dense = self.dense.iter().enumerate().filter_map(|(index, value)| value.map(|value| (index as i64, value)));
+
+		Box::new(sparse.into_iter().chain(dense).chain(above))
+	}
+
+	fn allocations(&self) -> u64 {
+		self.allocations
+	}
+
+	// The room goes to the vector, which is where most programs keep their values
+	fn reserve(&mut self, additional: usize) {
+		self.dense.reserve(additional.min(DENSE_LIMIT as usize));
+	}
+
+	fn shrink_to(&mut self, capacity: usize) {
+		self.dense.shrink_to(capacity);
+		self.sparse.shrink_to(0);
+	}
+
+	fn clear(&mut self) -> bool {
+		self.dense.clear();
+		self.sparse.clear();
+		self.occupied = 0;
+		true
+	}
+}
+
+impl Heap for PagedHeap {
+	fn get(&self, address: i64) -> Option<i64> {
+		let (page, offset) = locate(address);
+		self.pages.get(&page).and_then(|page| page[offset])
+	}
+
+	fn set(&mut self, address: i64, value: i64) {
+		let (page, offset) = locate(address);
+		if !self.pages.contains_key(&page) {
+			self.allocations += 1 + grows(&self.pages, page);
+		}
+		let page = self.pages.entry(page).or_insert_with(|| vec![None; PAGE_SIZE as usize]);
+
+		if page[offset].is_none() {
+			self.len += 1;
+		}
+		page[offset] = Some(value);
+	}
+
+	fn len(&self) -> usize {
+		self.len
+	}
+
+	fn iter(&self) -> Box<dyn Iterator<Item = (i64, i64)> + '_> {
+		let mut pages: Vec<i64> = self.pages.keys().cloned().collect();
+		pages.sort();
+
+		Box::new(pages.into_iter().flat_map(move |page| {
+			self.pages[&page].iter().enumerate().filter_map(move |(offset, value)| {
+				value.map(|value| (page * PAGE_SIZE + offset as i64, value))
+			})
+		}))
+	}
+
+	fn allocations(&self) -> u64 {
+		self.allocations
+	}
+
+	fn reserve(&mut self, additional: usize) {
+		self.pages.reserve(additional.div_ceil(PAGE_SIZE as usize));
+	}
+
+	// Pages that hold nothing are given back first
+	fn shrink_to(&mut self, capacity: usize) {
+		let mut keep = capacity.div_ceil(PAGE_SIZE as usize);
+		self.pages.retain(|_, page| {
+			let held = page.iter().any(Option::is_some);
+			if !held && keep > 0 {
+				keep -= 1;
+				return true;
+			}
+			held
+		});
+		self.pages.shrink_to(capacity.div_ceil(PAGE_SIZE as usize));
+	}
+
+	// The pages stay allocated, empty
+	fn clear(&mut self) -> bool {
+		for page in self.pages.values_mut() {
+			page.iter_mut().for_each(|value| *value = None);
+		}
+		self.len = 0;
+		true
+	}
+}
+
+impl Heap for SharedHeap {
+	fn get(&self, address: i64) -> Option<i64> {
+		self.lock().get(address)
+	}
+
+	fn set(&mut self, address: i64, value: i64) {
+		self.lock().set(address, value);
+	}
+
+	fn len(&self) -> usize {
+		self.lock().len()
+	}
+
+	// Takes a snapshot, since other vms may change the heap while it's iterated
+	fn iter(&self) -> Box<dyn Iterator<Item = (i64, i64)> + '_> {
+		let values: Vec<(i64, i64)> = self.lock().iter().collect();
+		Box::new(values.into_iter())
+	}
+
+	fn allocations(&self) -> u64 {
+		self.lock().allocations()
+	}
+}
+
+impl Heap for RegionHeap {
+	fn get(&self, address: i64) -> Option<i64> {
+		if self.region.contains(&address) {self.shared.get(address)} else {self.private.get(address)}
+	}
+
+	fn set(&mut self, address: i64, value: i64) {
+		if self.region.contains(&address) {self.shared.set(address, value)} else {self.private.set(address, value)}
+	}
+
+	fn len(&self) -> usize {
+		self.iter().count()
+	}
+
+	fn iter(&self) -> Box<dyn Iterator<Item = (i64, i64)> + '_> {
+		let shared  = self.shared.iter().filter(|&(address, _)| self.region.contains(&address));
+		let private = self.private.iter().filter(|&(address, _)| !self.region.contains(&address));
+
+		let mut values: Vec<(i64, i64)> = shared.chain(private).collect();
+		values.sort();
+		Box::new(values.into_iter())
+	}
+
+	// Only the private storage belongs to this heap alone
+	fn allocations(&self) -> u64 {
+		self.private.allocations()
+	}
+
+	fn reserve(&mut self, additional: usize) {
+		self.private.reserve(additional);
+	}
+
+	fn shrink_to(&mut self, capacity: usize) {
+		self.private.shrink_to(capacity);
+	}
+}
+
+impl Heap for ForkedHeap {
+	fn get(&self, address: i64) -> Option<i64> {
+		match self.changes.get(&address) {
+			Some(&value) => Some(value),
+			None         => self.layers.iter().rev().find_map(|layer| layer.get(&address).cloned()),
+		}
+	}
+
+	fn set(&mut self, address: i64, value: i64) {
+		if self.get(address).is_none() {
+			self.len += 1;
+		}
+		self.allocations += grows(&self.changes, address);
+		self.changes.insert(address, value);
+	}
+
+	fn len(&self) -> usize {
+		self.len
+	}
+
+	// Later layers override earlier ones, and the changes override them all
+	fn iter(&self) -> Box<dyn Iterator<Item = (i64, i64)> + '_> {
+		let mut values = BTreeMap::new();
+		for layer in self.layers.iter().map(|layer| &**layer).chain(Some(&self.changes)) {
+			values.extend(layer.iter().map(|(&address, &value)| (address, value)));
+		}
+		Box::new(values.into_iter())
+	}
+
+	fn fork(&mut self) -> Option<Box<dyn Heap>> {
+		Some(Box::new(self.split()))
+	}
+
+	// Merging the layers allocates too
+	fn allocations(&self) -> u64 {
+		self.allocations
+	}
+
+	fn reserve(&mut self, additional: usize) {
+		self.changes.reserve(additional);
+	}
+}
+
+
+/********************************************/
+// Public Functions
+/********************************************/
+// Create an empty heap of the kind with the given name
+pub fn from_name(name: &str) -> Option<Box<dyn Heap + Send>> {
+	match name {
+		"hash"  => Some(Box::new(HashHeap::default())),
+		"dense" => Some(Box::new(DenseHeap::default())),
+		"paged" => Some(Box::new(PagedHeap::default())),
+		_       => None,
+	}
+}
+
+
+// Write out a heap's contents, one `address value` per line in address order
+pub fn save(heap: &dyn Heap) -> String {
+	heap.iter().map(|(address, value)| format!("{} {}\n", address, value)).collect()
+}
+
+
+// Store the values written out by save in a heap. Blank lines and
+// lines starting with # are ignored.
+pub fn load(heap: &mut dyn Heap, text: &str) -> Result<(), String> {
+	for (number, line) in text.lines().enumerate() {
+		let line = line.trim();
+		if line.is_empty() || line.starts_with('#') {
+			continue;
+		}
+
+		let mut fields = line.split_whitespace();
+		match (fields.next().map(str::parse), fields.next().map(str::parse), fields.next()) {
+			(Some(Ok(address)), Some(Ok(value)), None) => heap.set(address, value),
+			_ => return Err(format!("Line {}: Expected `address value`", number + 1)),
+		}
+	}
+
+	Ok(())
+}
+
+
+/********************************************/
+// Private Functions
+/********************************************/
+// Whether storing at a key the map doesn't have yet makes it grow, as
+// one allocation or none
+fn grows<V>(map: &HashMap<i64, V>, key: i64) -> u64 {
+	(map.len() == map.capacity() && !map.contains_key(&key)) as u64
+}
+
+
+// Find the page holding an address and the address's offset within it
+fn locate(address: i64) -> (i64, usize) {
+	(address.div_euclid(PAGE_SIZE), address.rem_euclid(PAGE_SIZE) as usize)
+}
+
+
+/********************************************/
+// Tests
+/********************************************/
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	// Addresses on both sides of zero, past the dense limit, and on either
+	// side of page boundaries, stored out of order
+	const ADDRESSES: [i64; 12] = [5, -1, DENSE_LIMIT + 7, 0, -PAGE_SIZE, i64::MIN, PAGE_SIZE - 1, -PAGE_SIZE - 1, DENSE_LIMIT - 1, PAGE_SIZE, i64::MAX, 3 * PAGE_SIZE];
+
+	// Fill a heap with every address, the value at each being its index
+	fn fill(heap: &mut dyn Heap) {
+		for (value, &address) in ADDRESSES.iter().enumerate() {
+			heap.set(address, value as i64);
+		}
+	}
+
+	// Every address with its value, in address order
+	fn sorted() -> Vec<(i64, i64)> {
+		let mut values: Vec<(i64, i64)> = ADDRESSES.iter().enumerate().map(|(value, &address)| (address, value as i64)).collect();
+		values.sort();
+		values
+	}
+
+	#[test]
+	fn dense_heap_iterates_in_address_order() {
+		let mut heap = DenseHeap::default();
+		fill(&mut heap);
+		assert_eq!(heap.iter().collect::<Vec<_>>(), sorted());
+		assert_eq!(heap.len(), ADDRESSES.len());
+	}
+
+	#[test]
+	fn dense_heap_puts_negative_addresses_before_the_vector() {
+		let mut heap = DenseHeap::default();
+		heap.set(2, 20);
+		heap.set(-3, 30);
+		heap.set(-1, 10);
+		assert_eq!(heap.iter().collect::<Vec<_>>(), vec![(-3, 30), (-1, 10), (2, 20)]);
+		assert!(heap.dense.len() == 3 && heap.sparse.len() == 2);
+	}
+
+	#[test]
+	fn dense_heap_keeps_large_addresses_out_of_the_vector() {
+		let mut heap = DenseHeap::default();
+		heap.set(DENSE_LIMIT + 1, 2);
+		heap.set(DENSE_LIMIT, 1);
+		heap.set(1, 0);
+		assert_eq!(heap.iter().collect::<Vec<_>>(), vec![(1, 0), (DENSE_LIMIT, 1), (DENSE_LIMIT + 1, 2)]);
+		assert_eq!(heap.dense.len(), 2);
+		assert_eq!(heap.get(DENSE_LIMIT), Some(1));
+		assert_eq!(heap.get(DENSE_LIMIT - 1), None);
+	}
+
+	#[test]
+	fn dense_heap_counts_overwrites_once() {
+		let mut heap = DenseHeap::default();
+		heap.set(4, 1);
+		heap.set(4, 2);
+		heap.set(-4, 1);
+		heap.set(-4, 2);
+		assert_eq!(heap.len(), 2);
+		assert_eq!(heap.get(4), Some(2));
+		assert_eq!(heap.get(0), None);
+	}
+
+	#[test]
+	fn paged_heap_iterates_in_address_order() {
+		let mut heap = PagedHeap::default();
+		fill(&mut heap);
+		assert_eq!(heap.iter().collect::<Vec<_>>(), sorted());
+		assert_eq!(heap.len(), ADDRESSES.len());
+	}
+
+	#[test]
+	fn paged_heap_pages_negative_addresses_down() {
+		assert_eq!(locate(-1), (-1, (PAGE_SIZE - 1) as usize));
+		assert_eq!(locate(-PAGE_SIZE), (-1, 0));
+		assert_eq!(locate(-PAGE_SIZE - 1), (-2, (PAGE_SIZE - 1) as usize));
+		assert_eq!(locate(i64::MIN), (i64::MIN / PAGE_SIZE, 0));
+		assert_eq!(locate(i64::MAX), (i64::MAX / PAGE_SIZE, (PAGE_SIZE - 1) as usize));
+
+		let mut heap = PagedHeap::default();
+		heap.set(-1, 1);
+		heap.set(-PAGE_SIZE, 2);
+		heap.set(0, 3);
+		assert_eq!(heap.pages.len(), 2);
+		assert_eq!(heap.iter().collect::<Vec<_>>(), vec![(-PAGE_SIZE, 2), (-1, 1), (0, 3)]);
+	}
+
+	#[test]
+	fn heaps_agree() {
+		for name in &["hash", "dense", "paged"] {
+			let mut heap = from_name(name).unwrap();
+			fill(&mut *heap);
+			let mut values: Vec<(i64, i64)> = heap.iter().collect();
+			values.sort();
+			assert_eq!(values, sorted(), "{} heap", name);
+			for (value, &address) in ADDRESSES.iter().enumerate() {
+				assert_eq!(heap.get(address), Some(value as i64), "{} heap at {}", name, address);
+			}
+		}
+	}
+
+	#[test]
+	fn save_and_load_round_trip() {
+		for name in &["dense", "paged"] {
+			let mut heap = from_name(name).unwrap();
+			fill(&mut *heap);
+			let saved = save(&*heap);
+			assert_eq!(saved.lines().count(), ADDRESSES.len());
+
+			for other in &["hash", "dense", "paged"] {
+				let mut loaded = from_name(other).unwrap();
+				load(&mut *loaded, &saved).unwrap();
+				let mut values: Vec<(i64, i64)> = loaded.iter().collect();
+				values.sort();
+				assert_eq!(values, sorted(), "{} heap loaded into a {} heap", name, other);
+				if *other != "hash" {
+					assert_eq!(save(&*loaded), saved, "{} heap saved from a {} heap", other, name);
+				}
+			}
+		}
+	}
+
+	#[test]
+	fn load_skips_comments_and_refuses_other_lines() {
+		let mut heap = DenseHeap::default();
+		load(&mut heap, "# saved\n\n  -5 7\n3 -2\n").unwrap();
+		assert_eq!(heap.iter().collect::<Vec<_>>(), vec![(-5, 7), (3, -2)]);
+
+		assert_eq!(load(&mut heap, "1 2\n3\n"), Err("Line 2: Expected `address value`".to_string()));
+		assert_eq!(load(&mut heap, "1 2 3\n"), Err("Line 1: Expected `address value`".to_string()));
+		assert_eq!(load(&mut heap, "one 2\n"), Err("Line 1: Expected `address value`".to_string()));
+	}
+}
@@ -0,0 +1,192 @@
+/***********************************************************/
+//
+// Highlighting
+// ============
+// Classifies every token of a program for editors, which
+// have no way to show the structure of source that can't be
+// seen. Each stretch of tokens on a line gets a kind: the
+// group of the instruction it belongs to (stack, arithmetic,
+// heap, flow, io or extension), or for an operand, number or
+// label. Tokens past the point a program stops parsing are
+// invalid, so a program still being written is highlighted
+// up to where it goes wrong.
+//
+// Regions are given by byte offset and, the way the Language
+// Server Protocol counts, by line and UTF-16 character from
+// zero. As text, the source is drawn with placeholders for
+// its tokens, colored by kind on a terminal.
+//
+// struct Region <- A stretch of tokens of one kind on one line
+//
+// classify(source: &str, options: &Options) -> Vec<Region>
+// -- Find the kind of every token in a program
+//
+// render_text(source: &str, regions: &[Region], color: bool) -> String
+// render_json(regions: &[Region]) -> String
+// -- Write the kinds out, drawn over the source or as JSON
+//
+// regions_json(regions: &[Region]) -> String
+// -- Write just the regions as a JSON array
+//
+/***********************************************************/
+use std::fmt::Write;
+use json;
+use options::Options;
+use parser;
+use report;
+use symbols::Action::*;
+use symbols::Category;
+
+
+/********************************************/
+// Structures
+/********************************************/
+// A stretch of tokens of one kind on one line
+#[derive(Debug, Clone, PartialEq)]
+pub struct Region {
+	pub start:     usize,        // Byte offset of the first token
+	pub end:       usize,        // Byte offset just past the last token
+	pub line:      usize,        // Counting from zero
+	pub character: usize,        // UTF-16 units into the line, counting from zero
+	pub length:    usize,        // In UTF-16 units
+	pub kind:      &'static str,
+}
+
+
+/********************************************/
+// Constants
+/********************************************/
+static RESET: &str = "\x1b[0m";
+static DIM:   &str = "\x1b[2m";
+
+// Each kind, and the color it's drawn in on a terminal
+static KINDS: &[(&str, &str)] = &[
+	("stack",      "\x1b[36m"),
+	("arithmetic", "\x1b[33m"),
+	("heap",       "\x1b[35m"),
+	("flow",       "\x1b[34m"),
+	("io",         "\x1b[32m"),
+	("extension",  "\x1b[95m"),
+	("number",     "\x1b[1;33m"),
+	("label",      "\x1b[1;34m"),
+	("invalid",    "\x1b[41m"),
+];
+
+
+/********************************************/
+// Public Functions
+/********************************************/
+// Find the kind of every token in a program, in order
+pub fn classify(source: &str, options: &Options) -> Vec<Region> {
+	let (parsed, _) = parser::parse_partial(source.to_string(), options, &mut Vec::new());
+
+	// The kind of each token, by byte offset
+	let mut kinds = Vec::new();
+	for (action, span) in &parsed {
+		// Pushes and labelled instructions are followed by their operand
+		let (instruction, operand) = match *action {
+			StackPush(_)                                                     => (2, "number"),
+			Label(_) | Call(_) | Jump(_) | JumpIfZero(_) | JumpIfNegative(_) => (3, "label"),
+			_                                                                => (usize::MAX, ""),
+		};
+		let group = group(action.category());
+
+		let tokens = source[span.start..span.end].char_indices().filter(|&(_, character)| parser::token(character, options).is_some());
+		for (index, (offset, _)) in tokens.enumerate() {
+			kinds.push((span.start + offset, if index < instruction {group} else {operand}));
+		}
+	}
+	let parsed_to = parsed.last().map_or(0, |(_, span)| span.end);
+	kinds.extend(source[parsed_to..].char_indices()
+		.filter(|&(_, character)| parser::token(character, options).is_some())
+		.map(|(offset, _)| (parsed_to + offset, "invalid")));
+
+	// Join the tokens next to each other on a line into regions
+	let mut regions: Vec<Region> = Vec::new();
+	let mut kinds     = kinds.into_iter().peekable();
+	let mut line      = 0;
+	let mut character = 0;
+	for (offset, current) in source.char_indices() {
+		let width = current.len_utf16();
+		if kinds.peek().is_some_and(|&(next, _)| next == offset) {
+			let (_, kind) = kinds.next().unwrap();
+			match regions.last_mut() {
+				Some(last) if last.kind == kind && last.end == offset && last.line == line => {last.end += current.len_utf8(); last.length += width},
+				_ => regions.push(Region {start: offset, end: offset + current.len_utf8(), line, character, length: width, kind}),
+			}
+		}
+
+		if current == '\n' {
+			line     += 1;
+			character = 0;
+		} else {
+			character += width;
+		}
+	}
+	regions
+}
+
+
+// Draw the source with each token as a placeholder, colored by kind when
+// there's color, and comments dimmed
+pub fn render_text(source: &str, regions: &[Region], color: bool) -> String {
+	let mut text    = String::new();
+	let mut regions = regions.iter().peekable();
+	for (offset, character) in source.char_indices() {
+		while regions.peek().is_some_and(|region| region.end <= offset) {
+			regions.next();
+		}
+		let (drawn, comment) = report::visible(character);
+		let paint = match regions.peek() {
+			_ if !color                            => "",
+			Some(region) if region.start <= offset => KINDS.iter().find(|&&(kind, _)| kind == region.kind).map_or("", |&(_, paint)| paint),
+			_ if comment                           => DIM,
+			_                                      => "",
+		};
+
+		if paint.is_empty() {
+			text.push(drawn);
+		} else {
+			let _ = write!(text, "{}{}{}", paint, drawn, RESET);
+		}
+		if character == '\n' {
+			text.push('\n');
+		}
+	}
+	if !text.ends_with('\n') {
+		text.push('\n');
+	}
+	text
+}
+
+
+// Describe every region as a JSON object, with the kinds there are
+pub fn render_json(regions: &[Region]) -> String {
+	format!("{{\"kinds\":[{}],\"regions\":{}}}\n", KINDS.iter().map(|&(kind, _)| json::string(kind)).collect::<Vec<String>>().join(","), regions_json(regions))
+}
+
+
+// Describe every region as a JSON array, for the server's responses
+pub fn regions_json(regions: &[Region]) -> String {
+	let regions: Vec<String> = regions.iter().map(|region| format!(
+		"{{\"start\":{},\"end\":{},\"line\":{},\"character\":{},\"length\":{},\"kind\":{}}}",
+		region.start, region.end, region.line, region.character, region.length, json::string(region.kind),
+	)).collect();
+	format!("[{}]", regions.join(","))
+}
+
+
+/********************************************/
+// Private Functions
+/********************************************/
+// The kind of an instruction's own tokens, from its group
+fn group(category: Category) -> &'static str {
+	match category {
+		Category::Stack      => "stack",
+		Category::Arithmetic => "arithmetic",
+		Category::Heap       => "heap",
+		Category::Flow       => "flow",
+		Category::Io         => "io",
+		Category::Extension  => "extension",
+	}
+}
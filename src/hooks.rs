@@ -0,0 +1,304 @@
+/***********************************************************/
+//
+// Hooks
+// =====
+// Small Rhai scripts attached to places in a program or to
+// kinds of action, run just before the vm executes them,
+// that can look at and change the stack and heap. They give
+// a run custom instrumentation without recompiling anything.
+// Only built with the hooks feature, which brings in Rhai.
+//
+// A hook file is a list of hooks, each an event and a script:
+//    at 12: print(stack())
+//    on HeapStore: if top() < 0 { stop("storing a negative value") }
+//    on Halt:
+//       for address in 0..8 { print(heap(address)) }
+//       print(steps)
+// A script runs to the end of the line, or over the indented
+// lines after a hook with nothing after its colon. Scripts
+// see steps, pointer and action, the name of the action about
+// to execute, and can call:
+//    stack(), top(), depth(), push(value), pop(),
+//    heap(address), store(address, value), stop(),
+//    stop(message)
+// Blank lines and lines starting with # are ignored between
+// hooks.
+//
+// struct Hooks <- The hooks attached to a run, and where
+//                 they print to
+//
+/***********************************************************/
+use std::cell::RefCell;
+use std::io::Write;
+use std::mem;
+use std::rc::Rc;
+use rhai::{Array, Dynamic, Engine, EvalAltResult, Position, Scope, AST};
+use heap::{HashHeap, Heap};
+use symbols::Action;
+
+
+/********************************************/
+// Structures
+/********************************************/
+// The hooks attached to a run, and where they print to
+pub struct Hooks {
+	hooks:   Vec<Hook>,
+	engine:  Engine,
+	machine: Rc<RefCell<Machine>>,
+}
+
+// A hook in a hook file
+struct Hook {
+	line:   usize,
+	event:  Event,
+	script: AST,
+}
+
+// What a hook is attached to
+#[derive(Debug, Clone, PartialEq)]
+enum Event {
+	At(usize),      // The action at an index of the reduced program
+	On(String),     // Every action with a name, like HeapStore
+}
+
+// What the scripts see of the vm, lent to them for as long as a hook
+// runs, and where what they print goes
+struct Machine {
+	stack:   Vec<i64>,
+	heap:    Box<dyn Heap>,
+	log:     Box<dyn Write>,
+	line:    usize,          // The line of the hook that's running
+	stopped: Option<String>, // Why a hook stopped the program, if one did
+}
+
+
+/********************************************/
+// Constants
+/********************************************/
+// The names of the actions a hook can be attached to
+static ACTIONS: &[&str] = &[
+	"StackPush", "StackDuplicate", "StackSwap", "StackDiscard",
+	"Add", "Subtract", "Multiply", "Divide", "Modulo",
+	"HeapStore", "HeapRetrieve",
+	"Label", "Call", "Jump", "JumpIfZero", "JumpIfNegative", "EndSubroutine", "Halt",
+	"OutputChar", "OutputNumber", "ReadChar", "ReadNumber",
+	"GetEnv", "DumpHeap", "Assert", "CallDepth", "PeekReturn", "OutputFormatted", "Clock", "Random",
+];
+
+// The most operations a hook can take each time it runs, so one that
+// loops forever fails instead of hanging the run
+const MAX_OPERATIONS: u64 = 1_000_000;
+
+
+/********************************************/
+// Methods
+/********************************************/
+impl Hooks {
+	// Parse a hook file, with anything the hooks print going to the log
+	pub fn parse(text: &str, log: Box<dyn Write>) -> Result<Hooks, String> {
+		let machine = Rc::new(RefCell::new(Machine {stack: Vec::new(), heap: Box::new(HashHeap::default()), log, line: 0, stopped: None}));
+		let engine  = engine(&machine);
+
+		let mut hooks = Vec::new();
+		let mut lines = text.lines().enumerate().peekable();
+		while let Some((number, line)) = lines.next() {
+			let line = line.trim();
+			if line.is_empty() || line.starts_with('#') {
+				continue;
+			}
+
+			let (event, script) = parse_head(line).ok_or_else(|| format!("line {} of the hooks isn't a hook", number + 1))?;
+			let mut script = script.to_string();
+			if script.is_empty() {
+				while let Some(&(_, body)) = lines.peek().filter(|&&(_, body)| body.starts_with(char::is_whitespace) || body.trim().is_empty()) {
+					script.push_str(body);
+					script.push('\n');
+					lines.next();
+				}
+			}
+
+			let script = engine.compile(&script).map_err(|error| format!("the hook on line {} doesn't compile: {}", number + 1, error))?;
+			hooks.push(Hook {line: number + 1, event, script});
+		}
+
+		Ok(Hooks {hooks, engine, machine})
+	}
+
+	// Run the hooks for an action about to be executed, returning why the
+	// program should stop if one of them stops it or fails
+	pub fn before(&mut self, pointer: usize, steps: u64, action: &Action, stack: &mut Vec<i64>, heap: &mut Box<dyn Heap>) -> Result<(), String> {
+		let mut firing = self.hooks.iter().filter(|hook| match hook.event {
+			Event::At(at)       => at == pointer,
+			Event::On(ref name) => name == action.name(),
+		}).peekable();
+		if firing.peek().is_none() {
+			return Ok(());
+		}
+
+		// Lend the stack and heap to the scripts, taking them back afterwards
+		{
+			let mut machine = self.machine.borrow_mut();
+			machine.stack = mem::take(stack);
+			machine.heap  = mem::replace(heap, Box::new(HashHeap::default()));
+		}
+
+		let mut result = Ok(());
+		for hook in firing {
+			self.machine.borrow_mut().line = hook.line;
+			let mut scope = Scope::new();
+			scope.push_constant("steps", steps as i64);
+			scope.push_constant("pointer", pointer as i64);
+			scope.push_constant("action", action.name().to_string());
+
+			if let Err(error) = self.engine.run_ast_with_scope(&mut scope, &hook.script) {
+				result = Err(match self.machine.borrow_mut().stopped.take() {
+					Some(ref message) if message.is_empty() => format!("The hook on line {} stopped the program.", hook.line),
+					Some(message)                           => format!("The hook on line {} stopped the program: {}", hook.line, message),
+					None                                    => format!("The hook on line {} failed: {}", hook.line, error),
+				});
+				break;
+			}
+		}
+
+		let mut machine = self.machine.borrow_mut();
+		*stack = mem::take(&mut machine.stack);
+		*heap  = mem::replace(&mut machine.heap, Box::new(HashHeap::default()));
+		result
+	}
+}
+
+
+/********************************************/
+// Private Functions
+/********************************************/
+// Make the engine the scripts run on, with the functions that look at
+// and change the machine
+fn engine(machine: &Rc<RefCell<Machine>>) -> Engine {
+	let mut engine = Engine::new();
+	engine.set_max_operations(MAX_OPERATIONS);
+
+	let shared = Rc::clone(machine);
+	engine.on_print(move |text| {
+		let mut machine = shared.borrow_mut();
+		let line = machine.line;
+		let _ = writeln!(machine.log, "hook {}: {}", line, text);
+	});
+
+	let shared = Rc::clone(machine);
+	engine.register_fn("stack", move || -> Array {
+		shared.borrow().stack.iter().map(|&value| Dynamic::from(value)).collect()
+	});
+	let shared = Rc::clone(machine);
+	engine.register_fn("top", move || -> Result<i64, Box<EvalAltResult>> {
+		shared.borrow().stack.last().cloned().ok_or_else(|| failure("the stack is empty".to_string()))
+	});
+	let shared = Rc::clone(machine);
+	engine.register_fn("depth", move || shared.borrow().stack.len() as i64);
+	let shared = Rc::clone(machine);
+	engine.register_fn("push", move |value: i64| shared.borrow_mut().stack.push(value));
+	let shared = Rc::clone(machine);
+	engine.register_fn("pop", move || -> Result<i64, Box<EvalAltResult>> {
+		shared.borrow_mut().stack.pop().ok_or_else(|| failure("the stack is empty".to_string()))
+	});
+
+	let shared = Rc::clone(machine);
+	engine.register_fn("heap", move |address: i64| shared.borrow().heap.get(address).unwrap_or(0));
+	let shared = Rc::clone(machine);
+	engine.register_fn("store", move |address: i64, value: i64| shared.borrow_mut().heap.set(address, value));
+
+	// Stopping fails the script, with why kept aside to tell it apart from a mistake
+	let shared = Rc::clone(machine);
+	engine.register_fn("stop", move || -> Result<(), Box<EvalAltResult>> {
+		shared.borrow_mut().stopped = Some(String::new());
+		Err(failure("stopped".to_string()))
+	});
+	let shared = Rc::clone(machine);
+	engine.register_fn("stop", move |message: &str| -> Result<(), Box<EvalAltResult>> {
+		shared.borrow_mut().stopped = Some(message.to_string());
+		Err(failure(message.to_string()))
+	});
+
+	engine
+}
+
+
+// Parse the event a hook starts with, giving it and the script after it
+fn parse_head(line: &str) -> Option<(Event, &str)> {
+	let (head, script) = line.split_once(':')?;
+	let event = match head.trim().split_once(' ')? {
+		("at", index)                                  => Event::At(index.trim().parse().ok()?),
+		("on", name) if ACTIONS.contains(&name.trim()) => Event::On(name.trim().to_string()),
+		_                                              => return None,
+	};
+	Some((event, script.trim()))
+}
+
+
+// An error for a script to fail with
+fn failure(message: String) -> Box<EvalAltResult> {
+	Box::new(EvalAltResult::ErrorRuntime(message.into(), Position::NONE))
+}
+
+
+/********************************************/
+// Tests
+/********************************************/
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use streams::SharedBuffer;
+
+	// Run the hooks before an action, giving the result and what they printed
+	fn before(hooks: &str, pointer: usize, action: Action, stack: &mut Vec<i64>, heap: &mut Box<dyn Heap>) -> (Result<(), String>, String) {
+		let log = SharedBuffer::default();
+		let mut hooks = Hooks::parse(hooks, Box::new(log.clone())).unwrap();
+		let result = hooks.before(pointer, 10, &action, stack, heap);
+		(result, String::from_utf8(log.contents()).unwrap())
+	}
+
+	#[test]
+	fn hooks_change_the_stack_and_heap() {
+		let mut stack: Vec<i64> = vec![1, 2];
+		let mut heap: Box<dyn Heap> = Box::new(HashHeap::default());
+		heap.set(3, 30);
+
+		let (result, log) = before("at 4: push(pop() + top()); store(5, heap(3) + depth())\non Halt: push(0)", 4, Action::Add, &mut stack, &mut heap);
+		assert_eq!(result, Ok(()));
+		assert_eq!(log, "");
+		assert_eq!(stack, vec![1, 3]);
+		assert_eq!(heap.get(5), Some(32));
+	}
+
+	#[test]
+	fn hooks_print_with_their_line() {
+		let (result, log) = before("# a comment\n\non Halt:\n   print(action);\n   print(`${steps} ${pointer} ${stack()}`)\n", 2, Action::Halt, &mut vec![7], &mut (Box::new(HashHeap::default()) as Box<dyn Heap>));
+		assert_eq!(result, Ok(()));
+		assert_eq!(log, "hook 3: Halt\nhook 3: 10 2 [7]\n");
+	}
+
+	#[test]
+	fn hooks_stop_and_fail() {
+		let mut heap: Box<dyn Heap> = Box::new(HashHeap::default());
+		let (result, _) = before("on Add: if top() > 1 { stop(\"too big\") }", 0, Action::Add, &mut vec![2], &mut heap);
+		assert_eq!(result, Err("The hook on line 1 stopped the program: too big".to_string()));
+
+		let (result, _) = before("on Add: stop()", 0, Action::Add, &mut vec![], &mut heap);
+		assert_eq!(result, Err("The hook on line 1 stopped the program.".to_string()));
+
+		// The stack is handed back even when a hook fails partway
+		let mut stack = vec![1];
+		let (result, _) = before("on Add: pop(); pop()", 0, Action::Add, &mut stack, &mut heap);
+		assert!(result.unwrap_err().starts_with("The hook on line 1 failed: "));
+		assert!(stack.is_empty());
+
+		let (result, _) = before("on Add: loop {}", 0, Action::Add, &mut stack, &mut heap);
+		assert!(result.is_err());
+	}
+
+	#[test]
+	fn bad_hooks_are_refused() {
+		assert_eq!(Hooks::parse("sometimes: print(1)", Box::new(Vec::new())).err(), Some("line 1 of the hooks isn't a hook".to_string()));
+		assert_eq!(Hooks::parse("on Nothing: print(1)", Box::new(Vec::new())).err(), Some("line 1 of the hooks isn't a hook".to_string()));
+		assert!(Hooks::parse("\nat 1: (((", Box::new(Vec::new())).err().unwrap().starts_with("the hook on line 2 doesn't compile"));
+	}
+}
@@ -0,0 +1,182 @@
+/***********************************************************/
+//
+// Idioms
+// ======
+// Recognizes common shapes of code in a parsed program so
+// listings can say what a stretch of actions is for: text
+// printed a character at a time, loops printing a string
+// from the heap, loops copying heap cells, and subroutines
+// printing a number digit by digit.
+//
+// struct Idiom <- A recognized stretch of actions
+//
+// find(program: &[Action]) -> Vec<Idiom>
+// -- Find the idioms in a program, in program order
+//
+/***********************************************************/
+use symbols::Action;
+use symbols::Action::*;
+
+
+/********************************************/
+// Structures
+/********************************************/
+// A recognized stretch of actions
+#[derive(Debug, Clone)]
+pub struct Idiom {
+	pub start:       usize,        // Index of the first action
+	pub end:         usize,        // Index one past the last action
+	pub name:        &'static str, // What it does in a word or two, fit for naming a label
+	pub description: String,
+}
+
+
+/********************************************/
+// Public Functions
+/********************************************/
+// Find the idioms in a program, ordered by where they start
+pub fn find(program: &[Action]) -> Vec<Idiom> {
+	let mut idioms = Vec::new();
+	idioms.extend(printed_text(program));
+	idioms.extend(loops(program));
+	idioms.extend(number_printers(program));
+	idioms.sort_by_key(|idiom| idiom.start);
+	idioms
+}
+
+
+/********************************************/
+// Private Functions
+/********************************************/
+// Text written a character at a time from constants, either as
+// pushes each followed by an output or as a run of pushes followed
+// by as many outputs, which prints the characters in reverse
+fn printed_text(program: &[Action]) -> Vec<Idiom> {
+	let mut idioms = Vec::new();
+	let mut index  = 0;
+
+	while index < program.len() {
+		// Alternating pushes and outputs
+		let mut text = String::new();
+		let mut end  = index;
+		while let (Some(&StackPush(code)), Some(&OutputChar)) = (program.get(end), program.get(end + 1)) {
+			match printable(code) {
+				Some(character) => text.push(character),
+				None            => break,
+			}
+			end += 2;
+		}
+
+		// A run of pushes, then the same number of outputs
+		if text.chars().count() < 2 {
+			text.clear();
+			end = index;
+			while let Some(character) = program.get(end).and_then(|action| if let StackPush(code) = *action {printable(code)} else {None}) {
+				text.insert(0, character);
+				end += 1;
+			}
+
+			let pushes  = end - index;
+			let outputs = program[end..].iter().take_while(|action| matches!(action, OutputChar)).count();
+			if pushes < 2 || outputs < pushes {
+				text.clear();
+			} else {
+				end += pushes;
+			}
+		}
+
+		if text.chars().count() >= 2 {
+			idioms.push(Idiom {start: index, end, name: "print_text", description: format!("prints {:?}", text)});
+			index = end;
+		} else {
+			index += 1;
+		}
+	}
+
+	idioms
+}
+
+
+// Loops that walk the heap: printing a zero terminated string, or
+// copying cells from one place to another. A loop runs from a label
+// to the last jump back to it.
+fn loops(program: &[Action]) -> Vec<Idiom> {
+	let mut idioms = Vec::new();
+
+	for (start, action) in program.iter().enumerate() {
+		let label = match *action {
+			Label(label) => label,
+			_            => continue,
+		};
+
+		let back = match program.iter().rposition(|action| matches!(*action, Jump(target) if target == label)) {
+			Some(back) if back > start => back,
+			_                          => continue,
+		};
+
+		let body    = &program[start..=back];
+		let has     = |wanted: fn(&Action) -> bool| body.iter().any(wanted);
+		let steps   = body.windows(2).any(|pair| matches!(pair, [StackPush(1), Add] | [StackPush(-1), Subtract]));
+		let reads   = has(|action| matches!(action, HeapRetrieve));
+		let stores  = has(|action| matches!(action, HeapStore));
+		let outputs = has(|action| matches!(action, OutputChar));
+		let exits   = has(|action| matches!(action, JumpIfZero(_)));
+		let inputs  = has(|action| matches!(action, ReadChar | ReadNumber));
+		let calls   = has(|action| matches!(action, Call(_)));
+
+		let (name, description) = if reads && outputs && exits && steps && !stores && !calls {
+			("print_string", "prints the zero terminated string at an address in the heap")
+		} else if reads && stores && steps && !outputs && !inputs && !calls {
+			("copy_cells", "copies heap cells from one address to another, one at a time")
+		} else {
+			continue;
+		};
+
+		idioms.push(Idiom {start, end: back + 1, name, description: description.to_string()});
+	}
+
+	idioms
+}
+
+
+// Subroutines that print a number in decimal, splitting off digits by
+// dividing by ten and turning them into characters by adding '0'. A
+// subroutine runs from a called label to its first return.
+fn number_printers(program: &[Action]) -> Vec<Idiom> {
+	let mut idioms = Vec::new();
+
+	for (start, action) in program.iter().enumerate() {
+		let label = match *action {
+			Label(label) => label,
+			_            => continue,
+		};
+		if !program.iter().any(|action| matches!(*action, Call(target) if target == label)) {
+			continue;
+		}
+
+		let end = match program[start..].iter().position(|action| matches!(action, EndSubroutine)) {
+			Some(offset) => start + offset + 1,
+			None         => continue,
+		};
+
+		let body     = &program[start..end];
+		let by_ten   = body.windows(2).any(|pair| matches!(pair, [StackPush(10), Divide] | [StackPush(10), Modulo]));
+		let to_digit = body.windows(2).any(|pair| matches!(pair, [StackPush(48), Add]));
+		let outputs  = body.iter().any(|action| matches!(action, OutputChar));
+
+		if by_ten && to_digit && outputs {
+			idioms.push(Idiom {start, end, name: "print_number", description: "prints a number in decimal, a digit at a time".to_string()});
+		}
+	}
+
+	idioms
+}
+
+
+// The character a code stands for, if it's printable or a newline
+fn printable(code: i64) -> Option<char> {
+	match code {
+		0x20..=0x7e | 0x0a => Some(code as u8 as char),
+		_                  => None,
+	}
+}
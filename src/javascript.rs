@@ -0,0 +1,298 @@
+/***********************************************************/
+//
+// JavaScript Backend
+// ==================
+// Compiles a program to a standalone JavaScript module, so
+// it can be dropped into a web page or run with node. The
+// module exports an async run function that reads input
+// from a callback and writes output to another:
+//
+//    import {run} from "./program.mjs";
+//    await run({read: async () => prompt(), write: text => console.log(text)});
+//
+// Values are BigInts that fail the same way the interpreter
+// does when they leave 64 bits, the heap is a Map, and the
+// whole program is one switch with a case for each place
+// control can jump to, so straight-line code falls through
+// from one action to the next.
+//
+// struct JavaScript <- The JavaScript backend
+//
+/***********************************************************/
+use analysis;
+use compile;
+use compile::Backend;
+use labels::LabelId;
+use options::{CharEncoding, Division, EndOfProgram, EofPolicy, InvalidChar, InvalidNumber, Options, ReadCharDestination, UnsetHeap};
+use parser::UNRESOLVED;
+use symbols::Action;
+use symbols::Action::*;
+
+
+/********************************************/
+// Structures
+/********************************************/
+// The JavaScript backend
+pub struct JavaScript;
+
+impl Backend for JavaScript {
+	fn name(&self) -> &'static str {
+		"js"
+	}
+
+	fn emit(&self, program: &[Action], options: &Options) -> Result<Vec<u8>, String> {
+		let leaders = compile::leaders(program);
+		let mut body = String::new();
+
+		for (index, action) in program.iter().enumerate() {
+			if leaders[index] {
+				body.push_str(&format!("\t\tcase {}:\n", index));
+			}
+
+			let needed = analysis::stack_effect(action).pops;
+			if needed > 0 {
+				body.push_str(&format!("\t\t\tneed({}, {}, {:?});\n", index, needed, format!("{:?}", action)));
+			}
+			body.push_str(&format!("\t\t\t{}\n", self.action(index, action, options)?));
+		}
+
+		// Running off the end
+		body.push_str(&format!("\t\tcase {}:\n", program.len()));
+		body.push_str(&match options.end_of_program {
+			EndOfProgram::Halt  => "\t\t\treturn;\n".to_string(),
+			EndOfProgram::Error => format!("\t\t\tfail({}, \"Reached the end of the program without halting.\");\n", program.len()),
+		});
+
+		Ok(RUNTIME
+			.replace("$DIVISION", division(options.division))
+			.replace("$EOF", eof(options.eof))
+			.replace("$UNSET_HEAP", match options.unset_heap {UnsetHeap::Error => "false", UnsetHeap::Zero => "true"})
+			.replace("$UNICODE", match options.char_encoding {CharEncoding::Byte => "false", CharEncoding::Unicode => "true"})
+			.replace("$INVALID_CHAR_FAILS", match options.invalid_char {InvalidChar::Lossy => "false", InvalidChar::Error => "true"})
+			.replace("$REPROMPT", match options.invalid_number {InvalidNumber::Reprompt => "true", InvalidNumber::Error => "false"})
+			.replace("$WHITESPACE", &options.number_input.whitespace.to_string())
+			.replace("$PLUS_SIGN", &options.number_input.plus_sign.to_string())
+			.replace("$HEXADECIMAL", &options.number_input.hexadecimal.to_string())
+			.replace("$EMPTY_LINE", &format!("{:?}", format!("{:?}", options.number_input.empty_line).to_lowercase()))
+			.replace("$BODY", &body)
+			.into_bytes())
+	}
+}
+
+impl JavaScript {
+	// The statement an action compiles to
+	fn action(&self, index: usize, action: &Action, options: &Options) -> Result<String, String> {
+		let jump = |LabelId(target)| if target == UNRESOLVED {
+			format!("fail({}, \"Tried to jump to a label that doesn't exist.\");", index)
+		} else {
+			format!("pc = {}; continue;", target)
+		};
+
+		Ok(match *action {
+			StackPush(value) => format!("stack.push({}n);", value),
+			StackDuplicate   => "stack.push(stack[stack.length - 1]);".to_string(),
+			StackSwap        => "stack.push(stack.pop(), stack.pop());".to_string(),
+			StackDiscard     => "stack.pop();".to_string(),
+
+			Add      => format!("{{const r = stack.pop(), l = stack.pop(); stack.push(checked({}, l + r, \"Addition overflowed.\"));}}", index),
+			Subtract => format!("{{const r = stack.pop(), l = stack.pop(); stack.push(checked({}, l - r, \"Subtraction overflowed.\"));}}", index),
+			Multiply => format!("{{const r = stack.pop(), l = stack.pop(); stack.push(checked({}, l * r, \"Multiplication overflowed.\"));}}", index),
+			Divide   => format!("{{const r = stack.pop(), l = stack.pop(); if (r === 0n) fail({0}, \"Tried to divide by zero.\"); stack.push(checked({0}, quotient(l, r), \"Division overflowed.\"));}}", index),
+			Modulo   => format!("{{const r = stack.pop(), l = stack.pop(); if (r === 0n) fail({0}, \"Tried to take the remainder of a division by zero.\"); if (l === MIN && r === -1n) fail({0}, \"Modulo overflowed.\"); stack.push(remainder(l, r));}}", index),
+
+			HeapStore    => "{const v = stack.pop(), a = stack.pop(); heap.set(a, v);}".to_string(),
+			HeapRetrieve => format!("stack.push(retrieve({}, stack.pop()));", index),
+
+			Call(target)           => format!("calls.push({}); {}", index + 1, jump(target)),
+			Jump(target)           => jump(target),
+			JumpIfZero(target)     => format!("if (stack.pop() === 0n) {{{}}}", jump(target)),
+			JumpIfNegative(target) => format!("if (stack.pop() < 0n) {{{}}}", jump(target)),
+			EndSubroutine          => format!("if (calls.length === 0) fail({}, \"Tried to return from a procedure, but no procedure call was made.\"); pc = calls.pop(); continue;", index),
+			Halt                   => "return;".to_string(),
+
+			OutputChar   => format!("write(character({}, stack.pop()));", index),
+			OutputNumber => "write(stack.pop().toString());".to_string(),
+
+			// Reads take their destination only once the read is done, like the interpreter
+			ReadChar => match options.read_char {
+				ReadCharDestination::Heap  => format!("{{const v = await readChar({}); const a = stack.pop(); if (v !== null) heap.set(a, v);}}", index),
+				ReadCharDestination::Stack => format!("{{const v = await readChar({}); if (v !== null) stack.push(v);}}", index),
+			},
+			ReadNumber => format!("{{const v = await readNumber({}); const a = stack.pop(); if (v !== null) heap.set(a, v);}}", index),
+
+			Label(_) | GetEnv | DumpHeap | Assert | CallDepth | PeekReturn | OutputFormatted | Clock | Random => return Err(compile::unsupported(self, action)),
+		})
+	}
+}
+
+
+/********************************************/
+// Private Functions
+/********************************************/
+// The quotient and remainder functions for a kind of division
+fn division(division: Division) -> &'static str {
+	match division {
+		Division::Truncated => "\
+const quotient  = (l, r) => l / r;
+const remainder = (l, r) => l % r;",
+
+		Division::Floored => "\
+const quotient  = (l, r) => (l % r !== 0n && (l < 0n) !== (r < 0n)) ? l / r - 1n : l / r;
+const remainder = (l, r) => {const m = l % r; return (m !== 0n && (m < 0n) !== (r < 0n)) ? m + r : m;};",
+
+		Division::Euclidean => "\
+const remainder = (l, r) => {const m = l % r; return m < 0n ? m + (r < 0n ? -r : r) : m;};
+const quotient  = (l, r) => (l - remainder(l, r)) / r;",
+	}
+}
+
+
+// What a read gives at the end of the input, as JavaScript
+fn eof(eof: EofPolicy) -> &'static str {
+	match eof {
+		EofPolicy::Error       => "undefined",
+		EofPolicy::NegativeOne => "-1n",
+		EofPolicy::Zero        => "0n",
+		EofPolicy::Unchanged   => "null",
+	}
+}
+
+
+// The module the program's switch is placed in
+static RUNTIME: &str = r#"// Compiled from Whitespace with: whitespace compile --target js
+//
+// run() executes the program and resolves once it halts. Input comes from
+// read, an async function giving the next piece of input as a string or
+// bytes, or null once there's no more, and output goes to write:
+//    await run({read: async () => null, write: text => console.log(text)});
+// Run directly with node, it reads stdin and writes stdout.
+
+const MIN = -(2n ** 63n);
+const MAX = 2n ** 63n - 1n;
+
+// What a read gives at the end of the input: undefined for an error, or
+// null to leave the destination untouched
+const EOF = $EOF;
+const UNSET_HEAP_IS_ZERO = $UNSET_HEAP;
+const UNICODE = $UNICODE;
+const INVALID_CHAR_FAILS = $INVALID_CHAR_FAILS;
+const REPROMPT = $REPROMPT;
+const NUMBER_INPUT = {whitespace: $WHITESPACE, plus: $PLUS_SIGN, hex: $HEXADECIMAL, empty: $EMPTY_LINE};
+
+$DIVISION
+
+export class WhitespaceError extends Error {}
+
+export async function run({read = async () => null, write = () => {}} = {}) {
+	const stack = [], calls = [], heap = new Map();
+	const encoder = new TextEncoder(), decoder = new TextDecoder();
+	let input = new Uint8Array(0), position = 0, ended = false;
+	let pc = 0;
+
+	const fail = (at, message) => {throw new WhitespaceError(`Runtime Error: ${message} (at action ${at})`);};
+
+	const need = (at, count, action) => {
+		if (stack.length < count) fail(at, `${action} needs ${count} value(s) on the stack, but it holds ${stack.length}.`);
+	};
+
+	const checked = (at, value, message) => (value < MIN || value > MAX) ? fail(at, message) : value;
+
+	const retrieve = (at, address) => {
+		if (heap.has(address)) return heap.get(address);
+		if (UNSET_HEAP_IS_ZERO) return 0n;
+		fail(at, `Tried to get a value from the heap, but no value was found at address: ${address}`);
+	};
+
+	// The character a value stands for, as a byte or a code point
+	const character = (at, value) => {
+		const valid = UNICODE ? value >= 0n && value <= 0x10ffffn && (value < 0xd800n || value > 0xdfffn) : value >= 0n && value <= 255n;
+		if (!valid && INVALID_CHAR_FAILS) {
+			fail(at, UNICODE ? `Tried to write ${value} as a character, but it isn't a Unicode code point.` : `Tried to write ${value} as a character, but it's outside the range of a byte.`);
+		}
+		if (UNICODE) return String.fromCodePoint(valid ? Number(value) : 0xfffd);
+		return String.fromCharCode(Number(BigInt.asUintN(8, value)));
+	};
+
+	const atEnd = (at, reading) => EOF === undefined ? fail(at, `Reached the end of the input while reading ${reading}.`) : EOF;
+
+	// Make sure there's input to take, returning false at the end of it
+	const fill = async () => {
+		while (position === input.length && !ended) {
+			const chunk = await read();
+			if (chunk === null || chunk === undefined) ended = true;
+			else {input = typeof chunk === "string" ? encoder.encode(chunk) : chunk; position = 0;}
+		}
+		return position < input.length;
+	};
+
+	const readChar = async at => (await fill()) ? BigInt(input[position++]) : atEnd(at, "a character");
+
+	// Read a line with its line ending, or null at the end of the input
+	const readLine = async () => {
+		const bytes = [];
+		while (await fill()) {
+			const byte = input[position++];
+			bytes.push(byte);
+			if (byte === 10) break;
+		}
+		return bytes.length === 0 ? null : decoder.decode(new Uint8Array(bytes));
+	};
+
+	// Parse a line the way the interpreter's number input options say to,
+	// returning the number, null to skip the line, or a reason it isn't one
+	const parseNumber = line => {
+		const text = NUMBER_INPUT.whitespace ? line.replace(/^[ \t]+|[ \t]+$/g, "") : line;
+		if (text === "") {
+			if (NUMBER_INPUT.empty === "zero") return 0n;
+			if (NUMBER_INPUT.empty === "skip") return null;
+			return {error: "the line is empty"};
+		}
+
+		let negative = false, unsigned = text;
+		if (text[0] === "-") {negative = true; unsigned = text.slice(1);}
+		else if (text[0] === "+") {
+			if (!NUMBER_INPUT.plus) return {error: "a + sign isn't allowed"};
+			unsigned = text.slice(1);
+		}
+
+		const hex = NUMBER_INPUT.hex && /^0[xX]/.test(unsigned);
+		const digits = hex ? unsigned.slice(2) : unsigned;
+		if (!(hex ? /^[0-9a-fA-F]+$/ : /^[0-9]+$/).test(digits)) return {error: `${JSON.stringify(line)} isn't a number`};
+
+		const magnitude = BigInt(hex ? "0x" + digits : digits);
+		const value = negative ? -magnitude : magnitude;
+		return (value < MIN || value > MAX) ? {error: `${JSON.stringify(line)} doesn't fit in 64 bits`} : value;
+	};
+
+	const readNumber = async at => {
+		for (;;) {
+			const line = await readLine();
+			if (line === null) return atEnd(at, "a number");
+
+			const number = parseNumber(line.replace(/\r?\n$/, ""));
+			if (number === null) continue;
+			if (typeof number === "bigint") return number;
+			if (!REPROMPT) fail(at, `Unable to parse number: ${number.error}`);
+			write(`Unable to parse number: ${number.error}\n`);
+		}
+	};
+
+	for (;;) {
+		switch (pc) {
+$BODY		}
+	}
+}
+
+// Run with node, print errors the way the interpreter does
+if (typeof process === "object" && process.argv && import.meta.url === "file://" + process.argv[1]) {
+	const chunks = process.stdin[Symbol.asyncIterator]();
+	run({
+		read:  async () => {const next = await chunks.next(); return next.done ? null : next.value;},
+		write: text => process.stdout.write(text),
+	}).catch(error => {
+		if (!(error instanceof WhitespaceError)) throw error;
+		process.stdout.write(`Error: ${error.message}\n`);
+		process.exitCode = 6;
+	}).finally(() => process.stdin.destroy());
+}
+"#;
@@ -0,0 +1,276 @@
+/***********************************************************/
+//
+// JSON
+// ====
+// Helpers for writing machine-readable JSON output, and
+// reading JSON requests, without pulling in a
+// serialization library.
+//
+// enum Value <- A parsed JSON value
+//
+// string(value: &str) -> String
+// -- Quote and escape a string as a JSON string literal
+//
+// parse(text: &str) -> Result<Value, String>
+// -- Parse a JSON document
+//
+/***********************************************************/
+use std::fmt;
+use std::iter::Peekable;
+use std::str::Chars;
+
+
+/********************************************/
+// Structures
+/********************************************/
+// A parsed JSON value. Objects keep their keys in order.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+	Null,
+	Bool(bool),
+	Number(f64),
+	String(String),
+	Array(Vec<Value>),
+	Object(Vec<(String, Value)>),
+}
+
+impl Value {
+	// The value of a key, if this is an object that has it
+	pub fn get(&self, key: &str) -> Option<&Value> {
+		match *self {
+			Value::Object(ref members) => members.iter().find(|member| member.0 == key).map(|member| &member.1),
+			_                          => None,
+		}
+	}
+
+	// The string, if this is one
+	pub fn as_str(&self) -> Option<&str> {
+		match *self {
+			Value::String(ref text) => Some(text),
+			_                       => None,
+		}
+	}
+
+	// The number, if this is a whole number that fits in an i64
+	pub fn as_i64(&self) -> Option<i64> {
+		match *self {
+			Value::Number(number) if number.fract() == 0.0 && number.abs() < 9.2e18 => Some(number as i64),
+			_                                                                       => None,
+		}
+	}
+
+	// The number, if this is one
+	pub fn as_f64(&self) -> Option<f64> {
+		match *self {
+			Value::Number(number) => Some(number),
+			_                     => None,
+		}
+	}
+}
+
+
+/********************************************/
+// Trait Implementations
+/********************************************/
+// Write a value back out as JSON
+impl fmt::Display for Value {
+	fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+		match *self {
+			Value::Null             => write!(formatter, "null"),
+			Value::Bool(value)      => write!(formatter, "{}", value),
+			Value::Number(number)   => write!(formatter, "{}", number),
+			Value::String(ref text) => write!(formatter, "{}", string(text)),
+
+			Value::Array(ref elements) => {
+				let elements: Vec<String> = elements.iter().map(|element| element.to_string()).collect();
+				write!(formatter, "[{}]", elements.join(","))
+			},
+
+			Value::Object(ref members) => {
+				let members: Vec<String> = members.iter().map(|(key, value)| format!("{}:{}", string(key), value)).collect();
+				write!(formatter, "{{{}}}", members.join(","))
+			},
+		}
+	}
+}
+
+
+/********************************************/
+// Public Functions
+/********************************************/
+// Quote and escape a string as a JSON string literal
+pub fn string(value: &str) -> String {
+	let mut quoted = String::with_capacity(value.len() + 2);
+	quoted.push('"');
+
+	for character in value.chars() {
+		match character {
+			'"'  => quoted.push_str("\\\""),
+			'\\' => quoted.push_str("\\\\"),
+			'\n' => quoted.push_str("\\n"),
+			'\r' => quoted.push_str("\\r"),
+			'\t' => quoted.push_str("\\t"),
+			c if (c as u32) < 0x20 => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+			c => quoted.push(c),
+		}
+	}
+
+	quoted.push('"');
+	quoted
+}
+
+
+// Parse a JSON document
+pub fn parse(text: &str) -> Result<Value, String> {
+	let mut characters = text.chars().peekable();
+	let value = parse_value(&mut characters)?;
+
+	skip_whitespace(&mut characters);
+	match characters.next() {
+		None            => Ok(value),
+		Some(character) => Err(format!("unexpected {:?} after the value", character)),
+	}
+}
+
+
+/********************************************/
+// Private Functions
+/********************************************/
+// Parse the value starting at the next character that isn't whitespace
+fn parse_value(characters: &mut Peekable<Chars>) -> Result<Value, String> {
+	skip_whitespace(characters);
+
+	match characters.peek().cloned() {
+		Some('{') => {
+			characters.next();
+			let mut members = Vec::new();
+			if !take_if(characters, '}') {
+				loop {
+					skip_whitespace(characters);
+					if characters.next() != Some('"') {
+						return Err("expected a string key".to_string());
+					}
+					let key = parse_string(characters)?;
+					skip_whitespace(characters);
+					if characters.next() != Some(':') {
+						return Err("expected a : after a key".to_string());
+					}
+					members.push((key, parse_value(characters)?));
+					if !list_continues(characters, '}')? {
+						break;
+					}
+				}
+			}
+			Ok(Value::Object(members))
+		},
+
+		Some('[') => {
+			characters.next();
+			let mut elements = Vec::new();
+			if !take_if(characters, ']') {
+				loop {
+					elements.push(parse_value(characters)?);
+					if !list_continues(characters, ']')? {
+						break;
+					}
+				}
+			}
+			Ok(Value::Array(elements))
+		},
+
+		Some('"') => {
+			characters.next();
+			parse_string(characters).map(Value::String)
+		},
+
+		Some(character) if character == '-' || character.is_ascii_digit() => {
+			let mut number = String::new();
+			while let Some(&character) = characters.peek() {
+				if !(character.is_ascii_digit() || "+-.eE".contains(character)) {
+					break;
+				}
+				number.push(character);
+				characters.next();
+			}
+			number.parse().map(Value::Number).map_err(|_| format!("{} isn't a number", number))
+		},
+
+		Some(character) if character.is_ascii_alphabetic() => {
+			let mut word = String::new();
+			while let Some(&character) = characters.peek().filter(|character| character.is_ascii_alphabetic()) {
+				word.push(character);
+				characters.next();
+			}
+			match word.as_str() {
+				"null"  => Ok(Value::Null),
+				"true"  => Ok(Value::Bool(true)),
+				"false" => Ok(Value::Bool(false)),
+				_       => Err(format!("unexpected {}", word)),
+			}
+		},
+
+		Some(character) => Err(format!("unexpected {:?}", character)),
+		None            => Err("unexpected end of input".to_string()),
+	}
+}
+
+
+// Parse the rest of a string whose opening quote has been read
+fn parse_string(characters: &mut Peekable<Chars>) -> Result<String, String> {
+	let mut text = String::new();
+
+	loop {
+		match characters.next() {
+			Some('"')       => return Ok(text),
+			Some('\\')      => match characters.next() {
+				Some('"')  => text.push('"'),
+				Some('\\') => text.push('\\'),
+				Some('/')  => text.push('/'),
+				Some('b')  => text.push('\u{8}'),
+				Some('f')  => text.push('\u{c}'),
+				Some('n')  => text.push('\n'),
+				Some('r')  => text.push('\r'),
+				Some('t')  => text.push('\t'),
+				Some('u')  => {
+					let code: String = characters.by_ref().take(4).collect();
+					let code = u32::from_str_radix(&code, 16).map_err(|_| format!("\\u{} isn't an escape", code))?;
+					text.push(char::from_u32(code).unwrap_or('\u{fffd}'));
+				},
+				_          => return Err("unknown escape in a string".to_string()),
+			},
+			Some(character) => text.push(character),
+			None            => return Err("unterminated string".to_string()),
+		}
+	}
+}
+
+
+// After an element of an array or object, read the comma that means
+// another follows or the bracket that ends it
+fn list_continues(characters: &mut Peekable<Chars>, end: char) -> Result<bool, String> {
+	skip_whitespace(characters);
+	match characters.next() {
+		Some(',')                           => Ok(true),
+		Some(character) if character == end => Ok(false),
+		_                                   => Err(format!("expected a , or {}", end)),
+	}
+}
+
+
+// Read the next character that isn't whitespace if it's the one wanted
+fn take_if(characters: &mut Peekable<Chars>, wanted: char) -> bool {
+	skip_whitespace(characters);
+	let found = characters.peek() == Some(&wanted);
+	if found {
+		characters.next();
+	}
+	found
+}
+
+
+// Move past any whitespace
+fn skip_whitespace(characters: &mut Peekable<Chars>) {
+	while characters.peek().is_some_and(|character| character.is_whitespace()) {
+		characters.next();
+	}
+}
@@ -0,0 +1,769 @@
+/***********************************************************/
+//
+// Jupyter
+// =======
+// A Jupyter kernel, so notebook cells of whitespace or of
+// assembly can be run one after another, with each cell's
+// output shown under it and the heap kept from one cell to
+// the next. Only built with the jupyter feature, which
+// brings in ZeroMQ and HMAC signing.
+//
+// The kernel is started by Jupyter with a connection file
+// naming the ports of its five channels and the key the
+// messages are signed with:
+//    shell     - kernel_info, execute, is_complete,
+//                complete, inspect, history and comm_info
+//                requests, each answered with a reply
+//    control   - interrupt and shutdown requests, answered
+//                even while a cell is running
+//    iopub     - the kernel's status, each cell's code and
+//                output, and what failed
+//    stdin     - what a cell reads, asked of the frontend
+//                when the cell gets to it
+//    heartbeat - echoes whatever it's sent
+//
+// A cell is assembly, as the assemble command reads it,
+// unless it's nothing but whitespace, or its first line is
+// %%whitespace or %%stl, which make the rest of it
+// whitespace or STL notation.
+//
+// struct Connection <- Where a kernel listens, from the
+//                      connection file Jupyter writes
+//
+// serve(connection: &Connection, options: Options, io_options: IoOptions, limits: Limits) -> Result<(), String>
+// -- Run the kernel until it's shut down
+//
+/***********************************************************/
+use std::collections::hash_map::RandomState;
+use std::convert::TryFrom;
+use std::hash::{BuildHasher, Hasher};
+use std::io;
+use std::io::{BufReader, Read, Write};
+use std::rc::Rc;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{SystemTime, UNIX_EPOCH};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use zmq;
+use assembler;
+use cancel::Cancel;
+use conformance::Termination;
+use error::WhitespaceError;
+use heap::{HashHeap, Heap};
+use json;
+use json::Value;
+use options::{FlushPolicy, IoOptions, Limits, Options};
+use parser;
+use symbols::Action;
+use virtual_machine::WhitespaceVM;
+
+
+/********************************************/
+// Structures
+/********************************************/
+// Where a kernel listens, and the key its messages are signed with
+#[derive(Debug, Clone)]
+pub struct Connection {
+	pub transport: String,
+	pub ip:        String,
+	pub shell:     u16,
+	pub iopub:     u16,
+	pub stdin:     u16,
+	pub control:   u16,
+	pub heartbeat: u16,
+	pub key:       String,
+}
+
+// Signs the messages a kernel sends and checks the ones it's sent
+#[derive(Clone)]
+struct Signer {
+	key:     Vec<u8>,
+	session: String, // The id of the kernel's session, in every header it sends
+}
+
+// A message from a frontend
+struct Message {
+	identities: Vec<Vec<u8>>, // Where the reply is routed back to
+	header:     Value,
+	content:    Value,
+}
+
+// What the kernel keeps between cells
+struct Kernel {
+	signer:     Signer,
+	shell:      zmq::Socket,
+	iopub:      Rc<zmq::Socket>,
+	stdin:      Rc<zmq::Socket>,
+	options:    Options,
+	io_options: IoOptions,
+	limits:     Limits,
+	cancel:     Cancel, // Cancelled by the control channel to interrupt a cell
+	vm:         Option<WhitespaceVM>,
+	count:      u64,    // How many cells have been executed
+}
+
+// Where a cell's output goes: published to iopub as it's flushed
+struct Stream {
+	iopub:  Rc<zmq::Socket>,
+	signer: Signer,
+	parent: Value,
+	buffer: Vec<u8>,
+}
+
+// Where a cell's input comes from: asked of the frontend a line at a time
+struct Prompt {
+	stdin:      Rc<zmq::Socket>,
+	signer:     Signer,
+	identities: Vec<Vec<u8>>,
+	parent:     Value,
+	prompt:     String,
+	line:       Vec<u8>, // What's left of the last line given
+}
+
+
+/********************************************/
+// Constants
+/********************************************/
+// The version of the messaging protocol the kernel speaks
+const PROTOCOL_VERSION: &str = "5.3";
+
+// What separates the identities of a message from the rest of it
+const DELIMITER: &[u8] = b"<IDS|MSG>";
+
+// How often, in milliseconds, the shell channel looks for a shutdown
+// asked for on the control channel
+const POLL_INTERVAL: i64 = 100;
+
+
+/********************************************/
+// Methods
+/********************************************/
+impl Connection {
+	// Read a connection file
+	pub fn parse(text: &str) -> Result<Connection, String> {
+		let file = json::parse(text)?;
+		let text = |key: &str| file.get(key).and_then(Value::as_str).map(str::to_string).ok_or_else(|| format!("the connection file has no {}", key));
+		let port = |key: &str| file.get(key).and_then(Value::as_i64).and_then(|port| u16::try_from(port).ok()).ok_or_else(|| format!("the connection file has no {}", key));
+
+		match file.get("signature_scheme").and_then(Value::as_str) {
+			None | Some("hmac-sha256") => {},
+			Some(scheme)               => return Err(format!("messages signed with {} aren't supported, only hmac-sha256", scheme)),
+		}
+
+		Ok(Connection {
+			transport: text("transport").unwrap_or_else(|_| "tcp".to_string()),
+			ip:        text("ip")?,
+			shell:     port("shell_port")?,
+			iopub:     port("iopub_port")?,
+			stdin:     port("stdin_port")?,
+			control:   port("control_port")?,
+			heartbeat: port("hb_port")?,
+			key:       text("key")?,
+		})
+	}
+
+	// The address of a channel's port
+	fn address(&self, port: u16) -> String {
+		format!("{}://{}:{}", self.transport, self.ip, port)
+	}
+}
+
+impl Signer {
+	// The signature of a message's parts, in hex, or nothing without a key
+	fn sign(&self, parts: &[&[u8]]) -> String {
+		if self.key.is_empty() {
+			return String::new();
+		}
+		let mut mac = Hmac::<Sha256>::new_from_slice(&self.key).expect("HMAC takes keys of any length");
+		for part in parts {
+			mac.update(part);
+		}
+		mac.finalize().into_bytes().iter().map(|byte| format!("{:02x}", byte)).collect()
+	}
+
+	// Read a message received on a channel, checking its signature
+	fn read(&self, frames: Vec<Vec<u8>>) -> Result<Message, String> {
+		let delimiter = frames.iter().position(|frame| frame == DELIMITER).ok_or("a message had no delimiter")?;
+		let (identities, rest) = frames.split_at(delimiter);
+		if rest.len() < 6 {
+			return Err("a message was missing parts".to_string());
+		}
+
+		let parts: Vec<&[u8]> = rest[2..6].iter().map(|part| part.as_slice()).collect();
+		if !self.key.is_empty() && self.sign(&parts).as_bytes() != rest[1].as_slice() {
+			return Err("a message's signature didn't match".to_string());
+		}
+
+		let part = |index: usize| json::parse(&String::from_utf8_lossy(parts[index]));
+		Ok(Message {identities: identities.to_vec(), header: part(0)?, content: part(3)?})
+	}
+
+	// Send a message of a kind in response to a parent, to the identities
+	fn send(&self, socket: &zmq::Socket, identities: &[Vec<u8>], kind: &str, parent: &Value, content: Value) -> Result<(), String> {
+		let header = object(vec![
+			("msg_id",   Value::String(unique())),
+			("session",  Value::String(self.session.clone())),
+			("username", Value::String("kernel".to_string())),
+			("date",     Value::String(now())),
+			("msg_type", Value::String(kind.to_string())),
+			("version",  Value::String(PROTOCOL_VERSION.to_string())),
+		]).to_string();
+		let parent   = parent.to_string();
+		let metadata = "{}".to_string();
+		let content  = content.to_string();
+		let signature = self.sign(&[header.as_bytes(), parent.as_bytes(), metadata.as_bytes(), content.as_bytes()]);
+
+		let mut frames: Vec<Vec<u8>> = identities.to_vec();
+		frames.push(DELIMITER.to_vec());
+		frames.extend(vec![signature, header, parent, metadata, content].into_iter().map(String::into_bytes));
+		socket.send_multipart(frames, 0).map_err(|error| error.to_string())
+	}
+
+	// Publish the kernel's status while it handles a message
+	fn status(&self, iopub: &zmq::Socket, parent: &Value, state: &str) -> Result<(), String> {
+		self.send(iopub, &[], "status", parent, object(vec![("execution_state", Value::String(state.to_string()))]))
+	}
+}
+
+impl Message {
+	// The kind of message this is, like execute_request
+	fn kind(&self) -> &str {
+		self.header.get("msg_type").and_then(Value::as_str).unwrap_or("")
+	}
+}
+
+impl Kernel {
+	// Handle the requests sent on the shell channel until one shuts the
+	// kernel down or the control channel does
+	fn serve(&mut self, shutdown: &AtomicBool) -> Result<(), String> {
+		while !shutdown.load(Ordering::SeqCst) {
+			if self.shell.poll(zmq::POLLIN, POLL_INTERVAL).map_err(|error| error.to_string())? == 0 {
+				continue;
+			}
+
+			let frames  = self.shell.recv_multipart(0).map_err(|error| error.to_string())?;
+			let request = match self.signer.read(frames) {
+				Ok(request) => request,
+				Err(error)  => {eprintln!("Warning: {}", error); continue},
+			};
+
+			self.signer.status(&self.iopub, &request.header, "busy")?;
+			let done = self.respond(&request)?;
+			self.signer.status(&self.iopub, &request.header, "idle")?;
+			if done {
+				break;
+			}
+		}
+
+		Ok(())
+	}
+
+	// Answer a request on the shell channel, giving whether it shut the
+	// kernel down
+	fn respond(&mut self, request: &Message) -> Result<bool, String> {
+		let cursor = request.content.get("cursor_pos").cloned().unwrap_or(Value::Number(0.0));
+		let (kind, content) = match request.kind() {
+			"kernel_info_request" => ("kernel_info_reply", kernel_info()),
+			"execute_request"     => ("execute_reply", self.execute(request)?),
+			"is_complete_request" => ("is_complete_reply", object(vec![("status", Value::String("complete".to_string()))])),
+			"comm_info_request"   => ("comm_info_reply", object(vec![("status", ok()), ("comms", object(Vec::new()))])),
+			"history_request"     => ("history_reply", object(vec![("status", ok()), ("history", Value::Array(Vec::new()))])),
+			"inspect_request"     => ("inspect_reply", object(vec![("status", ok()), ("found", Value::Bool(false)), ("data", object(Vec::new())), ("metadata", object(Vec::new()))])),
+			"complete_request"    => ("complete_reply", object(vec![
+				("status",       ok()),
+				("matches",      Value::Array(Vec::new())),
+				("cursor_start", cursor.clone()),
+				("cursor_end",   cursor),
+				("metadata",     object(Vec::new())),
+			])),
+			"shutdown_request" => {
+				let restart = request.content.get("restart").cloned().unwrap_or(Value::Bool(false));
+				self.signer.send(&self.shell, &request.identities, "shutdown_reply", &request.header, object(vec![("status", ok()), ("restart", restart)]))?;
+				return Ok(true);
+			},
+			_ => return Ok(false),
+		};
+
+		self.signer.send(&self.shell, &request.identities, kind, &request.header, content)?;
+		Ok(false)
+	}
+
+	// Execute a cell, publishing what it writes, and give the reply's content
+	fn execute(&mut self, request: &Message) -> Result<Value, String> {
+		let code   = request.content.get("code").and_then(Value::as_str).unwrap_or("");
+		let silent = request.content.get("silent") == Some(&Value::Bool(true));
+		if !silent {
+			self.count += 1;
+		}
+		let count = Value::Number(self.count as f64);
+
+		if !silent {
+			self.signer.send(&self.iopub, &[], "execute_input", &request.header, object(vec![
+				("code",            Value::String(code.to_string())),
+				("execution_count", count.clone()),
+			]))?;
+		}
+
+		let ran = match cell(code, &self.options) {
+			Ok(program) => self.run(program, request, silent),
+			Err(failed) => Err(failed),
+		};
+
+		let (name, message) = match ran {
+			Ok(())                => return Ok(object(vec![("status", ok()), ("execution_count", count), ("payload", Value::Array(Vec::new())), ("user_expressions", object(Vec::new()))])),
+			Err((name, message)) => (Value::String(name.to_string()), Value::String(message)),
+		};
+		let traceback = Value::Array(vec![message.clone()]);
+		if !silent {
+			self.signer.send(&self.iopub, &[], "error", &request.header, object(vec![
+				("ename",     name.clone()),
+				("evalue",    message.clone()),
+				("traceback", traceback.clone()),
+			]))?;
+		}
+		Ok(object(vec![
+			("status",          Value::String("error".to_string())),
+			("execution_count", count),
+			("ename",           name),
+			("evalue",          message),
+			("traceback",       traceback),
+		]))
+	}
+
+	// Run a cell's program on the last cell's heap, giving how it failed, if
+	// it did
+	fn run(&mut self, program: Vec<Action>, request: &Message, silent: bool) -> Result<(), (&'static str, String)> {
+		let output: Box<dyn Write> = if silent {
+			Box::new(io::sink())
+		} else {
+			Box::new(Stream {iopub: Rc::clone(&self.iopub), signer: self.signer.clone(), parent: request.header.clone(), buffer: Vec::new()})
+		};
+		let input: Box<dyn io::BufRead> = match request.content.get("allow_stdin") {
+			Some(&Value::Bool(true)) => Box::new(BufReader::new(Prompt {
+				stdin:      Rc::clone(&self.stdin),
+				signer:     self.signer.clone(),
+				identities: request.identities.clone(),
+				parent:     request.header.clone(),
+				prompt:     self.io_options.prompt.clone(),
+				line:       Vec::new(),
+			})),
+			_ => Box::new(io::empty()),
+		};
+
+		// Carry the heap over, the way the server does for loads that keep it
+		let mut vm = match self.vm.take() {
+			Some(mut vm) => {
+				let mut heap = HashHeap::default();
+				for (address, value) in vm.heap().iter() {
+					heap.set(address, value);
+				}
+				vm.recycle(program, self.options, input, output);
+				vm.set_heap(Box::new(heap));
+				vm
+			},
+			None => {
+				let mut vm = WhitespaceVM::with_io(program, self.options, input, output);
+				vm.set_io_options(self.io_options.clone());
+				vm
+			},
+		};
+
+		self.cancel.reset();
+		vm.set_limits(self.limits);
+		vm.set_cancel(self.cancel.clone());
+		let result = vm.execute();
+		self.vm = Some(vm);
+		result.map_err(failure)
+	}
+}
+
+impl Write for Stream {
+	fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+		self.buffer.extend_from_slice(bytes);
+		Ok(bytes.len())
+	}
+
+	fn flush(&mut self) -> io::Result<()> {
+		if self.buffer.is_empty() {
+			return Ok(());
+		}
+		let text = String::from_utf8_lossy(&self.buffer).into_owned();
+		self.buffer.clear();
+		self.signer.send(&self.iopub, &[], "stream", &self.parent, object(vec![
+			("name", Value::String("stdout".to_string())),
+			("text", Value::String(text)),
+		])).map_err(io::Error::other)
+	}
+}
+
+impl Read for Prompt {
+	fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+		if self.line.is_empty() {
+			self.line = self.ask().map_err(io::Error::other)?;
+		}
+		let length = buffer.len().min(self.line.len());
+		buffer[..length].copy_from_slice(&self.line[..length]);
+		self.line.drain(..length);
+		Ok(length)
+	}
+}
+
+impl Prompt {
+	// Ask the frontend for a line of input
+	fn ask(&self) -> Result<Vec<u8>, String> {
+		self.signer.send(&self.stdin, &self.identities, "input_request", &self.parent, object(vec![
+			("prompt",   Value::String(self.prompt.clone())),
+			("password", Value::Bool(false)),
+		]))?;
+
+		loop {
+			let frames = self.stdin.recv_multipart(0).map_err(|error| error.to_string())?;
+			let reply  = self.signer.read(frames)?;
+			if reply.kind() == "input_reply" {
+				let mut line = reply.content.get("value").and_then(Value::as_str).unwrap_or("").as_bytes().to_vec();
+				line.push(b'\n');
+				return Ok(line);
+			}
+		}
+	}
+}
+
+
+/********************************************/
+// Public Functions
+/********************************************/
+// Run the kernel until it's shut down. Output is sent to the frontend a
+// line at a time, whatever the flush policy.
+pub fn serve(connection: &Connection, options: Options, mut io_options: IoOptions, limits: Limits) -> Result<(), String> {
+	let context = zmq::Context::new();
+	let bind = |kind: zmq::SocketType, port: u16| -> Result<zmq::Socket, String> {
+		let socket = context.socket(kind).map_err(|error| error.to_string())?;
+		socket.bind(&connection.address(port)).map_err(|error| format!("{}: {}", connection.address(port), error))?;
+		Ok(socket)
+	};
+
+	let signer    = Signer {key: connection.key.as_bytes().to_vec(), session: unique()};
+	let shell     = bind(zmq::ROUTER, connection.shell)?;
+	let iopub     = bind(zmq::PUB, connection.iopub)?;
+	let stdin     = bind(zmq::ROUTER, connection.stdin)?;
+	let control   = bind(zmq::ROUTER, connection.control)?;
+	let heartbeat = bind(zmq::REP, connection.heartbeat)?;
+
+	thread::Builder::new().spawn(move || {
+		while let Ok(ping) = heartbeat.recv_bytes(0) {
+			if heartbeat.send(ping, 0).is_err() {
+				break;
+			}
+		}
+	}).map_err(|error| error.to_string())?;
+
+	let cancel   = Cancel::default();
+	let shutdown = Arc::new(AtomicBool::new(false));
+	{
+		let (signer, cancel, shutdown) = (signer.clone(), cancel.clone(), Arc::clone(&shutdown));
+		thread::Builder::new().spawn(move || {
+			if let Err(error) = control_channel(&control, &signer, &cancel, &shutdown) {
+				eprintln!("Error: {}", error);
+			}
+		}).map_err(|error| error.to_string())?;
+	}
+
+	io_options.flush = FlushPolicy::Newline;
+	signer.status(&iopub, &object(Vec::new()), "starting")?;
+	let mut kernel = Kernel {signer, shell, iopub: Rc::new(iopub), stdin: Rc::new(stdin), options, io_options, limits, cancel, vm: None, count: 0};
+	kernel.serve(&shutdown)
+}
+
+
+/********************************************/
+// Private Functions
+/********************************************/
+// Answer the requests sent on the control channel, interrupting the cell
+// that's running when asked, until the kernel is shut down
+fn control_channel(control: &zmq::Socket, signer: &Signer, cancel: &Cancel, shutdown: &AtomicBool) -> Result<(), String> {
+	loop {
+		let frames  = control.recv_multipart(0).map_err(|error| error.to_string())?;
+		let request = match signer.read(frames) {
+			Ok(request) => request,
+			Err(error)  => {eprintln!("Warning: {}", error); continue},
+		};
+
+		match request.kind() {
+			"kernel_info_request" => signer.send(control, &request.identities, "kernel_info_reply", &request.header, kernel_info())?,
+			"interrupt_request"   => {
+				cancel.cancel();
+				signer.send(control, &request.identities, "interrupt_reply", &request.header, object(vec![("status", ok())]))?;
+			},
+			"shutdown_request" => {
+				cancel.cancel();
+				shutdown.store(true, Ordering::SeqCst);
+				let restart = request.content.get("restart").cloned().unwrap_or(Value::Bool(false));
+				signer.send(control, &request.identities, "shutdown_reply", &request.header, object(vec![("status", ok()), ("restart", restart)]))?;
+				return Ok(());
+			},
+			_ => {},
+		}
+	}
+}
+
+
+// Turn a cell into the program it holds, giving the name of the way it
+// failed and why if it doesn't hold one
+fn cell(code: &str, options: &Options) -> Result<Vec<Action>, (&'static str, String)> {
+	let (first, rest) = code.split_once('\n').unwrap_or((code, ""));
+
+	let source = match first.trim() {
+		"%%whitespace"                                  => rest.to_string(),
+		"%%stl"                                         => parser::from_stl(rest),
+		_ if code.chars().all(char::is_whitespace)      => code.to_string(),
+		_ => {
+			let assembly = assembler::assemble(code).map_err(|error| ("parse-error", error))?;
+			return parser::reduce_labels(assembly.program, &assembly.labels, options.labels).map_err(failure);
+		},
+	};
+	parser::parse_and_reduce(source, options).map_err(failure)
+}
+
+
+// The name of the way a cell failed, as conformance fixtures give it,
+// and why
+fn failure(error: WhitespaceError) -> (&'static str, String) {
+	let message = error.to_string();
+	(Termination::of(&Err(error)).name(), message)
+}
+
+
+// What the kernel tells frontends about itself
+fn kernel_info() -> Value {
+	object(vec![
+		("status",                 ok()),
+		("protocol_version",       Value::String(PROTOCOL_VERSION.to_string())),
+		("implementation",         Value::String("whitespace".to_string())),
+		("implementation_version", Value::String(env!("CARGO_PKG_VERSION").to_string())),
+		("language_info",          object(vec![
+			("name",           Value::String("whitespace".to_string())),
+			("version",        Value::String(String::new())),
+			("mimetype",       Value::String("text/x-whitespace".to_string())),
+			("file_extension", Value::String(".ws".to_string())),
+		])),
+		("banner",     Value::String("Whitespace, with cells of assembly, %%whitespace or %%stl".to_string())),
+		("help_links", Value::Array(Vec::new())),
+	])
+}
+
+
+// A JSON object of the members given
+fn object(members: Vec<(&str, Value)>) -> Value {
+	Value::Object(members.into_iter().map(|(key, value)| (key.to_string(), value)).collect())
+}
+
+
+// The status of a reply that succeeded
+fn ok() -> Value {
+	Value::String("ok".to_string())
+}
+
+
+// An id nothing else has, for messages and sessions
+fn unique() -> String {
+	let mut hasher = RandomState::new().build_hasher();
+	hasher.write_u128(SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |time| time.as_nanos()));
+	let high = hasher.finish();
+	hasher.write_u64(high);
+	format!("{:016x}{:016x}", high, hasher.finish())
+}
+
+
+// The time now, in ISO 8601 as message headers have it
+fn now() -> String {
+	let time    = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+	let seconds = time.as_secs() as i64;
+	let (days, second) = (seconds.div_euclid(86_400), seconds.rem_euclid(86_400));
+
+	// Days since 1970 to a date, counting in 400 year eras from March of the year 0
+	let days  = days + 719_468;
+	let era   = days.div_euclid(146_097);
+	let day   = days.rem_euclid(146_097);
+	let year  = (day - day / 1_460 + day / 36_524 - day / 146_096) / 365;
+	let yday  = day - (365 * year + year / 4 - year / 100);
+	let month = (5 * yday + 2) / 153;
+	let mday  = yday - (153 * month + 2) / 5 + 1;
+	let month = if month < 10 {month + 3} else {month - 9};
+	let year  = era * 400 + year + (month <= 2) as i64;
+
+	format!("{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:06}Z", year, month, mday, second / 3_600, second / 60 % 60, second % 60, time.subsec_micros())
+}
+
+
+/********************************************/
+// Tests
+/********************************************/
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::net::TcpListener;
+	use std::time::Duration;
+
+	// A frontend talking to a kernel the way Jupyter does
+	struct Frontend {
+		signer:    Signer,
+		shell:     zmq::Socket,
+		iopub:     zmq::Socket,
+		stdin:     zmq::Socket,
+		control:   zmq::Socket,
+		heartbeat: zmq::Socket,
+	}
+
+	impl Frontend {
+		// Send a request on a channel and wait for its reply
+		fn request(&self, socket: &zmq::Socket, kind: &str, content: Value) -> Message {
+			self.signer.send(socket, &[], kind, &object(Vec::new()), content).unwrap();
+			self.signer.read(socket.recv_multipart(0).unwrap()).unwrap()
+		}
+
+		// Execute a cell, giving its reply and what it wrote, answering each
+		// read with the next of the lines given
+		fn execute(&self, code: &str, lines: &[&str]) -> (Value, String) {
+			let allow = !lines.is_empty();
+			self.signer.send(&self.shell, &[], "execute_request", &object(Vec::new()), object(vec![
+				("code",        Value::String(code.to_string())),
+				("silent",      Value::Bool(false)),
+				("allow_stdin", Value::Bool(allow)),
+			])).unwrap();
+
+			for line in lines {
+				let asked = self.signer.read(self.stdin.recv_multipart(0).unwrap()).unwrap();
+				assert_eq!(asked.kind(), "input_request");
+				self.signer.send(&self.stdin, &[], "input_reply", &asked.header, object(vec![("value", Value::String(line.to_string()))])).unwrap();
+			}
+			let reply = self.signer.read(self.shell.recv_multipart(0).unwrap()).unwrap();
+
+			// What the cell published ends with the kernel going idle
+			let mut output = String::new();
+			loop {
+				let published = self.signer.read(self.iopub.recv_multipart(0).unwrap()).unwrap();
+				match published.kind() {
+					"stream"                                                                                  => output.push_str(published.content.get("text").and_then(Value::as_str).unwrap()),
+					"status" if published.content.get("execution_state").and_then(Value::as_str) == Some("idle") => break,
+					_                                                                                         => {},
+				}
+			}
+			(reply.content, output)
+		}
+	}
+
+	// Ports nothing is listening on
+	fn free_ports() -> Vec<u16> {
+		let listeners: Vec<TcpListener> = (0..5).map(|_| TcpListener::bind("127.0.0.1:0").unwrap()).collect();
+		listeners.iter().map(|listener| listener.local_addr().unwrap().port()).collect()
+	}
+
+	// Start a kernel on a thread of its own and connect a frontend to it
+	fn start() -> (Frontend, thread::JoinHandle<Result<(), String>>) {
+		let ports = free_ports();
+		let connection = Connection::parse(&format!(
+			"{{\"transport\":\"tcp\",\"ip\":\"127.0.0.1\",\"shell_port\":{},\"iopub_port\":{},\"stdin_port\":{},\"control_port\":{},\"hb_port\":{},\"key\":\"secret\",\"signature_scheme\":\"hmac-sha256\"}}",
+			ports[0], ports[1], ports[2], ports[3], ports[4],
+		)).unwrap();
+		let kernel = {
+			let connection = connection.clone();
+			thread::spawn(move || serve(&connection, Options::default(), IoOptions::default(), Limits::default()))
+		};
+
+		let context = zmq::Context::new();
+		// Jupyter gives the shell and stdin sockets the same identity, which is
+		// how the kernel knows where to ask for a cell's input
+		let connect = |kind: zmq::SocketType, port: u16| {
+			let socket = context.socket(kind).unwrap();
+			socket.set_identity(b"frontend").unwrap();
+			socket.set_rcvtimeo(10_000).unwrap();
+			socket.connect(&connection.address(port)).unwrap();
+			socket
+		};
+		let frontend = Frontend {
+			signer:    Signer {key: b"secret".to_vec(), session: unique()},
+			shell:     connect(zmq::DEALER, connection.shell),
+			iopub:     connect(zmq::SUB, connection.iopub),
+			stdin:     connect(zmq::DEALER, connection.stdin),
+			control:   connect(zmq::DEALER, connection.control),
+			heartbeat: connect(zmq::REQ, connection.heartbeat),
+		};
+		frontend.iopub.set_subscribe(b"").unwrap();
+
+		// Give the subscription time to reach the kernel, so nothing published is missed
+		thread::sleep(Duration::from_millis(300));
+		(frontend, kernel)
+	}
+
+	#[test]
+	fn kernel_runs_cells_keeping_the_heap() {
+		let (frontend, kernel) = start();
+
+		let info = frontend.request(&frontend.shell, "kernel_info_request", object(Vec::new()));
+		assert_eq!(info.kind(), "kernel_info_reply");
+		assert_eq!(info.content.get("protocol_version").and_then(Value::as_str), Some(PROTOCOL_VERSION));
+		while frontend.iopub.poll(zmq::POLLIN, 200).unwrap() > 0 {
+			frontend.iopub.recv_multipart(0).unwrap();
+		}
+
+		let (reply, output) = frontend.execute("push 0\npush 42\nstore\npush 'h'\noutc\npush 10\noutc\nhalt", &[]);
+		assert_eq!(reply.get("status").and_then(Value::as_str), Some("ok"));
+		assert_eq!(reply.get("execution_count").and_then(Value::as_i64), Some(1));
+		assert_eq!(output, "h\n");
+
+		let (reply, output) = frontend.execute(&format!("%%stl\n{}", "SSSSL TTT SSSTL TSSS TLST LLL"), &[]);
+		assert_eq!(reply.get("status").and_then(Value::as_str), Some("ok"));
+		assert_eq!(output, "43");
+
+		let (reply, output) = frontend.execute("push 'a'\noutc\npush 1\npush 0\ndiv\nhalt", &[]);
+		assert_eq!(reply.get("status").and_then(Value::as_str), Some("error"));
+		assert_eq!(reply.get("ename").and_then(Value::as_str), Some("runtime-error"));
+		assert_eq!(reply.get("execution_count").and_then(Value::as_i64), Some(3));
+		assert_eq!(output, "a");
+
+		let (reply, _) = frontend.execute("push", &[]);
+		assert_eq!(reply.get("ename").and_then(Value::as_str), Some("parse-error"));
+
+		let (reply, output) = frontend.execute("push 1\nreadn\npush 1\nretrieve\npush 0\nretrieve\nadd\noutn\nhalt", &["7"]);
+		assert_eq!(reply.get("status").and_then(Value::as_str), Some("ok"));
+		assert_eq!(output, "49");
+
+		// A cell that loops forever runs until it's interrupted
+		frontend.signer.send(&frontend.shell, &[], "execute_request", &object(Vec::new()), object(vec![("code", Value::String("loop:\njump loop".to_string()))])).unwrap();
+		thread::sleep(Duration::from_millis(100));
+		assert_eq!(frontend.request(&frontend.control, "interrupt_request", object(Vec::new())).kind(), "interrupt_reply");
+		let reply = frontend.signer.read(frontend.shell.recv_multipart(0).unwrap()).unwrap();
+		assert_eq!(reply.content.get("ename").and_then(Value::as_str), Some("cancelled"));
+
+		frontend.heartbeat.send("ping", 0).unwrap();
+		assert_eq!(frontend.heartbeat.recv_bytes(0).unwrap(), b"ping");
+
+		let shutdown = frontend.request(&frontend.control, "shutdown_request", object(vec![("restart", Value::Bool(false))]));
+		assert_eq!(shutdown.kind(), "shutdown_reply");
+		assert_eq!(kernel.join().unwrap(), Ok(()));
+	}
+
+	#[test]
+	fn signatures_are_checked() {
+		let connection = "{\"ip\":\"127.0.0.1\",\"shell_port\":1,\"iopub_port\":2,\"stdin_port\":3,\"control_port\":4,\"hb_port\":5,\"key\":\"k\",\"signature_scheme\":\"hmac-md5\"}";
+		assert!(Connection::parse(connection).unwrap_err().contains("hmac-md5"));
+
+		let signer = Signer {key: b"key".to_vec(), session: unique()};
+		let frames = vec![b"id".to_vec(), DELIMITER.to_vec(), b"bad".to_vec(), b"{}".to_vec(), b"{}".to_vec(), b"{}".to_vec(), b"{}".to_vec()];
+		assert_eq!(signer.read(frames).err(), Some("a message's signature didn't match".to_string()));
+
+		// The signature is HMAC-SHA256, as RFC 4231 gives it for this key and message
+		let signer = Signer {key: b"Jefe".to_vec(), session: String::new()};
+		assert_eq!(signer.sign(&[b"what do ya want ", b"for nothing?"]), "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843");
+	}
+
+	#[test]
+	fn dates_are_iso_8601() {
+		let date = now();
+		assert_eq!(date.len(), "2026-10-14T13:39:21.000000Z".len());
+		assert!(date.starts_with("20") && date.ends_with('Z'));
+		assert_eq!(&date[10..11], "T");
+	}
+}
@@ -0,0 +1,1356 @@
+/***********************************************************/
+//
+// JVM Backend
+// ===========
+// Compiles a program to a class file, so it runs anywhere
+// there's a JVM. The class is always called Program, so the
+// output has to be saved as Program.class:
+//
+//    whitespace compile --target jvm prog.ws > Program.class
+//    java Program < input.txt
+//
+// The class keeps a long[] operand stack and a HashMap heap
+// in static fields, with a small method for each thing an
+// action does that can fail. The program itself is the body
+// of main, where jumps are gotos and returning from a call
+// looks the return address up in a lookupswitch.
+//
+// The class file is version 49, the last one the JVM will
+// verify without stack map frames, which keeps the
+// assembler below to bytes, labels and a constant pool.
+//
+// struct Jvm <- The JVM backend
+//
+/***********************************************************/
+use std::collections::HashMap;
+use analysis;
+use compile;
+use compile::Backend;
+use labels::LabelId;
+use options::{CharEncoding, Division, EndOfProgram, EmptyLine, EofPolicy, InvalidChar, InvalidNumber, Options, ReadCharDestination, UnsetHeap};
+use parser::UNRESOLVED;
+use symbols::Action;
+use symbols::Action::*;
+
+
+/********************************************/
+// Constants
+/********************************************/
+// The name of the compiled class
+const CLASS: &str = "Program";
+
+// The operand stack slots given to every method, more than any of them use
+const MAX_STACK: u16 = 16;
+
+// Class, field and method access flags
+const ACC_PUBLIC:  u16 = 0x0001;
+const ACC_PRIVATE: u16 = 0x0002;
+const ACC_STATIC:  u16 = 0x0008;
+const ACC_SUPER:   u16 = 0x0020;
+
+// The opcodes the backend writes
+const ACONST_NULL:   u8 = 0x01;
+const ICONST_0:      u8 = 0x03;
+const LCONST_0:      u8 = 0x09;
+const LCONST_1:      u8 = 0x0a;
+const BIPUSH:        u8 = 0x10;
+const SIPUSH:        u8 = 0x11;
+const LDC:           u8 = 0x12;
+const LDC_W:         u8 = 0x13;
+const LDC2_W:        u8 = 0x14;
+const ILOAD:         u8 = 0x15;
+const LLOAD:         u8 = 0x16;
+const ALOAD:         u8 = 0x19;
+const IALOAD:        u8 = 0x2e;
+const LALOAD:        u8 = 0x2f;
+const ISTORE:        u8 = 0x36;
+const LSTORE:        u8 = 0x37;
+const ASTORE:        u8 = 0x3a;
+const IASTORE:       u8 = 0x4f;
+const LASTORE:       u8 = 0x50;
+const POP:           u8 = 0x57;
+const POP2:          u8 = 0x58;
+const DUP:           u8 = 0x59;
+const DUP2:          u8 = 0x5c;
+const DUP2_X2:       u8 = 0x5e;
+const SWAP:          u8 = 0x5f;
+const IADD:          u8 = 0x60;
+const LADD:          u8 = 0x61;
+const ISUB:          u8 = 0x64;
+const LSUB:          u8 = 0x65;
+const IMUL:          u8 = 0x68;
+const LDIV:          u8 = 0x6d;
+const LREM:          u8 = 0x71;
+const IAND:          u8 = 0x7e;
+const I2L:           u8 = 0x85;
+const L2I:           u8 = 0x88;
+const I2C:           u8 = 0x92;
+const LCMP:          u8 = 0x94;
+const IFEQ:          u8 = 0x99;
+const IFNE:          u8 = 0x9a;
+const IFLT:          u8 = 0x9b;
+const IFGE:          u8 = 0x9c;
+const IFGT:          u8 = 0x9d;
+const IFLE:          u8 = 0x9e;
+const IF_ICMPEQ:     u8 = 0x9f;
+const IF_ICMPLT:     u8 = 0xa1;
+const IF_ICMPLE:     u8 = 0xa4;
+const LOOKUPSWITCH:  u8 = 0xab;
+const IRETURN:       u8 = 0xac;
+const LRETURN:       u8 = 0xad;
+const ARETURN:       u8 = 0xb0;
+const RETURN:        u8 = 0xb1;
+const GETSTATIC:     u8 = 0xb2;
+const PUTSTATIC:     u8 = 0xb3;
+const INVOKEVIRTUAL: u8 = 0xb6;
+const INVOKESPECIAL: u8 = 0xb7;
+const INVOKESTATIC:  u8 = 0xb8;
+const NEW:           u8 = 0xbb;
+const NEWARRAY:      u8 = 0xbc;
+const ARRAYLENGTH:   u8 = 0xbe;
+const CHECKCAST:     u8 = 0xc0;
+const INSTANCEOF:    u8 = 0xc1;
+const IFNULL:        u8 = 0xc6;
+const IFNONNULL:     u8 = 0xc7;
+const GOTO_W:        u8 = 0xc8;
+
+// The element types given to newarray
+const T_INT:  u8 = 10;
+const T_LONG: u8 = 11;
+
+// The class's static fields, holding the state of the running program
+static FIELDS: &[(&str, &str)] = &[
+	("stack", "[J"),                    // The operand stack, grown as it fills
+	("size",  "I"),                     // How many values it holds
+	("calls", "[I"),                    // The actions calls return to
+	("depth", "I"),                     // How many calls are unfinished
+	("heap",  "Ljava/util/HashMap;"),   // Long addresses to Long values
+	("out",   "Ljava/io/PrintStream;"), // Standard output, buffered until a read or the end
+];
+
+
+/********************************************/
+// Structures
+/********************************************/
+// The JVM backend
+pub struct Jvm;
+
+impl Backend for Jvm {
+	fn name(&self) -> &'static str {
+		"jvm"
+	}
+
+	fn emit(&self, program: &[Action], options: &Options) -> Result<Vec<u8>, String> {
+		let mut class = ClassFile::new();
+		for &(name, descriptor) in FIELDS {
+			class.field(name, descriptor);
+		}
+
+		class.method(ACC_PUBLIC, "main", "([Ljava/lang/String;)V", 1, |code| self.main(code, program, options))?;
+		class.method(ACC_PRIVATE, "push",  "(J)V", 2, |code| {grow(code, "stack", "size", "[J", LLOAD, LASTORE); Ok(())})?;
+		class.method(ACC_PRIVATE, "enter", "(I)V", 1, |code| {grow(code, "calls", "depth", "[I", ILOAD, IASTORE); Ok(())})?;
+		class.method(ACC_PRIVATE, "pop",   "()J",  0, |code| {pop(code); Ok(())})?;
+		class.method(ACC_PRIVATE, "leave", "(I)I", 1, |code| {leave(code); Ok(())})?;
+		class.method(ACC_PRIVATE, "need",  "(IILjava/lang/String;)V", 3, |code| {need(code); Ok(())})?;
+		class.method(ACC_PRIVATE, "fail",  "(ILjava/lang/String;)V", 2, |code| {fail(code); Ok(())})?;
+
+		class.method(ACC_PRIVATE, "add",      "(I)V", 5, |code| {exact(code, "addExact", "Addition overflowed."); Ok(())})?;
+		class.method(ACC_PRIVATE, "subtract", "(I)V", 5, |code| {exact(code, "subtractExact", "Subtraction overflowed."); Ok(())})?;
+		class.method(ACC_PRIVATE, "multiply", "(I)V", 5, |code| {exact(code, "multiplyExact", "Multiplication overflowed."); Ok(())})?;
+		class.method(ACC_PRIVATE, "divide",   "(I)V", 7, |code| {divide(code, options.division); Ok(())})?;
+		class.method(ACC_PRIVATE, "modulo",   "(I)V", 7, |code| {modulo(code, options.division); Ok(())})?;
+
+		class.method(ACC_PRIVATE, "store",    "()V",   4, |code| {store(code); Ok(())})?;
+		class.method(ACC_PRIVATE, "retrieve", "(IJ)J", 4, |code| {retrieve(code, options.unset_heap); Ok(())})?;
+
+		class.method(ACC_PRIVATE, "outputChar", "(I)V", 3, |code| {output_char(code, options); Ok(())})?;
+		class.method(ACC_PRIVATE, "readChar",   "(I)V", 4, |code| {read_char(code, options); Ok(())})?;
+		class.method(ACC_PRIVATE, "readNumber", "(I)V", 5, |code| {read_number(code, options); Ok(())})?;
+		class.method(ACC_PRIVATE, "readLine",   "()Ljava/lang/String;", 2, |code| {read_line(code); Ok(())})?;
+		class.method(ACC_PRIVATE, "parse",      "(Ljava/lang/String;)Ljava/lang/Object;", 3, |code| {parse(code, options); Ok(())})?;
+		class.method(ACC_PRIVATE, "quote",      "(Ljava/lang/String;)Ljava/lang/String;", 1, |code| {quote(code); Ok(())})?;
+
+		class.finish()
+	}
+}
+
+impl Jvm {
+	// The body of main: set up the state, then run the program
+	fn main(&self, code: &mut Code, program: &[Action], options: &Options) -> Result<(), String> {
+		code.int(16);
+		code.push(NEWARRAY, T_LONG);
+		code.put("stack", "[J");
+		code.int(16);
+		code.push(NEWARRAY, T_INT);
+		code.put("calls", "[I");
+		code.new_object("java/util/HashMap");
+		code.invoke(INVOKESPECIAL, "java/util/HashMap", "<init>", "()V");
+		code.put("heap", "Ljava/util/HashMap;");
+
+		// new PrintStream(new BufferedOutputStream(new FileOutputStream(FileDescriptor.out)), false, "UTF-8")
+		code.new_object("java/io/PrintStream");
+		code.new_object("java/io/BufferedOutputStream");
+		code.new_object("java/io/FileOutputStream");
+		code.field(GETSTATIC, "java/io/FileDescriptor", "out", "Ljava/io/FileDescriptor;");
+		code.invoke(INVOKESPECIAL, "java/io/FileOutputStream", "<init>", "(Ljava/io/FileDescriptor;)V");
+		code.invoke(INVOKESPECIAL, "java/io/BufferedOutputStream", "<init>", "(Ljava/io/OutputStream;)V");
+		code.int(0);
+		code.text("UTF-8");
+		code.invoke(INVOKESPECIAL, "java/io/PrintStream", "<init>", "(Ljava/io/OutputStream;ZLjava/lang/String;)V");
+		code.put("out", "Ljava/io/PrintStream;");
+
+		let labels: Vec<Target> = (0..program.len() + 1).map(|_| code.label()).collect();
+		let halt = code.label();
+		let returns: Vec<(i32, Target)> = program.iter().enumerate()
+			.filter(|&(_, action)| matches!(*action, Call(_)))
+			.map(|(index, _)| (index as i32 + 1, labels[index + 1]))
+			.collect();
+
+		for (index, action) in program.iter().enumerate() {
+			code.place(labels[index]);
+
+			let needed = analysis::stack_effect(action).pops;
+			if needed > 0 {
+				code.int(index as i32);
+				code.int(needed as i32);
+				code.text(&format!("{:?}", action));
+				code.call("need", "(IILjava/lang/String;)V");
+			}
+
+			let jump = |code: &mut Code, LabelId(target)| if target == UNRESOLVED {
+				code.int(index as i32);
+				code.text("Tried to jump to a label that doesn't exist.");
+				code.call("fail", "(ILjava/lang/String;)V");
+				code.goto(halt);
+			} else {
+				code.goto(labels[target as usize]);
+			};
+
+			match *action {
+				StackPush(value) => {code.long(value); code.call("push", "(J)V");},
+				StackDuplicate   => {code.call("pop", "()J"); code.op(DUP2); code.call("push", "(J)V"); code.call("push", "(J)V");},
+				StackSwap        => {
+					code.call("pop", "()J");
+					code.call("pop", "()J");
+					code.op(DUP2_X2);
+					code.op(POP2);
+					code.call("push", "(J)V");
+					code.call("push", "(J)V");
+				},
+				StackDiscard => {code.call("pop", "()J"); code.op(POP2);},
+
+				Add      => {code.int(index as i32); code.call("add", "(I)V");},
+				Subtract => {code.int(index as i32); code.call("subtract", "(I)V");},
+				Multiply => {code.int(index as i32); code.call("multiply", "(I)V");},
+				Divide   => {code.int(index as i32); code.call("divide", "(I)V");},
+				Modulo   => {code.int(index as i32); code.call("modulo", "(I)V");},
+
+				HeapStore    => code.call("store", "()V"),
+				HeapRetrieve => {
+					code.int(index as i32);
+					code.call("pop", "()J");
+					code.call("retrieve", "(IJ)J");
+					code.call("push", "(J)V");
+				},
+
+				Call(target) => {
+					code.int(index as i32 + 1);
+					code.call("enter", "(I)V");
+					jump(code, target);
+				},
+				Jump(target) => jump(code, target),
+				JumpIfZero(target) | JumpIfNegative(target) => {
+					let skip = code.label();
+					code.call("pop", "()J");
+					code.op(LCONST_0);
+					code.op(LCMP);
+					code.branch(if matches!(*action, JumpIfZero(_)) {IFNE} else {IFGE}, skip);
+					jump(code, target);
+					code.place(skip);
+				},
+				EndSubroutine => {
+					code.int(index as i32);
+					code.call("leave", "(I)I");
+					code.lookup(halt, &returns);
+				},
+				Halt => code.goto(halt),
+
+				OutputChar => {code.int(index as i32); code.call("outputChar", "(I)V");},
+				OutputNumber => {
+					code.get("out", "Ljava/io/PrintStream;");
+					code.call("pop", "()J");
+					code.invoke(INVOKEVIRTUAL, "java/io/PrintStream", "print", "(J)V");
+				},
+				ReadChar   => {code.int(index as i32); code.call("readChar", "(I)V");},
+				ReadNumber => {code.int(index as i32); code.call("readNumber", "(I)V");},
+
+				Label(_) | GetEnv | DumpHeap | Assert | CallDepth | PeekReturn | OutputFormatted | Clock | Random => return Err(compile::unsupported(self, action)),
+			}
+		}
+
+		// Running off the end
+		code.place(labels[program.len()]);
+		if options.end_of_program == EndOfProgram::Error {
+			code.int(program.len() as i32);
+			code.text("Reached the end of the program without halting.");
+			code.call("fail", "(ILjava/lang/String;)V");
+		}
+
+		code.place(halt);
+		code.get("out", "Ljava/io/PrintStream;");
+		code.invoke(INVOKEVIRTUAL, "java/io/PrintStream", "flush", "()V");
+		code.op(RETURN);
+		Ok(())
+	}
+}
+
+
+// A class file being written
+struct ClassFile {
+	pool:    Pool,
+	fields:  Vec<u8>,
+	methods: Vec<u8>,
+	counts:  (u16, u16), // How many fields and methods have been written
+}
+
+impl ClassFile {
+	fn new() -> ClassFile {
+		ClassFile {pool: Pool::new(), fields: Vec::new(), methods: Vec::new(), counts: (0, 0)}
+	}
+
+	// Add a private static field
+	fn field(&mut self, name: &str, descriptor: &str) {
+		let (name, descriptor) = (self.pool.utf8(name), self.pool.utf8(descriptor));
+		put_u16(&mut self.fields, ACC_PRIVATE | ACC_STATIC);
+		put_u16(&mut self.fields, name);
+		put_u16(&mut self.fields, descriptor);
+		put_u16(&mut self.fields, 0);
+		self.counts.0 += 1;
+	}
+
+	// Add a static method, with locals slots for its arguments and variables,
+	// and a body written by a function
+	fn method<F>(&mut self, access: u16, name: &str, descriptor: &str, locals: u16, body: F) -> Result<(), String>
+	where F: FnOnce(&mut Code) -> Result<(), String> {
+		let (code, handlers) = {
+			let mut code = Code::new(&mut self.pool);
+			body(&mut code)?;
+			code.assemble()?
+		};
+
+		let (name, descriptor, attribute) = (self.pool.utf8(name), self.pool.utf8(descriptor), self.pool.utf8("Code"));
+		put_u16(&mut self.methods, access | ACC_STATIC);
+		put_u16(&mut self.methods, name);
+		put_u16(&mut self.methods, descriptor);
+		put_u16(&mut self.methods, 1);
+
+		put_u16(&mut self.methods, attribute);
+		put_u32(&mut self.methods, (12 + code.len() + handlers.len()) as u32);
+		put_u16(&mut self.methods, MAX_STACK);
+		put_u16(&mut self.methods, locals);
+		put_u32(&mut self.methods, code.len() as u32);
+		self.methods.extend_from_slice(&code);
+		put_u16(&mut self.methods, (handlers.len() / 8) as u16);
+		self.methods.extend_from_slice(&handlers);
+		put_u16(&mut self.methods, 0);
+
+		self.counts.1 += 1;
+		Ok(())
+	}
+
+	// The bytes of the finished class file
+	fn finish(mut self) -> Result<Vec<u8>, String> {
+		let (this, superclass) = (self.pool.class(CLASS), self.pool.class("java/lang/Object"));
+		if self.pool.count > u16::MAX as usize {
+			return Err("the program needs more constants than a class file can hold".to_string());
+		}
+
+		let mut bytes = vec![0xCA, 0xFE, 0xBA, 0xBE];
+		put_u16(&mut bytes, 0);  // Minor version
+		put_u16(&mut bytes, 49); // Major version
+		put_u16(&mut bytes, self.pool.count as u16);
+		bytes.extend_from_slice(&self.pool.bytes);
+
+		put_u16(&mut bytes, ACC_PUBLIC | ACC_SUPER);
+		put_u16(&mut bytes, this);
+		put_u16(&mut bytes, superclass);
+		put_u16(&mut bytes, 0); // Interfaces
+		put_u16(&mut bytes, self.counts.0);
+		bytes.extend_from_slice(&self.fields);
+		put_u16(&mut bytes, self.counts.1);
+		bytes.extend_from_slice(&self.methods);
+		put_u16(&mut bytes, 0); // Attributes
+		Ok(bytes)
+	}
+}
+
+
+// The constant pool, where each distinct constant is written once
+struct Pool {
+	bytes:   Vec<u8>,
+	count:   usize, // The index the next constant gets, which starts from one
+	indices: HashMap<Vec<u8>, u16>,
+}
+
+impl Pool {
+	fn new() -> Pool {
+		Pool {bytes: Vec::new(), count: 1, indices: HashMap::new()}
+	}
+
+	// The index of a constant, given its encoding and how many slots it takes
+	fn entry(&mut self, encoding: Vec<u8>, slots: usize) -> u16 {
+		if let Some(&index) = self.indices.get(&encoding) {
+			return index;
+		}
+
+		let index = self.count as u16;
+		self.bytes.extend_from_slice(&encoding);
+		self.indices.insert(encoding, index);
+		self.count += slots;
+		index
+	}
+
+	// Every string here is ASCII, which modified UTF-8 leaves as it is
+	fn utf8(&mut self, text: &str) -> u16 {
+		let mut encoding = vec![1];
+		put_u16(&mut encoding, text.len() as u16);
+		encoding.extend_from_slice(text.as_bytes());
+		self.entry(encoding, 1)
+	}
+
+	fn integer(&mut self, value: i32) -> u16 {
+		let mut encoding = vec![3];
+		put_u32(&mut encoding, value as u32);
+		self.entry(encoding, 1)
+	}
+
+	fn long(&mut self, value: i64) -> u16 {
+		let mut encoding = vec![5];
+		encoding.extend_from_slice(&value.to_be_bytes());
+		self.entry(encoding, 2)
+	}
+
+	fn class(&mut self, name: &str) -> u16 {
+		self.reference(7, &[name])
+	}
+
+	fn string(&mut self, text: &str) -> u16 {
+		self.reference(8, &[text])
+	}
+
+	fn field(&mut self, class: &str, name: &str, descriptor: &str) -> u16 {
+		self.member(9, class, name, descriptor)
+	}
+
+	fn method(&mut self, class: &str, name: &str, descriptor: &str) -> u16 {
+		self.member(10, class, name, descriptor)
+	}
+
+	fn member(&mut self, tag: u8, class: &str, name: &str, descriptor: &str) -> u16 {
+		let (class, signature) = (self.class(class), self.reference(12, &[name, descriptor]));
+		let mut encoding = vec![tag];
+		put_u16(&mut encoding, class);
+		put_u16(&mut encoding, signature);
+		self.entry(encoding, 1)
+	}
+
+	// A constant made of references to utf8 constants
+	fn reference(&mut self, tag: u8, texts: &[&str]) -> u16 {
+		let mut encoding = vec![tag];
+		for text in texts {
+			let index = self.utf8(text);
+			put_u16(&mut encoding, index);
+		}
+		self.entry(encoding, 1)
+	}
+}
+
+
+// A place in a method's code that can be jumped to
+#[derive(Debug, Copy, Clone)]
+struct Target(usize);
+
+// The code of a method being written
+struct Code<'a> {
+	pool:     &'a mut Pool,
+	bytes:    Vec<u8>,
+	labels:   Vec<Option<usize>>,
+	offsets:  Vec<(usize, usize, Target, bool)>, // Where a jump is, where its offset goes, where it goes to, and whether the offset is 4 bytes
+	handlers: Vec<(Target, Target, Target, u16)>, // The start and end of the code caught, the handler, and the class caught
+}
+
+impl<'a> Code<'a> {
+	fn new(pool: &'a mut Pool) -> Code<'a> {
+		Code {pool, bytes: Vec::new(), labels: Vec::new(), offsets: Vec::new(), handlers: Vec::new()}
+	}
+
+	// A new label, placed later
+	fn label(&mut self) -> Target {
+		self.labels.push(None);
+		Target(self.labels.len() - 1)
+	}
+
+	// Make a label point at the next instruction
+	fn place(&mut self, label: Target) {
+		self.labels[label.0] = Some(self.bytes.len());
+	}
+
+	fn op(&mut self, op: u8) {
+		self.bytes.push(op);
+	}
+
+	// An instruction with a one byte operand, like a local variable's slot
+	fn push(&mut self, op: u8, operand: u8) {
+		self.bytes.push(op);
+		self.bytes.push(operand);
+	}
+
+	// An instruction with a two byte operand, like a constant pool index
+	fn push_u16(&mut self, op: u8, operand: u16) {
+		self.bytes.push(op);
+		put_u16(&mut self.bytes, operand);
+	}
+
+	// Push an int, in as few bytes as possible
+	fn int(&mut self, value: i32) {
+		match value {
+			-1..=5           => self.op((ICONST_0 as i32 + value) as u8),
+			-128..=127       => self.push(BIPUSH, value as u8),
+			-32768..=32767   => self.push_u16(SIPUSH, value as u16),
+			_                => {let index = self.pool.integer(value); self.constant(index);},
+		}
+	}
+
+	// Push a long, in as few bytes as possible
+	fn long(&mut self, value: i64) {
+		match value {
+			0 => self.op(LCONST_0),
+			1 => self.op(LCONST_1),
+			-32768..=32767 => {self.int(value as i32); self.op(I2L);},
+			_ => {let index = self.pool.long(value); self.push_u16(LDC2_W, index);},
+		}
+	}
+
+	// Push a String
+	fn text(&mut self, text: &str) {
+		let index = self.pool.string(text);
+		self.constant(index);
+	}
+
+	// Push a one slot constant from the pool
+	fn constant(&mut self, index: u16) {
+		if index < 256 {
+			self.push(LDC, index as u8);
+		} else {
+			self.push_u16(LDC_W, index);
+		}
+	}
+
+	fn field(&mut self, op: u8, class: &str, name: &str, descriptor: &str) {
+		let index = self.pool.field(class, name, descriptor);
+		self.push_u16(op, index);
+	}
+
+	// Get and put the compiled class's own fields
+	fn get(&mut self, name: &str, descriptor: &str) {
+		self.field(GETSTATIC, CLASS, name, descriptor);
+	}
+
+	fn put(&mut self, name: &str, descriptor: &str) {
+		self.field(PUTSTATIC, CLASS, name, descriptor);
+	}
+
+	fn invoke(&mut self, op: u8, class: &str, name: &str, descriptor: &str) {
+		let index = self.pool.method(class, name, descriptor);
+		self.push_u16(op, index);
+	}
+
+	// Call one of the compiled class's own methods
+	fn call(&mut self, name: &str, descriptor: &str) {
+		self.invoke(INVOKESTATIC, CLASS, name, descriptor);
+	}
+
+	// An instruction taking a class, like checkcast
+	fn class(&mut self, op: u8, class: &str) {
+		let index = self.pool.class(class);
+		self.push_u16(op, index);
+	}
+
+	// Push a new object twice, once for its constructor and once to keep
+	fn new_object(&mut self, class: &str) {
+		self.class(NEW, class);
+		self.op(DUP);
+	}
+
+	// Start building a string, leaving a StringBuilder on the stack
+	fn builder(&mut self) {
+		self.new_object("java/lang/StringBuilder");
+		self.invoke(INVOKESPECIAL, "java/lang/StringBuilder", "<init>", "()V");
+	}
+
+	// Append the value on top of the stack, of a type like I or Ljava/lang/String;
+	fn append(&mut self, descriptor: &str) {
+		self.invoke(INVOKEVIRTUAL, "java/lang/StringBuilder", "append", &format!("({})Ljava/lang/StringBuilder;", descriptor));
+	}
+
+	fn append_text(&mut self, text: &str) {
+		self.text(text);
+		self.append("Ljava/lang/String;");
+	}
+
+	// Finish building a string, leaving it on the stack
+	fn build(&mut self) {
+		self.invoke(INVOKEVIRTUAL, "java/lang/StringBuilder", "toString", "()Ljava/lang/String;");
+	}
+
+	// A conditional branch, which can only go a short way
+	fn branch(&mut self, op: u8, label: Target) {
+		let from = self.bytes.len();
+		self.push_u16(op, 0);
+		self.offsets.push((from, from + 1, label, false));
+	}
+
+	// An unconditional jump, which can go anywhere in the method
+	fn goto(&mut self, label: Target) {
+		let from = self.bytes.len();
+		self.op(GOTO_W);
+		put_u32(&mut self.bytes, 0);
+		self.offsets.push((from, from + 1, label, true));
+	}
+
+	// Jump to the label for the int on top of the stack, or to a default
+	fn lookup(&mut self, default: Target, cases: &[(i32, Target)]) {
+		let from = self.bytes.len();
+		self.op(LOOKUPSWITCH);
+		while !self.bytes.len().is_multiple_of(4) {
+			self.op(0);
+		}
+
+		let at = self.bytes.len();
+		put_u32(&mut self.bytes, 0);
+		self.offsets.push((from, at, default, true));
+		put_u32(&mut self.bytes, cases.len() as u32);
+		for &(value, label) in cases {
+			put_u32(&mut self.bytes, value as u32);
+			let at = self.bytes.len();
+			put_u32(&mut self.bytes, 0);
+			self.offsets.push((from, at, label, true));
+		}
+	}
+
+	// Catch exceptions of a class thrown between two labels
+	fn catch(&mut self, start: Target, end: Target, handler: Target, class: &str) {
+		let class = self.pool.class(class);
+		self.handlers.push((start, end, handler, class));
+	}
+
+	// The finished code and exception table
+	fn assemble(mut self) -> Result<(Vec<u8>, Vec<u8>), String> {
+		if self.bytes.len() > u16::MAX as usize {
+			return Err("the program is too big for one JVM method".to_string());
+		}
+
+		let labels = self.labels;
+		let place = |label: Target| labels[label.0].expect("a label was jumped to but never placed");
+
+		for &(from, at, label, wide) in &self.offsets {
+			let offset = place(label) as i64 - from as i64;
+			if wide {
+				self.bytes[at..at + 4].copy_from_slice(&(offset as i32).to_be_bytes());
+			} else {
+				self.bytes[at..at + 2].copy_from_slice(&(offset as i16).to_be_bytes());
+			}
+		}
+
+		let mut handlers = Vec::new();
+		for &(start, end, handler, class) in &self.handlers {
+			put_u16(&mut handlers, place(start) as u16);
+			put_u16(&mut handlers, place(end) as u16);
+			put_u16(&mut handlers, place(handler) as u16);
+			put_u16(&mut handlers, class);
+		}
+
+		Ok((self.bytes, handlers))
+	}
+}
+
+
+/********************************************/
+// Private Functions
+/********************************************/
+// Write big-endian numbers, as class files are
+fn put_u16(bytes: &mut Vec<u8>, value: u16) {
+	bytes.extend_from_slice(&value.to_be_bytes());
+}
+
+fn put_u32(bytes: &mut Vec<u8>, value: u32) {
+	bytes.extend_from_slice(&value.to_be_bytes());
+}
+
+
+// Push the argument onto one of the growable arrays, doubling it when it's full
+fn grow(code: &mut Code, array: &str, size: &str, descriptor: &str, load: u8, store: u8) {
+	let room = code.label();
+	code.get(size, "I");
+	code.get(array, descriptor);
+	code.op(ARRAYLENGTH);
+	code.branch(IF_ICMPLT, room);
+	code.get(array, descriptor);
+	code.get(size, "I");
+	code.int(2);
+	code.op(IMUL);
+	code.invoke(INVOKESTATIC, "java/util/Arrays", "copyOf", &format!("({}I){}", descriptor, descriptor));
+	code.put(array, descriptor);
+
+	code.place(room);
+	code.get(array, descriptor);
+	code.get(size, "I");
+	code.push(load, 0);
+	code.op(store);
+	code.get(size, "I");
+	code.int(1);
+	code.op(IADD);
+	code.put(size, "I");
+	code.op(RETURN);
+}
+
+
+// pop(): take the top of the stack, which need has already checked is there
+fn pop(code: &mut Code) {
+	code.get("size", "I");
+	code.int(1);
+	code.op(ISUB);
+	code.op(DUP);
+	code.put("size", "I");
+	code.get("stack", "[J");
+	code.op(SWAP);
+	code.op(LALOAD);
+	code.op(LRETURN);
+}
+
+
+// leave(at): take the action the latest call returns to
+fn leave(code: &mut Code) {
+	let called = code.label();
+	code.get("depth", "I");
+	code.branch(IFNE, called);
+	code.push(ILOAD, 0);
+	code.text("Tried to return from a procedure, but no procedure call was made.");
+	code.call("fail", "(ILjava/lang/String;)V");
+	code.int(0);
+	code.op(IRETURN);
+
+	code.place(called);
+	code.get("depth", "I");
+	code.int(1);
+	code.op(ISUB);
+	code.op(DUP);
+	code.put("depth", "I");
+	code.get("calls", "[I");
+	code.op(SWAP);
+	code.op(IALOAD);
+	code.op(IRETURN);
+}
+
+
+// need(at, count, action): fail unless the stack holds enough values
+fn need(code: &mut Code) {
+	let short = code.label();
+	code.get("size", "I");
+	code.push(ILOAD, 1);
+	code.branch(IF_ICMPLT, short);
+	code.op(RETURN);
+
+	code.place(short);
+	code.push(ILOAD, 0);
+	code.builder();
+	code.push(ALOAD, 2);
+	code.append("Ljava/lang/String;");
+	code.append_text(" needs ");
+	code.push(ILOAD, 1);
+	code.append("I");
+	code.append_text(" value(s) on the stack, but it holds ");
+	code.get("size", "I");
+	code.append("I");
+	code.append_text(".");
+	code.build();
+	code.call("fail", "(ILjava/lang/String;)V");
+	code.op(RETURN);
+}
+
+
+// fail(at, message): print the error the way the interpreter does and exit
+fn fail(code: &mut Code) {
+	code.get("out", "Ljava/io/PrintStream;");
+	code.builder();
+	code.append_text("Error: Runtime Error: ");
+	code.push(ALOAD, 1);
+	code.append("Ljava/lang/String;");
+	code.append_text(" (at action ");
+	code.push(ILOAD, 0);
+	code.append("I");
+	code.append_text(")\n");
+	code.build();
+	code.invoke(INVOKEVIRTUAL, "java/io/PrintStream", "print", "(Ljava/lang/String;)V");
+	code.get("out", "Ljava/io/PrintStream;");
+	code.invoke(INVOKEVIRTUAL, "java/io/PrintStream", "flush", "()V");
+	code.int(6);
+	code.invoke(INVOKESTATIC, "java/lang/System", "exit", "(I)V");
+	code.op(RETURN);
+}
+
+
+// add(at) and the others: apply one of Math's overflow-checked operations
+// to the top two values, failing with a message if it overflows
+fn exact(code: &mut Code, operation: &str, message: &str) {
+	let (start, end, overflowed) = (code.label(), code.label(), code.label());
+	operands(code);
+
+	code.place(start);
+	code.push(LLOAD, 3);
+	code.push(LLOAD, 1);
+	code.invoke(INVOKESTATIC, "java/lang/Math", operation, "(JJ)J");
+	code.place(end);
+	code.call("push", "(J)V");
+	code.op(RETURN);
+
+	code.place(overflowed);
+	code.op(POP);
+	code.push(ILOAD, 0);
+	code.text(message);
+	code.call("fail", "(ILjava/lang/String;)V");
+	code.op(RETURN);
+	code.catch(start, end, overflowed, "java/lang/ArithmeticException");
+}
+
+
+// Pop the right operand into locals 1 and 2, and the left into 3 and 4
+fn operands(code: &mut Code) {
+	code.call("pop", "()J");
+	code.push(LSTORE, 1);
+	code.call("pop", "()J");
+	code.push(LSTORE, 3);
+}
+
+
+// Pop the operands of a division, failing if the right one is zero or the
+// division is the one that overflows
+fn checked_division(code: &mut Code, by_zero: &str, overflowed: &str) {
+	let (nonzero, fits) = (code.label(), code.label());
+	operands(code);
+
+	code.push(LLOAD, 1);
+	code.op(LCONST_0);
+	code.op(LCMP);
+	code.branch(IFNE, nonzero);
+	code.push(ILOAD, 0);
+	code.text(by_zero);
+	code.call("fail", "(ILjava/lang/String;)V");
+	code.op(RETURN);
+
+	code.place(nonzero);
+	code.push(LLOAD, 3);
+	code.long(i64::MIN);
+	code.op(LCMP);
+	code.branch(IFNE, fits);
+	code.push(LLOAD, 1);
+	code.long(-1);
+	code.op(LCMP);
+	code.branch(IFNE, fits);
+	code.push(ILOAD, 0);
+	code.text(overflowed);
+	code.call("fail", "(ILjava/lang/String;)V");
+	code.op(RETURN);
+	code.place(fits);
+}
+
+
+// divide(at): push the quotient of the top two values
+fn divide(code: &mut Code, division: Division) {
+	checked_division(code, "Tried to divide by zero.", "Division overflowed.");
+	code.push(LLOAD, 3);
+	code.push(LLOAD, 1);
+
+	match division {
+		Division::Truncated => code.op(LDIV),
+		Division::Floored   => code.invoke(INVOKESTATIC, "java/lang/Math", "floorDiv", "(JJ)J"),
+
+		// Take one from the quotient away from zero when the remainder is negative
+		Division::Euclidean => {
+			let (done, negative) = (code.label(), code.label());
+			code.op(LDIV);
+			code.push(LSTORE, 5);
+			code.push(LLOAD, 3);
+			code.push(LLOAD, 1);
+			code.op(LREM);
+			code.op(LCONST_0);
+			code.op(LCMP);
+			code.branch(IFGE, done);
+			code.push(LLOAD, 1);
+			code.op(LCONST_0);
+			code.op(LCMP);
+			code.branch(IFLE, negative);
+			code.push(LLOAD, 5);
+			code.op(LCONST_1);
+			code.op(LSUB);
+			code.push(LSTORE, 5);
+			code.goto(done);
+			code.place(negative);
+			code.push(LLOAD, 5);
+			code.op(LCONST_1);
+			code.op(LADD);
+			code.push(LSTORE, 5);
+			code.place(done);
+			code.push(LLOAD, 5);
+		},
+	}
+
+	code.call("push", "(J)V");
+	code.op(RETURN);
+}
+
+
+// modulo(at): push the remainder of the top two values
+fn modulo(code: &mut Code, division: Division) {
+	checked_division(code, "Tried to take the remainder of a division by zero.", "Modulo overflowed.");
+	code.push(LLOAD, 3);
+	code.push(LLOAD, 1);
+
+	match division {
+		Division::Truncated => code.op(LREM),
+		Division::Floored   => code.invoke(INVOKESTATIC, "java/lang/Math", "floorMod", "(JJ)J"),
+
+		// Move a negative remainder up by the size of the right operand
+		Division::Euclidean => {
+			let (done, positive) = (code.label(), code.label());
+			code.op(LREM);
+			code.push(LSTORE, 5);
+			code.push(LLOAD, 5);
+			code.op(LCONST_0);
+			code.op(LCMP);
+			code.branch(IFGE, done);
+			code.push(LLOAD, 1);
+			code.op(LCONST_0);
+			code.op(LCMP);
+			code.branch(IFGE, positive);
+			code.push(LLOAD, 5);
+			code.push(LLOAD, 1);
+			code.op(LSUB);
+			code.push(LSTORE, 5);
+			code.goto(done);
+			code.place(positive);
+			code.push(LLOAD, 5);
+			code.push(LLOAD, 1);
+			code.op(LADD);
+			code.push(LSTORE, 5);
+			code.place(done);
+			code.push(LLOAD, 5);
+		},
+	}
+
+	code.call("push", "(J)V");
+	code.op(RETURN);
+}
+
+
+// Box the long on top of the stack
+fn boxed(code: &mut Code) {
+	code.invoke(INVOKESTATIC, "java/lang/Long", "valueOf", "(J)Ljava/lang/Long;");
+}
+
+
+// Put the value in locals 2 and 3 in the heap at an address popped off the stack
+fn put_in_heap(code: &mut Code) {
+	code.get("heap", "Ljava/util/HashMap;");
+	code.call("pop", "()J");
+	boxed(code);
+	code.push(LLOAD, 2);
+	boxed(code);
+	code.invoke(INVOKEVIRTUAL, "java/util/HashMap", "put", "(Ljava/lang/Object;Ljava/lang/Object;)Ljava/lang/Object;");
+	code.op(POP);
+}
+
+
+// store(): pop a value, then the address to store it at
+fn store(code: &mut Code) {
+	code.call("pop", "()J");
+	code.push(LSTORE, 2);
+	put_in_heap(code);
+	code.op(RETURN);
+}
+
+
+// retrieve(at, address): the value stored at an address
+fn retrieve(code: &mut Code, unset_heap: UnsetHeap) {
+	let missing = code.label();
+	code.get("heap", "Ljava/util/HashMap;");
+	code.push(LLOAD, 1);
+	boxed(code);
+	code.invoke(INVOKEVIRTUAL, "java/util/HashMap", "get", "(Ljava/lang/Object;)Ljava/lang/Object;");
+	code.push(ASTORE, 3);
+	code.push(ALOAD, 3);
+	code.branch(IFNULL, missing);
+	code.push(ALOAD, 3);
+	code.class(CHECKCAST, "java/lang/Long");
+	code.invoke(INVOKEVIRTUAL, "java/lang/Long", "longValue", "()J");
+	code.op(LRETURN);
+
+	code.place(missing);
+	if unset_heap == UnsetHeap::Error {
+		code.push(ILOAD, 0);
+		code.builder();
+		code.append_text("Tried to get a value from the heap, but no value was found at address: ");
+		code.push(LLOAD, 1);
+		code.append("J");
+		code.build();
+		code.call("fail", "(ILjava/lang/String;)V");
+	}
+	code.op(LCONST_0);
+	code.op(LRETURN);
+}
+
+
+// outputChar(at): pop a value and write it as a character, taking it as a
+// byte or a code point
+fn output_char(code: &mut Code, options: &Options) {
+	let (write, invalid) = (code.label(), code.label());
+	code.call("pop", "()J");
+	code.push(LSTORE, 1);
+
+	// Characters are from zero to the most the encoding holds, leaving out the surrogates
+	let most = match options.char_encoding {
+		CharEncoding::Byte    => 0xFF,
+		CharEncoding::Unicode => 0x10FFFF,
+	};
+	code.push(LLOAD, 1);
+	code.op(LCONST_0);
+	code.op(LCMP);
+	code.branch(IFLT, invalid);
+	code.push(LLOAD, 1);
+	code.long(most);
+	code.op(LCMP);
+	code.branch(IFGT, invalid);
+	if options.char_encoding == CharEncoding::Unicode {
+		code.push(LLOAD, 1);
+		code.long(0xD800);
+		code.op(LCMP);
+		code.branch(IFLT, write);
+		code.push(LLOAD, 1);
+		code.long(0xDFFF);
+		code.op(LCMP);
+		code.branch(IFLE, invalid);
+	}
+	code.goto(write);
+
+	// A byte loses all but its low bits on the way, and a code point becomes U+FFFD
+	code.place(invalid);
+	match (options.invalid_char, options.char_encoding) {
+		(InvalidChar::Error, encoding) => {
+			code.push(ILOAD, 0);
+			code.builder();
+			code.append_text("Tried to write ");
+			code.push(LLOAD, 1);
+			code.append("J");
+			code.append_text(match encoding {
+				CharEncoding::Byte    => " as a character, but it's outside the range of a byte.",
+				CharEncoding::Unicode => " as a character, but it isn't a Unicode code point.",
+			});
+			code.build();
+			code.call("fail", "(ILjava/lang/String;)V");
+			code.op(RETURN);
+		},
+		(InvalidChar::Lossy, CharEncoding::Byte)    => {},
+		(InvalidChar::Lossy, CharEncoding::Unicode) => {
+			code.long(0xFFFD);
+			code.push(LSTORE, 1);
+		},
+	}
+
+	code.place(write);
+	code.get("out", "Ljava/io/PrintStream;");
+	code.push(LLOAD, 1);
+	code.op(L2I);
+	match options.char_encoding {
+		CharEncoding::Byte => {
+			code.int(0xFF);
+			code.op(IAND);
+			code.op(I2C);
+			code.invoke(INVOKEVIRTUAL, "java/io/PrintStream", "print", "(C)V");
+		},
+		CharEncoding::Unicode => {
+			code.invoke(INVOKESTATIC, "java/lang/Character", "toChars", "(I)[C");
+			code.invoke(INVOKEVIRTUAL, "java/io/PrintStream", "print", "([C)V");
+		},
+	}
+	code.op(RETURN);
+}
+
+
+// Handle the end of the input while reading: store what the options say a
+// read gives there by jumping to the store, or return without storing
+fn at_end(code: &mut Code, options: &Options, reading: &str, heap: bool, store: Target) {
+	let value = match options.eof {
+		EofPolicy::Error => {
+			code.push(ILOAD, 0);
+			code.text(&format!("Reached the end of the input while reading {}.", reading));
+			code.call("fail", "(ILjava/lang/String;)V");
+			code.op(RETURN);
+			return;
+		},
+		EofPolicy::Unchanged => {
+			if heap {
+				code.call("pop", "()J");
+				code.op(POP2);
+			}
+			code.op(RETURN);
+			return;
+		},
+		EofPolicy::NegativeOne => -1,
+		EofPolicy::Zero        => 0,
+	};
+
+	code.long(value);
+	code.push(LSTORE, 2);
+	code.goto(store);
+}
+
+
+// Write out anything buffered before waiting for input
+fn flush(code: &mut Code) {
+	code.get("out", "Ljava/io/PrintStream;");
+	code.invoke(INVOKEVIRTUAL, "java/io/PrintStream", "flush", "()V");
+}
+
+
+// readChar(at): read a byte into wherever the options say ReadChar stores it
+fn read_char(code: &mut Code, options: &Options) {
+	let (end, store) = (code.label(), code.label());
+	let heap = options.read_char == ReadCharDestination::Heap;
+	flush(code);
+	code.field(GETSTATIC, "java/lang/System", "in", "Ljava/io/InputStream;");
+	code.invoke(INVOKEVIRTUAL, "java/io/InputStream", "read", "()I");
+	code.push(ISTORE, 1);
+	code.push(ILOAD, 1);
+	code.branch(IFLT, end);
+	code.push(ILOAD, 1);
+	code.op(I2L);
+	code.push(LSTORE, 2);
+	code.goto(store);
+
+	code.place(end);
+	at_end(code, options, "a character", heap, store);
+
+	// Reads take their destination only once the read is done, like the interpreter
+	code.place(store);
+	if heap {
+		put_in_heap(code);
+	} else {
+		code.push(LLOAD, 2);
+		code.call("push", "(J)V");
+	}
+	code.op(RETURN);
+}
+
+
+// readNumber(at): read lines until one is a number, and store it in the heap
+fn read_number(code: &mut Code, options: &Options) {
+	let (next, line, invalid, store) = (code.label(), code.label(), code.label(), code.label());
+	code.place(next);
+	flush(code);
+	code.call("readLine", "()Ljava/lang/String;");
+	code.push(ASTORE, 1);
+	code.push(ALOAD, 1);
+	code.branch(IFNONNULL, line);
+	at_end(code, options, "a number", true, store);
+
+	// A line that's skipped gives null, and one that isn't a number gives the reason
+	code.place(line);
+	code.push(ALOAD, 1);
+	code.call("parse", "(Ljava/lang/String;)Ljava/lang/Object;");
+	code.push(ASTORE, 4);
+	code.push(ALOAD, 4);
+	code.branch(IFNULL, next);
+	code.push(ALOAD, 4);
+	code.class(INSTANCEOF, "java/lang/Long");
+	code.branch(IFEQ, invalid);
+	code.push(ALOAD, 4);
+	code.class(CHECKCAST, "java/lang/Long");
+	code.invoke(INVOKEVIRTUAL, "java/lang/Long", "longValue", "()J");
+	code.push(LSTORE, 2);
+	code.goto(store);
+
+	code.place(invalid);
+	if options.invalid_number == InvalidNumber::Reprompt {
+		code.get("out", "Ljava/io/PrintStream;");
+	} else {
+		code.push(ILOAD, 0);
+	}
+	code.builder();
+	code.append_text("Unable to parse number: ");
+	code.push(ALOAD, 4);
+	code.append("Ljava/lang/Object;");
+	if options.invalid_number == InvalidNumber::Reprompt {
+		code.append_text("\n");
+		code.build();
+		code.invoke(INVOKEVIRTUAL, "java/io/PrintStream", "print", "(Ljava/lang/String;)V");
+		code.goto(next);
+	} else {
+		code.build();
+		code.call("fail", "(ILjava/lang/String;)V");
+		code.op(RETURN);
+	}
+
+	code.place(store);
+	put_in_heap(code);
+	code.op(RETURN);
+}
+
+
+// readLine(): the next line of input without its line ending, or null at
+// the end of the input
+fn read_line(code: &mut Code) {
+	let (next, byte, done) = (code.label(), code.label(), code.label());
+	code.new_object("java/io/ByteArrayOutputStream");
+	code.invoke(INVOKESPECIAL, "java/io/ByteArrayOutputStream", "<init>", "()V");
+	code.push(ASTORE, 0);
+
+	code.place(next);
+	code.field(GETSTATIC, "java/lang/System", "in", "Ljava/io/InputStream;");
+	code.invoke(INVOKEVIRTUAL, "java/io/InputStream", "read", "()I");
+	code.push(ISTORE, 1);
+	code.push(ILOAD, 1);
+	code.int(10);
+	code.branch(IF_ICMPEQ, done);
+	code.push(ILOAD, 1);
+	code.branch(IFGE, byte);
+	code.push(ALOAD, 0);
+	code.invoke(INVOKEVIRTUAL, "java/io/ByteArrayOutputStream", "size", "()I");
+	code.branch(IFNE, done);
+	code.op(ACONST_NULL);
+	code.op(ARETURN);
+
+	code.place(byte);
+	code.push(ALOAD, 0);
+	code.push(ILOAD, 1);
+	code.invoke(INVOKEVIRTUAL, "java/io/ByteArrayOutputStream", "write", "(I)V");
+	code.goto(next);
+
+	code.place(done);
+	code.push(ALOAD, 0);
+	code.text("UTF-8");
+	code.invoke(INVOKEVIRTUAL, "java/io/ByteArrayOutputStream", "toString", "(Ljava/lang/String;)Ljava/lang/String;");
+	code.text("\\r\\z");
+	code.text("");
+	code.invoke(INVOKEVIRTUAL, "java/lang/String", "replaceFirst", "(Ljava/lang/String;Ljava/lang/String;)Ljava/lang/String;");
+	code.op(ARETURN);
+}
+
+
+// parse(line): parse a line the way the interpreter's number input options
+// say to, giving a Long, null to skip the line, or a reason it isn't a number
+fn parse(code: &mut Code, options: &Options) {
+	let (present, decimal, range, invalid, fits) = (code.label(), code.label(), code.label(), code.label(), code.label());
+	let input = options.number_input;
+
+	code.push(ALOAD, 0);
+	if input.whitespace {
+		code.text("^[ \\t]+|[ \\t]+\\z");
+		code.text("");
+		code.invoke(INVOKEVIRTUAL, "java/lang/String", "replaceAll", "(Ljava/lang/String;Ljava/lang/String;)Ljava/lang/String;");
+	}
+	code.push(ASTORE, 1);
+
+	code.push(ALOAD, 1);
+	code.invoke(INVOKEVIRTUAL, "java/lang/String", "isEmpty", "()Z");
+	code.branch(IFEQ, present);
+	match input.empty_line {
+		EmptyLine::Zero    => {code.op(LCONST_0); boxed(code);},
+		EmptyLine::Skip    => code.op(ACONST_NULL),
+		EmptyLine::Invalid => code.text("the line is empty"),
+	}
+	code.op(ARETURN);
+
+	code.place(present);
+	if !input.plus_sign {
+		let unsigned = code.label();
+		code.push(ALOAD, 1);
+		code.text("+");
+		code.invoke(INVOKEVIRTUAL, "java/lang/String", "startsWith", "(Ljava/lang/String;)Z");
+		code.branch(IFEQ, unsigned);
+		code.text("a + sign isn't allowed");
+		code.op(ARETURN);
+		code.place(unsigned);
+	}
+
+	code.push(ALOAD, 1);
+	code.text("[+-]?[0-9]+");
+	code.invoke(INVOKEVIRTUAL, "java/lang/String", "matches", "(Ljava/lang/String;)Z");
+	code.branch(IFNE, decimal);
+	if input.hexadecimal {
+		code.push(ALOAD, 1);
+		code.text("[+-]?0[xX][0-9a-fA-F]+");
+		code.invoke(INVOKEVIRTUAL, "java/lang/String", "matches", "(Ljava/lang/String;)Z");
+		code.branch(IFEQ, invalid);
+		code.new_object("java/math/BigInteger");
+		code.push(ALOAD, 1);
+		code.text("0[xX]");
+		code.text("");
+		code.invoke(INVOKEVIRTUAL, "java/lang/String", "replaceFirst", "(Ljava/lang/String;Ljava/lang/String;)Ljava/lang/String;");
+		code.int(16);
+		code.invoke(INVOKESPECIAL, "java/math/BigInteger", "<init>", "(Ljava/lang/String;I)V");
+		code.push(ASTORE, 2);
+		code.goto(range);
+	}
+
+	code.place(invalid);
+	code.builder();
+	code.push(ALOAD, 0);
+	code.call("quote", "(Ljava/lang/String;)Ljava/lang/String;");
+	code.append("Ljava/lang/String;");
+	code.append_text(" isn't a number");
+	code.build();
+	code.op(ARETURN);
+
+	code.place(decimal);
+	code.new_object("java/math/BigInteger");
+	code.push(ALOAD, 1);
+	code.invoke(INVOKESPECIAL, "java/math/BigInteger", "<init>", "(Ljava/lang/String;)V");
+	code.push(ASTORE, 2);
+
+	// Every number that fits in a long needs at most 63 bits besides its sign
+	code.place(range);
+	code.push(ALOAD, 2);
+	code.invoke(INVOKEVIRTUAL, "java/math/BigInteger", "bitLength", "()I");
+	code.int(63);
+	code.branch(IF_ICMPLE, fits);
+	code.builder();
+	code.push(ALOAD, 0);
+	code.call("quote", "(Ljava/lang/String;)Ljava/lang/String;");
+	code.append("Ljava/lang/String;");
+	code.append_text(" doesn't fit in 64 bits");
+	code.build();
+	code.op(ARETURN);
+
+	code.place(fits);
+	code.push(ALOAD, 2);
+	code.invoke(INVOKEVIRTUAL, "java/math/BigInteger", "longValue", "()J");
+	boxed(code);
+	code.op(ARETURN);
+}
+
+
+// quote(line): quote a line the way the interpreter does in its messages
+fn quote(code: &mut Code) {
+	code.builder();
+	code.append_text("\"");
+	code.push(ALOAD, 0);
+	for &(from, to) in &[("\\", "\\\\"), ("\"", "\\\"")] {
+		code.text(from);
+		code.text(to);
+		code.invoke(INVOKEVIRTUAL, "java/lang/String", "replace", "(Ljava/lang/CharSequence;Ljava/lang/CharSequence;)Ljava/lang/String;");
+	}
+	code.append("Ljava/lang/String;");
+	code.append_text("\"");
+	code.build();
+	code.op(ARETURN);
+}
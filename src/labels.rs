@@ -0,0 +1,98 @@
+/***********************************************************/
+//
+// Labels
+// ======
+// Interns the labels of a program. The parser gives every
+// distinct label an id, counting from one in the order the
+// labels first appear, and calls and jumps refer to labels
+// by their id. Labels of any length then compare as cheaply
+// as numbers and never collide. The table keeps each
+// label's tokens, so it can still be shown the way it was
+// written.
+//
+// A table can be handed to the parser again, so an edited
+// version of a program gives the labels it kept the ids
+// they had before.
+//
+// struct LabelId <- A label interned in a SymbolTable
+//
+// struct SymbolTable <- The labels of a program, by id
+//
+/***********************************************************/
+use std::collections::HashMap;
+use std::fmt;
+use symbols::Token;
+
+
+/********************************************/
+// Structures
+/********************************************/
+// A label interned in a SymbolTable. Once the labels of a program are
+// reduced, calls and jumps hold program pointers in its place. It has
+// no Display, so a label is shown by its name in the table rather than
+// by an id that means nothing to whoever wrote the program.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Default)]
+pub struct LabelId(pub u64);
+
+// The labels of a program, by id
+#[derive(Debug, Clone, Default)]
+pub struct SymbolTable {
+	spellings: Vec<String>,              // Each label's tokens as S and T, indexed by id - 1
+	ids:       HashMap<String, LabelId>, // The id of each spelling
+}
+
+impl SymbolTable {
+	// The id of the label with the given tokens, giving it the next one
+	// if it hasn't been seen before
+	pub fn intern(&mut self, tokens: &[Token]) -> LabelId {
+		let spelling = tokens.iter().map(|token| if *token == Token::Space {'S'} else {'T'}).collect::<String>();
+		if let Some(&id) = self.ids.get(&spelling) {
+			return id;
+		}
+
+		self.spellings.push(spelling.clone());
+		let id = LabelId(self.spellings.len() as u64);
+		self.ids.insert(spelling, id);
+		id
+	}
+
+	// The name a label is shown with: its tokens as S and T, or its id
+	// for labels the parser never saw, like those made up by rewrites
+	pub fn name(&self, id: LabelId) -> String {
+		match self.spelling(id) {
+			Some("")       => "(empty)".to_string(),
+			Some(spelling) => spelling.to_string(),
+			None           => format!("#{}", id.0),
+		}
+	}
+
+	// A label's tokens as S and T, if the parser saw it
+	pub fn spelling(&self, id: LabelId) -> Option<&str> {
+		id.0.checked_sub(1).and_then(|index| self.spellings.get(index as usize)).map(String::as_str)
+	}
+
+	// The text a label spells, taking S as 0 and T as 1 a byte at a time,
+	// when every byte is printable ASCII, the way many programs name their
+	// labels
+	pub fn text(&self, id: LabelId) -> Option<String> {
+		let spelling = self.spelling(id)?;
+		if spelling.is_empty() || !spelling.len().is_multiple_of(8) {
+			return None;
+		}
+
+		let bytes: Vec<u8> = spelling.as_bytes().chunks(8).map(|bits| bits.iter().fold(0, |byte, &bit| byte << 1 | (bit == b'T') as u8)).collect();
+		Some(bytes).filter(|bytes| bytes.iter().all(|byte| byte.is_ascii_graphic() || *byte == b' ')).map(|bytes| String::from_utf8(bytes).unwrap())
+	}
+}
+
+
+/********************************************/
+// Trait Implementations
+/********************************************/
+// Debugged as the bare number, so an action reads Call(3) whether it
+// holds an id or, once reduced, a program pointer
+impl fmt::Debug for LabelId {
+	fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+		write!(formatter, "{}", self.0)
+	}
+}
@@ -0,0 +1,166 @@
+/**************************************************/
+//
+// Whitespace Library
+// ==================
+// The interpreter as a library, for tools that
+// embed it. A program is parsed with parse, which
+// resolves its labels too, and run by a Vm; run
+// does both for a program that only needs its
+// input and output.
+//
+// parse(program: &str, options: &Options) -> Result<Vec<Action>, WhitespaceError>
+// -- Parses a whitespace program and resolves its
+//    labels, ready for a Vm to run
+//
+// run(program: &str, options: Options, input: Box<dyn BufRead>, output: Box<dyn Write>) -> Result<(), WhitespaceError>
+// -- Parses a whitespace program and runs it until
+//    it halts or fails
+//
+// from_stl(notation: &str) -> String
+// -- Converts a program written in STL notation into
+//    whitespace
+//
+// untrusted(options: &mut Options, io_options: &mut IoOptions, limits: &mut Limits)
+// -- Sets what a vm running other people's programs
+//    needs: no extensions or environment, and limits
+//    on steps, time and memory
+//
+// A Vm given a Cancel token with set_cancel can be
+// stopped from another thread by cancelling it. The
+// run stops with a Limit::Cancelled error, and can
+// carry on once the token is reset.
+//
+// A Vm given a Codec with set_codec writes and reads
+// characters through it: Bytes, Utf8, a Table like
+// Table::ebcdic(), or an encoding of the application's
+// own.
+//
+// A Vm given a Heap with set_heap keeps the
+// program's heap in it: a HashHeap, a DenseHeap or
+// PagedHeap for programs that keep arrays, or a
+// SharedHeap that several vms use at once.
+//
+// Everything a Vm's methods take or give is
+// exported here at the top, and kept stable between
+// versions. The modules behind them are private to
+// the crate. The command line is the cli module,
+// public so the binary can use it, but hidden from
+// the documentation and free to change.
+//
+/**************************************************/
+use std::io::{BufRead, Write};
+
+#[cfg(feature = "jupyter")]
+extern crate hmac;
+#[cfg(feature = "hooks")]
+extern crate rhai;
+#[cfg(feature = "jupyter")]
+extern crate sha2;
+#[cfg(feature = "jupyter")]
+extern crate zmq;
+
+pub(crate) mod accesses;
+pub(crate) mod allocation;
+pub(crate) mod analysis;
+pub(crate) mod assembler;
+pub(crate) mod audit;
+pub(crate) mod budget;
+pub(crate) mod bundle;
+pub(crate) mod cache;
+pub(crate) mod callgraph;
+pub(crate) mod cancel;
+pub(crate) mod checkpoint;
+#[doc(hidden)] pub mod cli;
+pub(crate) mod codec;
+pub(crate) mod capabilities;
+pub(crate) mod compile;
+pub(crate) mod conformance;
+pub(crate) mod coverage;
+pub(crate) mod debugger;
+pub(crate) mod decompile;
+pub(crate) mod diff;
+pub(crate) mod disassembler;
+pub(crate) mod emit;
+pub(crate) mod config;
+pub(crate) mod error;
+pub(crate) mod explain;
+pub(crate) mod explore;
+pub(crate) mod fuzz;
+pub(crate) mod heap;
+pub(crate) mod highlight;
+#[cfg(feature = "hooks")]
+pub(crate) mod hooks;
+pub(crate) mod idioms;
+pub(crate) mod javascript;
+pub(crate) mod jvm;
+pub(crate) mod json;
+#[cfg(feature = "jupyter")]
+pub(crate) mod jupyter;
+pub(crate) mod labels;
+pub(crate) mod lint;
+pub(crate) mod metrics;
+#[cfg(feature = "llvm")]
+pub(crate) mod llvm;
+pub(crate) mod native;
+pub(crate) mod obfuscate;
+pub(crate) mod options;
+pub(crate) mod parser;
+pub(crate) mod passes;
+pub(crate) mod program;
+pub(crate) mod python;
+pub(crate) mod reload;
+pub(crate) mod repl;
+pub(crate) mod report;
+pub(crate) mod script;
+pub(crate) mod server;
+pub(crate) mod statistics;
+pub(crate) mod status;
+pub(crate) mod streams;
+pub(crate) mod streaming;
+#[cfg(feature = "symbolic")]
+pub(crate) mod symbolic;
+pub(crate) mod symbols;
+pub(crate) mod taint;
+pub(crate) mod trace;
+pub(crate) mod transcript;
+pub(crate) mod virtual_machine;
+
+pub use accesses::{Accesses, Format as AccessFormat};
+pub use allocation::{Allocations, Capacity, Shrink};
+pub use audit::{Audit, Source as AuditSource};
+pub use budget::Budget;
+pub use cancel::Cancel;
+pub use checkpoint::{Checkpoint, Checkpoints};
+pub use codec::{Bytes, Codec, Table, Utf8};
+pub use error::{Fault, Limit, LimitError, ParseError, RuntimeError, UnresolvedLabel, WhitespaceError};
+pub use heap::{DenseHeap, HashHeap, Heap, PagedHeap, RegionHeap, SharedHeap};
+#[cfg(feature = "hooks")]
+pub use hooks::Hooks;
+pub use labels::LabelId;
+pub use options::{CharEncoding, Division, EmptyLine, EmptyNumber, EndOfProgram, EofPolicy, Extensions, FlushPolicy, InvalidChar, InvalidNumber, IoOptions, LabelResolution, Limits, NumberInput, Options, OtherWhitespace, Profile, ReadCharDestination, TokenAliases, UnsetHeap};
+pub use options::untrusted;
+pub use parser::from_stl;
+pub use statistics::Statistics;
+pub use status::Status;
+pub use symbols::{Action, Category, Token};
+pub use taint::Taint;
+pub use trace::{Trace, TraceFilter};
+pub use virtual_machine::WhitespaceVM as Vm;
+
+
+/*******************************/
+// Functions
+/*******************************/
+// Parse a whitespace program and resolve its labels to the actions they
+// point at, ready to be given to a Vm
+pub fn parse(program: &str, options: &Options) -> Result<Vec<Action>, WhitespaceError> {
+	parser::parse_and_reduce(program.to_string(), options)
+}
+
+
+// Parse a whitespace program and run it with the given input and output
+// until it halts or fails
+pub fn run(program: &str, options: Options, input: Box<dyn BufRead>, output: Box<dyn Write>) -> Result<(), WhitespaceError> {
+	let program = parse(program, &options)?;
+	Vm::with_io(program, options, input, output).execute()
+}
@@ -0,0 +1,358 @@
+/***********************************************************/
+//
+// Lints
+// =====
+// Wraps the static analysis passes in named lints whose
+// severity can be configured, and renders the findings
+// as text or as JSON.
+//
+// check(program: &[Action], labels: &SymbolTable, config: &LintConfig) -> Vec<Diagnostic>
+// -- Run every lint that isn't allowed over a parsed program,
+//    naming its labels from the table they were interned in
+//
+// render_text(diagnostics: &[Diagnostic]) -> String
+// render_json(diagnostics: &[Diagnostic]) -> String
+// -- Format the findings for people or for tools
+//
+/***********************************************************/
+use std::collections::{HashMap, HashSet};
+use analysis;
+use analysis::{BasicBlock, Certainty};
+use json;
+use labels::{LabelId, SymbolTable};
+use symbols::Action;
+use symbols::Action::*;
+
+
+/********************************************/
+// Structures
+/********************************************/
+// How seriously a lint's findings are taken
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+	Allow, // The lint isn't run
+	Warn,  // Findings are reported
+	Deny,  // Findings are reported and fail the check
+}
+
+impl Level {
+	// Get the level with the given name
+	pub fn from_name(name: &str) -> Option<Level> {
+		match name {
+			"allow" => Some(Level::Allow),
+			"warn"  => Some(Level::Warn),
+			"deny"  => Some(Level::Deny),
+			_       => None,
+		}
+	}
+
+	// The name of the level, as used on the command line and in reports
+	pub fn name(&self) -> &'static str {
+		match *self {
+			Level::Allow => "allow",
+			Level::Warn  => "warn",
+			Level::Deny  => "deny",
+		}
+	}
+}
+
+
+// An analysis pass, returning the index of each offending action and a message
+type LintPass = fn(&[Action], &[BasicBlock], &SymbolTable) -> Vec<(usize, String)>;
+
+// A named analysis pass
+pub struct Lint {
+	pub name:        &'static str,
+	pub description: &'static str,
+	pub default:     Level,
+	pass:            LintPass,
+}
+
+
+// Every lint, in the order they are run
+pub static LINTS: &[Lint] = &[
+	Lint {
+		name:        "unused-label",
+		description: "a label is never the target of a call or jump",
+		default:     Level::Warn,
+		pass:        unused_label,
+	},
+	Lint {
+		name:        "orphan-subroutine",
+		description: "a subroutine is only referred to by code that can never be executed",
+		default:     Level::Warn,
+		pass:        orphan_subroutine,
+	},
+	Lint {
+		name:        "unreachable-code",
+		description: "actions that can never be executed",
+		default:     Level::Warn,
+		pass:        unreachable_code,
+	},
+	Lint {
+		name:        "stack-underflow",
+		description: "an action may pop more values than the stack holds",
+		default:     Level::Deny,
+		pass:        stack_underflow,
+	},
+	Lint {
+		name:        "division-by-zero",
+		description: "a division or remainder is always by zero",
+		default:     Level::Deny,
+		pass:        division_by_zero,
+	},
+	Lint {
+		name:        "unset-heap-read",
+		description: "a heap address is read that nothing ever stores to",
+		default:     Level::Warn,
+		pass:        unset_heap_read,
+	},
+	Lint {
+		name:        "constant-branch",
+		description: "a conditional jump is always taken, or never is",
+		default:     Level::Warn,
+		pass:        constant_branch,
+	},
+	Lint {
+		name:        "huge-literal",
+		description: "a pushed number doesn't fit in 32 bits, which usually means a malformed literal",
+		default:     Level::Warn,
+		pass:        huge_literal,
+	},
+];
+
+
+// A single finding of a lint
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+	pub lint:    &'static str,
+	pub level:   Level,
+	pub index:   usize, // Index of the offending action in the parsed program
+	pub message: String,
+}
+
+
+// The configured level of every lint
+#[derive(Debug, Clone)]
+pub struct LintConfig {
+	levels: HashMap<&'static str, Level>,
+}
+
+impl Default for LintConfig {
+	// Every lint at its default level
+	fn default() -> LintConfig {
+		LintConfig {
+			levels: LINTS.iter().map(|lint| (lint.name, lint.default)).collect(),
+		}
+	}
+}
+
+impl LintConfig {
+	// Set the level of a lint, failing if no lint has the given name
+	pub fn set(&mut self, name: &str, level: Level) -> Result<(), String> {
+		match LINTS.iter().find(|lint| lint.name == name) {
+			Some(lint) => {self.levels.insert(lint.name, level); Ok(())},
+			None       => Err(format!("Unknown lint: {}", name)),
+		}
+	}
+
+	// Get the level of a lint
+	pub fn level(&self, name: &str) -> Level {
+		self.levels.get(name).cloned().unwrap_or(Level::Allow)
+	}
+
+	// Read lint levels from a manifest containing lines of the form
+	// `lint-name = level`. Blank lines and lines starting with # are ignored.
+	pub fn load_manifest(&mut self, manifest: &str) -> Result<(), String> {
+		for (number, line) in manifest.lines().enumerate() {
+			let line = line.trim();
+			if line.is_empty() || line.starts_with('#') {
+				continue;
+			}
+
+			let mut parts = line.splitn(2, '=');
+			let name  = parts.next().unwrap_or("").trim();
+			let level = parts.next().unwrap_or("").trim().trim_matches('"');
+
+			match Level::from_name(level) {
+				Some(level) => self.set(name, level).map_err(|error| format!("Line {}: {}", number + 1, error))?,
+				None        => return Err(format!("Line {}: Expected `lint-name = allow|warn|deny`", number + 1)),
+			}
+		}
+
+		Ok(())
+	}
+}
+
+
+/********************************************/
+// Public Functions
+/********************************************/
+// Run every lint that isn't allowed over a parsed program, naming its
+// labels from the table they were interned in
+pub fn check(program: &[Action], labels: &SymbolTable, config: &LintConfig) -> Vec<Diagnostic> {
+	let blocks = analysis::basic_blocks(program);
+	let mut diagnostics = Vec::new();
+
+	for lint in LINTS {
+		let level = config.level(lint.name);
+		if level == Level::Allow {
+			continue;
+		}
+
+		for (index, message) in (lint.pass)(program, &blocks, labels) {
+			diagnostics.push(Diagnostic {lint: lint.name, level, index, message});
+		}
+	}
+
+	diagnostics.sort_by_key(|diagnostic| diagnostic.index);
+	diagnostics
+}
+
+
+// Render the diagnostics as human readable text
+pub fn render_text(diagnostics: &[Diagnostic]) -> String {
+	let mut text = String::new();
+
+	for diagnostic in diagnostics {
+		let severity = if diagnostic.level == Level::Deny {"error"} else {"warning"};
+		text.push_str(&format!("{}[{}]: action {}: {}\n", severity, diagnostic.lint, diagnostic.index, diagnostic.message));
+	}
+
+	let errors = diagnostics.iter().filter(|diagnostic| diagnostic.level == Level::Deny).count();
+	text.push_str(&format!("{} error(s), {} warning(s)\n", errors, diagnostics.len() - errors));
+	text
+}
+
+
+// Render the diagnostics as a JSON array of objects
+pub fn render_json(diagnostics: &[Diagnostic]) -> String {
+	let entries: Vec<String> = diagnostics.iter().map(|diagnostic| format!(
+		"{{\"lint\":{},\"level\":{},\"index\":{},\"message\":{}}}",
+		json::string(diagnostic.lint),
+		json::string(diagnostic.level.name()),
+		diagnostic.index,
+		json::string(&diagnostic.message),
+	)).collect();
+
+	format!("[{}]\n", entries.join(","))
+}
+
+
+/********************************************/
+// Lint Passes
+/********************************************/
+// Labels that no call or jump refers to
+fn unused_label(program: &[Action], _blocks: &[BasicBlock], labels: &SymbolTable) -> Vec<(usize, String)> {
+	let mut targets = HashSet::new();
+	for action in program {
+		if let Call(label) | Jump(label) | JumpIfZero(label) | JumpIfNegative(label) = *action {
+			targets.insert(label);
+		}
+	}
+
+	program.iter().enumerate().filter_map(|(index, action)| match *action {
+		Label(label) if !targets.contains(&label) => Some((index, format!("label {} is never called or jumped to", labels.name(label)))),
+		_ => None,
+	}).collect()
+}
+
+
+// Subroutines that are only referred to by unreachable code. A subroutine
+// starts at a label that can't be fallen into and runs to the next return;
+// labels inside a reported subroutine aren't reported again.
+fn orphan_subroutine(program: &[Action], blocks: &[BasicBlock], labels: &SymbolTable) -> Vec<(usize, String)> {
+	let reachable = analysis::reachable_blocks(program, blocks);
+	let mut references: HashMap<LabelId, bool> = HashMap::new(); // Label -> referred to from reachable code
+
+	for (block, is_reachable) in blocks.iter().zip(reachable) {
+		for action in &program[block.start..block.end] {
+			if let Call(label) | Jump(label) | JumpIfZero(label) | JumpIfNegative(label) = *action {
+				*references.entry(label).or_insert(false) |= is_reachable;
+			}
+		}
+	}
+
+	let mut orphans   = Vec::new();
+	let mut in_orphan = false;
+
+	for (index, action) in program.iter().enumerate() {
+		match *action {
+			Label(label) if !in_orphan && references.get(&label) == Some(&false) => {
+				let fallen_into = index > 0 && !matches!(program[index - 1], Jump(_) | EndSubroutine | Halt);
+				if !fallen_into {
+					orphans.push((index, format!("subroutine {} is only referred to by unreachable code", labels.name(label))));
+					in_orphan = true;
+				}
+			},
+			EndSubroutine => in_orphan = false,
+			_ => {},
+		}
+	}
+
+	orphans
+}
+
+
+// Blocks that control can never reach
+fn unreachable_code(program: &[Action], blocks: &[BasicBlock], _labels: &SymbolTable) -> Vec<(usize, String)> {
+	let reachable = analysis::reachable_blocks(program, blocks);
+
+	blocks.iter().zip(reachable).filter(|&(block, reachable)| {
+		!reachable && program[block.start..block.end].iter().any(|action| !matches!(*action, Label(_)))
+	}).map(|(block, _)| {
+		(block.start, format!("actions {}-{} can never be executed", block.start, block.end - 1))
+	}).collect()
+}
+
+
+// Actions that may pop from a stack that is too small
+fn stack_underflow(program: &[Action], blocks: &[BasicBlock], _labels: &SymbolTable) -> Vec<(usize, String)> {
+	analysis::stack_underflows(program, blocks).into_iter().map(|underflow| {
+		(underflow.index, format!(
+			"{:?} pops {} value(s), but the stack may hold only {}",
+			program[underflow.index], underflow.needed, underflow.depth,
+		))
+	}).collect()
+}
+
+
+// Divisions and remainders by a value that's always zero
+fn division_by_zero(program: &[Action], blocks: &[BasicBlock], _labels: &SymbolTable) -> Vec<(usize, String)> {
+	analysis::certainties(program, blocks).into_iter().filter_map(|(index, certainty)| match certainty {
+		Certainty::DivisionByZero => Some((index, format!("{:?} always divides by zero", program[index]))),
+		_                         => None,
+	}).collect()
+}
+
+
+// Heap reads of a known address that no store or read can ever have set
+fn unset_heap_read(program: &[Action], blocks: &[BasicBlock], _labels: &SymbolTable) -> Vec<(usize, String)> {
+	analysis::certainties(program, blocks).into_iter().filter_map(|(index, certainty)| match certainty {
+		Certainty::UnsetRetrieve(address) => Some((index, format!("HeapRetrieve reads address {}, which nothing ever stores to", address))),
+		_                                 => None,
+	}).collect()
+}
+
+
+// Conditional jumps that test a value known before the program runs
+fn constant_branch(program: &[Action], blocks: &[BasicBlock], _labels: &SymbolTable) -> Vec<(usize, String)> {
+	analysis::certainties(program, blocks).into_iter().filter_map(|(index, certainty)| match certainty {
+		Certainty::Branch(taken, value) => Some((index, format!(
+			"{:?} is {} taken, since the value it tests is always {}",
+			program[index], if taken {"always"} else {"never"}, value,
+		))),
+		_ => None,
+	}).collect()
+}
+
+
+// Pushes of numbers too large for any reasonable program
+fn huge_literal(program: &[Action], _blocks: &[BasicBlock], _labels: &SymbolTable) -> Vec<(usize, String)> {
+	program.iter().enumerate().filter_map(|(index, action)| match *action {
+		StackPush(value) if value > i32::MAX as i64 || value < i32::MIN as i64 => {
+			Some((index, format!("the literal {} doesn't fit in 32 bits", value)))
+		},
+		_ => None,
+	}).collect()
+}
@@ -0,0 +1,1247 @@
+/***********************************************************/
+//
+// LLVM Backend
+// ============
+// Compiles a program to textual LLVM IR, so clang can build
+// it ahead of time into a native binary and optimize it like
+// any other code. Only built with the llvm feature.
+//
+//    whitespace compile --target llvm prog.ws > prog.ll
+//    clang -O2 prog.ll -o prog
+//
+// Every basic block of the program becomes a block of main,
+// with jumps as branches between them. Calls push the index
+// of the action they return to onto a stack of their own,
+// and returning pops it and switches on it to find the
+// block to go back to. The stack is a growable array and
+// the heap a hash table, both kept in globals and handled
+// by small functions in the runtime below.
+//
+// The IR uses typed pointers, which LLVM still reads on
+// versions that have moved on to opaque ones.
+//
+// struct Llvm <- The LLVM backend
+//
+/***********************************************************/
+use std::collections::HashMap;
+use analysis;
+use compile;
+use compile::Backend;
+use labels::LabelId;
+use options::{CharEncoding, Division, EmptyLine, EndOfProgram, EofPolicy, InvalidChar, InvalidNumber, Options, ReadCharDestination, UnsetHeap};
+use parser::UNRESOLVED;
+use symbols::Action;
+use symbols::Action::*;
+
+
+/********************************************/
+// Structures
+/********************************************/
+// The LLVM backend
+pub struct Llvm;
+
+impl Backend for Llvm {
+	fn name(&self) -> &'static str {
+		"llvm"
+	}
+
+	fn emit(&self, program: &[Action], options: &Options) -> Result<Vec<u8>, String> {
+		let mut strings = Strings::default();
+		let main = self.main(program, options, &mut strings)?;
+
+		let heap = options.read_char == ReadCharDestination::Heap;
+		let (eof_fails, eof_keeps, eof_value) = match options.eof {
+			EofPolicy::Error       => (true,  false, 0),
+			EofPolicy::Unchanged   => (false, true,  0),
+			EofPolicy::NegativeOne => (false, false, -1),
+			EofPolicy::Zero        => (false, false, 0),
+		};
+
+		let mut settings = vec![
+			("$ROUNDING",           division(options.division).to_string()),
+			("$UNSET_HEAP_IS_ZERO", boolean(options.unset_heap == UnsetHeap::Zero).to_string()),
+			("$UNICODE",            boolean(options.char_encoding == CharEncoding::Unicode).to_string()),
+			("$INVALID_CHAR_FAILS", boolean(options.invalid_char == InvalidChar::Error).to_string()),
+			("$EOF_FAILS",          boolean(eof_fails).to_string()),
+			("$EOF_KEEPS",          boolean(eof_keeps).to_string()),
+			("$EOF_VALUE",          eof_value.to_string()),
+			("$STORE_CHAR",         if heap {"\t%address = call i64 @pop()\n\tcall void @heap_set(i64 %address, i64 %stored)"} else {"\tcall void @push(i64 %stored)"}.to_string()),
+			("$SKIP_CHAR",          if heap {"\t%discarded = call i64 @pop()"} else {""}.to_string()),
+			("$REPROMPTS",          boolean(options.invalid_number == InvalidNumber::Reprompt).to_string()),
+			("$TRIMS_WHITESPACE",   boolean(options.number_input.whitespace).to_string()),
+			("$ALLOWS_PLUS",        boolean(options.number_input.plus_sign).to_string()),
+			("$ALLOWS_HEX",         boolean(options.number_input.hexadecimal).to_string()),
+			("$EMPTY_LINE",         match options.number_input.empty_line {EmptyLine::Zero => "0", EmptyLine::Skip => "1", EmptyLine::Invalid => "2"}.to_string()),
+		];
+		for &(name, text) in MESSAGES {
+			settings.push((name, strings.get(text)));
+		}
+
+		// Replace the longest names first, so none is mistaken for the start of another
+		settings.sort_by_key(|&(name, _)| usize::MAX - name.len());
+		let mut module = RUNTIME.to_string();
+		for (name, value) in settings {
+			module = module.replace(name, &value);
+		}
+
+		module.push_str(&main);
+		module.push('\n');
+		module.push_str(&strings.globals);
+		Ok(module.into_bytes())
+	}
+}
+
+impl Llvm {
+	// The main function, holding the program's blocks
+	fn main(&self, program: &[Action], options: &Options, strings: &mut Strings) -> Result<String, String> {
+		let mut starts = compile::leaders(program);
+		for block in analysis::basic_blocks(program) {
+			starts[block.start] = true;
+		}
+
+		let returns: Vec<String> = program.iter().enumerate()
+			.filter(|&(_, action)| matches!(*action, Call(_)))
+			.map(|(index, _)| format!("i64 {}, label %a{}", index + 1, index + 1))
+			.collect();
+
+		let mut body  = String::from("define i32 @main() {\nentry:\n\tcall void @allocate(i64 16)\n\tbr label %a0\n");
+		let mut extra = String::new();
+		let mut value = 0;
+		let mut ended = true;
+
+		for (index, action) in program.iter().enumerate() {
+			if starts[index] {
+				if !ended {
+					body.push_str(&format!("\tbr label %a{}\n", index));
+				}
+				body.push_str(&format!("\na{}:\n", index));
+			}
+
+			let needed = analysis::stack_effect(action).pops;
+			if needed > 0 {
+				body.push_str(&format!("\tcall void @need(i32 {}, i64 {}, i8* {})\n", index, needed, strings.get(&format!("{:?}", action))));
+			}
+
+			// Calls and jumps to missing labels go to a block that fails
+			let mut target = |LabelId(target)| if target == UNRESOLVED {
+				let missing = strings.get("Tried to jump to a label that doesn't exist.");
+				extra.push_str(&format!("\nu{}:\n\tcall void @fail(i32 {}, i8* {})\n\tunreachable\n", index, index, missing));
+				format!("u{}", index)
+			} else {
+				format!("a{}", target)
+			};
+
+			value += 1;
+			body.push_str(&match *action {
+				StackPush(number) => format!("\tcall void @push(i64 {})\n", number),
+				StackDuplicate    => format!("\t%t{0} = call i64 @pop()\n\tcall void @push(i64 %t{0})\n\tcall void @push(i64 %t{0})\n", value),
+				StackSwap         => format!("\t%t{0}a = call i64 @pop()\n\t%t{0}b = call i64 @pop()\n\tcall void @push(i64 %t{0}a)\n\tcall void @push(i64 %t{0}b)\n", value),
+				StackDiscard      => format!("\t%t{} = call i64 @pop()\n", value),
+
+				Add      => format!("\tcall void @add(i32 {})\n", index),
+				Subtract => format!("\tcall void @subtract(i32 {})\n", index),
+				Multiply => format!("\tcall void @multiply(i32 {})\n", index),
+				Divide   => format!("\tcall void @divide(i32 {})\n", index),
+				Modulo   => format!("\tcall void @modulo(i32 {})\n", index),
+
+				HeapStore    => "\tcall void @store()\n".to_string(),
+				HeapRetrieve => format!("\t%t{0}a = call i64 @pop()\n\t%t{0}b = call i64 @retrieve(i32 {1}, i64 %t{0}a)\n\tcall void @push(i64 %t{0}b)\n", value, index),
+
+				Call(called) => format!("\tcall void @enter(i64 {})\n\tbr label %{}\n", index + 1, target(called)),
+				Jump(jumped) => format!("\tbr label %{}\n", target(jumped)),
+				JumpIfZero(jumped) => {
+					format!("\t%t{0}a = call i64 @pop()\n\t%t{0}b = icmp eq i64 %t{0}a, 0\n\tbr i1 %t{0}b, label %{1}, label %a{2}\n", value, target(jumped), index + 1)
+				},
+				JumpIfNegative(jumped) => {
+					format!("\t%t{0}a = call i64 @pop()\n\t%t{0}b = icmp slt i64 %t{0}a, 0\n\tbr i1 %t{0}b, label %{1}, label %a{2}\n", value, target(jumped), index + 1)
+				},
+				EndSubroutine => format!("\t%t{} = call i64 @leave(i32 {})\n\tswitch i64 %t{}, label %invalid [{}]\n", value, index, value, returns.join(" ")),
+				Halt          => "\tbr label %halt\n".to_string(),
+
+				OutputChar   => format!("\tcall void @output_char(i32 {})\n", index),
+				OutputNumber => "\tcall void @output_number()\n".to_string(),
+				ReadChar     => format!("\tcall void @read_char(i32 {})\n", index),
+				ReadNumber   => format!("\tcall void @read_number(i32 {})\n", index),
+
+				Label(_) | GetEnv | DumpHeap | Assert | CallDepth | PeekReturn | OutputFormatted | Clock | Random => return Err(compile::unsupported(self, action)),
+			});
+
+			ended = analysis::ends_block(action);
+		}
+
+		// Running off the end
+		if !ended {
+			body.push_str(&format!("\tbr label %a{}\n", program.len()));
+		}
+		body.push_str(&format!("\na{}:\n", program.len()));
+		body.push_str(&match options.end_of_program {
+			EndOfProgram::Halt  => "\tbr label %halt\n".to_string(),
+			EndOfProgram::Error => format!("\tcall void @fail(i32 {}, i8* {})\n\tunreachable\n", program.len(), strings.get("Reached the end of the program without halting.")),
+		});
+
+		body.push_str("\nhalt:\n\t%flushed = call i32 @fflush(i8* null)\n\tret i32 0\n");
+
+		// Returns only ever go to the actions after calls
+		body.push_str("\ninvalid:\n\tunreachable\n");
+		body.push_str(&extra);
+		body.push_str("}\n");
+		Ok(body)
+	}
+}
+
+
+// The module's string constants
+#[derive(Default)]
+struct Strings {
+	globals: String,                // Their definitions
+	indices: HashMap<String, usize>, // The number of each one's global
+}
+
+impl Strings {
+	// A pointer to a NUL-terminated copy of a string, defining it the first time
+	fn get(&mut self, text: &str) -> String {
+		let index = match self.indices.get(text) {
+			Some(&index) => index,
+			None         => {
+				let index = self.indices.len();
+				self.indices.insert(text.to_string(), index);
+				self.globals.push_str(&format!("@.str.{} = private unnamed_addr constant [{} x i8] c\"{}\\00\"\n", index, text.len() + 1, escape(text)));
+				index
+			},
+		};
+
+		format!("getelementptr inbounds ([{0} x i8], [{0} x i8]* @.str.{1}, i64 0, i64 0)", text.len() + 1, index)
+	}
+}
+
+
+/********************************************/
+// Private Functions
+/********************************************/
+// Escape a string for a c"..." constant
+fn escape(text: &str) -> String {
+	text.bytes().map(|byte| match byte {
+		b'"' | b'\\'   => format!("\\{:02X}", byte),
+		0x20..=0x7e    => (byte as char).to_string(),
+		_              => format!("\\{:02X}", byte),
+	}).collect()
+}
+
+
+// A boolean, as IR
+fn boolean(value: bool) -> &'static str {
+	if value {"true"} else {"false"}
+}
+
+
+// The quotient and remainder functions for a kind of division
+fn division(division: Division) -> &'static str {
+	match division {
+		Division::Truncated => "\
+define internal i64 @quotient(i64 %left, i64 %right) {
+	%quotient = sdiv i64 %left, %right
+	ret i64 %quotient
+}
+
+define internal i64 @remainder(i64 %left, i64 %right) {
+	%remainder = srem i64 %left, %right
+	ret i64 %remainder
+}",
+
+		// Round down when the operands have different signs and the division isn't exact
+		Division::Floored => "\
+define internal i64 @quotient(i64 %left, i64 %right) {
+	%truncated = sdiv i64 %left, %right
+	%remainder = srem i64 %left, %right
+	%inexact = icmp ne i64 %remainder, 0
+	%signs = xor i64 %left, %right
+	%opposite = icmp slt i64 %signs, 0
+	%adjust = and i1 %inexact, %opposite
+	%step = zext i1 %adjust to i64
+	%quotient = sub i64 %truncated, %step
+	ret i64 %quotient
+}
+
+define internal i64 @remainder(i64 %left, i64 %right) {
+	%truncated = srem i64 %left, %right
+	%inexact = icmp ne i64 %truncated, 0
+	%signs = xor i64 %truncated, %right
+	%opposite = icmp slt i64 %signs, 0
+	%adjust = and i1 %inexact, %opposite
+	%moved = add i64 %truncated, %right
+	%remainder = select i1 %adjust, i64 %moved, i64 %truncated
+	ret i64 %remainder
+}",
+
+		// Keep the remainder positive, moving the quotient away from zero to match
+		Division::Euclidean => "\
+define internal i64 @quotient(i64 %left, i64 %right) {
+	%truncated = sdiv i64 %left, %right
+	%remainder = srem i64 %left, %right
+	%negative = icmp slt i64 %remainder, 0
+	%positive_right = icmp sgt i64 %right, 0
+	%step = select i1 %positive_right, i64 -1, i64 1
+	%moved = add i64 %truncated, %step
+	%quotient = select i1 %negative, i64 %moved, i64 %truncated
+	ret i64 %quotient
+}
+
+define internal i64 @remainder(i64 %left, i64 %right) {
+	%truncated = srem i64 %left, %right
+	%negative = icmp slt i64 %truncated, 0
+	%negative_right = icmp slt i64 %right, 0
+	%negated = sub i64 0, %right
+	%magnitude = select i1 %negative_right, i64 %negated, i64 %right
+	%moved = add i64 %truncated, %magnitude
+	%remainder = select i1 %negative, i64 %moved, i64 %truncated
+	ret i64 %remainder
+}",
+	}
+}
+
+
+// The strings the runtime uses, by the names it uses them by
+static MESSAGES: &[(&str, &str)] = &[
+	("$FAIL_FORMAT",         "Error: Runtime Error: %s (at action %d)\n"),
+	("$INVALID_FORMAT",      "Error: Runtime Error: Unable to parse number: %s (at action %d)\n"),
+	("$UNPARSED_FORMAT",     "Unable to parse number: %s\n"),
+	("$NEED_FORMAT",         "%s needs %lld value(s) on the stack, but it holds %lld."),
+	("$MISSING_FORMAT",      "Tried to get a value from the heap, but no value was found at address: %lld"),
+	("$NOT_A_BYTE",          "Tried to write %lld as a character, but it's outside the range of a byte."),
+	("$NOT_A_CODE_POINT",    "Tried to write %lld as a character, but it isn't a Unicode code point."),
+	("$NUMBER_FORMAT",       "%lld"),
+	("$NO_CALL",             "Tried to return from a procedure, but no procedure call was made."),
+	("$ADD_OVERFLOWED",      "Addition overflowed."),
+	("$SUBTRACT_OVERFLOWED", "Subtraction overflowed."),
+	("$MULTIPLY_OVERFLOWED", "Multiplication overflowed."),
+	("$DIVIDE_BY_ZERO",      "Tried to divide by zero."),
+	("$DIVISION_OVERFLOWED", "Division overflowed."),
+	("$MODULO_BY_ZERO",      "Tried to take the remainder of a division by zero."),
+	("$MODULO_OVERFLOWED",   "Modulo overflowed."),
+	("$CHAR_AT_END",         "Reached the end of the input while reading a character."),
+	("$NUMBER_AT_END",       "Reached the end of the input while reading a number."),
+	("$EMPTY_REASON",        "the line is empty"),
+	("$PLUS_REASON",         "a + sign isn't allowed"),
+	("$NOT_A_NUMBER",        " isn't a number"),
+	("$TOO_BIG",             " doesn't fit in 64 bits"),
+];
+
+
+// The module main is added to
+static RUNTIME: &str = r#"; Compiled from Whitespace with: whitespace compile --target llvm
+;
+; Build it with clang into a program that reads stdin and writes stdout:
+;    clang -O2 prog.ll -o prog
+
+declare i8* @realloc(i8*, i64)
+declare i8* @calloc(i64, i64)
+declare void @free(i8*)
+declare i8* @strcpy(i8*, i8*)
+declare i32 @printf(i8*, ...)
+declare i32 @snprintf(i8*, i64, i8*, ...)
+declare i32 @putchar(i32)
+declare i32 @getchar()
+declare i32 @fflush(i8*)
+declare void @exit(i32) noreturn
+declare {i64, i1} @llvm.sadd.with.overflow.i64(i64, i64)
+declare {i64, i1} @llvm.ssub.with.overflow.i64(i64, i64)
+declare {i64, i1} @llvm.smul.with.overflow.i64(i64, i64)
+
+; The stack, and the actions unfinished calls return to, with the number
+; of bytes allocated for each
+@stack = internal global i8* null
+@stack_bytes = internal global i64 0
+@size = internal global i64 0
+@calls = internal global i8* null
+@calls_bytes = internal global i64 0
+@depth = internal global i64 0
+
+; The heap is a hash table with linear probing, kept at most half full
+@keys = internal global i8* null
+@values = internal global i8* null
+@used = internal global i8* null
+@slots = internal global i64 0
+@count = internal global i64 0
+
+; The line being read by ReadNumber, what it parsed to, and why it
+; didn't when it isn't a number
+@line = internal global i8* null
+@line_bytes = internal global i64 0
+@parsed = internal global i64 0
+@reason = internal global i8* null
+@quoted = internal global i8* null
+@quoted_bytes = internal global i64 0
+@message = internal global [256 x i8] zeroinitializer
+
+
+; Stop the program, printing an error the way the interpreter does
+define internal void @stop(i8* %format, i8* %text, i32 %at) noreturn {
+	%flushed = call i32 @fflush(i8* null)
+	%printed = call i32 (i8*, ...) @printf(i8* %format, i8* %text, i32 %at)
+	%written = call i32 @fflush(i8* null)
+	call void @exit(i32 6)
+	unreachable
+}
+
+define internal void @fail(i32 %at, i8* %message) noreturn {
+	call void @stop(i8* $FAIL_FORMAT, i8* %message, i32 %at)
+	unreachable
+}
+
+
+; Make sure an array has room for some number of bytes, doubling it when it doesn't
+define internal void @reserve(i8** %array, i64* %allocated, i64 %bytes) {
+entry:
+	%size = load i64, i64* %allocated
+	%full = icmp ugt i64 %bytes, %size
+	br i1 %full, label %grow, label %done
+
+grow:
+	%doubled = mul i64 %bytes, 2
+	%old = load i8*, i8** %array
+	%new = call i8* @realloc(i8* %old, i64 %doubled)
+	store i8* %new, i8** %array
+	store i64 %doubled, i64* %allocated
+	br label %done
+
+done:
+	ret void
+}
+
+
+define internal void @push(i64 %value) {
+	%size = load i64, i64* @size
+	%grown = add i64 %size, 1
+	%bytes = mul i64 %grown, 8
+	call void @reserve(i8** @stack, i64* @stack_bytes, i64 %bytes)
+	%raw = load i8*, i8** @stack
+	%stack = bitcast i8* %raw to i64*
+	%slot = getelementptr inbounds i64, i64* %stack, i64 %size
+	store i64 %value, i64* %slot
+	store i64 %grown, i64* @size
+	ret void
+}
+
+; Take the top of the stack, which need has already checked is there
+define internal i64 @pop() {
+	%size = load i64, i64* @size
+	%shrunk = sub i64 %size, 1
+	store i64 %shrunk, i64* @size
+	%raw = load i8*, i8** @stack
+	%stack = bitcast i8* %raw to i64*
+	%slot = getelementptr inbounds i64, i64* %stack, i64 %shrunk
+	%value = load i64, i64* %slot
+	ret i64 %value
+}
+
+; Fail unless the stack holds enough values for an action
+define internal void @need(i32 %at, i64 %count, i8* %action) {
+entry:
+	%size = load i64, i64* @size
+	%short = icmp ult i64 %size, %count
+	br i1 %short, label %fail, label %done
+
+fail:
+	%message = getelementptr inbounds [256 x i8], [256 x i8]* @message, i64 0, i64 0
+	%written = call i32 (i8*, i64, i8*, ...) @snprintf(i8* %message, i64 256, i8* $NEED_FORMAT, i8* %action, i64 %count, i64 %size)
+	call void @fail(i32 %at, i8* %message)
+	unreachable
+
+done:
+	ret void
+}
+
+
+; Remember the action a call returns to
+define internal void @enter(i64 %return) {
+	%depth = load i64, i64* @depth
+	%grown = add i64 %depth, 1
+	%bytes = mul i64 %grown, 8
+	call void @reserve(i8** @calls, i64* @calls_bytes, i64 %bytes)
+	%raw = load i8*, i8** @calls
+	%calls = bitcast i8* %raw to i64*
+	%slot = getelementptr inbounds i64, i64* %calls, i64 %depth
+	store i64 %return, i64* %slot
+	store i64 %grown, i64* @depth
+	ret void
+}
+
+; The action the latest call returns to
+define internal i64 @leave(i32 %at) {
+entry:
+	%depth = load i64, i64* @depth
+	%empty = icmp eq i64 %depth, 0
+	br i1 %empty, label %fail, label %return
+
+fail:
+	call void @fail(i32 %at, i8* $NO_CALL)
+	unreachable
+
+return:
+	%shrunk = sub i64 %depth, 1
+	store i64 %shrunk, i64* @depth
+	%raw = load i8*, i8** @calls
+	%calls = bitcast i8* %raw to i64*
+	%slot = getelementptr inbounds i64, i64* %calls, i64 %shrunk
+	%action = load i64, i64* %slot
+	ret i64 %action
+}
+
+
+define internal void @add(i32 %at) {
+entry:
+	%right = call i64 @pop()
+	%left = call i64 @pop()
+	%result = call {i64, i1} @llvm.sadd.with.overflow.i64(i64 %left, i64 %right)
+	%overflowed = extractvalue {i64, i1} %result, 1
+	br i1 %overflowed, label %fail, label %done
+
+fail:
+	call void @fail(i32 %at, i8* $ADD_OVERFLOWED)
+	unreachable
+
+done:
+	%value = extractvalue {i64, i1} %result, 0
+	call void @push(i64 %value)
+	ret void
+}
+
+define internal void @subtract(i32 %at) {
+entry:
+	%right = call i64 @pop()
+	%left = call i64 @pop()
+	%result = call {i64, i1} @llvm.ssub.with.overflow.i64(i64 %left, i64 %right)
+	%overflowed = extractvalue {i64, i1} %result, 1
+	br i1 %overflowed, label %fail, label %done
+
+fail:
+	call void @fail(i32 %at, i8* $SUBTRACT_OVERFLOWED)
+	unreachable
+
+done:
+	%value = extractvalue {i64, i1} %result, 0
+	call void @push(i64 %value)
+	ret void
+}
+
+define internal void @multiply(i32 %at) {
+entry:
+	%right = call i64 @pop()
+	%left = call i64 @pop()
+	%result = call {i64, i1} @llvm.smul.with.overflow.i64(i64 %left, i64 %right)
+	%overflowed = extractvalue {i64, i1} %result, 1
+	br i1 %overflowed, label %fail, label %done
+
+fail:
+	call void @fail(i32 %at, i8* $MULTIPLY_OVERFLOWED)
+	unreachable
+
+done:
+	%value = extractvalue {i64, i1} %result, 0
+	call void @push(i64 %value)
+	ret void
+}
+
+; Fail if a division is by zero or is the one that overflows
+define internal void @check_division(i32 %at, i64 %left, i64 %right, i8* %by_zero, i8* %overflowed) {
+entry:
+	%zero = icmp eq i64 %right, 0
+	br i1 %zero, label %fail_zero, label %nonzero
+
+fail_zero:
+	call void @fail(i32 %at, i8* %by_zero)
+	unreachable
+
+nonzero:
+	%smallest = icmp eq i64 %left, -9223372036854775808
+	%negative_one = icmp eq i64 %right, -1
+	%overflow = and i1 %smallest, %negative_one
+	br i1 %overflow, label %fail_overflow, label %done
+
+fail_overflow:
+	call void @fail(i32 %at, i8* %overflowed)
+	unreachable
+
+done:
+	ret void
+}
+
+$ROUNDING
+
+define internal void @divide(i32 %at) {
+	%right = call i64 @pop()
+	%left = call i64 @pop()
+	call void @check_division(i32 %at, i64 %left, i64 %right, i8* $DIVIDE_BY_ZERO, i8* $DIVISION_OVERFLOWED)
+	%value = call i64 @quotient(i64 %left, i64 %right)
+	call void @push(i64 %value)
+	ret void
+}
+
+define internal void @modulo(i32 %at) {
+	%right = call i64 @pop()
+	%left = call i64 @pop()
+	call void @check_division(i32 %at, i64 %left, i64 %right, i8* $MODULO_BY_ZERO, i8* $MODULO_OVERFLOWED)
+	%value = call i64 @remainder(i64 %left, i64 %right)
+	call void @push(i64 %value)
+	ret void
+}
+
+
+; Replace the heap's table with an empty one
+define internal void @allocate(i64 %slots) {
+	%keys = call i8* @calloc(i64 %slots, i64 8)
+	%values = call i8* @calloc(i64 %slots, i64 8)
+	%used = call i8* @calloc(i64 %slots, i64 1)
+	store i8* %keys, i8** @keys
+	store i8* %values, i8** @values
+	store i8* %used, i8** @used
+	store i64 %slots, i64* @slots
+	store i64 0, i64* @count
+	ret void
+}
+
+; The slot an address is in, or the empty slot it would go in
+define internal i64 @find(i64 %address) {
+entry:
+	%slots = load i64, i64* @slots
+	%mask = sub i64 %slots, 1
+	%raw_keys = load i8*, i8** @keys
+	%keys = bitcast i8* %raw_keys to i64*
+	%used = load i8*, i8** @used
+	%mixed = mul i64 %address, -7046029254386353131
+	%high = lshr i64 %mixed, 32
+	%hash = xor i64 %mixed, %high
+	%start = and i64 %hash, %mask
+	br label %probe
+
+probe:
+	%slot = phi i64 [%start, %entry], [%next, %taken]
+	%used_slot = getelementptr inbounds i8, i8* %used, i64 %slot
+	%in_use = load i8, i8* %used_slot
+	%empty = icmp eq i8 %in_use, 0
+	br i1 %empty, label %found, label %compare
+
+compare:
+	%key_slot = getelementptr inbounds i64, i64* %keys, i64 %slot
+	%key = load i64, i64* %key_slot
+	%same = icmp eq i64 %key, %address
+	br i1 %same, label %found, label %taken
+
+taken:
+	%following = add i64 %slot, 1
+	%next = and i64 %following, %mask
+	br label %probe
+
+found:
+	ret i64 %slot
+}
+
+; Fill a slot, counting it if it was empty
+define internal void @set_slot(i64 %slot, i64 %address, i64 %value) {
+entry:
+	%used = load i8*, i8** @used
+	%used_slot = getelementptr inbounds i8, i8* %used, i64 %slot
+	%in_use = load i8, i8* %used_slot
+	%empty = icmp eq i8 %in_use, 0
+	br i1 %empty, label %claim, label %fill
+
+claim:
+	store i8 1, i8* %used_slot
+	%count = load i64, i64* @count
+	%more = add i64 %count, 1
+	store i64 %more, i64* @count
+	br label %fill
+
+fill:
+	%raw_keys = load i8*, i8** @keys
+	%keys = bitcast i8* %raw_keys to i64*
+	%key_slot = getelementptr inbounds i64, i64* %keys, i64 %slot
+	store i64 %address, i64* %key_slot
+	%raw_values = load i8*, i8** @values
+	%values = bitcast i8* %raw_values to i64*
+	%value_slot = getelementptr inbounds i64, i64* %values, i64 %slot
+	store i64 %value, i64* %value_slot
+	ret void
+}
+
+; Move every value into a table of a new size
+define internal void @rehash(i64 %slots) {
+entry:
+	%old_slots = load i64, i64* @slots
+	%raw_keys = load i8*, i8** @keys
+	%keys = bitcast i8* %raw_keys to i64*
+	%raw_values = load i8*, i8** @values
+	%values = bitcast i8* %raw_values to i64*
+	%used = load i8*, i8** @used
+	call void @allocate(i64 %slots)
+	br label %next
+
+next:
+	%index = phi i64 [0, %entry], [%following, %moved]
+	%finished = icmp eq i64 %index, %old_slots
+	br i1 %finished, label %done, label %move
+
+move:
+	%used_slot = getelementptr inbounds i8, i8* %used, i64 %index
+	%in_use = load i8, i8* %used_slot
+	%empty = icmp eq i8 %in_use, 0
+	br i1 %empty, label %moved, label %copy
+
+copy:
+	%key_slot = getelementptr inbounds i64, i64* %keys, i64 %index
+	%key = load i64, i64* %key_slot
+	%value_slot = getelementptr inbounds i64, i64* %values, i64 %index
+	%value = load i64, i64* %value_slot
+	%slot = call i64 @find(i64 %key)
+	call void @set_slot(i64 %slot, i64 %key, i64 %value)
+	br label %moved
+
+moved:
+	%following = add i64 %index, 1
+	br label %next
+
+done:
+	call void @free(i8* %raw_keys)
+	call void @free(i8* %raw_values)
+	call void @free(i8* %used)
+	ret void
+}
+
+define internal void @heap_set(i64 %address, i64 %value) {
+entry:
+	%count = load i64, i64* @count
+	%slots = load i64, i64* @slots
+	%needed = mul i64 %count, 2
+	%full = icmp uge i64 %needed, %slots
+	br i1 %full, label %grow, label %insert
+
+grow:
+	%doubled = mul i64 %slots, 2
+	call void @rehash(i64 %doubled)
+	br label %insert
+
+insert:
+	%slot = call i64 @find(i64 %address)
+	call void @set_slot(i64 %slot, i64 %address, i64 %value)
+	ret void
+}
+
+; Pop a value, then the address to store it at
+define internal void @store() {
+	%value = call i64 @pop()
+	%address = call i64 @pop()
+	call void @heap_set(i64 %address, i64 %value)
+	ret void
+}
+
+define internal i64 @retrieve(i32 %at, i64 %address) {
+entry:
+	%slot = call i64 @find(i64 %address)
+	%used = load i8*, i8** @used
+	%used_slot = getelementptr inbounds i8, i8* %used, i64 %slot
+	%in_use = load i8, i8* %used_slot
+	%empty = icmp eq i8 %in_use, 0
+	br i1 %empty, label %missing, label %present
+
+present:
+	%raw_values = load i8*, i8** @values
+	%values = bitcast i8* %raw_values to i64*
+	%value_slot = getelementptr inbounds i64, i64* %values, i64 %slot
+	%value = load i64, i64* %value_slot
+	ret i64 %value
+
+missing:
+	br i1 $UNSET_HEAP_IS_ZERO, label %zero, label %fail
+
+zero:
+	ret i64 0
+
+fail:
+	%message = getelementptr inbounds [256 x i8], [256 x i8]* @message, i64 0, i64 0
+	%written = call i32 (i8*, i64, i8*, ...) @snprintf(i8* %message, i64 256, i8* $MISSING_FORMAT, i64 %address)
+	call void @fail(i32 %at, i8* %message)
+	unreachable
+}
+
+
+; Write the top of the stack as a character, in UTF-8, taking it as a byte
+; or a code point
+define internal void @output_char(i32 %at) {
+entry:
+	%value = call i64 @pop()
+	br i1 $UNICODE, label %unicode, label %byte
+
+byte:
+	%in_byte = icmp ult i64 %value, 256
+	br i1 %in_byte, label %low_byte, label %invalid_byte
+
+invalid_byte:
+	br i1 $INVALID_CHAR_FAILS, label %fail_byte, label %low_byte
+
+fail_byte:
+	%byte_message = getelementptr inbounds [256 x i8], [256 x i8]* @message, i64 0, i64 0
+	%byte_written = call i32 (i8*, i64, i8*, ...) @snprintf(i8* %byte_message, i64 256, i8* $NOT_A_BYTE, i64 %value)
+	call void @fail(i32 %at, i8* %byte_message)
+	unreachable
+
+low_byte:
+	%low_value = and i64 %value, 255
+	br label %encode
+
+; Code points stop at U+10FFFF, and skip the surrogates U+D800 to U+DFFF
+unicode:
+	%in_range = icmp ult i64 %value, 1114112
+	%below = icmp ult i64 %value, 55296
+	%above = icmp ugt i64 %value, 57343
+	%outside = or i1 %below, %above
+	%valid = and i1 %in_range, %outside
+	br i1 %valid, label %code_point, label %invalid_point
+
+invalid_point:
+	br i1 $INVALID_CHAR_FAILS, label %fail_point, label %replacement
+
+fail_point:
+	%point_message = getelementptr inbounds [256 x i8], [256 x i8]* @message, i64 0, i64 0
+	%point_written = call i32 (i8*, i64, i8*, ...) @snprintf(i8* %point_message, i64 256, i8* $NOT_A_CODE_POINT, i64 %value)
+	call void @fail(i32 %at, i8* %point_message)
+	unreachable
+
+replacement:
+	br label %encode
+
+code_point:
+	br label %encode
+
+encode:
+	%wide = phi i64 [%low_value, %low_byte], [65533, %replacement], [%value, %code_point]
+	%char = trunc i64 %wide to i32
+	%ascii = icmp ult i32 %char, 128
+	br i1 %ascii, label %single, label %multiple
+
+single:
+	%written = call i32 @putchar(i32 %char)
+	ret void
+
+multiple:
+	%last = and i32 %char, 63
+	%last_trail = or i32 %last, 128
+	%two = icmp ult i32 %char, 2048
+	br i1 %two, label %double, label %longer
+
+double:
+	%double_high = lshr i32 %char, 6
+	%double_lead = or i32 %double_high, 192
+	%double_first = call i32 @putchar(i32 %double_lead)
+	%double_second = call i32 @putchar(i32 %last_trail)
+	ret void
+
+longer:
+	%middle_shifted = lshr i32 %char, 6
+	%middle = and i32 %middle_shifted, 63
+	%middle_trail = or i32 %middle, 128
+	%three = icmp ult i32 %char, 65536
+	br i1 %three, label %triple, label %quadruple
+
+triple:
+	%triple_high = lshr i32 %char, 12
+	%triple_lead = or i32 %triple_high, 224
+	%triple_first = call i32 @putchar(i32 %triple_lead)
+	%triple_second = call i32 @putchar(i32 %middle_trail)
+	%triple_third = call i32 @putchar(i32 %last_trail)
+	ret void
+
+quadruple:
+	%quadruple_high = lshr i32 %char, 18
+	%quadruple_lead = or i32 %quadruple_high, 240
+	%upper_shifted = lshr i32 %char, 12
+	%upper = and i32 %upper_shifted, 63
+	%upper_trail = or i32 %upper, 128
+	%quadruple_first = call i32 @putchar(i32 %quadruple_lead)
+	%quadruple_second = call i32 @putchar(i32 %upper_trail)
+	%quadruple_third = call i32 @putchar(i32 %middle_trail)
+	%quadruple_fourth = call i32 @putchar(i32 %last_trail)
+	ret void
+}
+
+define internal void @output_number() {
+	%value = call i64 @pop()
+	%written = call i32 (i8*, ...) @printf(i8* $NUMBER_FORMAT, i64 %value)
+	ret void
+}
+
+
+; Reads take their destination only once the read is done, like the interpreter
+define internal void @read_char(i32 %at) {
+entry:
+	%flushed = call i32 @fflush(i8* null)
+	%byte = call i32 @getchar()
+	%ended = icmp slt i32 %byte, 0
+	br i1 %ended, label %end, label %read
+
+read:
+	%value = sext i32 %byte to i64
+	br label %store
+
+end:
+	br i1 $EOF_FAILS, label %fail, label %kept
+
+fail:
+	call void @fail(i32 %at, i8* $CHAR_AT_END)
+	unreachable
+
+kept:
+	br i1 $EOF_KEEPS, label %unchanged, label %store
+
+store:
+	%stored = phi i64 [%value, %read], [$EOF_VALUE, %kept]
+$STORE_CHAR
+	ret void
+
+unchanged:
+$SKIP_CHAR
+	ret void
+}
+
+; Read the next line into @line without its line ending, giving its
+; length, or -1 at the end of the input
+define internal i64 @read_line() {
+entry:
+	br label %next
+
+next:
+	%length = phi i64 [0, %entry], [%longer, %byte]
+	%char = call i32 @getchar()
+	%ended = icmp slt i32 %char, 0
+	br i1 %ended, label %end, label %got
+
+got:
+	%newline = icmp eq i32 %char, 10
+	br i1 %newline, label %line_feed, label %byte
+
+byte:
+	%longer = add i64 %length, 1
+	call void @reserve(i8** @line, i64* @line_bytes, i64 %longer)
+	%buffer = load i8*, i8** @line
+	%slot = getelementptr inbounds i8, i8* %buffer, i64 %length
+	%truncated = trunc i32 %char to i8
+	store i8 %truncated, i8* %slot
+	br label %next
+
+end:
+	%nothing = icmp eq i64 %length, 0
+	br i1 %nothing, label %none, label %finish
+
+none:
+	ret i64 -1
+
+; Drop a carriage return before the line feed
+line_feed:
+	%some = icmp ugt i64 %length, 0
+	br i1 %some, label %check_return, label %finish
+
+check_return:
+	%line = load i8*, i8** @line
+	%previous = sub i64 %length, 1
+	%last_slot = getelementptr inbounds i8, i8* %line, i64 %previous
+	%last = load i8, i8* %last_slot
+	%is_return = icmp eq i8 %last, 13
+	%without = select i1 %is_return, i64 %previous, i64 %length
+	br label %finish
+
+finish:
+	%final = phi i64 [%length, %end], [%length, %line_feed], [%without, %check_return]
+	ret i64 %final
+}
+
+define internal i1 @blank(i8 %char) {
+	%space = icmp eq i8 %char, 32
+	%tab = icmp eq i8 %char, 9
+	%blank = or i1 %space, %tab
+	ret i1 %blank
+}
+
+; The value of a digit in a radix, or -1 if it isn't one
+define internal i64 @digit(i8 %char, i64 %radix) {
+entry:
+	%code = zext i8 %char to i64
+	%decimal = sub i64 %code, 48
+	%is_decimal = icmp ult i64 %decimal, 10
+	br i1 %is_decimal, label %check, label %letter
+
+letter:
+	%lower = or i64 %code, 32
+	%from_a = sub i64 %lower, 97
+	%is_letter = icmp ult i64 %from_a, 26
+	%letter_value = add i64 %from_a, 10
+	br i1 %is_letter, label %check, label %none
+
+check:
+	%value = phi i64 [%decimal, %entry], [%letter_value, %letter]
+	%fits = icmp ult i64 %value, %radix
+	br i1 %fits, label %some, label %none
+
+some:
+	ret i64 %value
+
+none:
+	ret i64 -1
+}
+
+; Set the reason a line isn't a number to the line, quoted the way the
+; interpreter quotes it, followed by some text
+define internal void @quote_reason(i64 %length, i8* %suffix) {
+entry:
+	%doubled = mul i64 %length, 2
+	%bytes = add i64 %doubled, 64
+	call void @reserve(i8** @quoted, i64* @quoted_bytes, i64 %bytes)
+	%quoted = load i8*, i8** @quoted
+	%line = load i8*, i8** @line
+	store i8 34, i8* %quoted
+	br label %next
+
+next:
+	%index = phi i64 [0, %entry], [%following, %copy]
+	%position = phi i64 [1, %entry], [%after, %copy]
+	%done = icmp eq i64 %index, %length
+	br i1 %done, label %close, label %character
+
+character:
+	%slot = getelementptr inbounds i8, i8* %line, i64 %index
+	%char = load i8, i8* %slot
+	%backslash = icmp eq i8 %char, 92
+	%quote = icmp eq i8 %char, 34
+	%special = or i1 %backslash, %quote
+	br i1 %special, label %escape, label %copy
+
+escape:
+	%escape_slot = getelementptr inbounds i8, i8* %quoted, i64 %position
+	store i8 92, i8* %escape_slot
+	%escaped = add i64 %position, 1
+	br label %copy
+
+copy:
+	%into = phi i64 [%position, %character], [%escaped, %escape]
+	%copy_slot = getelementptr inbounds i8, i8* %quoted, i64 %into
+	store i8 %char, i8* %copy_slot
+	%after = add i64 %into, 1
+	%following = add i64 %index, 1
+	br label %next
+
+close:
+	%close_slot = getelementptr inbounds i8, i8* %quoted, i64 %position
+	store i8 34, i8* %close_slot
+	%suffix_at = add i64 %position, 1
+	%suffix_slot = getelementptr inbounds i8, i8* %quoted, i64 %suffix_at
+	%copied = call i8* @strcpy(i8* %suffix_slot, i8* %suffix)
+	store i8* %quoted, i8** @reason
+	ret void
+}
+
+; Parse the line in @line the way the interpreter's number input options
+; say to, giving 0 with the number in @parsed, 1 to skip the line, or 2
+; with the reason it isn't a number in @reason
+define internal i32 @parse_number(i64 %length) {
+entry:
+	%line = load i8*, i8** @line
+	br i1 $TRIMS_WHITESPACE, label %trim_start, label %trimmed
+
+; Skip spaces and tabs at the start, then at the end
+trim_start:
+	%start = phi i64 [0, %entry], [%after_start, %check_start]
+	%all_blank = icmp eq i64 %start, %length
+	br i1 %all_blank, label %trim_end, label %check_start
+
+check_start:
+	%first_slot = getelementptr inbounds i8, i8* %line, i64 %start
+	%first = load i8, i8* %first_slot
+	%blank_first = call i1 @blank(i8 %first)
+	%after_start = add i64 %start, 1
+	br i1 %blank_first, label %trim_start, label %trim_end
+
+trim_end:
+	%end = phi i64 [%length, %trim_start], [%length, %check_start], [%previous, %check_end]
+	%met = icmp eq i64 %end, %start
+	br i1 %met, label %trimmed, label %check_end
+
+check_end:
+	%previous = sub i64 %end, 1
+	%last_slot = getelementptr inbounds i8, i8* %line, i64 %previous
+	%last = load i8, i8* %last_slot
+	%blank_last = call i1 @blank(i8 %last)
+	br i1 %blank_last, label %trim_end, label %trimmed
+
+trimmed:
+	%from = phi i64 [0, %entry], [%start, %trim_end], [%start, %check_end]
+	%to = phi i64 [%length, %entry], [%end, %trim_end], [%end, %check_end]
+	%empty = icmp eq i64 %from, %to
+	br i1 %empty, label %empty_line, label %sign
+
+empty_line:
+	switch i32 $EMPTY_LINE, label %empty_invalid [i32 0, label %empty_zero i32 1, label %empty_skip]
+
+empty_zero:
+	store i64 0, i64* @parsed
+	ret i32 0
+
+empty_skip:
+	ret i32 1
+
+empty_invalid:
+	store i8* $EMPTY_REASON, i8** @reason
+	ret i32 2
+
+sign:
+	%sign_slot = getelementptr inbounds i8, i8* %line, i64 %from
+	%sign_char = load i8, i8* %sign_slot
+	%minus = icmp eq i8 %sign_char, 45
+	%plus = icmp eq i8 %sign_char, 43
+	%forbid_plus = xor i1 $ALLOWS_PLUS, true
+	%forbidden = and i1 %plus, %forbid_plus
+	br i1 %forbidden, label %plus_invalid, label %signed
+
+plus_invalid:
+	store i8* $PLUS_REASON, i8** @reason
+	ret i32 2
+
+; Split off the 0x of a hexadecimal number, when they're allowed
+signed:
+	%has_sign = or i1 %minus, %plus
+	%sign_length = zext i1 %has_sign to i64
+	%unsigned = add i64 %from, %sign_length
+	%remaining = sub i64 %to, %unsigned
+	%long_enough = icmp uge i64 %remaining, 2
+	%may_be_hex = and i1 %long_enough, $ALLOWS_HEX
+	br i1 %may_be_hex, label %check_hex, label %digits
+
+check_hex:
+	%zero_slot = getelementptr inbounds i8, i8* %line, i64 %unsigned
+	%zero_char = load i8, i8* %zero_slot
+	%is_zero = icmp eq i8 %zero_char, 48
+	%x_at = add i64 %unsigned, 1
+	%x_slot = getelementptr inbounds i8, i8* %line, i64 %x_at
+	%x_char = load i8, i8* %x_slot
+	%lower_x = or i8 %x_char, 32
+	%is_x = icmp eq i8 %lower_x, 120
+	%prefixed = and i1 %is_zero, %is_x
+	%hex_start = add i64 %unsigned, 2
+	br i1 %prefixed, label %hex, label %digits
+
+hex:
+	br label %digits
+
+digits:
+	%digits_start = phi i64 [%unsigned, %signed], [%unsigned, %check_hex], [%hex_start, %hex]
+	%radix = phi i64 [10, %signed], [10, %check_hex], [16, %hex]
+	%no_digits = icmp eq i64 %digits_start, %to
+	br i1 %no_digits, label %not_a_number, label %accumulate
+
+; Count down from zero, so the most negative number fits, and carry on
+; past an overflow so that a line that isn't a number is reported as one
+accumulate:
+	%index = phi i64 [%digits_start, %digits], [%following, %digit]
+	%value = phi i64 [0, %digits], [%next_value, %digit]
+	%overflowed = phi i1 [false, %digits], [%still_overflowed, %digit]
+	%finished = icmp eq i64 %index, %to
+	br i1 %finished, label %finish, label %character
+
+character:
+	%slot = getelementptr inbounds i8, i8* %line, i64 %index
+	%char = load i8, i8* %slot
+	%digit_value = call i64 @digit(i8 %char, i64 %radix)
+	%invalid = icmp slt i64 %digit_value, 0
+	br i1 %invalid, label %not_a_number, label %digit
+
+digit:
+	%scaled = call {i64, i1} @llvm.smul.with.overflow.i64(i64 %value, i64 %radix)
+	%scaled_value = extractvalue {i64, i1} %scaled, 0
+	%scale_overflowed = extractvalue {i64, i1} %scaled, 1
+	%shifted = call {i64, i1} @llvm.ssub.with.overflow.i64(i64 %scaled_value, i64 %digit_value)
+	%next_value = extractvalue {i64, i1} %shifted, 0
+	%shift_overflowed = extractvalue {i64, i1} %shifted, 1
+	%either = or i1 %scale_overflowed, %shift_overflowed
+	%still_overflowed = or i1 %overflowed, %either
+	%following = add i64 %index, 1
+	br label %accumulate
+
+finish:
+	br i1 %overflowed, label %too_big, label %negate
+
+negate:
+	br i1 %minus, label %negative, label %positive
+
+negative:
+	store i64 %value, i64* @parsed
+	ret i32 0
+
+positive:
+	%negated = call {i64, i1} @llvm.ssub.with.overflow.i64(i64 0, i64 %value)
+	%flip_overflowed = extractvalue {i64, i1} %negated, 1
+	br i1 %flip_overflowed, label %too_big, label %positive_fits
+
+positive_fits:
+	%positive_value = extractvalue {i64, i1} %negated, 0
+	store i64 %positive_value, i64* @parsed
+	ret i32 0
+
+not_a_number:
+	call void @quote_reason(i64 %length, i8* $NOT_A_NUMBER)
+	ret i32 2
+
+too_big:
+	call void @quote_reason(i64 %length, i8* $TOO_BIG)
+	ret i32 2
+}
+
+; Read lines until one is a number, and store it in the heap
+define internal void @read_number(i32 %at) {
+entry:
+	br label %next
+
+next:
+	%flushed = call i32 @fflush(i8* null)
+	%length = call i64 @read_line()
+	%ended = icmp slt i64 %length, 0
+	br i1 %ended, label %end, label %parse
+
+parse:
+	%status = call i32 @parse_number(i64 %length)
+	switch i32 %status, label %invalid [i32 0, label %number i32 1, label %next]
+
+number:
+	%value = load i64, i64* @parsed
+	br label %store
+
+invalid:
+	%reason = load i8*, i8** @reason
+	br i1 $REPROMPTS, label %reprompt, label %fail
+
+reprompt:
+	%written = call i32 (i8*, ...) @printf(i8* $UNPARSED_FORMAT, i8* %reason)
+	br label %next
+
+fail:
+	call void @stop(i8* $INVALID_FORMAT, i8* %reason, i32 %at)
+	unreachable
+
+end:
+	br i1 $EOF_FAILS, label %fail_end, label %kept
+
+fail_end:
+	call void @fail(i32 %at, i8* $NUMBER_AT_END)
+	unreachable
+
+kept:
+	br i1 $EOF_KEEPS, label %unchanged, label %store
+
+store:
+	%stored = phi i64 [%value, %number], [$EOF_VALUE, %kept]
+	%address = call i64 @pop()
+	call void @heap_set(i64 %address, i64 %stored)
+	ret void
+
+unchanged:
+	%discarded = call i64 @pop()
+	ret void
+}
+
+"#;
@@ -5,28 +5,15 @@
 // This program reads a whitespace program from
 // the disk, parses it, and either prints out the
 // parsed program, or executes it depending on
-// the users choice.
+// the users choice. The interpreter itself is the
+// whitespace library, in lib.rs, and the command
+// line its cli module; this only picks which
+// command runs.
 //
 /**************************************************/
-use std::fs::File;
-use std::io::Read;
-
-mod parser;
-mod symbols;
-mod virtual_machine;
-use virtual_machine::WhitespaceVM;
-
-
-/*******************************/
-// Macros
-/*******************************/
-// Return the value of a result, or print the error message
-macro_rules! handle_err {
-    ($expression:expr) => (match $expression {
-    	Ok(val)  => val,
-    	Err(err) => {println!("Error: {:?}", err.to_string()); return;},
-    })
-}
+extern crate whitespace;
+use whitespace::cli;
+use whitespace::cli::{Arguments, Flags, Parsed, Program};
 
 
 /*******************************/
@@ -34,55 +21,48 @@ macro_rules! handle_err {
 /*******************************/
 // Main function
 fn main() {
-	// Get the command-line arguments and validate them
-	let arguments: Vec<String> = std::env::args().collect();
-
-	if arguments.len() > 3 || arguments.len() < 2 {
-		print_usage();
-		return;
+	// The cache and the description of the build take no options or program
+	let arguments = Arguments::from_env();
+	match arguments.command {
+		"cache"        => return cli::cache(&arguments),
+		"capabilities" => return cli::capabilities(&arguments),
+		_              => {},
 	}
 
-	if arguments.len() == 3 && arguments[1] != "list" {
-		print_usage();
-		return;
+	// Some commands take a directory, a connection file or nothing instead of a program
+	let flags = Flags::parse(&arguments);
+	match flags.command {
+		"server"                  => return cli::server(&flags),
+		"jupyter"                 => return cli::jupyter(&flags),
+		"assemble"                => return cli::assemble(&flags),
+		"repl"                    => return cli::repl(&flags),
+		"conformance" | "run-dir" => return cli::conformance(&flags),
+		_                         => {},
 	}
 
-	// Get the path to the whitespace file
-	let file_path = if arguments.len() == 2 {
-		arguments[1].clone()
-	} else {
-		arguments[2].clone()
-	};
-
-	// Open the file and read it into a string
-	let mut file = handle_err!(File::open(file_path));
-	let mut program = String::new();
-	handle_err!(file.read_to_string(&mut program));
-
-	// Parse the program
-	let parsed = parser::parse(program);
-
-	// List the program, or execute it
-	if arguments.len() == 3 && arguments[1] == "list" {
-		for action in parsed {
-			println!("{:?}", action);
-		}
-	} else {
-		// Reduce the routine labels to program pointers
-		let reduced = parser::reduce_labels(parsed);
-
-		// Create the vm and execute the program
-		let mut vm = WhitespaceVM::new(reduced);
-		vm.execute();
+	// The rest read a program, which most of them need parsed
+	let program = Program::read(&flags);
+	match flags.command {
+		"highlight"           => return cli::highlight(&flags, program),
+		"verify"              => return cli::verify(&flags, program),
+		"run" if flags.bundle => return cli::bundle(&flags, program),
+		_                     => {},
 	}
-}
 
-
-// Print the program's usage instructions
-fn print_usage() {
-	println!("Usage:    whitespace [command] <file>");
-	println!("Commands: run   - (default) run the program");
-	println!("          list  - list the commands that the file contains");
-	println!("\n");
+	let parsed = Parsed::parse(&flags, program);
+	match flags.command {
+		"check"     => cli::check(&flags, &parsed),
+		"callgraph" => cli::callgraph(&flags, &parsed),
+		"compile"   => cli::compile(&flags, &parsed),
+		"explore"   => cli::explore(&flags, &parsed),
+		"fuzz"      => cli::fuzz(&flags, &parsed),
+		"minify"    => cli::minify(&parsed),
+		"pretty"    => cli::pretty(&flags, &parsed),
+		"obfuscate" => cli::obfuscate(&flags, &parsed),
+		"decompile" => cli::decompile(&flags, &parsed),
+		"disasm"    => cli::disasm(&flags, &parsed),
+		"explain"   => cli::explain(&flags, &parsed),
+		"list"      => cli::list(&flags, &parsed),
+		_           => cli::run(flags, parsed),
+	}
 }
-
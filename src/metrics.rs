@@ -0,0 +1,91 @@
+/***********************************************************/
+//
+// Metrics
+// =======
+// How long each phase of a run took and how much memory it
+// used, for run --metrics json, so benchmarks can track the
+// interpreter from one version to the next. The JSON always
+// has the same keys, with null for anything that wasn't
+// measured, like the parse of a program loaded from the
+// cache, and a schema number that only changes when a key's
+// meaning does.
+//
+// struct Metrics <- What a run took
+//
+// time<T>(phase: &mut Option<Duration>, work: impl FnOnce() -> T) -> T
+// -- Do some work, timing it as a phase
+//
+/***********************************************************/
+use std::fs;
+use std::mem;
+use std::time::{Duration, Instant};
+use json;
+
+
+/********************************************/
+// Structures
+/********************************************/
+// What a run took. Phases that didn't happen have no time.
+#[derive(Debug, Clone, Default)]
+pub struct Metrics {
+	pub parse:      Option<Duration>,
+	pub optimize:   Option<Duration>,
+	pub resolve:    Option<Duration>,
+	pub execute:    Option<Duration>,
+	pub cached:     bool,  // Whether the program came from the cache, skipping the other phases
+	pub steps:      u64,
+	pub stack:      usize, // The most values the stack had room for
+	pub call_stack: usize, // The most return pointers the call stack had room for
+	pub heap:       usize, // The values in the heap at the end, which is the most it held
+}
+
+// The version of the JSON's layout
+const SCHEMA: u32 = 1;
+
+
+/********************************************/
+// Methods
+/********************************************/
+impl Metrics {
+	// Describe the run as a single line of JSON
+	pub fn render_json(&self) -> String {
+		let seconds = |phase: Option<Duration>| phase.map_or("null".to_string(), |time| time.as_secs_f64().to_string());
+		let rate    = match self.execute {
+			Some(time) if time > Duration::ZERO => (self.steps as f64 / time.as_secs_f64()).round().to_string(),
+			_                                   => "null".to_string(),
+		};
+
+		format!(
+			"{{\"schema\":{},\"version\":{},\"cached\":{},\"parse_seconds\":{},\"optimize_seconds\":{},\"resolve_seconds\":{},\"execute_seconds\":{},\"steps\":{},\"steps_per_second\":{},\"memory\":{{\"stack_bytes\":{},\"call_stack_bytes\":{},\"heap_values\":{},\"peak_resident_bytes\":{}}}}}\n",
+			SCHEMA, json::string(env!("CARGO_PKG_VERSION")), self.cached,
+			seconds(self.parse), seconds(self.optimize), seconds(self.resolve), seconds(self.execute),
+			self.steps, rate,
+			self.stack * mem::size_of::<i64>(), self.call_stack * mem::size_of::<usize>(), self.heap,
+			peak_resident().map_or("null".to_string(), |bytes| bytes.to_string()),
+		)
+	}
+}
+
+
+/********************************************/
+// Public Functions
+/********************************************/
+// Do some work, timing it as a phase
+pub fn time<T>(phase: &mut Option<Duration>, work: impl FnOnce() -> T) -> T {
+	let started = Instant::now();
+	let result  = work();
+	*phase = Some(phase.unwrap_or_default() + started.elapsed());
+	result
+}
+
+
+/********************************************/
+// Private Functions
+/********************************************/
+// The most memory the process has had resident, which only Linux says
+fn peak_resident() -> Option<u64> {
+	let status = fs::read_to_string("/proc/self/status").ok()?;
+	let line   = status.lines().find(|line| line.starts_with("VmHWM:"))?;
+	let kilobytes: u64 = line.trim_start_matches("VmHWM:").trim().trim_end_matches("kB").trim().parse().ok()?;
+	Some(kilobytes * 1024)
+}
@@ -0,0 +1,114 @@
+/***********************************************************/
+//
+// Native Execution
+// ================
+// Runs a program as a native binary instead of interpreting
+// it, for run --native. The binary is built from the IR of
+// the LLVM backend, with clang or, without clang, with llc
+// and the system's C compiler to link it. It's kept in the
+// cache under the program's key, so only the first run of a
+// program pays for building it.
+//
+// Building needs the llvm feature and a toolchain, and only
+// covers the actions the LLVM backend does. When it can't
+// be built, run falls back to the interpreter.
+//
+// run(key: &str, program: &[Action], options: &Options, input: Stdio, rebuild: bool) -> Result<i32, String>
+// -- Run a resolved program natively, building it first if
+//    it isn't cached or rebuild is set, and return its exit
+//    status
+//
+/***********************************************************/
+use std::ffi::OsStr;
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use cache;
+use compile;
+use options::Options;
+use symbols::Action;
+
+
+/********************************************/
+// Public Functions
+/********************************************/
+// Run a resolved program natively, with the given stdin and the
+// interpreter's stdout and stderr, returning its exit status. Fails
+// without running anything when the binary can't be built.
+pub fn run(key: &str, program: &[Action], options: &Options, input: Stdio, rebuild: bool) -> Result<i32, String> {
+	let binary = cache::binary(key).ok_or("there's nowhere to cache the binary")?;
+	if rebuild || !binary.is_file() {
+		build(program, options, &binary)?;
+	}
+
+	let status = Command::new(&binary).stdin(input).status().map_err(|error| format!("{}: {}", binary.display(), error))?;
+
+	// A binary killed by a signal has no status, so report a failure
+	Ok(status.code().unwrap_or(1))
+}
+
+
+/********************************************/
+// Private Functions
+/********************************************/
+// Build the native binary of a program at the given path, leaving
+// nothing behind when that fails
+fn build(program: &[Action], options: &Options, binary: &Path) -> Result<(), String> {
+	if !cfg!(feature = "llvm") {
+		return Err("--native needs the llvm feature: build with cargo build --features llvm".to_string());
+	}
+
+	let ir = compile::backend("llvm").expect("the llvm backend is always present").emit(program, options)?;
+
+	let source    = binary.with_extension("ll");
+	let object    = binary.with_extension("o");
+	let temporary = binary.with_extension("tmp");
+	if let Some(parent) = binary.parent() {
+		fs::create_dir_all(parent).map_err(|error| format!("{}: {}", parent.display(), error))?;
+	}
+	fs::write(&source, ir).map_err(|error| format!("{}: {}", source.display(), error))?;
+
+	// Write to a temporary file first so a run never starts half a binary
+	let built = link(&source, &object, &temporary).and_then(|()| {
+		fs::rename(&temporary, binary).map_err(|error| format!("{}: {}", binary.display(), error))
+	});
+
+	for leftover in [&source, &object, &temporary] {
+		let _ = fs::remove_file(leftover);
+	}
+	built
+}
+
+
+// Compile IR into an executable, with clang if it's installed and
+// llc and cc otherwise
+fn link(source: &Path, object: &Path, binary: &Path) -> Result<(), String> {
+	let (source, object, binary) = (source.as_os_str(), object.as_os_str(), binary.as_os_str());
+
+	match tool("clang", &["-O2".as_ref(), "-x".as_ref(), "ir".as_ref(), source, "-o".as_ref(), binary]) {
+		Ok(())             => return Ok(()),
+		Err(Some(message)) => return Err(message),
+		Err(None)          => {},
+	}
+
+	tool("llc", &["-O2".as_ref(), "-filetype=obj".as_ref(), "-relocation-model=pic".as_ref(), source, "-o".as_ref(), object])
+		.and_then(|()| tool("cc", &[object, "-o".as_ref(), binary]))
+		.map_err(|message| message.unwrap_or_else(|| "neither clang nor llc and cc are installed".to_string()))
+}
+
+
+// Run a tool quietly, failing with None if it isn't installed and
+// otherwise with the first thing it said about why it failed
+fn tool(name: &str, arguments: &[&OsStr]) -> Result<(), Option<String>> {
+	let output = match Command::new(name).args(arguments).stdin(Stdio::null()).output() {
+		Ok(output) => output,
+		Err(_)     => return Err(None),
+	};
+
+	if output.status.success() {
+		return Ok(());
+	}
+
+	let said = String::from_utf8_lossy(&output.stderr);
+	Err(Some(format!("{}: {}", name, said.lines().next().unwrap_or("failed"))))
+}
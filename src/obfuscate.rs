@@ -0,0 +1,147 @@
+/***********************************************************/
+//
+// Obfuscator
+// ==========
+// Rewrites a parsed program into an equivalent one that's
+// harder to follow: labels get random encodings, no-op
+// sequences are scattered between the actions, and the
+// pieces of the program that are only entered through a
+// label are shuffled around. The same seed always gives
+// the same result.
+//
+// obfuscate(program: &[Action], seed: u64) -> Vec<Action>
+// -- Rewrite a parsed program into an equivalent harder to read one
+//
+/***********************************************************/
+use std::collections::{HashMap, HashSet};
+use labels::LabelId;
+use passes;
+use symbols::Action;
+use symbols::Action::*;
+
+
+/********************************************/
+// Structures
+/********************************************/
+// A small xorshift generator. Obfuscation needs variety, not
+// statistical quality, and this keeps results reproducible.
+struct Random {
+	state: u64,
+}
+
+impl Random {
+	// Constructor, the state must never be zero
+	fn new(seed: u64) -> Random {
+		Random {state: (seed ^ 0x9e37_79b9_7f4a_7c15) | 1}
+	}
+
+	// The next number in the sequence
+	fn next(&mut self) -> u64 {
+		self.state ^= self.state << 13;
+		self.state ^= self.state >> 7;
+		self.state ^= self.state << 17;
+		self.state
+	}
+
+	// A number from 0 up to, but not including, a bound
+	fn below(&mut self, bound: u64) -> u64 {
+		self.next() % bound
+	}
+}
+
+// The chance, out of 100, of inserting a no-op before each action
+const NOOP_CHANCE: u64 = 30;
+
+
+/********************************************/
+// Public Functions
+/********************************************/
+// Rewrite a parsed program into an equivalent harder to read one
+pub fn obfuscate(program: &[Action], seed: u64) -> Vec<Action> {
+	let mut random = Random::new(seed);
+	let renamed    = rename_labels(program, &mut random);
+	let shuffled   = shuffle_pieces(&renamed, &mut random);
+	insert_noops(&shuffled, &mut random)
+}
+
+
+/********************************************/
+// Private Functions
+/********************************************/
+// Give every label a new, random encoding between 2 and 16 tokens long
+fn rename_labels(program: &[Action], random: &mut Random) -> Vec<Action> {
+	let mut names = HashMap::new();
+	let mut taken = HashSet::new();
+
+	for action in program {
+		if let Label(label) | Call(label) | Jump(label) | JumpIfZero(label) | JumpIfNegative(label) = *action {
+			if names.contains_key(&label) {
+				continue;
+			}
+
+			// The parser reads a label as a one followed by a bit per token
+			let name = loop {
+				let length = 2 + random.below(15);
+				let name   = (1 << length) | random.below(1 << length);
+				if taken.insert(name) {
+					break name;
+				}
+			};
+			names.insert(label, LabelId(name));
+		}
+	}
+
+	passes::map_labels(program, &|label| names[&label])
+}
+
+
+// Split the program into pieces that start at a label control can't
+// fall into, and shuffle every piece but the first. A last piece that
+// runs off the end of the program stays last, so it still does.
+fn shuffle_pieces(program: &[Action], random: &mut Random) -> Vec<Action> {
+	let mut pieces: Vec<&[Action]> = Vec::new();
+	let mut start = 0;
+
+	for index in 1..program.len() {
+		let label      = matches!(program[index], Label(_));
+		let falls_into = !matches!(program[index - 1], Jump(_) | EndSubroutine | Halt);
+		if label && !falls_into {
+			pieces.push(&program[start..index]);
+			start = index;
+		}
+	}
+	pieces.push(&program[start..]);
+
+	let last   = pieces.len() - 1;
+	let pinned = !matches!(pieces[last].last(), Some(&Jump(_)) | Some(&EndSubroutine) | Some(&Halt));
+	let end    = if pinned {last} else {pieces.len()};
+
+	// Fisher-Yates over everything between the first piece and the end
+	for index in (2..end).rev() {
+		let other = 1 + random.below(index as u64) as usize;
+		pieces.swap(index, other);
+	}
+
+	pieces.concat()
+}
+
+
+// Scatter sequences that leave the stack and heap as they were between
+// the actions
+fn insert_noops(program: &[Action], random: &mut Random) -> Vec<Action> {
+	let mut result = Vec::new();
+
+	for &action in program {
+		if random.below(100) < NOOP_CHANCE {
+			let value = random.below(1 << 16) as i64 - (1 << 15);
+			match random.below(3) {
+				0 => result.extend(&[StackPush(value), StackDiscard]),
+				1 => result.extend(&[StackPush(value), StackPush(-value), StackSwap, StackSwap, StackDiscard, StackDiscard]),
+				_ => result.extend(&[StackPush(value), StackDuplicate, Subtract, StackDiscard]),
+			}
+		}
+		result.push(action);
+	}
+
+	result
+}
@@ -0,0 +1,625 @@
+/***********************************************************/
+//
+// Options
+// =======
+// The semantic switches that decide how the interpreter
+// handles the corners of the language that interpreters
+// disagree on, and named profiles that set them all at
+// once.
+//
+// struct Options <- Every semantic switch of the parser and vm
+//
+// enum Profile <- A named set of options
+//
+// struct IoOptions <- How the vm's input and output behave, which
+//                     doesn't change the meaning of a program
+//
+// struct Limits <- How far the vm lets a program go before stopping it
+//
+// untrusted(options: &mut Options, io_options: &mut IoOptions, limits: &mut Limits)
+// -- Settle what a program from someone you don't trust can do
+//
+/***********************************************************/
+use std::io;
+use std::io::IsTerminal;
+use std::time::Duration;
+use symbols::Token;
+
+
+/********************************************/
+// Structures
+/********************************************/
+// What a read does once the input has run out
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum EofPolicy {
+	Error,       // Fail with a runtime error
+	NegativeOne, // Store -1
+	Zero,        // Store 0
+	Unchanged,   // Leave the destination untouched
+}
+
+
+// What happens when execution runs past the last action
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum EndOfProgram {
+	Error, // Fail with a runtime error; programs must end with Halt
+	Halt,  // Stop as if a Halt had been executed
+}
+
+
+// Where ReadChar puts the character it reads
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ReadCharDestination {
+	Heap,  // Store it at the heap address popped off the stack
+	Stack, // Push it onto the stack
+}
+
+
+// How Divide and Modulo round
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Division {
+	Truncated, // Round towards zero, like Rust's / and %
+	Floored,   // Round towards negative infinity, like Haskell's div and mod
+	Euclidean, // Keep the remainder non-negative
+}
+
+impl Division {
+	// Divide two numbers, returning None if right is zero or the quotient overflows
+	pub fn quotient(&self, left: i64, right: i64) -> Option<i64> {
+		match *self {
+			Division::Truncated => left.checked_div(right),
+			Division::Euclidean => left.checked_div_euclid(right),
+			Division::Floored   => left.checked_div(right).map(|quotient| {
+				if left % right != 0 && (left < 0) != (right < 0) {quotient - 1} else {quotient}
+			}),
+		}
+	}
+
+	// Get the remainder of dividing two numbers, returning None if right is zero
+	// or the division overflows. The remainder takes the sign of the left
+	// operand when truncating, and of the right operand when flooring.
+	pub fn remainder(&self, left: i64, right: i64) -> Option<i64> {
+		match *self {
+			Division::Truncated => left.checked_rem(right),
+			Division::Euclidean => left.checked_rem_euclid(right),
+			Division::Floored   => left.checked_rem(right).map(|remainder| {
+				if remainder != 0 && (remainder < 0) != (right < 0) {remainder + right} else {remainder}
+			}),
+		}
+	}
+}
+
+
+// When calls and jumps to labels that don't exist are reported
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum LabelResolution {
+	Eager, // Before the program runs
+	Lazy,  // Only when such a call or jump is taken
+}
+
+
+// What ReadNumber does with a line that isn't a number
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum InvalidNumber {
+	Reprompt, // Report the problem and read another line
+	Error,    // Fail with a runtime error
+}
+
+
+// What HeapRetrieve does with an address that was never stored to
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum UnsetHeap {
+	Error, // Fail with a runtime error
+	Zero,  // Treat the address as holding 0
+}
+
+
+// How OutputChar turns a value into the character it writes
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum CharEncoding {
+	Byte,    // The byte the value is, as the Latin-1 character it stands for
+	Unicode, // The Unicode code point the value is
+}
+
+
+// What OutputChar does with a value that isn't a character in its encoding
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum InvalidChar {
+	Lossy, // Write the value's low byte, or U+FFFD for a code point that doesn't exist
+	Error, // Fail with a runtime error
+}
+
+
+// What the parser does with a number literal that has a sign but no digits
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum EmptyNumber {
+	Zero,  // Read it as 0
+	Warn,  // Read it as 0, printing a warning
+	Error, // Fail with a parse error
+}
+
+
+// What the parser does with whitespace characters that aren't tokens, like
+// vertical tabs, form feeds and no-break spaces, which usually mean the file
+// was mangled on the way. Carriage returns are always ignored, since they're
+// how some systems end lines.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum OtherWhitespace {
+	Ignore, // Skip them like any other comment
+	Warn,   // Skip them, printing a warning for each
+	Error,  // Fail with a parse error
+}
+
+
+// Other whitespace characters read as tokens, for dialects that use them
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub struct TokenAliases {
+	pub vertical_tab:   Option<Token>,
+	pub form_feed:      Option<Token>,
+	pub no_break_space: Option<Token>,
+}
+
+
+// What ReadNumber does with an empty line
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum EmptyLine {
+	Invalid, // Treat it like any other line that isn't a number
+	Zero,    // Read it as 0
+	Skip,    // Ignore it and read the next line
+}
+
+
+// Which spellings of a number ReadNumber accepts
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct NumberInput {
+	pub whitespace:  bool, // Spaces and tabs around the number
+	pub plus_sign:   bool, // A leading + on positive numbers
+	pub hexadecimal: bool, // Numbers written as 0x1f
+	pub empty_line:  EmptyLine,
+}
+
+impl NumberInput {
+	// Parse a line of input, without its line ending, as a number.
+	// Returns None if the line should be skipped.
+	pub fn parse(&self, line: &str) -> Result<Option<i64>, String> {
+		let text = if self.whitespace {line.trim_matches([' ', '\t'])} else {line};
+
+		if text.is_empty() {
+			return match self.empty_line {
+				EmptyLine::Invalid => Err("the line is empty".to_string()),
+				EmptyLine::Zero    => Ok(Some(0)),
+				EmptyLine::Skip    => Ok(None),
+			};
+		}
+
+		// Split off the sign
+		let (negative, unsigned) = match text.as_bytes()[0] {
+			b'-' => (true,  &text[1..]),
+			b'+' => if self.plus_sign {(false, &text[1..])} else {return Err("a + sign isn't allowed".to_string())},
+			_    => (false, text),
+		};
+
+		// Split off the base
+		let (radix, digits) = match unsigned.get(..2) {
+			Some("0x") | Some("0X") if self.hexadecimal => (16, &unsigned[2..]),
+			_                                           => (10, unsigned),
+		};
+
+		if digits.is_empty() || !digits.chars().all(|c| c.is_digit(radix)) {
+			return Err(format!("{:?} isn't a number", line));
+		}
+
+		// Parse the magnitude with its sign so that the most negative number fits
+		let signed = if negative {format!("-{}", digits)} else {digits.to_string()};
+		i64::from_str_radix(&signed, radix).map(Some).map_err(|_| format!("{:?} doesn't fit in 64 bits", line))
+	}
+}
+
+
+// The extension instructions the parser accepts
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub struct Extensions {
+	pub environment: bool, // GetEnv, reading the environment variables allowed by IoOptions
+	pub debug:       bool, // DumpHeap, for looking at the heap during development
+	pub assert:      bool, // Assert, for programs that test themselves
+	pub call_stack:  bool, // CallDepth and PeekReturn, for looking at the subroutines being executed
+	pub format:      bool, // OutputFormatted, for printing numbers in other bases and padded to a width
+	pub clock:       bool, // Clock, for reading the time
+	pub random:      bool, // Random, for drawing random numbers
+}
+
+impl Extensions {
+	// Whether any extension is enabled
+	pub fn any(&self) -> bool {
+		self.environment || self.debug || self.assert || self.call_stack || self.format || self.clock || self.random
+	}
+}
+
+
+// Every semantic switch of the interpreter
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Options {
+	pub eof:            EofPolicy,
+	pub end_of_program: EndOfProgram,
+	pub read_char:      ReadCharDestination,
+	pub division:       Division,
+	pub labels:         LabelResolution,
+	pub invalid_number: InvalidNumber,
+	pub unset_heap:     UnsetHeap,
+	pub char_encoding:  CharEncoding,
+	pub invalid_char:   InvalidChar,
+	pub empty_number:   EmptyNumber,
+	pub whitespace:     OtherWhitespace,
+	pub token_aliases:  TokenAliases,
+	pub number_input:   NumberInput,
+	pub extensions:     Extensions,
+}
+
+impl Default for Options {
+	// The default profile's options
+	fn default() -> Options {
+		Profile::Default.options()
+	}
+}
+
+
+// A named set of options
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Profile {
+	Default,   // The interpreter's usual behavior, with the specification's floored division
+	Strict,    // Exactly what the published specification describes
+	Lenient,   // Keep running wherever another interpreter would
+	Reference, // Behave like the original Haskell interpreter, quirks included
+}
+
+impl Profile {
+	// Get the profile with the given name
+	pub fn from_name(name: &str) -> Option<Profile> {
+		match name {
+			"default"   => Some(Profile::Default),
+			"strict"    => Some(Profile::Strict),
+			"lenient"   => Some(Profile::Lenient),
+			"reference" => Some(Profile::Reference),
+			_           => None,
+		}
+	}
+
+	// The options the profile selects
+	pub fn options(&self) -> Options {
+		match *self {
+			Profile::Default => Options {
+				eof:            EofPolicy::Error,
+				end_of_program: EndOfProgram::Error,
+				read_char:      ReadCharDestination::Heap,
+				division:       Division::Floored,
+				labels:         LabelResolution::Eager,
+				invalid_number: InvalidNumber::Reprompt,
+				unset_heap:     UnsetHeap::Error,
+				char_encoding:  CharEncoding::Byte,
+				invalid_char:   InvalidChar::Lossy,
+				empty_number:   EmptyNumber::Warn,
+				whitespace:     OtherWhitespace::Ignore,
+				token_aliases:  TokenAliases::default(),
+				number_input:   NumberInput {whitespace: true,  plus_sign: true,  hexadecimal: false, empty_line: EmptyLine::Invalid},
+				extensions:     Extensions::default(),
+			},
+
+			Profile::Strict => Options {
+				eof:            EofPolicy::Error,
+				end_of_program: EndOfProgram::Error,
+				read_char:      ReadCharDestination::Heap,
+				division:       Division::Floored,
+				labels:         LabelResolution::Eager,
+				invalid_number: InvalidNumber::Error,
+				unset_heap:     UnsetHeap::Error,
+				char_encoding:  CharEncoding::Byte,
+				invalid_char:   InvalidChar::Error,
+				empty_number:   EmptyNumber::Error,
+				whitespace:     OtherWhitespace::Error,
+				token_aliases:  TokenAliases::default(),
+				number_input:   NumberInput {whitespace: true,  plus_sign: false, hexadecimal: false, empty_line: EmptyLine::Invalid},
+				extensions:     Extensions::default(),
+			},
+
+			Profile::Lenient => Options {
+				eof:            EofPolicy::NegativeOne,
+				end_of_program: EndOfProgram::Halt,
+				read_char:      ReadCharDestination::Heap,
+				division:       Division::Floored,
+				labels:         LabelResolution::Eager,
+				invalid_number: InvalidNumber::Reprompt,
+				unset_heap:     UnsetHeap::Zero,
+				char_encoding:  CharEncoding::Byte,
+				invalid_char:   InvalidChar::Lossy,
+				empty_number:   EmptyNumber::Zero,
+				whitespace:     OtherWhitespace::Ignore,
+				token_aliases:  TokenAliases::default(),
+				number_input:   NumberInput {whitespace: true,  plus_sign: true,  hexadecimal: true,  empty_line: EmptyLine::Zero},
+				extensions:     Extensions::default(),
+			},
+
+			// The reference interpreter uses Haskell's div and mod, dies when
+			// getChar or read fail, only looks labels up when jumping, reads
+			// numbers with Haskell's read, which takes hexadecimal, and writes
+			// characters with chr, which dies for values that aren't code points
+			Profile::Reference => Options {
+				eof:            EofPolicy::Error,
+				end_of_program: EndOfProgram::Error,
+				read_char:      ReadCharDestination::Heap,
+				division:       Division::Floored,
+				labels:         LabelResolution::Lazy,
+				invalid_number: InvalidNumber::Error,
+				unset_heap:     UnsetHeap::Error,
+				char_encoding:  CharEncoding::Unicode,
+				invalid_char:   InvalidChar::Error,
+				empty_number:   EmptyNumber::Zero,
+				whitespace:     OtherWhitespace::Ignore,
+				token_aliases:  TokenAliases::default(),
+				number_input:   NumberInput {whitespace: true,  plus_sign: false, hexadecimal: true,  empty_line: EmptyLine::Invalid},
+				extensions:     Extensions::default(),
+			},
+		}
+	}
+}
+
+
+// When buffered output is written out
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum FlushPolicy {
+	Always,  // After every OutputChar and OutputNumber
+	Newline, // After output containing a newline, and before every read
+	Input,   // Before every read
+	Exit,    // Only when the buffer fills up or the program stops
+}
+
+impl FlushPolicy {
+	// Line buffer output going to a terminal so it shows up as the program
+	// runs, and fully buffer output going to a pipe or file
+	pub fn detect() -> FlushPolicy {
+		if io::stdout().is_terminal() {
+			FlushPolicy::Newline
+		} else {
+			FlushPolicy::Exit
+		}
+	}
+}
+
+
+// How the vm's input and output behave. Output is always
+// flushed when the program stops, whatever the policy.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IoOptions {
+	pub flush:          FlushPolicy,
+	pub prompt:         String,      // Written before every read, unless empty
+	pub input_messages: bool,        // Whether ReadNumber explains why it's reprompting
+	pub allowed_env:    Vec<String>, // The environment variables GetEnv may read
+	pub virtual_time:   bool,        // Whether Clock reads a virtual clock that only moves when it's read
+	pub seed:           Option<u64>, // What Random's numbers are drawn from, or the system's entropy without one
+}
+
+impl Default for IoOptions {
+	fn default() -> IoOptions {
+		IoOptions {
+			flush:          FlushPolicy::Input,
+			prompt:         String::new(),
+			input_messages: true,
+			allowed_env:    Vec::new(),
+			virtual_time:   false,
+			seed:           None,
+		}
+	}
+}
+
+
+// How far the vm lets a program go before stopping it with a runtime
+// error. Nothing is limited by default.
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub struct Limits {
+	pub steps:  Option<u64>,      // The most actions to execute
+	pub time:   Option<Duration>, // The longest to run, checked between actions
+	pub memory: Option<usize>,    // The most values to hold at once, on the stacks and in the heap
+}
+
+
+// The limits untrusted programs run under
+const UNTRUSTED_STEPS:  u64      = 100_000_000;
+const UNTRUSTED_TIME:   Duration = Duration::from_secs(10);
+const UNTRUSTED_MEMORY: usize    = 1_000_000;
+
+
+/********************************************/
+// Public Functions
+/********************************************/
+// The command line flags that set a single option, each taking a value
+pub static FLAGS: &[&str] = &[
+	"--eof", "--end-of-program", "--read-char", "--division", "--labels", "--invalid-number",
+	"--unset-heap", "--empty-number", "--other-whitespace", "--whitespace-tokens", "--empty-line",
+	"--number-input", "--char-encoding", "--invalid-char", "--extensions",
+];
+
+
+// Parse the value of a command line flag that sets a single option,
+// returning false if the flag isn't an option or its value is invalid
+pub fn set(options: &mut Options, flag: &str, value: &str) -> bool {
+	match (flag, value) {
+		("--eof", "error")     => options.eof = EofPolicy::Error,
+		("--eof", "-1")        => options.eof = EofPolicy::NegativeOne,
+		("--eof", "0")         => options.eof = EofPolicy::Zero,
+		("--eof", "unchanged") => options.eof = EofPolicy::Unchanged,
+
+		("--end-of-program", "error") => options.end_of_program = EndOfProgram::Error,
+		("--end-of-program", "halt")  => options.end_of_program = EndOfProgram::Halt,
+
+		("--read-char", "heap")  => options.read_char = ReadCharDestination::Heap,
+		("--read-char", "stack") => options.read_char = ReadCharDestination::Stack,
+
+		("--division", "truncated") => options.division = Division::Truncated,
+		("--division", "floored")   => options.division = Division::Floored,
+		("--division", "euclidean") => options.division = Division::Euclidean,
+
+		("--labels", "eager") => options.labels = LabelResolution::Eager,
+		("--labels", "lazy")  => options.labels = LabelResolution::Lazy,
+
+		("--invalid-number", "reprompt") => options.invalid_number = InvalidNumber::Reprompt,
+		("--invalid-number", "error")    => options.invalid_number = InvalidNumber::Error,
+
+		("--unset-heap", "error") => options.unset_heap = UnsetHeap::Error,
+		("--unset-heap", "zero")  => options.unset_heap = UnsetHeap::Zero,
+
+		("--char-encoding", "byte")    => options.char_encoding = CharEncoding::Byte,
+		("--char-encoding", "unicode") => options.char_encoding = CharEncoding::Unicode,
+
+		("--invalid-char", "lossy") => options.invalid_char = InvalidChar::Lossy,
+		("--invalid-char", "error") => options.invalid_char = InvalidChar::Error,
+
+		("--empty-number", "zero")  => options.empty_number = EmptyNumber::Zero,
+		("--empty-number", "warn")  => options.empty_number = EmptyNumber::Warn,
+		("--empty-number", "error") => options.empty_number = EmptyNumber::Error,
+
+		("--other-whitespace", "ignore") => options.whitespace = OtherWhitespace::Ignore,
+		("--other-whitespace", "warn")   => options.whitespace = OtherWhitespace::Warn,
+		("--other-whitespace", "error")  => options.whitespace = OtherWhitespace::Error,
+
+		// A comma separated list of characters and the tokens they're read as, like vt=tab, or none
+		("--whitespace-tokens", list) => {
+			let mut aliases = TokenAliases::default();
+			for alias in list.split(',').filter(|&alias| alias != "none") {
+				let token = match alias.split_once('=').map(|(_, token)| token) {
+					Some("space") => Token::Space,
+					Some("tab")   => Token::Tab,
+					Some("lf")    => Token::Return,
+					_             => return false,
+				};
+				match alias.split_once('=').map(|(character, _)| character) {
+					Some("vt")   => aliases.vertical_tab   = Some(token),
+					Some("ff")   => aliases.form_feed      = Some(token),
+					Some("nbsp") => aliases.no_break_space = Some(token),
+					_            => return false,
+				}
+			}
+			options.token_aliases = aliases;
+		},
+
+		("--empty-line", "invalid") => options.number_input.empty_line = EmptyLine::Invalid,
+		("--empty-line", "zero")    => options.number_input.empty_line = EmptyLine::Zero,
+		("--empty-line", "skip")    => options.number_input.empty_line = EmptyLine::Skip,
+
+		// A comma separated list of the spellings to accept, or none
+		("--number-input", list) => {
+			let mut input = NumberInput {whitespace: false, plus_sign: false, hexadecimal: false, ..options.number_input};
+			for spelling in list.split(',') {
+				match spelling {
+					"whitespace" => input.whitespace  = true,
+					"plus"       => input.plus_sign   = true,
+					"hex"        => input.hexadecimal = true,
+					"none"       => {},
+					_            => return false,
+				}
+			}
+			options.number_input = input;
+		},
+
+		// A comma separated list of the extensions to enable, or none
+		("--extensions", list) => {
+			let mut extensions = Extensions::default();
+			for extension in list.split(',') {
+				match extension {
+					"env"       => extensions.environment = true,
+					"debug"     => extensions.debug       = true,
+					"assert"    => extensions.assert      = true,
+					"callstack" => extensions.call_stack  = true,
+					"format"    => extensions.format      = true,
+					"clock"     => extensions.clock       = true,
+					"random"    => extensions.random      = true,
+					"none"      => {},
+					_           => return false,
+				}
+			}
+			options.extensions = extensions;
+		},
+
+		_ => return false,
+	}
+
+	true
+}
+
+
+// The command line flags that set a single I/O option, each taking a value
+pub static IO_FLAGS: &[&str] = &["--flush", "--prompt", "--input-messages", "--allow-env", "--seed"];
+
+
+// Parse the value of a command line flag that sets a single I/O option,
+// returning false if the flag isn't an option or its value is invalid
+pub fn set_io(options: &mut IoOptions, flag: &str, value: &str) -> bool {
+	match (flag, value) {
+		("--flush", "always")  => options.flush = FlushPolicy::Always,
+		("--flush", "newline") => options.flush = FlushPolicy::Newline,
+		("--flush", "input")   => options.flush = FlushPolicy::Input,
+		("--flush", "exit")    => options.flush = FlushPolicy::Exit,
+		("--flush", "auto")    => options.flush = FlushPolicy::detect(),
+
+		("--prompt", prompt) => options.prompt = prompt.to_string(),
+
+		("--input-messages", "show") => options.input_messages = true,
+		("--input-messages", "hide") => options.input_messages = false,
+
+		// A comma separated list of names, adding to those already allowed
+		("--allow-env", list) => options.allowed_env.extend(list.split(',').filter(|name| !name.is_empty()).map(str::to_string)),
+
+		("--seed", seed) => match seed.parse() {
+			Ok(seed) => options.seed = Some(seed),
+			Err(_)   => return false,
+		},
+
+		_ => return false,
+	}
+
+	true
+}
+
+
+// The command line flags that set a single limit, each taking a value
+pub static LIMIT_FLAGS: &[&str] = &["--max-steps", "--timeout", "--max-memory"];
+
+
+// Parse the value of a command line flag that sets a single limit,
+// returning false if the flag isn't a limit or its value is invalid
+pub fn set_limit(limits: &mut Limits, flag: &str, value: &str) -> bool {
+	match flag {
+		"--max-steps" => match value.parse() {
+			Ok(steps) => limits.steps = Some(steps),
+			Err(_)    => return false,
+		},
+
+		"--max-memory" => match value.parse() {
+			Ok(memory) => limits.memory = Some(memory),
+			Err(_)     => return false,
+		},
+
+		// A number of seconds, which can have a fraction
+		"--timeout" => match value.parse::<f64>().ok().and_then(|seconds| Duration::try_from_secs_f64(seconds).ok()) {
+			Some(time) => limits.time = Some(time),
+			None       => return false,
+		},
+
+		_ => return false,
+	}
+
+	true
+}
+
+
+// Settle everything a program from someone you don't trust could use to
+// reach outside the interpreter or hold onto the machine: no extensions,
+// no environment variables, and limits on how long it runs and how much
+// it holds. Settings made afterwards can still loosen them.
+pub fn untrusted(options: &mut Options, io_options: &mut IoOptions, limits: &mut Limits) {
+	options.extensions     = Extensions::default();
+	io_options.allowed_env = Vec::new();
+	*limits = Limits {
+		steps:  Some(UNTRUSTED_STEPS),
+		time:   Some(UNTRUSTED_TIME),
+		memory: Some(UNTRUSTED_MEMORY),
+	};
+}
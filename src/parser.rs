@@ -5,16 +5,57 @@
 // This file contains routines for parsing a
 // whitespace program.
 //
-// parse(program: String) -> Vec<Action>
+// struct Span <- Where in the source an action came from
+//
+// parse(program: String, options: &Options) -> Result<Vec<Action>, WhitespaceError>
 // -- Parses a whitespace program, returning a list
 //    of Actions
 //
-// reduce_labels(program: Vec<Action>) -> Vec<Action>
+// parse_with_spans(program: String, options: &Options) -> Result<Vec<(Action, Span)>, WhitespaceError>
+// -- Parses a whitespace program, returning each Action
+//    along with where it came from
+//
+// parse_with_symbols(program: String, options: &Options, labels: &mut SymbolTable, warnings: &mut Vec<ParseError>) -> Result<Vec<(Action, Span)>, WhitespaceError>
+// -- Parses a whitespace program like parse_with_spans,
+//    interning its labels in the given table
+//
+// parse_partial(program: String, options: &Options, warnings: &mut Vec<ParseError>) -> (Vec<(Action, Span)>, Result<(), WhitespaceError>)
+// -- Parses a whitespace program like parse_with_spans,
+//    keeping the Actions before any error
+//
+// parse_each(program: String, options: &Options, labels: &mut SymbolTable, each: &mut dyn FnMut(Action), warnings: &mut Vec<ParseError>) -> Result<(), WhitespaceError>
+// -- Parses a whitespace program, handing over each
+//    Action as soon as it's parsed and interning its
+//    labels in the given table
+//
+// The parser never prints anything. What the options only
+// warn about, like a number with no digits, is added to the
+// warnings given as a ParseError, for the caller to show;
+// parse and parse_with_spans leave them out.
+//
+// reduce_labels(program: Vec<Action>, labels: &SymbolTable, resolution: LabelResolution) -> Result<Vec<Action>, WhitespaceError>
 // -- Takes a program of Actions and rewrites the labels
-//    from names to simple action index pointers
+//    from names to simple action index pointers, naming
+//    any that are missing from the table they were
+//    interned in
+//
+// parse_and_reduce(program: String, options: &Options) -> Result<Vec<Action>, WhitespaceError>
+// -- Parses a whitespace program and reduces its labels,
+//    ready to be executed
+//
+// from_stl(notation: &str) -> String
+// -- Converts a program written in STL notation, where
+//    S, T, and L stand for space, tab, and line feed,
+//    into whitespace
+//
+// token(character: char, options: &Options) -> Option<Token>
+// -- The token a character is read as, if it's one
 //
 /***********************************************************/
 use std::collections::HashMap;
+use error::{ParseError, UnresolvedLabel, WhitespaceError};
+use labels::{LabelId, SymbolTable};
+use options::{EmptyNumber, LabelResolution, Options, OtherWhitespace};
 use symbols::Action;
 use symbols::Action::*;
 use symbols::Token;
@@ -22,52 +63,154 @@ use symbols::Token::*;
 
 
 
+/********************************************/
+// Constants
+/********************************************/
+// The program pointer given to calls and jumps to missing labels when
+// labels are resolved lazily; the vm fails if one of them is taken
+pub const UNRESOLVED: u64 = u64::MAX;
+
+
+
 /********************************************/
 // Public Functions
 /********************************************/
 // Parse a whitespace program, returning a list of actions
-pub fn parse(program: String) -> Vec<Action> {
+pub fn parse(program: String, options: &Options) -> Result<Vec<Action>, WhitespaceError> {
+   let spanned = parse_with_spans(program, options)?;
+   Ok(spanned.into_iter().map(|(action, _)| action).collect())
+}
+
+
+// Parse a whitespace program, returning each action along with the
+// part of the source it was parsed from
+pub fn parse_with_spans(program: String, options: &Options) -> Result<Vec<(Action, Span)>, WhitespaceError> {
+   parse_with_symbols(program, options, &mut SymbolTable::default(), &mut Vec::new())
+}
+
+
+// Parse a whitespace program like parse_with_spans, interning its labels
+// in the given table. Labels already in the table keep their ids.
+pub fn parse_with_symbols(program: String, options: &Options, labels: &mut SymbolTable, warnings: &mut Vec<ParseError>) -> Result<Vec<(Action, Span)>, WhitespaceError> {
    let     reversed_program = program.chars().rev().collect();
-   let mut tokenizer        = Tokenizer::new(reversed_program);
-   let     parsed_program   = _parse(&mut tokenizer);
+   let mut tokenizer        = Tokenizer::new(reversed_program, *options, std::mem::take(labels));
+
+   let mut actions = Vec::new();
+   let parsed = _parse(&mut tokenizer, &mut |action, span| actions.push((action, span)));
+   *labels = tokenizer.labels;
+   warnings.append(&mut tokenizer.warnings);
+   parsed?;
+   Ok(actions)
+}
+
+
+// Parse a whitespace program like parse_with_spans, but keep the actions
+// parsed before the error when it doesn't parse, as an editor wants for
+// a program that's still being written
+pub fn parse_partial(program: String, options: &Options, warnings: &mut Vec<ParseError>) -> (Vec<(Action, Span)>, Result<(), WhitespaceError>) {
+   let mut tokenizer = Tokenizer::new(program.chars().rev().collect(), *options, SymbolTable::default());
+   let mut actions   = Vec::new();
+   let parsed = _parse(&mut tokenizer, &mut |action, span| actions.push((action, span)));
+   warnings.append(&mut tokenizer.warnings);
+   (actions, parsed.map_err(WhitespaceError::from))
+}
+
 
-   parsed_program
+// Parse a whitespace program, handing over each action as soon as it's
+// parsed, so the first can be used before the last has been read. Its
+// labels are interned in the given table, as parse_with_symbols does.
+pub fn parse_each(program: String, options: &Options, labels: &mut SymbolTable, each: &mut dyn FnMut(Action), warnings: &mut Vec<ParseError>) -> Result<(), WhitespaceError> {
+   let mut tokenizer = Tokenizer::new(program.chars().rev().collect(), *options, std::mem::take(labels));
+   let parsed = _parse(&mut tokenizer, &mut |action, _| each(action));
+   *labels = tokenizer.labels;
+   warnings.append(&mut tokenizer.warnings);
+   Ok(parsed?)
 }
 
 
-// Replace the labels in a whitespace program with simple index pointers
-pub fn reduce_labels(program: Vec<Action>) -> Vec<Action> {
+// Replace the labels in a whitespace program with simple index pointers.
+// Every reference to a missing label is reported at once, unless labels
+// are resolved lazily, in which case they point at UNRESOLVED.
+pub fn reduce_labels(program: Vec<Action>, labels: &SymbolTable, resolution: LabelResolution) -> Result<Vec<Action>, WhitespaceError> {
    let mut reduced_program = Vec::new();
-   let mut labels = HashMap::new();
+   let mut pointers = HashMap::new();
 
    // Get all the labels in the program
    let mut program_pointer = 0;
    for action in program.iter() {
       if let &Label(label) = action {
-         labels.insert(label, program_pointer);
+         pointers.insert(label, program_pointer);
       } else {
          program_pointer += 1;
       }
    }
 
+   // Look up the program pointer of a label, recording it if it is missing
+   let mut unresolved = Vec::new();
+   let mut resolve = |label: LabelId, index: usize, action: Action| match pointers.get(&label) {
+      Some(&pointer) => LabelId(pointer),
+      None           => {unresolved.push(UnresolvedLabel {label, name: labels.name(label), index, action}); LabelId(UNRESOLVED)},
+   };
+
    // Convert label names in flow control actions to program pointers
-   for action in program {
+   for (index, action) in program.into_iter().enumerate() {
       match action {
          // Drop label statements
          Label(_) => {},
 
          // Rewrite named labels
-         Call          (ref label)  => reduced_program.push(Call          (*labels.get(label).unwrap())),
-         Jump          (ref label)  => reduced_program.push(Jump          (*labels.get(label).unwrap())),
-         JumpIfZero    (ref label)  => reduced_program.push(JumpIfZero    (*labels.get(label).unwrap())),
-         JumpIfNegative(ref label)  => reduced_program.push(JumpIfNegative(*labels.get(label).unwrap())),
+         Call          (label)  => reduced_program.push(Call          (resolve(label, index, action))),
+         Jump          (label)  => reduced_program.push(Jump          (resolve(label, index, action))),
+         JumpIfZero    (label)  => reduced_program.push(JumpIfZero    (resolve(label, index, action))),
+         JumpIfNegative(label)  => reduced_program.push(JumpIfNegative(resolve(label, index, action))),
 
          // Ignore other actions
          other_action => reduced_program.push(other_action),
       }
    }
 
-   reduced_program
+   if unresolved.is_empty() || resolution == LabelResolution::Lazy {
+      Ok(reduced_program)
+   } else {
+      Err(WhitespaceError::Resolve(unresolved))
+   }
+}
+
+
+// Parse a whitespace program and reduce its labels, naming any that are
+// missing the way they were written
+pub fn parse_and_reduce(program: String, options: &Options) -> Result<Vec<Action>, WhitespaceError> {
+   let mut labels = SymbolTable::default();
+   let spanned = parse_with_symbols(program, options, &mut labels, &mut Vec::new())?;
+   reduce_labels(spanned.into_iter().map(|(action, _)| action).collect(), &labels, options.labels)
+}
+
+
+// Convert a program written in STL notation into whitespace. Any other
+// characters are dropped, so they can be used to space the notation out.
+pub fn from_stl(notation: &str) -> String {
+   notation.chars().filter_map(|character| match character {
+      'S' => Some(' '),
+      'T' => Some('\t'),
+      'L' => Some('\n'),
+      _   => None,
+   }).collect()
+}
+
+
+// The token a character of the source is read as, if it's one rather
+// than part of a comment
+pub fn token(character: char, options: &Options) -> Option<Token> {
+   let aliases = options.token_aliases;
+   match character {
+      ' '        => Some(Space),
+      '\n'       => Some(Return),
+      '\t'       => Some(Tab),
+      '\u{0b}'   => aliases.vertical_tab,
+      '\u{0c}'   => aliases.form_feed,
+      '\u{a0}'   => aliases.no_break_space,
+      _          => None,
+   }
 }
 
 
@@ -75,40 +218,181 @@ pub fn reduce_labels(program: Vec<Action>) -> Vec<Action> {
 /*****************************************/
 // Structures
 /*****************************************/
+// Where in the source an action came from
+#[derive(Debug, Clone, PartialEq)]
+pub struct Span {
+   pub start:  usize,  // Byte offset of the action's first token
+   pub end:    usize,  // Byte offset just past its last token
+   pub line:   usize,  // Line of the action's first token, counting from one
+   pub column: usize,  // Column of the action's first token in characters, counting from one
+   pub tokens: String, // Its tokens in STL notation
+}
+
+
 // Tokenizes the whitespace program, removing all non-whitespace
 struct Tokenizer {
-   program: String
+   program:           String,
+   consumed:          usize,          // Number of tokens returned so far
+   instruction_start: usize,          // Value of consumed when the current instruction began
+   offset:            usize,          // Number of bytes of the program read so far
+   last:              usize,          // Byte offset of the most recently read character
+   at:                (usize, usize), // Line and column of the next character
+   last_at:           (usize, usize), // Line and column of the most recently read character
+   span:              Span,           // The tokens of the current instruction so far
+   options:           Options,
+   labels:            SymbolTable,
+   warnings:          Vec<ParseError>, // What the options only warn about, in the order it was found
 }
 
 impl Tokenizer {
    // Constructor, create a tokenizer
-   fn new(program: String) -> Tokenizer {
+   fn new(program: String, options: Options, labels: SymbolTable) -> Tokenizer {
       Tokenizer {
-         program: program,
+         program,
+         consumed:          0,
+         instruction_start: 0,
+         offset:            0,
+         last:              0,
+         at:                (1, 1),
+         last_at:           (1, 1),
+         span:              Span {start: 0, end: 0, line: 1, column: 1, tokens: String::new()},
+         options,
+         labels,
+         warnings:          Vec::new(),
       }
    }
 
+   // Mark the next token as the first token of a new instruction
+   fn start_instruction(&mut self) {
+      self.instruction_start = self.consumed;
+      self.span.tokens.clear();
+   }
+
+   // Take the span of the instruction just parsed
+   fn take_span(&mut self) -> Span {
+      let tokens = std::mem::take(&mut self.span.tokens);
+      Span {tokens, ..self.span}
+   }
+
+   // Create an error pointing at the most recently returned token
+   fn error<T>(&self, message: &str) -> Result<T, ParseError> {
+      Err(self.located(message))
+   }
+
+   // Describe a problem with the most recently returned token
+   fn located(&self, message: &str) -> ParseError {
+      ParseError {
+         message:  message.to_string(),
+         token:    self.consumed - 1,
+         position: self.consumed - 1 - self.instruction_start,
+         offset:   self.last,
+         line:     self.last_at.0,
+         column:   self.last_at.1,
+      }
+   }
+
+   // Take the next character of the program, keeping track of where it was
+   fn read(&mut self) -> Option<char> {
+      let character = self.program.pop()?;
+      self.last    = self.offset;
+      self.last_at = self.at;
+      self.offset += character.len_utf8();
+      self.at      = if character == '\n' {(self.at.0 + 1, 1)} else {(self.at.0, self.at.1 + 1)};
+      Some(character)
+   }
+
    // Get the next token
-   fn next(&mut self, matching: &'static str) -> Token {
+   fn next(&mut self, matching: &'static str) -> Result<Token, ParseError> {
       'search:loop {
-         match self.program.pop().unwrap_or_else(|| panic!("Program ended while trying to match: {}", matching)) {
-            ' '  => return Space,
-            '\n' => return Return,
-            '\t' => return Tab,
-            _ => continue 'search,
+         let character = match self.read() {
+            Some(character) => character,
+            None => return Err(ParseError {
+               message:  format!("Program ended while trying to match {}", matching),
+               token:    self.consumed,
+               position: self.consumed - self.instruction_start,
+               offset:   self.offset,
+               line:     self.at.0,
+               column:   self.at.1,
+            }),
+         };
+
+         let token = match self.token(character) {
+            Some(token) => token,
+            None        => {
+               self.stray(character)?;
+               continue 'search;
+            },
+         };
+         let notation = match token {
+            Space  => 'S',
+            Tab    => 'T',
+            Return => 'L',
+         };
+
+         // The offset is already past the token
+         if self.span.tokens.is_empty() {
+            self.span.start  = self.offset - character.len_utf8();
+            self.span.line   = self.last_at.0;
+            self.span.column = self.last_at.1;
          }
+         self.span.end = self.offset;
+         self.span.tokens.push(notation);
+
+         self.consumed += 1;
+         return Ok(token);
       }
    }
 
    // Check if there are more tokens to get
    fn more(&self) -> bool {
-      for character in self.program.chars() {
-         if character == ' ' || character == '\t' || character == '\n' {
-            return true
-         }
+      self.program.chars().any(|character| self.token(character).is_some())
+   }
+
+   // Go through what's left after the last instruction, so whitespace
+   // that isn't a token is reported there too
+   fn finish(&mut self) -> Result<(), ParseError> {
+      self.instruction_start = self.consumed;
+      while let Some(character) = self.read() {
+         self.stray(character)?;
+      }
+
+      Ok(())
+   }
+
+   // The token a character is read as, if any
+   fn token(&self, character: char) -> Option<Token> {
+      token(character, &self.options)
+   }
+
+   // Deal with a character that isn't a token, which is a comment unless
+   // it's whitespace and those are warned about or refused
+   fn stray(&mut self, character: char) -> Result<(), ParseError> {
+      if !character.is_whitespace() || character == '\r' || self.options.whitespace == OtherWhitespace::Ignore {
+         return Ok(());
       }
 
-      return false
+      let name = match character {
+         '\u{0b}' => " (vertical tab)",
+         '\u{0c}' => " (form feed)",
+         '\u{a0}' => " (no-break space)",
+         _        => "",
+      };
+      let error = ParseError {
+         message:  format!("Found U+{:04X}{} at byte {}, which is whitespace but not a token", character as u32, name, self.offset - character.len_utf8()),
+         token:    self.consumed,
+         position: self.consumed - self.instruction_start,
+         offset:   self.last,
+         line:     self.last_at.0,
+         column:   self.last_at.1,
+      };
+
+      match self.options.whitespace {
+         OtherWhitespace::Error => Err(error),
+         _                      => {
+            self.warnings.push(error);
+            Ok(())
+         },
+      }
    }
 }
 
@@ -117,26 +401,45 @@ impl Tokenizer {
 /*****************************************/
 // Private Functions
 /*****************************************/
-// Parse a token stream into a list of Actions
-fn _parse(tokens: &mut Tokenizer) -> Vec<Action> {
-   let mut actions = Vec::new();
-
+// Parse a token stream into Actions, handing each over with where it came from
+fn _parse(tokens: &mut Tokenizer, each: &mut dyn FnMut(Action, Span)) -> Result<(), ParseError> {
    while tokens.more() {
-      actions.push(parse_token(tokens));
+      tokens.start_instruction();
+      let action = parse_token(tokens)?;
+      each(action, tokens.take_span());
    }
+   tokens.finish()
+}
+
 
-   actions
+// Parse the number naming an extension, failing unless it's enabled
+fn consume_extension(tokens: &mut Tokenizer) -> Result<Action, ParseError> {
+   if !tokens.options.extensions.any() {
+      return tokens.error("Unexpected Space");
+   }
+
+   match consume_number(tokens)? {
+      1 if tokens.options.extensions.environment => Ok(GetEnv),
+      2 if tokens.options.extensions.debug       => Ok(DumpHeap),
+      3 if tokens.options.extensions.assert      => Ok(Assert),
+      4 if tokens.options.extensions.call_stack  => Ok(CallDepth),
+      5 if tokens.options.extensions.call_stack  => Ok(PeekReturn),
+      6 if tokens.options.extensions.format      => Ok(OutputFormatted),
+      7 if tokens.options.extensions.clock       => Ok(Clock),
+      8 if tokens.options.extensions.random      => Ok(Random),
+      number => tokens.error(&format!("Extension {} isn't enabled", number)),
+   }
 }
 
 
 // Parse a single whitespace token, returning it as an action
-fn parse_token(tokens: &mut Tokenizer) -> Action {
-   match tokens.next("Stack Manipulation, Flow Control, or {Arithmetic, Heap, I/O}") {
+fn parse_token(tokens: &mut Tokenizer) -> Result<Action, ParseError> {
+   let action = match tokens.next("Stack Manipulation, Flow Control, or {Arithmetic, Heap, I/O}")? {
       /*** Stack Manipulation ***/
-      Space  => match tokens.next("Stack Manipulation") {
-         Tab    => Error("Unexpected Tab"),
-         Space  => StackPush(consume_number(tokens)),
-         Return => match tokens.next("Stack Manipulation: StackDuplicate, StackSwap, StackDiscard") {
+      Space  => match tokens.next("Stack Manipulation")? {
+         Tab    => tokens.error("Unexpected Tab")?,
+         Space  => StackPush(consume_number(tokens)?),
+         Return => match tokens.next("Stack Manipulation: StackDuplicate, StackSwap, StackDiscard")? {
             Space  => StackDuplicate,
             Tab    => StackSwap,
             Return => StackDiscard,
@@ -144,106 +447,136 @@ fn parse_token(tokens: &mut Tokenizer) -> Action {
       },
 
       /*** Flow Control ***/
-      Return => match tokens.next("Flow Control") {
-         Space  => match tokens.next("Flow Control: Call, Label, Jump") {
-            Tab    => Call (consume_label(tokens)),
-            Space  => Label(consume_label(tokens)),
-            Return => Jump (consume_label(tokens)),
+      Return => match tokens.next("Flow Control")? {
+         Space  => match tokens.next("Flow Control: Call, Label, Jump")? {
+            Tab    => Call (consume_label(tokens)?),
+            Space  => Label(consume_label(tokens)?),
+            Return => Jump (consume_label(tokens)?),
          },
 
-         Tab    => match tokens.next("Flow Control: JumpIfZero, JumpIfNegative, EndSubroutine") {
-            Space  => JumpIfZero    (consume_label(tokens)),
-            Tab    => JumpIfNegative(consume_label(tokens)),
+         Tab    => match tokens.next("Flow Control: JumpIfZero, JumpIfNegative, EndSubroutine")? {
+            Space  => JumpIfZero    (consume_label(tokens)?),
+            Tab    => JumpIfNegative(consume_label(tokens)?),
             Return => EndSubroutine,
          },
 
-         Return => match tokens.next("Flow Control: Halt") {
-            Space  => Error("Unexpected Space"),
-            Tab    => Error("Unexpected Tab"),
+         Return => match tokens.next("Flow Control: Halt")? {
+            Space  => consume_extension(tokens)?,
+            Tab    => tokens.error("Unexpected Tab")?,
             Return => Halt,
          }
       },
 
       /*** Arithmetic, Heap, I/O ***/
-      Tab    => match tokens.next("Arithmetic, Heap, I/O") {
+      Tab    => match tokens.next("Arithmetic, Heap, I/O")? {
          /*** Arithmetic ***/
-         Space => match tokens.next("Arithmetic") {
-            Return => Error("Unexpected Return"),
-            Space  => match tokens.next("Arithmetic: Add, Subtract, Multiply") {
+         Space => match tokens.next("Arithmetic")? {
+            Return => tokens.error("Unexpected Return")?,
+            Space  => match tokens.next("Arithmetic: Add, Subtract, Multiply")? {
                Space  => Add,
                Tab    => Subtract,
                Return => Multiply,
             },
-            Tab    => match tokens.next("Arithmetic: Divide, Modulo") {
-               Return => Error("Unexpected Return"),
+            Tab    => match tokens.next("Arithmetic: Divide, Modulo")? {
+               Return => tokens.error("Unexpected Return")?,
                Space  => Divide,
                Tab    => Modulo,
             }
          },
 
          /*** Heap Manipulation ***/
-         Tab   => match tokens.next("Heap Manipulation: HeapStore, HeapRetrieve") {
-            Return => Error("Unexpected Return"),
+         Tab   => match tokens.next("Heap Manipulation: HeapStore, HeapRetrieve")? {
+            Return => tokens.error("Unexpected Return")?,
             Space  => HeapStore,
             Tab    => HeapRetrieve,
          },
 
          /*** I/O ***/
-         Return => match tokens.next("I/O") {
-            Return => Error("Unexpected Return"),
-            Space  => match tokens.next("I/O: OutputChar, OutputNumber") {
-               Return => Error("Unexpected Return"),
+         Return => match tokens.next("I/O")? {
+            Return => tokens.error("Unexpected Return")?,
+            Space  => match tokens.next("I/O: OutputChar, OutputNumber")? {
+               Return => tokens.error("Unexpected Return")?,
                Space  => OutputChar,
                Tab    => OutputNumber,
             },
-            Tab    => match tokens.next("I/O: ReadChar, ReadNumber") {
-               Return => Error("Unexpected Return"),
+            Tab    => match tokens.next("I/O: ReadChar, ReadNumber")? {
+               Return => tokens.error("Unexpected Return")?,
                Space  => ReadChar,
                Tab    => ReadNumber,
             }
          }
       }
-   }
+   };
+
+   Ok(action)
 }
 
 
 // Match a return terminated number
-fn consume_number(program: &mut Tokenizer) -> i64 {
+fn consume_number(program: &mut Tokenizer) -> Result<i64, ParseError> {
    let mut number: u64 = 0;
+   let mut digits = 0;
 
-   let negative = match program.next("Number: Positive/Negative") {
+   let negative = match program.next("Number: Positive/Negative")? {
       Token::Space  => false,
       Token::Tab    => true,
-      Token::Return => panic!("Unexpected Return in Number definition. Expected space or tab representing sign."),
+      Token::Return => return program.error("Unexpected Return in Number definition. Expected space or tab representing sign"),
    };
 
    loop {
-      match program.next("Number: 0/1 (Space/Tab)") {
+      let digit = program.next("Number: 0/1 (Space/Tab)")?;
+
+      // Shifting out a set bit would silently wrap the literal
+      if digit != Token::Return && number >> 63 != 0 {
+         return program.error("Number literal doesn't fit in 64 bits");
+      }
+
+      match digit {
          Token::Space  => {number <<= 1;},
          Token::Tab    => {number <<= 1; number |= 0b1;},
          Token::Return => {break;},
       }
+
+      digits += 1;
+   }
+
+   // The magnitude of a negative number can be one larger than that of a positive one
+   let limit = if negative {1 << 63} else {i64::MAX as u64};
+   if number > limit {
+      return program.error("Number literal doesn't fit in a signed 64 bit integer");
+   }
+
+   // A sign followed directly by the terminator has no digits at all
+   if digits == 0 {
+      let message = "Number has a sign but no digits";
+      match program.options.empty_number {
+         EmptyNumber::Zero  => {},
+         EmptyNumber::Warn  => {
+            let warning = program.located(message);
+            program.warnings.push(warning);
+         },
+         EmptyNumber::Error => return program.error(message),
+      }
    }
 
    if negative {
-      -(number as i64)
+      Ok((number as i64).wrapping_neg())
    } else {
-      number as i64
+      Ok(number as i64)
    }
 }
 
 
-// Match a return terminated label
-fn consume_label(program: &mut Tokenizer) -> u64 {
-   let mut label: u64 = 1;
+// Match a return terminated label, returning its interned id
+fn consume_label(program: &mut Tokenizer) -> Result<LabelId, ParseError> {
+   let mut tokens = Vec::new();
 
    loop {
-      match program.next("Label") {
-         Token::Space  => {label <<= 1; label |= 0b1;},
-         Token::Tab    => {label <<= 1; label |= 0b0;},
+      match program.next("Label")? {
          Token::Return => break,
+         token         => tokens.push(token),
       }
    }
 
-   label
+   Ok(program.labels.intern(&tokens))
 }
\ No newline at end of file
@@ -0,0 +1,222 @@
+/***********************************************************/
+//
+// Passes
+// ======
+// A common shape for the analyses and rewrites that work on
+// a parsed program, so a new one only has to say what it
+// does with the actions it cares about instead of matching
+// on every action itself. Rewrites are collected into a
+// pipeline that runs them in order, which is how the
+// optimizer is put together.
+//
+// trait ProgramVisitor <- Looks at each action of a program
+//
+// trait ProgramTransformer <- Rewrites a program
+//
+// struct Pipeline <- Rewrites to run one after another
+//
+// struct StripDebug <- Removes the instructions only there for development
+//
+// walk(program: &[Action], visitor: &mut dyn ProgramVisitor)
+// -- Show a visitor every action of a program, in order
+//
+// map_labels(program: &[Action], rename: &dyn Fn(LabelId) -> LabelId) -> Vec<Action>
+// -- Rename every label, both where it's defined and used
+//
+/***********************************************************/
+use std::collections::HashSet;
+use labels::LabelId;
+use symbols::Action;
+use symbols::Action::*;
+
+
+/********************************************/
+// Structures
+/********************************************/
+// Looks at each action of a program. Every method does nothing unless
+// it's overridden, and visit_action hands each action to the method for
+// its kind, so a visitor only implements the ones it needs.
+pub trait ProgramVisitor {
+	// Any action
+	fn visit_action(&mut self, index: usize, action: &Action) {
+		match *action {
+			StackPush(value) => self.visit_push(index, value),
+			Label(label)     => self.visit_label(index, label),
+			Call(label) | Jump(label) | JumpIfZero(label) | JumpIfNegative(label) => self.visit_transfer(index, action, label),
+			_                => self.visit_other(index, action),
+		}
+	}
+
+	// A number pushed onto the stack
+	fn visit_push(&mut self, _index: usize, _value: i64) {}
+
+	// A label being defined
+	fn visit_label(&mut self, _index: usize, _label: LabelId) {}
+
+	// A call or jump to a label, conditional or not
+	fn visit_transfer(&mut self, _index: usize, _action: &Action, _label: LabelId) {}
+
+	// Any other action
+	fn visit_other(&mut self, _index: usize, _action: &Action) {}
+
+	// Called once every action has been visited
+	fn finish(&mut self) {}
+}
+
+
+// Rewrites a program into an equivalent one. Transformers work on
+// parsed programs, with their labels still in place.
+pub trait ProgramTransformer {
+	// A short name for the rewrite, like "peephole"
+	fn name(&self) -> &'static str;
+
+	// Rewrite the program
+	fn transform(&mut self, program: Vec<Action>) -> Vec<Action>;
+}
+
+
+// Rewrites to run one after another
+#[derive(Default)]
+pub struct Pipeline {
+	passes: Vec<Box<dyn ProgramTransformer>>,
+}
+
+impl Pipeline {
+	// The rewrites --optimize runs
+	pub fn optimizer() -> Pipeline {
+		let mut pipeline = Pipeline::default();
+		pipeline.add(Box::new(RemoveUnusedLabels));
+		pipeline.add(Box::new(Peephole));
+		pipeline
+	}
+
+	// Add a rewrite to the end of the pipeline
+	pub fn add(&mut self, pass: Box<dyn ProgramTransformer>) {
+		self.passes.push(pass);
+	}
+
+	// The names of the rewrites, in the order they run
+	pub fn names(&self) -> Vec<&'static str> {
+		self.passes.iter().map(|pass| pass.name()).collect()
+	}
+
+	// Run every rewrite over the program in turn
+	pub fn run(&mut self, program: Vec<Action>) -> Vec<Action> {
+		self.passes.iter_mut().fold(program, |program, pass| pass.transform(program))
+	}
+}
+
+
+// Removes labels that nothing calls or jumps to, which lets other
+// rewrites see across the places they used to be
+struct RemoveUnusedLabels;
+
+// Collects the labels that are called or jumped to
+#[derive(Default)]
+struct UsedLabels {
+	used: HashSet<LabelId>,
+}
+
+impl ProgramVisitor for UsedLabels {
+	fn visit_transfer(&mut self, _index: usize, _action: &Action, label: LabelId) {
+		self.used.insert(label);
+	}
+}
+
+impl ProgramTransformer for RemoveUnusedLabels {
+	fn name(&self) -> &'static str {
+		"unused-labels"
+	}
+
+	fn transform(&mut self, program: Vec<Action>) -> Vec<Action> {
+		let mut labels = UsedLabels::default();
+		walk(&program, &mut labels);
+		program.into_iter().filter(|action| !matches!(*action, Label(label) if !labels.used.contains(&label))).collect()
+	}
+}
+
+
+// Simplifies short runs of stack actions whose values are all known:
+// duplicating or swapping pushed values, adding, subtracting or
+// multiplying them, and discarding them. Only runs that can't fail are
+// rewritten, so the program fails in the same places as before.
+struct Peephole;
+
+impl ProgramTransformer for Peephole {
+	fn name(&self) -> &'static str {
+		"peephole"
+	}
+
+	fn transform(&mut self, program: Vec<Action>) -> Vec<Action> {
+		let mut result: Vec<Action> = Vec::with_capacity(program.len());
+
+		// Simplify the end of the result after each action, until it can't be
+		for action in program {
+			result.push(action);
+
+			loop {
+				// How many actions at the end are replaced, and what with
+				let rewritten = match *result.as_slice() {
+					[.., StackPush(value), StackDuplicate]               => Some((2, vec![StackPush(value), StackPush(value)])),
+					[.., StackPush(first), StackPush(second), StackSwap] => Some((3, vec![StackPush(second), StackPush(first)])),
+					[.., StackPush(left), StackPush(right), Add]         => left.checked_add(right).map(|sum| (3, vec![StackPush(sum)])),
+					[.., StackPush(left), StackPush(right), Subtract]    => left.checked_sub(right).map(|difference| (3, vec![StackPush(difference)])),
+					[.., StackPush(left), StackPush(right), Multiply]    => left.checked_mul(right).map(|product| (3, vec![StackPush(product)])),
+					[.., StackPush(_), StackDiscard]                     => Some((2, Vec::new())),
+					_                                                    => None,
+				};
+
+				match rewritten {
+					Some((replaced, actions)) => {
+						let length = result.len();
+						result.truncate(length - replaced);
+						result.extend(actions);
+					},
+					None => break,
+				}
+			}
+		}
+
+		result
+	}
+}
+
+
+// Removes the instructions that are only there for development, like
+// DumpHeap, so a finished program runs without them
+pub struct StripDebug;
+
+impl ProgramTransformer for StripDebug {
+	fn name(&self) -> &'static str {
+		"strip-debug"
+	}
+
+	fn transform(&mut self, program: Vec<Action>) -> Vec<Action> {
+		program.into_iter().filter(|action| !matches!(*action, DumpHeap)).collect()
+	}
+}
+
+
+/********************************************/
+// Public Functions
+/********************************************/
+// Show a visitor every action of a program, in order
+pub fn walk(program: &[Action], visitor: &mut dyn ProgramVisitor) {
+	for (index, action) in program.iter().enumerate() {
+		visitor.visit_action(index, action);
+	}
+	visitor.finish();
+}
+
+
+// Rename every label, both where it's defined and where it's used
+pub fn map_labels(program: &[Action], rename: &dyn Fn(LabelId) -> LabelId) -> Vec<Action> {
+	program.iter().map(|action| match *action {
+		Label(label)          => Label(rename(label)),
+		Call(label)           => Call(rename(label)),
+		Jump(label)           => Jump(rename(label)),
+		JumpIfZero(label)     => JumpIfZero(rename(label)),
+		JumpIfNegative(label) => JumpIfNegative(rename(label)),
+		action                => action,
+	}).collect()
+}
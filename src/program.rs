@@ -0,0 +1,274 @@
+/***********************************************************/
+//
+// Loaded Programs
+// ===============
+// The form the vm executes a resolved program in. Actions
+// stay the program's representation everywhere else, but
+// an enum of them is sixteen bytes each, most of it padding.
+// Loading splits them into two parallel arrays instead, an
+// opcode byte per action and an operand per action, so the
+// dispatch loop reads far less memory.
+//
+// Loading also finds the straight-line stretches of the
+// program, the actions up to the next one that can transfer
+// control, so the vm can run a stretch as a single batch.
+// Batches see the program with every run of characters that
+// are pushed and then printed straight away fused into one
+// action writing their text, which is how most generated
+// programs print.
+//
+// A program still being parsed can be loaded a piece at a
+// time, each extending the last.
+//
+// enum Opcode <- What an action does, without its operand
+//
+// struct Program <- A resolved program laid out for the vm
+//
+/***********************************************************/
+use std::collections::HashMap;
+use labels::LabelId;
+use symbols::Action;
+use symbols::Action::*;
+
+
+/********************************************/
+// Structures
+/********************************************/
+// What an action does, without its operand. Labels are only here so
+// a program that wasn't reduced can still be loaded and fail when
+// one is reached. OutputText is only ever seen by batches.
+#[repr(u8)]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Opcode {
+	StackPush,
+	StackDuplicate,
+	StackSwap,
+	StackDiscard,
+	Add,
+	Subtract,
+	Multiply,
+	Divide,
+	Modulo,
+	HeapStore,
+	HeapRetrieve,
+	Label,
+	Call,
+	Jump,
+	JumpIfZero,
+	JumpIfNegative,
+	EndSubroutine,
+	Halt,
+	OutputChar,
+	OutputNumber,
+	ReadChar,
+	ReadNumber,
+	GetEnv,
+	DumpHeap,
+	Assert,
+	CallDepth,
+	PeekReturn,
+	OutputFormatted,
+	Clock,
+	Random,
+	OutputText, // Write the text of a run of pushes each followed by OutputChar
+}
+
+impl Opcode {
+	// How many values the action takes off the stack, which the vm
+	// checks are there before executing it
+	pub fn pops(self) -> usize {
+		match self {
+			Opcode::StackSwap | Opcode::Add | Opcode::Subtract | Opcode::Multiply             => 2,
+			Opcode::Divide | Opcode::Modulo | Opcode::HeapStore | Opcode::Assert              => 2,
+			Opcode::StackDuplicate | Opcode::StackDiscard | Opcode::HeapRetrieve              => 1,
+			Opcode::JumpIfZero | Opcode::JumpIfNegative | Opcode::GetEnv                      => 1,
+			Opcode::PeekReturn | Opcode::Random                                               => 1,
+			Opcode::OutputFormatted                                                           => 3,
+			Opcode::OutputChar | Opcode::OutputNumber | Opcode::ReadChar | Opcode::ReadNumber => 1,
+			Opcode::StackPush | Opcode::Label | Opcode::Call | Opcode::Jump                   => 0,
+			Opcode::OutputText | Opcode::DumpHeap | Opcode::CallDepth | Opcode::Clock         => 0,
+			Opcode::EndSubroutine | Opcode::Halt                                              => 0,
+		}
+	}
+
+	// Whether the action can send control anywhere but the next action.
+	// Labels count, so the vm reports one it reaches.
+	pub fn transfers(self) -> bool {
+		matches!(self, Opcode::Label | Opcode::Call | Opcode::Jump | Opcode::JumpIfZero | Opcode::JumpIfNegative | Opcode::EndSubroutine | Opcode::Halt)
+	}
+
+	// Whether the action reads the program's input or writes its output,
+	// where a cancelled run stops before it
+	pub fn performs_io(self) -> bool {
+		matches!(self, Opcode::ReadChar | Opcode::ReadNumber | Opcode::OutputChar | Opcode::OutputNumber | Opcode::OutputFormatted | Opcode::OutputText)
+	}
+}
+
+// A resolved program laid out for the vm
+#[derive(Debug, Clone, Default)]
+pub struct Program {
+	opcodes:  Vec<Opcode>,                     // What each action does
+	operands: Vec<i64>,                        // The value each push pushes and the pointer each call or jump goes to, or zero
+	ends:     Vec<usize>,                      // Where the straight-line stretch from each action ends
+	batched:  Vec<Opcode>,                     // The opcodes batches see, with fused runs of output
+	texts:    HashMap<usize, (String, usize)>, // The text each fused run writes and how many actions it covers, by where it starts
+}
+
+impl Program {
+	// Constructor, lay out a resolved program
+	pub fn load(actions: &[Action]) -> Program {
+		let mut program = Program::default();
+		program.extend(actions);
+		program
+	}
+
+	// Add actions to the end of the program. Runs of output fused in
+	// batches don't reach back into what was loaded before.
+	pub fn extend(&mut self, actions: &[Action]) {
+		let (opcodes, operands) = actions.iter().map(|action| match *action {
+			StackPush(value)        => (Opcode::StackPush,       value),
+			StackDuplicate          => (Opcode::StackDuplicate,  0),
+			StackSwap               => (Opcode::StackSwap,       0),
+			StackDiscard            => (Opcode::StackDiscard,    0),
+			Add                     => (Opcode::Add,             0),
+			Subtract                => (Opcode::Subtract,        0),
+			Multiply                => (Opcode::Multiply,        0),
+			Divide                  => (Opcode::Divide,          0),
+			Modulo                  => (Opcode::Modulo,          0),
+			HeapStore               => (Opcode::HeapStore,       0),
+			HeapRetrieve            => (Opcode::HeapRetrieve,    0),
+			Label(label)            => (Opcode::Label,           label.0 as i64),
+			Call(pointer)           => (Opcode::Call,            pointer.0 as i64),
+			Jump(pointer)           => (Opcode::Jump,            pointer.0 as i64),
+			JumpIfZero(pointer)     => (Opcode::JumpIfZero,      pointer.0 as i64),
+			JumpIfNegative(pointer) => (Opcode::JumpIfNegative,  pointer.0 as i64),
+			EndSubroutine           => (Opcode::EndSubroutine,   0),
+			Halt                    => (Opcode::Halt,            0),
+			OutputChar              => (Opcode::OutputChar,      0),
+			OutputNumber            => (Opcode::OutputNumber,    0),
+			ReadChar                => (Opcode::ReadChar,        0),
+			ReadNumber              => (Opcode::ReadNumber,      0),
+			GetEnv                  => (Opcode::GetEnv,          0),
+			DumpHeap                => (Opcode::DumpHeap,        0),
+			Assert                  => (Opcode::Assert,          0),
+			CallDepth               => (Opcode::CallDepth,       0),
+			PeekReturn              => (Opcode::PeekReturn,      0),
+			OutputFormatted         => (Opcode::OutputFormatted, 0),
+			Clock                   => (Opcode::Clock,           0),
+			Random                  => (Opcode::Random,          0),
+		}).unzip::<_, _, Vec<Opcode>, Vec<i64>>();
+
+		let start = self.opcodes.len();
+		self.opcodes.extend(opcodes);
+		self.operands.extend(operands);
+		let (opcodes, operands) = (&self.opcodes, &self.operands);
+
+		// A stretch ends at the next action that transfers control, or the
+		// end, so the stretch the old end was in now carries on into the new
+		self.ends.resize(opcodes.len(), opcodes.len());
+		for index in (0..opcodes.len()).rev() {
+			if index < start && self.ends[index] != start {
+				break;
+			}
+
+			if opcodes[index].transfers() {
+				self.ends[index] = index;
+			} else if index + 1 < opcodes.len() {
+				self.ends[index] = self.ends[index + 1];
+			} else {
+				self.ends[index] = opcodes.len();
+			}
+		}
+
+		// Fuse runs of at least two characters pushed and printed at once.
+		// Only bytes are fused, which every encoding writes the same.
+		self.batched.extend_from_slice(&opcodes[start..]);
+		let mut index = start;
+		while index < opcodes.len() {
+			let pairs = opcodes[index..].chunks_exact(2).enumerate()
+				.take_while(|&(pair, opcodes)| opcodes == [Opcode::StackPush, Opcode::OutputChar] && (0..=255).contains(&operands[index + 2 * pair]))
+				.count();
+			if pairs < 2 {
+				index += 1;
+				continue;
+			}
+
+			let text = (0..pairs).map(|pair| (operands[index + 2 * pair] as u8) as char).collect::<String>();
+			self.batched[index] = Opcode::OutputText;
+			self.texts.insert(index, (text, 2 * pairs));
+			index += 2 * pairs;
+		}
+	}
+
+	// Point a call or jump that was loaded before its label was found at
+	// the label
+	pub fn resolve(&mut self, index: usize, pointer: usize) {
+		self.operands[index] = pointer as i64;
+	}
+
+	// What the action at an index does, or None past the end of the program
+	pub fn opcode(&self, index: usize) -> Option<Opcode> {
+		self.opcodes.get(index).copied()
+	}
+
+	// The operand of the action at an index
+	pub fn operand(&self, index: usize) -> i64 {
+		self.operands[index]
+	}
+
+	// The opcodes and operands of the straight-line stretch starting at an
+	// index, up to but not including the action that transfers control, as
+	// batches see them
+	pub fn straight(&self, start: usize) -> (&[Opcode], &[i64]) {
+		match self.ends.get(start) {
+			Some(&end) => (&self.batched[start..end], &self.operands[start..end]),
+			None       => (&[], &[]),
+		}
+	}
+
+	// The text written by the fused run of output starting at an index,
+	// and how many actions it covers
+	pub fn text(&self, index: usize) -> (&str, usize) {
+		let (ref text, length) = self.texts[&index];
+		(text, length)
+	}
+
+	// The action at an index, put back together for tracing and errors
+	pub fn action(&self, index: usize) -> Action {
+		let operand = self.operands[index];
+		match self.opcodes[index] {
+			Opcode::StackPush       => StackPush(operand),
+			Opcode::StackDuplicate  => StackDuplicate,
+			Opcode::StackSwap       => StackSwap,
+			Opcode::StackDiscard    => StackDiscard,
+			Opcode::Add             => Add,
+			Opcode::Subtract        => Subtract,
+			Opcode::Multiply        => Multiply,
+			Opcode::Divide          => Divide,
+			Opcode::Modulo          => Modulo,
+			Opcode::HeapStore       => HeapStore,
+			Opcode::HeapRetrieve    => HeapRetrieve,
+			Opcode::Label           => Label(LabelId(operand as u64)),
+			Opcode::Call            => Call(LabelId(operand as u64)),
+			Opcode::Jump            => Jump(LabelId(operand as u64)),
+			Opcode::JumpIfZero      => JumpIfZero(LabelId(operand as u64)),
+			Opcode::JumpIfNegative  => JumpIfNegative(LabelId(operand as u64)),
+			Opcode::EndSubroutine   => EndSubroutine,
+			Opcode::Halt            => Halt,
+			Opcode::OutputChar      => OutputChar,
+			Opcode::OutputNumber    => OutputNumber,
+			Opcode::ReadChar        => ReadChar,
+			Opcode::ReadNumber      => ReadNumber,
+			Opcode::GetEnv          => GetEnv,
+			Opcode::DumpHeap        => DumpHeap,
+			Opcode::Assert          => Assert,
+			Opcode::CallDepth       => CallDepth,
+			Opcode::PeekReturn      => PeekReturn,
+			Opcode::OutputFormatted => OutputFormatted,
+			Opcode::Clock           => Clock,
+			Opcode::Random          => Random,
+			Opcode::OutputText      => unreachable!("fused output is only seen by batches"),
+		}
+	}
+}
+
@@ -0,0 +1,363 @@
+/***********************************************************/
+//
+// Python Backend
+// ==============
+// Compiles a program to a Python 3 script that reads stdin
+// and writes stdout, for running where a native binary
+// can't be shipped, and for reading: every action is
+// written next to a comment naming it.
+//
+// Python has no goto, so the program is split into blocks
+// at every place control can jump to, each a function that
+// runs its actions and returns the index of the block to
+// run next, or None once the program halts.
+//
+// struct Python <- The Python backend
+//
+/***********************************************************/
+use analysis;
+use compile;
+use compile::Backend;
+use labels::LabelId;
+use options::{CharEncoding, Division, EndOfProgram, EofPolicy, InvalidChar, InvalidNumber, Options, ReadCharDestination, UnsetHeap};
+use parser::UNRESOLVED;
+use symbols::Action;
+use symbols::Action::*;
+
+
+/********************************************/
+// Structures
+/********************************************/
+// The Python backend
+pub struct Python;
+
+impl Backend for Python {
+	fn name(&self) -> &'static str {
+		"py"
+	}
+
+	fn emit(&self, program: &[Action], options: &Options) -> Result<Vec<u8>, String> {
+		let leaders = compile::leaders(program);
+		let mut blocks = String::new();
+		let mut starts = Vec::new();
+
+		for (index, action) in program.iter().enumerate() {
+			if leaders[index] {
+				blocks.push_str(&format!("\n\ndef block_{}():\n", index));
+				starts.push(index);
+			}
+
+			blocks.push_str(&format!("    # {}: {:?}\n", index, action));
+			let needed = analysis::stack_effect(action).pops;
+			if needed > 0 {
+				blocks.push_str(&format!("    need({}, {}, {:?})\n", index, needed, format!("{:?}", action)));
+			}
+			for line in self.action(index, action, options)? {
+				blocks.push_str(&format!("    {}\n", line));
+			}
+
+			// Carry on into the next block
+			if leaders[index + 1] {
+				blocks.push_str(&format!("    return {}\n", index + 1));
+			}
+		}
+
+		// Running off the end
+		blocks.push_str(&format!("\n\ndef block_{}():\n", program.len()));
+		blocks.push_str(&match options.end_of_program {
+			EndOfProgram::Halt  => "    return None\n".to_string(),
+			EndOfProgram::Error => format!("    fail({}, \"Reached the end of the program without halting.\")\n", program.len()),
+		});
+		starts.push(program.len());
+
+		let table: Vec<String> = starts.iter().map(|start| format!("    {}: block_{},", start, start)).collect();
+
+		Ok(RUNTIME
+			.replace("$DIVISION", division(options.division))
+			.replace("$EOF", eof(options.eof))
+			.replace("$UNSET_HEAP", boolean(options.unset_heap == UnsetHeap::Zero))
+			.replace("$UNICODE", boolean(options.char_encoding == CharEncoding::Unicode))
+			.replace("$INVALID_CHAR_FAILS", boolean(options.invalid_char == InvalidChar::Error))
+			.replace("$REPROMPT", boolean(options.invalid_number == InvalidNumber::Reprompt))
+			.replace("$WHITESPACE", boolean(options.number_input.whitespace))
+			.replace("$PLUS_SIGN", boolean(options.number_input.plus_sign))
+			.replace("$HEXADECIMAL", boolean(options.number_input.hexadecimal))
+			.replace("$EMPTY_LINE", &format!("{:?}", format!("{:?}", options.number_input.empty_line).to_lowercase()))
+			.replace("$BLOCKS", &blocks)
+			.replace("$TABLE", &table.join("\n"))
+			.into_bytes())
+	}
+}
+
+impl Python {
+	// The statements an action compiles to
+	fn action(&self, index: usize, action: &Action, options: &Options) -> Result<Vec<String>, String> {
+		let jump = |LabelId(target)| if target == UNRESOLVED {
+			format!("fail({}, \"Tried to jump to a label that doesn't exist.\")", index)
+		} else {
+			format!("return {}", target)
+		};
+		let operands = "r = stack.pop(); l = stack.pop()".to_string();
+
+		Ok(match *action {
+			StackPush(value) => vec![format!("stack.append({})", value)],
+			StackDuplicate   => vec!["stack.append(stack[-1])".to_string()],
+			StackSwap        => vec!["stack[-1], stack[-2] = stack[-2], stack[-1]".to_string()],
+			StackDiscard     => vec!["stack.pop()".to_string()],
+
+			Add      => vec![operands, format!("stack.append(checked({}, l + r, \"Addition overflowed.\"))", index)],
+			Subtract => vec![operands, format!("stack.append(checked({}, l - r, \"Subtraction overflowed.\"))", index)],
+			Multiply => vec![operands, format!("stack.append(checked({}, l * r, \"Multiplication overflowed.\"))", index)],
+			Divide   => vec![
+				operands,
+				format!("if r == 0: fail({}, \"Tried to divide by zero.\")", index),
+				format!("stack.append(checked({}, quotient(l, r), \"Division overflowed.\"))", index),
+			],
+			Modulo   => vec![
+				operands,
+				format!("if r == 0: fail({}, \"Tried to take the remainder of a division by zero.\")", index),
+				format!("if l == MIN and r == -1: fail({}, \"Modulo overflowed.\")", index),
+				"stack.append(remainder(l, r))".to_string(),
+			],
+
+			HeapStore    => vec!["v = stack.pop(); a = stack.pop()".to_string(), "heap[a] = v".to_string()],
+			HeapRetrieve => vec![format!("stack.append(retrieve({}, stack.pop()))", index)],
+
+			Call(target)           => vec![format!("calls.append({})", index + 1), jump(target)],
+			Jump(target)           => vec![jump(target)],
+			JumpIfZero(target)     => vec![format!("if stack.pop() == 0: {}", jump(target))],
+			JumpIfNegative(target) => vec![format!("if stack.pop() < 0: {}", jump(target))],
+			EndSubroutine          => vec![
+				format!("if not calls: fail({}, \"Tried to return from a procedure, but no procedure call was made.\")", index),
+				"return calls.pop()".to_string(),
+			],
+			Halt => vec!["return None".to_string()],
+
+			OutputChar   => vec![format!("write(character({}, stack.pop()))", index)],
+			OutputNumber => vec!["write(str(stack.pop()))".to_string()],
+
+			// Reads take their destination only once the read is done, like the interpreter
+			ReadChar => match options.read_char {
+				ReadCharDestination::Heap  => vec![format!("v = read_char({})", index), "a = stack.pop()".to_string(), "if v is not None: heap[a] = v".to_string()],
+				ReadCharDestination::Stack => vec![format!("v = read_char({})", index), "if v is not None: stack.append(v)".to_string()],
+			},
+			ReadNumber => vec![format!("v = read_number({})", index), "a = stack.pop()".to_string(), "if v is not None: heap[a] = v".to_string()],
+
+			Label(_) | GetEnv | DumpHeap | Assert | CallDepth | PeekReturn | OutputFormatted | Clock | Random => return Err(compile::unsupported(self, action)),
+		})
+	}
+}
+
+
+/********************************************/
+// Private Functions
+/********************************************/
+// The quotient and remainder functions for a kind of division
+fn division(division: Division) -> &'static str {
+	match division {
+		Division::Floored => "\
+def quotient(l, r):
+    return l // r
+
+
+def remainder(l, r):
+    return l % r",
+
+		Division::Truncated => "\
+def quotient(l, r):
+    q = abs(l) // abs(r)
+    return q if (l < 0) == (r < 0) else -q
+
+
+def remainder(l, r):
+    return l - r * quotient(l, r)",
+
+		Division::Euclidean => "\
+def remainder(l, r):
+    return l % abs(r)
+
+
+def quotient(l, r):
+    return (l - remainder(l, r)) // r",
+	}
+}
+
+
+// What a read gives at the end of the input, as Python
+fn eof(eof: EofPolicy) -> &'static str {
+	match eof {
+		EofPolicy::Error       => "FAIL",
+		EofPolicy::NegativeOne => "-1",
+		EofPolicy::Zero        => "0",
+		EofPolicy::Unchanged   => "None",
+	}
+}
+
+
+// A boolean, as Python
+fn boolean(value: bool) -> &'static str {
+	if value {"True"} else {"False"}
+}
+
+
+// The script the program's blocks are placed in
+static RUNTIME: &str = r#"#!/usr/bin/env python3
+# Compiled from Whitespace with: whitespace compile --target py
+#
+# Reads the program's input from stdin and writes its output to stdout.
+# Each block below runs the actions from one place control can jump to
+# up to the next, and returns the index of the block to run after it.
+import re
+import sys
+
+MIN = -(2 ** 63)
+MAX = 2 ** 63 - 1
+
+# What a read gives at the end of the input, where FAIL stops the
+# program and None leaves the destination untouched
+FAIL = object()
+EOF = $EOF
+UNSET_HEAP_IS_ZERO = $UNSET_HEAP
+UNICODE = $UNICODE
+INVALID_CHAR_FAILS = $INVALID_CHAR_FAILS
+REPROMPT = $REPROMPT
+NUMBER_INPUT = {"whitespace": $WHITESPACE, "plus": $PLUS_SIGN, "hex": $HEXADECIMAL, "empty": $EMPTY_LINE}
+
+stack = []
+calls = []
+heap = {}
+
+
+class WhitespaceError(Exception):
+    pass
+
+
+def fail(at, message):
+    raise WhitespaceError("Runtime Error: %s (at action %d)" % (message, at))
+
+
+def need(at, count, action):
+    if len(stack) < count:
+        fail(at, "%s needs %d value(s) on the stack, but it holds %d." % (action, count, len(stack)))
+
+
+def checked(at, value, message):
+    if value < MIN or value > MAX:
+        fail(at, message)
+    return value
+
+
+$DIVISION
+
+
+def retrieve(at, address):
+    if address in heap:
+        return heap[address]
+    if UNSET_HEAP_IS_ZERO:
+        return 0
+    fail(at, "Tried to get a value from the heap, but no value was found at address: %d" % address)
+
+
+def character(at, value):
+    if UNICODE:
+        valid = 0 <= value <= 0x10FFFF and not 0xD800 <= value <= 0xDFFF
+    else:
+        valid = 0 <= value <= 255
+    if not valid and INVALID_CHAR_FAILS:
+        if UNICODE:
+            fail(at, "Tried to write %d as a character, but it isn't a Unicode code point." % value)
+        fail(at, "Tried to write %d as a character, but it's outside the range of a byte." % value)
+    if UNICODE:
+        return chr(value if valid else 0xFFFD)
+    return chr(value & 0xFF)
+
+
+def write(text):
+    sys.stdout.buffer.write(text.encode("utf-8"))
+
+
+def at_end(at, reading):
+    if EOF is FAIL:
+        fail(at, "Reached the end of the input while reading %s." % reading)
+    return EOF
+
+
+def read_char(at):
+    sys.stdout.buffer.flush()
+    byte = sys.stdin.buffer.read(1)
+    return byte[0] if byte else at_end(at, "a character")
+
+
+# Parse a line the way the interpreter's number input options say to,
+# returning the number, None to skip the line, or a reason it isn't one
+def parse_number(line):
+    text = line.strip(" \t") if NUMBER_INPUT["whitespace"] else line
+    if text == "":
+        if NUMBER_INPUT["empty"] == "zero":
+            return 0
+        if NUMBER_INPUT["empty"] == "skip":
+            return None
+        return "the line is empty"
+
+    negative, unsigned = False, text
+    if text[0] == "-":
+        negative, unsigned = True, text[1:]
+    elif text[0] == "+":
+        if not NUMBER_INPUT["plus"]:
+            return "a + sign isn't allowed"
+        unsigned = text[1:]
+
+    hexadecimal = NUMBER_INPUT["hex"] and unsigned[:2] in ("0x", "0X")
+    digits = unsigned[2:] if hexadecimal else unsigned
+    if not re.fullmatch("[0-9a-fA-F]+" if hexadecimal else "[0-9]+", digits):
+        return "%s isn't a number" % quote(line)
+
+    value = int(digits, 16 if hexadecimal else 10)
+    value = -value if negative else value
+    if value < MIN or value > MAX:
+        return "%s doesn't fit in 64 bits" % quote(line)
+    return value
+
+
+# Quote a line the way the interpreter does in its messages
+def quote(line):
+    return '"' + line.replace("\\", "\\\\").replace('"', '\\"') + '"'
+
+
+def read_number(at):
+    while True:
+        sys.stdout.buffer.flush()
+        line = sys.stdin.buffer.readline()
+        if not line:
+            return at_end(at, "a number")
+
+        number = parse_number(re.sub("\r?\n$", "", line.decode("utf-8", "replace")))
+        if number is None:
+            continue
+        if isinstance(number, int):
+            return number
+        if not REPROMPT:
+            fail(at, "Unable to parse number: %s" % number)
+        write("Unable to parse number: %s\n" % number)
+$BLOCKS
+
+BLOCKS = {
+$TABLE
+}
+
+
+def main():
+    pc = 0
+    try:
+        while pc is not None:
+            pc = BLOCKS[pc]()
+    except WhitespaceError as error:
+        write("Error: %s\n" % error)
+        sys.stdout.buffer.flush()
+        sys.exit(6)
+    sys.stdout.buffer.flush()
+
+
+if __name__ == "__main__":
+    main()
+"#;
@@ -0,0 +1,61 @@
+/***********************************************************/
+//
+// Reloading
+// =========
+// Maps positions in a running program onto an edited
+// version of it, so the vm can switch to the new code
+// without starting over. Positions are found relative to
+// the nearest label before them, which survive most edits
+// better than raw indexes do.
+//
+// remap(old: &[Action], new: &[Action], pointer: usize) -> usize
+// -- Find where a program pointer into the old program
+//    belongs in the new one
+//
+/***********************************************************/
+use std::collections::HashMap;
+use labels::LabelId;
+use symbols::Action;
+use symbols::Action::*;
+
+
+/********************************************/
+// Public Functions
+/********************************************/
+// Find where a program pointer into the old program belongs in the new
+// one. Both programs are parsed but not reduced, while the pointer is
+// into the reduced program. The pointer keeps its distance from the
+// nearest label before it that's still in the new program, or from the
+// start if none is.
+pub fn remap(old: &[Action], new: &[Action], pointer: usize) -> usize {
+	let new_labels = label_pointers(new);
+	let length     = new.iter().filter(|action| !matches!(action, Label(_))).count();
+
+	let mut anchors: Vec<(LabelId, usize)> = label_pointers(old).into_iter().filter(|&(_, at)| at <= pointer).collect();
+	anchors.sort_by_key(|&(_, at)| at);
+
+	let (start, offset) = anchors.iter().rev()
+		.find_map(|&(label, at)| new_labels.get(&label).map(|&start| (start, pointer - at)))
+		.unwrap_or((0, pointer));
+
+	(start + offset).min(length)
+}
+
+
+/********************************************/
+// Private Functions
+/********************************************/
+// The pointer each label resolves to once the labels are reduced away
+fn label_pointers(program: &[Action]) -> HashMap<LabelId, usize> {
+	let mut labels  = HashMap::new();
+	let mut pointer = 0;
+
+	for action in program {
+		match *action {
+			Label(label) => {labels.insert(label, pointer);},
+			_            => pointer += 1,
+		}
+	}
+
+	labels
+}
@@ -0,0 +1,290 @@
+/***********************************************************/
+//
+// REPL
+// ====
+// An interactive session, for the repl command, for trying
+// code out and building a program up a piece at a time.
+// Each line of STL notation is run as soon as it's entered,
+// against a stack and heap that last the whole session, and
+// commands starting with a colon look after the session:
+//    :load <file>        - load a program, replacing the last
+//    :run                - run the loaded program from the start
+//    :def <name> <code>  - define a subroutine for the session
+//    :defs               - list the subroutines defined
+//    :reset [heap|stack] - empty the heap, the stack, or both
+//    :save <file>        - write the session out as a program
+//    :help, :quit
+//
+// A line is run as a program of its own, followed by a halt,
+// the loaded program and the subroutines, so it can call any
+// of them. In code, @name stands for the label of the
+// subroutine with that name, so LST@nameL calls it; the
+// subroutine returns by itself at the end of its code. A
+// line that fails leaves the stack and heap as they were.
+//
+// A saved session is the lines that ran, in order, with the
+// loaded program and the subroutines after them, written out
+// with a comment naming each instruction. It's an ordinary
+// program, which :load reads back.
+//
+// Programs run in a session read nothing, since the commands
+// take stdin.
+//
+// repl(options: Options, io_options: IoOptions, limits: Limits, loaded: Option<String>, commands: &mut dyn BufRead, output: &mut dyn Write) -> io::Result<()>
+// -- Take lines until the user quits or runs out of them
+//
+/***********************************************************/
+use std::fs;
+use std::io;
+use std::io::{BufRead, Write};
+use checkpoint::Checkpoint;
+use emit;
+use error::WhitespaceError;
+use options::{IoOptions, Limits, Options};
+use parser;
+use streams::SharedBuffer;
+use symbols::Action;
+use virtual_machine::WhitespaceVM;
+
+
+/********************************************/
+// Structures
+/********************************************/
+// Everything a session remembers between lines
+struct Session {
+	options:     Options,
+	io_options:  IoOptions,
+	limits:      Limits,
+	loaded:      String,                 // The loaded program's source
+	subroutines: Vec<(String, String)>,  // Each subroutine's name and code, in whitespace, in the order they were defined
+	history:     String,                 // The lines that ran, in whitespace
+	state:       Checkpoint,             // The stack and heap
+}
+
+
+/********************************************/
+// Constants
+/********************************************/
+static HELP: &str = "\
+<code>              - run a line of STL notation, where @name is a subroutine's label
+:load <file>        - load a program, whose subroutines lines can call
+:run                - run the loaded program from the start
+:def <name> <code>  - define a subroutine, called with LST@nameL
+:defs               - list the subroutines defined
+:reset [heap|stack] - empty the heap, the stack, or both
+:save <file>        - write the session out as a program
+:quit               - stop the session
+";
+
+
+/********************************************/
+// Public Functions
+/********************************************/
+// Take lines until the user quits or runs out of them, starting with a
+// program loaded when there's one
+pub fn repl(options: Options, io_options: IoOptions, limits: Limits, loaded: Option<String>, commands: &mut dyn BufRead, output: &mut dyn Write) -> io::Result<()> {
+	let mut session = Session {options, io_options, limits, loaded: loaded.unwrap_or_default(), subroutines: Vec::new(), history: String::new(), state: Checkpoint::default()};
+
+	loop {
+		write!(output, "ws> ")?;
+		output.flush()?;
+
+		let mut line = String::new();
+		if commands.read_line(&mut line)? == 0 {
+			writeln!(output)?;
+			return Ok(());
+		}
+
+		let line = line.trim();
+		let (command, rest) = line.split_once(' ').map_or((line, ""), |(command, rest)| (command, rest.trim()));
+		match command {
+			"" => {},
+
+			":load" => match fs::read_to_string(rest) {
+				Ok(source) => {
+					let previous = std::mem::replace(&mut session.loaded, source);
+					match session.resolve("", true) {
+						Ok(_)      => writeln!(output, "Loaded {}", rest)?,
+						Err(error) => {writeln!(output, "Error: {}", error)?; session.loaded = previous},
+					}
+				},
+				Err(error) => writeln!(output, "Error: {}: {}", rest, error)?,
+			},
+
+			":run" => {
+				let loaded = session.loaded.clone();
+				session.run(&loaded, false, output)?;
+			},
+
+			":def" => match rest.split_once(' ') {
+				Some((name, notation)) if !name.is_empty() && name.chars().all(is_name) => {
+					let code = code(notation);
+					let previous = session.subroutines.iter().position(|(defined, _)| defined == name).map(|index| session.subroutines.remove(index));
+					session.subroutines.push((name.to_string(), code));
+					match session.resolve("", true) {
+						Ok(_)      => writeln!(output, "Defined {}", name)?,
+						Err(error) => {
+							writeln!(output, "Error: {}", error)?;
+							session.subroutines.pop();
+							session.subroutines.extend(previous);
+						},
+					}
+				},
+				_ => writeln!(output, "Expected :def <name> <code>, with a name of small letters, digits and _")?,
+			},
+
+			":defs" if session.subroutines.is_empty() => writeln!(output, "No subroutines are defined.")?,
+			":defs" => {
+				for (name, code) in &session.subroutines {
+					writeln!(output, "{:<16} {}", name, notation(code))?;
+				}
+			},
+
+			":reset" => {
+				match rest {
+					"heap"  => session.state.heap.clear(),
+					"stack" => session.state.stack.clear(),
+					_       => {session.state.heap.clear(); session.state.stack.clear()},
+				}
+				writeln!(output, "{}", stack(&session.state.stack))?;
+			},
+
+			":save" => {
+				// Only a session that resolves is saved, so it loads back
+				let saved = session.resolve(&session.history, true).and_then(|_| session.parse(&session.history, true)).map(|program| emit::pretty(&program, true));
+				match saved.map_err(|error| error.to_string()).and_then(|saved| fs::write(rest, saved).map_err(|error| format!("{}: {}", rest, error))) {
+					Ok(())     => writeln!(output, "Saved the session to {}", rest)?,
+					Err(error) => writeln!(output, "Error: {}", error)?,
+				}
+			},
+
+			":help" => write!(output, "{}", HELP)?,
+			":quit" => return Ok(()),
+
+			_ if command.starts_with(':') => writeln!(output, "Unknown command, try :help")?,
+
+			// Anything else is code, which is remembered once it runs
+			_ => {
+				let code = code(line);
+				if session.run(&code, true, output)? {
+					session.history.push_str(&code);
+				}
+			},
+		}
+	}
+}
+
+
+/********************************************/
+// Methods
+/********************************************/
+impl Session {
+	// The source of a program starting with some code, then a halt, the
+	// loaded program unless the code is it, and the subroutines
+	fn source(&self, code: &str, loaded: bool) -> String {
+		let mut source = code.to_string();
+		source.push_str("\n\n\n");
+		if loaded {
+			source.push_str(&self.loaded);
+		}
+		for (name, code) in &self.subroutines {
+			source.push_str(&format!("\n  {}\n{}\n\t\n", parser::from_stl(&label(name)), code));
+		}
+		source
+	}
+
+	// Parse the program starting with some code
+	fn parse(&self, code: &str, loaded: bool) -> Result<Vec<Action>, WhitespaceError> {
+		parser::parse(self.source(code, loaded), &self.options)
+	}
+
+	// Parse the program starting with some code and resolve its labels
+	fn resolve(&self, code: &str, loaded: bool) -> Result<Vec<Action>, WhitespaceError> {
+		parser::parse_and_reduce(self.source(code, loaded), &self.options)
+	}
+
+	// Run the program starting with some code against the session's stack
+	// and heap, showing what it wrote and the stack afterwards, and return
+	// whether it halted
+	fn run(&mut self, code: &str, loaded: bool, output: &mut dyn Write) -> io::Result<bool> {
+		let program = match self.resolve(code, loaded) {
+			Ok(program) => program,
+			Err(error)  => {writeln!(output, "Error: {}", error)?; return Ok(false)},
+		};
+
+		let written = SharedBuffer::default();
+		let mut vm  = WhitespaceVM::with_io(program, self.options, Box::new(io::empty()), Box::new(written.clone()));
+		vm.set_io_options(self.io_options.clone());
+		vm.set_limits(self.limits);
+		vm.restore(&Checkpoint {pointer: 0, steps: 0, call_stack: Vec::new(), ..self.state.clone()});
+		let result = vm.execute();
+
+		let written = written.take();
+		output.write_all(&written)?;
+		if !written.is_empty() && !written.ends_with(b"\n") {
+			writeln!(output)?;
+		}
+		match result {
+			Ok(()) => {
+				self.state = vm.snapshot();
+				writeln!(output, "{}", stack(&self.state.stack))?;
+				Ok(true)
+			},
+			Err(error) => {
+				writeln!(output, "Error: {}", error)?;
+				Ok(false)
+			},
+		}
+	}
+}
+
+
+/********************************************/
+// Private Functions
+/********************************************/
+// Convert a line of STL notation to whitespace, with each @name replaced
+// by the label of the subroutine with that name
+fn code(notation: &str) -> String {
+	let mut replaced = String::new();
+	let mut rest     = notation;
+	while let Some(start) = rest.find('@') {
+		replaced.push_str(&rest[..start]);
+		let name   = &rest[start + 1..];
+		let length = name.find(|character| !is_name(character)).unwrap_or(name.len());
+		replaced.push_str(&label(&name[..length]));
+		rest = &name[length..];
+	}
+	replaced.push_str(rest);
+	parser::from_stl(&replaced)
+}
+
+
+// Whether a character can be part of a subroutine's name. Capitals
+// aren't, so the S, T and L after a name aren't taken for it.
+fn is_name(character: char) -> bool {
+	character.is_ascii_lowercase() || character.is_ascii_digit() || character == '_'
+}
+
+
+// The label of the subroutine with a name, in STL notation: each byte of
+// the name, a bit at a time
+fn label(name: &str) -> String {
+	name.bytes().flat_map(|byte| (0..8).rev().map(move |bit| if byte >> bit & 1 == 1 {'T'} else {'S'})).collect()
+}
+
+
+// Whitespace written in STL notation, to show
+fn notation(code: &str) -> String {
+	code.chars().map(|character| match character {
+		' '  => 'S',
+		'\t' => 'T',
+		_    => 'L',
+	}).collect()
+}
+
+
+// The stack after a line, bottom first
+fn stack(stack: &[i64]) -> String {
+	let values: Vec<String> = stack.iter().map(i64::to_string).collect();
+	format!("stack: [{}]", values.join(", "))
+}
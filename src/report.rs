@@ -0,0 +1,346 @@
+/***********************************************************/
+//
+// Error Reports
+// =============
+// Shows the errors a program stops with the way compilers
+// show theirs: a severity and code, the message, and an
+// excerpt of the source with the offending tokens marked.
+// Whitespace is invisible, so the excerpt draws spaces as
+// ·, tabs as → and line feeds as ↵, and dims the comments
+// around them. Reports are meant for stderr, so color is
+// only used when it's a terminal.
+//
+// The code is the status the command line exits with, so
+// E04 is a parse error and E06 a runtime error. Runtime
+// errors point at an action of the resolved program, which
+// is only traced back to the source when no rewrites ran.
+//
+// Warnings the parser gives are shown the same way, with
+// W04 for their code, since they don't stop anything.
+//
+// enum ErrorFormat <- How errors are shown
+//
+// struct Reporter <- Shows errors against the source they came from
+//
+// visible(character: char) -> (char, bool)
+// -- How a character of the source is drawn, and whether it's a comment
+//
+/***********************************************************/
+use std::io;
+use std::io::IsTerminal;
+use std::ops::Range;
+use error::{ParseError, WhitespaceError};
+use json;
+use options::Options;
+use parser;
+use parser::Span;
+use symbols::Action;
+
+
+/********************************************/
+// Structures
+/********************************************/
+// How errors are shown
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ErrorFormat {
+	Plain, // The message on a single line, the way errors always were
+	Rich,  // The message with an excerpt of the source, in color on a terminal
+	Json,  // A JSON object on a single line, for tools
+}
+
+impl ErrorFormat {
+	// Get a format from its name on the command line
+	pub fn from_name(name: &str) -> Option<ErrorFormat> {
+		match name {
+			"plain" => Some(ErrorFormat::Plain),
+			"rich"  => Some(ErrorFormat::Rich),
+			"json"  => Some(ErrorFormat::Json),
+			_       => None,
+		}
+	}
+
+	// The format used when none is asked for: rich when errors go to a
+	// terminal, and plain otherwise, so scripts reading them keep working
+	pub fn detect() -> ErrorFormat {
+		if io::stderr().is_terminal() {ErrorFormat::Rich} else {ErrorFormat::Plain}
+	}
+}
+
+// Shows errors against the source they came from
+pub struct Reporter {
+	format:  ErrorFormat,
+	color:   bool,
+	source:  String,               // Every file of the program, one after another
+	files:   Vec<(String, usize)>, // The name of each file and where it starts in the source
+	options: Options,              // The options the program was parsed with
+	exact:   bool,                 // Whether the resolved program's actions match the source's
+}
+
+// An error or warning ready to show
+struct Report {
+	severity: Severity,
+	code:     String,
+	message:  String,
+	marks:    Vec<Mark>,
+}
+
+// How bad what a report says is
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Severity {
+	Error,   // It stopped the program
+	Warning, // The program carried on
+}
+
+// Part of the source a report points at
+struct Mark {
+	bytes: Range<usize>,
+	label: String,
+}
+
+
+/********************************************/
+// Constants
+/********************************************/
+static RED:    &str = "\x1b[1;31m";
+static YELLOW: &str = "\x1b[1;33m";
+static BLUE:   &str = "\x1b[1;34m";
+static DIM:    &str = "\x1b[2m";
+static BOLD:   &str = "\x1b[1m";
+static RESET:  &str = "\x1b[0m";
+
+// The most characters of a line an excerpt shows, and the most lines
+const WIDTH: usize = 80;
+const LINES: usize = 4;
+
+
+/********************************************/
+// Methods
+/********************************************/
+impl Reporter {
+	// Constructor, a reporter for the program read from the given files.
+	// Exact says whether the actions executed are the ones parsed, which
+	// rewrites like --optimize change.
+	pub fn new(format: ErrorFormat, source: String, files: Vec<(String, usize)>, options: Options, exact: bool) -> Reporter {
+		Reporter {format, color: io::stderr().is_terminal(), source, files, options, exact}
+	}
+
+	// Show an error, ending with a line feed
+	pub fn render(&self, error: &WhitespaceError) -> String {
+		if self.format == ErrorFormat::Plain {
+			return match *error {
+				WhitespaceError::Parse(ref parse) => format!("Error: Parse Error: {}\n", self.plain(parse)),
+				_                                 => format!("Error: {}\n", error),
+			};
+		}
+
+		let report = self.report(error);
+		match self.format {
+			ErrorFormat::Json => self.render_json(&report),
+			_                 => self.render_text(&report),
+		}
+	}
+
+	// Show something the parser only warned about, ending with a line feed
+	pub fn render_warning(&self, warning: &ParseError) -> String {
+		if self.format == ErrorFormat::Plain {
+			return format!("Warning: {}\n", self.plain(warning));
+		}
+
+		let report = Report {severity: Severity::Warning, code: "W04".to_string(), ..self.report(&WhitespaceError::Parse(warning.clone()))};
+		match self.format {
+			ErrorFormat::Json => self.render_json(&report),
+			_                 => self.render_text(&report),
+		}
+	}
+
+	// The file and line each action of the resolved program starts on, or
+	// nothing when rewrites lost track of where the actions came from
+	pub fn lines(&self) -> Vec<(&str, usize)> {
+		if !self.exact {
+			return Vec::new();
+		}
+
+		self.spans().iter()
+			.filter(|&&(action, _)| !matches!(action, Action::Label(_)))
+			.map(|(_, span)| {let (name, line, _) = self.locate(span.start); (name, line)})
+			.collect()
+	}
+
+	// Work out what to say about an error and where it points
+	fn report(&self, error: &WhitespaceError) -> Report {
+		let code = format!("E{:02}", error.exit_code());
+		let (message, marks) = match *error {
+			WhitespaceError::Io(ref error)    => (error.to_string(), Vec::new()),
+			WhitespaceError::Parse(ref error) => {
+				let length = self.source[error.offset..].chars().next().map_or(0, char::len_utf8);
+				let label  = format!("token {} of the instruction", error.position);
+				(error.message.clone(), vec![Mark {bytes: error.offset..error.offset + length, label}])
+			},
+			WhitespaceError::Resolve(ref labels) => {
+				let spans = self.spans();
+				let marks = labels.iter().filter_map(|label| spans.get(label.index).map(|(_, span)| Mark {
+					bytes: span.start..span.end,
+					label: format!("{} refers to label {}, which doesn't exist", label.action.name(), label.name),
+				})).collect();
+				(format!("{} reference(s) to undefined labels", labels.len()), marks)
+			},
+			WhitespaceError::Runtime(ref error) => (error.message.clone(), self.action(error.program_pointer, "failed running")),
+			WhitespaceError::Limit(ref error)   => {
+				let message = error.to_string();
+				let message = message.rsplit_once(" (at action").map_or(message.clone(), |(message, _)| message.to_string());
+				(message, self.action(error.program_pointer, "stopped before"))
+			},
+		};
+
+		Report {severity: Severity::Error, code, message, marks}
+	}
+
+	// A parse error on a single line. The parser counts lines through every
+	// file, so point into the one the error is in when there are several.
+	fn plain(&self, error: &ParseError) -> String {
+		if self.files.len() < 2 {
+			return error.to_string();
+		}
+		let (name, line, column) = self.locate(error.offset);
+		format!("{} in {}", ParseError {line, column, ..error.clone()}, name)
+	}
+
+	// Mark the action at a pointer into the resolved program, if it can be
+	// traced back to the source
+	fn action(&self, pointer: usize, label: &str) -> Vec<Mark> {
+		if !self.exact {
+			return Vec::new();
+		}
+
+		// Resolving drops the labels, so skip them to find the action
+		let spans  = self.spans();
+		let parsed = spans.iter().filter(|&&(action, _)| !matches!(action, Action::Label(_))).nth(pointer);
+		parsed.map(|&(action, ref span)| Mark {
+			bytes: span.start..span.end,
+			label: format!("{} {:?} (action {})", label, action, pointer),
+		}).into_iter().collect()
+	}
+
+	// Parse the source again for where each action came from
+	fn spans(&self) -> Vec<(Action, Span)> {
+		parser::parse_with_spans(self.source.clone(), &self.options).unwrap_or_default()
+	}
+
+	// The file a byte of the source is in, and its line and column there, counting from one
+	fn locate(&self, offset: usize) -> (&str, usize, usize) {
+		let (name, start) = self.files.iter().rev().find(|&&(_, start)| start <= offset).map_or(("-", 0), |(name, start)| (name.as_str(), *start));
+		let before = &self.source[start..offset];
+		let line   = before.matches('\n').count() + 1;
+		let column = before.rsplit('\n').next().map_or(0, |line| line.chars().count()) + 1;
+		(name, line, column)
+	}
+
+	// Show a report the way compilers show errors
+	fn render_text(&self, report: &Report) -> String {
+		let paint = |color: &'static str| if self.color {color} else {""};
+		let (severity, color) = match report.severity {
+			Severity::Error   => ("error", RED),
+			Severity::Warning => ("warning", YELLOW),
+		};
+		let mut text = format!("{}{}[{}]{}{}: {}{}\n", paint(color), severity, report.code, paint(RESET), paint(BOLD), report.message, paint(RESET));
+
+		for mark in &report.marks {
+			let (name, line, column) = self.locate(mark.bytes.start);
+			let gutter = " ".repeat((line + LINES - 1).to_string().len());
+			text.push_str(&format!("{}{}-->{} {}:{}:{}\n", gutter, paint(BLUE), paint(RESET), name, line, column));
+			text.push_str(&format!("{} {}|{}\n", gutter, paint(BLUE), paint(RESET)));
+
+			// Show each line the mark covers, underlining the part of it that's marked
+			let mut start = self.source[..mark.bytes.start].rfind('\n').map_or(0, |newline| newline + 1);
+			let mut number = line;
+			loop {
+				let end = self.source[start..].find('\n').map_or(self.source.len(), |newline| start + newline + 1);
+				let (excerpt, underline) = self.excerpt(start..end, &mark.bytes);
+				let last = end >= mark.bytes.end || end == self.source.len() || number - line + 1 == LINES;
+				let marked = if last {format!("{} {}", underline, mark.label)} else {underline};
+
+				text.push_str(&format!("{}{:>width$} |{} {}\n", paint(BLUE), number, paint(RESET), excerpt, width = gutter.len()));
+				text.push_str(&format!("{} {}|{} {}{}{}\n", gutter, paint(BLUE), paint(RESET), paint(color), marked.trim_end(), paint(RESET)));
+				if last {
+					break;
+				}
+				start   = end;
+				number += 1;
+			}
+		}
+
+		text
+	}
+
+	// Draw a line of the source visibly, with a line of carets under the
+	// marked part of it
+	fn excerpt(&self, line: Range<usize>, marked: &Range<usize>) -> (String, String) {
+		let paint = |color: &'static str| if self.color {color} else {""};
+		let chars: Vec<(usize, char)> = self.source[line.clone()].char_indices().map(|(offset, character)| (line.start + offset, character)).collect();
+		let inside = |offset: usize| marked.contains(&offset) || (marked.is_empty() && offset == marked.start);
+
+		// Only show part of a long line, around where the mark starts on it
+		let first = chars.iter().position(|&(offset, _)| offset >= marked.start).unwrap_or(chars.len());
+		let from  = first.saturating_sub(WIDTH / 2).min(chars.len().saturating_sub(WIDTH));
+		let shown = &chars[from..chars.len().min(from + WIDTH)];
+
+		let mut excerpt   = String::new();
+		let mut underline = String::new();
+		if from > 0 {
+			excerpt.push('…');
+			underline.push(' ');
+		}
+		for &(offset, character) in shown {
+			match visible(character) {
+				(drawn, true)  => excerpt.push_str(&format!("{}{}{}", paint(DIM), drawn, paint(RESET))),
+				(drawn, false) => excerpt.push(drawn),
+			}
+			underline.push(if inside(offset) {'^'} else {' '});
+		}
+		if from + shown.len() < chars.len() {
+			excerpt.push('…');
+		}
+
+		// A mark at the very end of the source points just past the last line
+		if marked.start == self.source.len() && line.end == self.source.len() {
+			underline.push('^');
+		}
+		(excerpt, underline.trim_end().to_string())
+	}
+
+	// Show a report as a JSON object on a single line
+	fn render_json(&self, report: &Report) -> String {
+		let marks: Vec<String> = report.marks.iter().map(|mark| {
+			let (name, line, column) = self.locate(mark.bytes.start);
+			format!(
+				"{{\"file\":{},\"start\":{},\"end\":{},\"line\":{},\"column\":{},\"label\":{}}}",
+				json::string(name), mark.bytes.start, mark.bytes.end, line, column, json::string(&mark.label),
+			)
+		}).collect();
+
+		format!(
+			"{{\"severity\":\"{}\",\"code\":{},\"message\":{},\"marks\":[{}]}}\n",
+			if report.severity == Severity::Warning {"warning"} else {"error"}, json::string(&report.code), json::string(&report.message), marks.join(","),
+		)
+	}
+}
+
+
+/********************************************/
+// Public Functions
+/********************************************/
+// How a character of the source is drawn where it has to be seen, and
+// whether it's part of a comment
+pub fn visible(character: char) -> (char, bool) {
+	match character {
+		' '      => ('·', false),
+		'\t'     => ('→', false),
+		'\n'     => ('↵', false),
+		'\r'     => ('␍', true),
+		'\u{0b}' => ('␋', false),
+		'\u{0c}' => ('␌', false),
+		'\u{a0}' => ('⍽', false),
+		_ if character.is_control() => ('�', true),
+		_        => (character, true),
+	}
+}
@@ -0,0 +1,198 @@
+/***********************************************************/
+//
+// Interaction Scripts
+// ===================
+// Drives an interactive program from a script of expected
+// output and input to send, so programs that prompt the
+// user can be tested like batch programs.
+//
+// A script has one step per line:
+//    expect "Enter a number:"   - the program writes this next
+//    send "42\n"                - give this to the program's input
+// Strings can use the escapes \n, \t, \r, \\ and \". Blank
+// lines and lines starting with # are ignored.
+//
+// Every expect before a send has to be satisfied by the time
+// the program asks for input, and the expects after the last
+// send have to be satisfied by the time it stops.
+//
+// struct ScriptedInput <- An input stream that plays a script
+//                         against the program's output
+//
+/***********************************************************/
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::io;
+use std::io::Read;
+use std::rc::Rc;
+use streams::SharedBuffer;
+
+
+/********************************************/
+// Structures
+/********************************************/
+// A single step of a script
+#[derive(Debug, Clone, PartialEq)]
+enum Step {
+	Expect(Vec<u8>),
+	Send(Vec<u8>),
+}
+
+
+// An input stream that sends the script's input once the output it
+// expects has been written. Clones share the same progress, so a clone
+// can be handed to the vm and the original checked afterwards.
+#[derive(Debug, Clone)]
+pub struct ScriptedInput {
+	state: Rc<RefCell<ScriptState>>,
+}
+
+#[derive(Debug)]
+struct ScriptState {
+	steps:   VecDeque<Step>,
+	output:  SharedBuffer,   // Everything the program has written
+	matched: usize,          // How much of the output earlier expects have consumed
+	sending: Vec<u8>,        // What's left of the step being sent
+	failure: Option<String>, // Why the script failed, once it has
+}
+
+impl ScriptedInput {
+	// Parse a script that will be played against the given output
+	pub fn parse(script: &str, output: SharedBuffer) -> Result<ScriptedInput, String> {
+		let mut steps = VecDeque::new();
+
+		for (number, line) in script.lines().enumerate() {
+			let line = line.trim();
+			if line.is_empty() || line.starts_with('#') {
+				continue;
+			}
+
+			let step = if let Some(text) = line.strip_prefix("expect ") {
+				unquote(text).map(Step::Expect)
+			} else if let Some(text) = line.strip_prefix("send ") {
+				unquote(text).map(Step::Send)
+			} else {
+				None
+			};
+
+			match step {
+				Some(step) => steps.push_back(step),
+				None       => return Err(format!("line {} of the script isn't an expect or send step", number + 1)),
+			}
+		}
+
+		let state = ScriptState {steps, output, matched: 0, sending: Vec::new(), failure: None};
+		Ok(ScriptedInput {state: Rc::new(RefCell::new(state))})
+	}
+
+	// Check the steps left over once the program has stopped, returning
+	// why the script failed, if it did
+	pub fn finish(&self) -> Option<String> {
+		let mut state = self.state.borrow_mut();
+
+		if state.failure.is_none() {
+			match state.steps.iter().find(|step| matches!(step, Step::Send(_))) {
+				Some(Step::Send(text)) => {
+					state.failure = Some(format!("the program stopped before reading {}", quote(text)));
+				},
+				_                      => state.check_expects(),
+			}
+		}
+
+		state.failure.clone()
+	}
+}
+
+impl ScriptState {
+	// Satisfy the expects at the front of the script from the output
+	// written so far, recording a failure if one can't be
+	fn check_expects(&mut self) {
+		let output = self.output.contents();
+
+		while let Some(Step::Expect(text)) = self.steps.front().cloned() {
+			let found = if text.is_empty() {
+				Some(0)
+			} else {
+				output[self.matched..].windows(text.len()).position(|window| window == text.as_slice())
+			};
+
+			match found {
+				Some(offset) => {
+					self.matched += offset + text.len();
+					self.steps.pop_front();
+				},
+				None         => {
+					self.failure = Some(format!("expected {} but the program wrote {}", quote(&text), quote(&output[self.matched..])));
+					return;
+				},
+			}
+		}
+	}
+}
+
+
+/********************************************/
+// Trait Implementations
+/********************************************/
+impl Read for ScriptedInput {
+	fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+		let mut state = self.state.borrow_mut();
+
+		// The program wants input, so everything expected before it must be there
+		if state.sending.is_empty() {
+			if state.failure.is_none() {
+				state.check_expects();
+			}
+			if state.failure.is_some() {
+				return Err(io::Error::other("the interaction script failed"));
+			}
+
+			match state.steps.pop_front() {
+				Some(Step::Send(text)) => state.sending = text,
+				_                      => return Ok(0),
+			}
+		}
+
+		let count = buffer.len().min(state.sending.len());
+		buffer[..count].copy_from_slice(&state.sending[..count]);
+		state.sending.drain(..count);
+		Ok(count)
+	}
+}
+
+
+/********************************************/
+// Private Functions
+/********************************************/
+// Parse a double quoted string with escapes, returning None if it isn't one
+pub fn unquote(text: &str) -> Option<Vec<u8>> {
+	let text = text.trim().strip_prefix('"')?.strip_suffix('"')?;
+	let mut bytes = Vec::new();
+	let mut characters = text.chars();
+
+	while let Some(character) = characters.next() {
+		let character = match character {
+			'\\' => match characters.next()? {
+				'n'  => '\n',
+				't'  => '\t',
+				'r'  => '\r',
+				'\\' => '\\',
+				'"'  => '"',
+				_    => return None,
+			},
+			'"'  => return None,
+			_    => character,
+		};
+
+		let mut encoded = [0; 4];
+		bytes.extend_from_slice(character.encode_utf8(&mut encoded).as_bytes());
+	}
+
+	Some(bytes)
+}
+
+
+// Quote bytes for a failure message
+fn quote(bytes: &[u8]) -> String {
+	format!("{:?}", String::from_utf8_lossy(bytes))
+}
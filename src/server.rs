@@ -0,0 +1,365 @@
+/***********************************************************/
+//
+// Server
+// ======
+// Keeps an interpreter running for editors and web pages,
+// which send it requests as lines of JSON instead of
+// starting a process for every run. Every request gets a
+// line of JSON back.
+//
+// A request is an object with a "method", and an optional
+// "id" that's copied into its response:
+//    load  - start a run of "program", given as whitespace,
+//            or of "stl", given in STL notation, starting
+//            with the last run's heap if "keep_heap" is true
+//    input - add "text" to the program's input
+//    close - end the input, so reads past it see the end
+//    step  - execute "count" actions, or one
+//    run   - execute until the program stops, or for at most
+//            "max_steps" more actions and "timeout" seconds
+//    state - describe the stack, call stack and heap, and how
+//            often their storage has had to grow
+//    checkpoint - keep a copy of the run as it is now
+//    restore - go back to the checkpoint, which can be
+//            restored again, with no input given yet
+//    highlight - classify the tokens of "program" or "stl"
+//            for an editor to color, without running it
+//    cancel - stop the steps and runs asked for before it,
+//            leaving the program paused where it stopped
+// A response has "ok", and either an "error" or what the
+// method gives back. Step and run give the "status" they
+// leave the program in (paused, waiting for input, halted or
+// failed, with the "failure") along with the "output" it
+// wrote meanwhile.
+//
+// Requests are read on a thread of their own, so a cancel
+// is seen as soon as it's sent, even while an earlier request
+// is still running the program, which stops at its next safe
+// point. Its response comes in turn, after the one it
+// stopped, and requests after it execute as usual.
+//
+// Each load recycles the last run's vm, so a session that
+// runs many short programs keeps reusing the same storage.
+// Whatever a program grows it to past the capacity hints is
+// given back when the next is loaded.
+//
+// serve(requests: Box<dyn BufRead + Send>, responses: &mut dyn Write, options: Options, io_options: IoOptions, limits: Limits, capacity: Capacity) -> io::Result<()>
+// -- Answer requests until there are no more
+//
+/***********************************************************/
+use std::io;
+use std::io::{BufRead, Write};
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+use allocation::{Capacity, Shrink};
+use cancel::Cancel;
+use error::WhitespaceError;
+use heap::{HashHeap, Heap};
+use highlight;
+use json;
+use json::Value;
+use options::{IoOptions, Limits, Options};
+use parser;
+use streams::{PendingInput, SharedBuffer};
+use virtual_machine::WhitespaceVM;
+
+
+/********************************************/
+// Structures
+/********************************************/
+// What the server keeps between requests
+struct Session {
+	options:    Options,
+	io_options: IoOptions,
+	limits:     Limits, // The limits a run request has unless it gives its own
+	capacity:   Capacity,
+	run:        Option<Run>,
+	checkpoint: Option<Run>, // A copy of the run to go back to
+	cancel:     Cancel,      // Cancelled by the thread reading requests when one asks
+}
+
+// The program loaded most recently, and how far it's got
+struct Run {
+	vm:     WhitespaceVM,
+	input:  PendingInput,
+	output: SharedBuffer,
+	status: Status,
+}
+
+// Where a run has got to
+#[derive(Debug, Clone, PartialEq)]
+enum Status {
+	Paused,         // It can carry on whenever asked
+	Waiting,        // It's stopped at a read, and carries on once there's input
+	Halted,
+	Failed(String),
+}
+
+// The members of a successful response, as JSON
+type Members = Vec<(&'static str, String)>;
+
+impl Session {
+	// Answer a request
+	fn respond(&mut self, request: &Value) -> String {
+		let id = request.get("id").map_or("null".to_string(), |id| id.to_string());
+		let method = request.get("method").and_then(Value::as_str).unwrap_or("");
+
+		let answered = match method {
+			"load"       => self.load(request),
+			"input"      => self.input(request),
+			"close"      => self.running().map(|run| {run.input.close(); Vec::new()}),
+			"step"       => self.step(request),
+			"run"        => self.execute(request),
+			"state"      => self.state(),
+			"checkpoint" => self.checkpoint(),
+			"restore"    => self.restore(),
+			"highlight"  => self.highlight(request),
+			"cancel"     => {self.cancel.reset(); Ok(Vec::new())},
+			_            => Err(format!("{:?} isn't a method", method)),
+		};
+
+		match answered {
+			Ok(members) => {
+				let members: String = members.iter().map(|&(key, ref value)| format!(",{}:{}", json::string(key), value)).collect();
+				format!("{{\"id\":{},\"ok\":true{}}}", id, members)
+			},
+			Err(error) => format!("{{\"id\":{},\"ok\":false,\"error\":{}}}", id, json::string(&error)),
+		}
+	}
+
+	// Parse a program and start a new run of it
+	fn load(&mut self, request: &Value) -> Result<Members, String> {
+		let source  = source(request)?;
+		let reduced = parser::parse_and_reduce(source, &self.options).map_err(|error| error.to_string())?;
+		let actions = reduced.len();
+
+		// Carry the heap over, the way a notebook keeps state from one cell to the next
+		let kept = match self.run {
+			Some(ref previous) if request.get("keep_heap") == Some(&Value::Bool(true)) => {
+				let mut heap = HashHeap::default();
+				for (address, value) in previous.vm.heap().iter() {
+					heap.set(address, value);
+				}
+				Some(heap)
+			},
+			_ => None,
+		};
+
+		let input  = PendingInput::default();
+		let output = SharedBuffer::default();
+		let mut vm = match self.run.take() {
+			Some(previous) => {
+				let mut vm = previous.vm;
+				vm.recycle(reduced, self.options, Box::new(input.clone()), Box::new(output.clone()));
+				vm
+			},
+			None => {
+				let mut vm = WhitespaceVM::with_io(reduced, self.options, Box::new(input.clone()), Box::new(output.clone()));
+				vm.set_io_options(self.io_options.clone());
+				vm.set_capacity(self.capacity);
+				vm
+			},
+		};
+		if let Some(heap) = kept {
+			vm.set_heap(Box::new(heap));
+		}
+		vm.set_cancel(self.cancel.clone());
+
+		self.run = Some(Run {vm, input, output, status: Status::Paused});
+		self.checkpoint = None;
+		Ok(vec![("actions", actions.to_string())])
+	}
+
+	// Add to the program's input
+	fn input(&mut self, request: &Value) -> Result<Members, String> {
+		let text = request.get("text").and_then(Value::as_str).ok_or("input needs text")?.to_string();
+		self.running()?.input.feed(text.as_bytes());
+		Ok(Vec::new())
+	}
+
+	// Execute a number of actions
+	fn step(&mut self, request: &Value) -> Result<Members, String> {
+		let count = match request.get("count") {
+			Some(count) => count.as_i64().filter(|&count| count >= 0).ok_or("count has to be a whole number")? as u64,
+			None        => 1,
+		};
+
+		let memory = self.limits.memory;
+		let run    = self.resumable()?;
+		let steps  = run.vm.steps() + count;
+		run.vm.set_limits(Limits {steps: Some(steps), time: None, memory});
+		Ok(run.execute())
+	}
+
+	// Execute until the program stops or reaches the limits
+	fn execute(&mut self, request: &Value) -> Result<Members, String> {
+		let max_steps = match request.get("max_steps") {
+			Some(steps) => Some(steps.as_i64().filter(|&steps| steps >= 0).ok_or("max_steps has to be a whole number")? as u64),
+			None        => self.limits.steps,
+		};
+		let timeout = match request.get("timeout") {
+			Some(seconds) => Some(Duration::try_from_secs_f64(seconds.as_f64().unwrap_or(-1.0)).map_err(|_| "timeout has to be a number of seconds")?),
+			None          => self.limits.time,
+		};
+
+		let memory = self.limits.memory;
+		let run    = self.resumable()?;
+		let limits = Limits {steps: max_steps.map(|steps| run.vm.steps() + steps), time: timeout, memory};
+		run.vm.set_limits(limits);
+		Ok(run.execute())
+	}
+
+	// Describe the run
+	fn state(&mut self) -> Result<Members, String> {
+		let run  = self.running()?;
+		let heap = run.vm.heap().iter().map(|(address, value)| format!("[{},{}]", address, value)).collect::<Vec<String>>();
+
+		let mut members = run.status.members();
+		members.push(("pointer",    run.vm.program_pointer().to_string()));
+		members.push(("steps",      run.vm.steps().to_string()));
+		members.push(("stack",      format!("{:?}", run.vm.stack()).replace(' ', "")));
+		members.push(("call_stack", format!("{:?}", run.vm.call_stack()).replace(' ', "")));
+		members.push(("heap",       format!("[{}]", heap.join(","))));
+
+		let allocations = run.vm.allocations();
+		members.push(("allocations", format!("{{\"stack\":{},\"call_stack\":{},\"heap\":{}}}", allocations.stack, allocations.call_stack, allocations.heap)));
+		Ok(members)
+	}
+
+	// Keep a copy of the run as it is now
+	fn checkpoint(&mut self) -> Result<Members, String> {
+		let copy = self.running()?.fork();
+		self.checkpoint = Some(copy);
+		Ok(Vec::new())
+	}
+
+	// Go back to the checkpoint, keeping it so it can be gone back to again
+	fn restore(&mut self) -> Result<Members, String> {
+		let run = self.checkpoint.as_mut().ok_or("no checkpoint has been made")?.fork();
+		let mut members = run.status.members();
+		members.push(("steps", run.vm.steps().to_string()));
+		self.run = Some(run);
+		Ok(members)
+	}
+
+	// Classify the tokens of a program, giving the regions of each kind
+	// the way the highlight command does
+	fn highlight(&self, request: &Value) -> Result<Members, String> {
+		let regions = highlight::classify(&source(request)?, &self.options);
+		Ok(vec![("regions", highlight::regions_json(&regions))])
+	}
+
+	// The loaded program, whatever its status
+	fn running(&mut self) -> Result<&mut Run, String> {
+		self.run.as_mut().ok_or_else(|| "no program is loaded".to_string())
+	}
+
+	// The loaded program, if it can still execute
+	fn resumable(&mut self) -> Result<&mut Run, String> {
+		let run = self.running()?;
+		match run.status {
+			Status::Paused | Status::Waiting => Ok(run),
+			_                                => Err("the program has stopped, load it again to start over".to_string()),
+		}
+	}
+
+}
+
+impl Run {
+	// A copy of the run that carries on from here with an input and output
+	// of its own. Input the run has been given but hasn't read stays behind.
+	fn fork(&mut self) -> Run {
+		let input  = PendingInput::default();
+		let output = SharedBuffer::default();
+		let vm = self.vm.fork(Box::new(input.clone()), Box::new(output.clone()));
+		Run {vm, input, output, status: self.status.clone()}
+	}
+
+	// Execute under the limits already set, describing where it got to
+	fn execute(&mut self) -> Members {
+		self.status = match self.vm.execute() {
+			Ok(())                         => Status::Halted,
+			Err(WhitespaceError::Limit(_)) => Status::Paused,
+			Err(WhitespaceError::Io(ref error)) if error.kind() == io::ErrorKind::WouldBlock => Status::Waiting,
+			Err(error)                     => Status::Failed(error.to_string()),
+		};
+
+		let output = self.output.take();
+		let mut members = self.status.members();
+		members.push(("output", json::string(&String::from_utf8_lossy(&output))));
+		members.push(("steps",  self.vm.steps().to_string()));
+		members
+	}
+}
+
+impl Status {
+	// The status as members of a response
+	fn members(&self) -> Members {
+		let name = match *self {
+			Status::Paused    => "paused",
+			Status::Waiting   => "waiting",
+			Status::Halted    => "halted",
+			Status::Failed(_) => "failed",
+		};
+
+		let mut members = vec![("status", json::string(name))];
+		if let Status::Failed(ref error) = *self {
+			members.push(("failure", json::string(error)));
+		}
+		members
+	}
+}
+
+
+/********************************************/
+// Public Functions
+/********************************************/
+// Answer requests, one per line, until there are no more
+pub fn serve(requests: Box<dyn BufRead + Send>, responses: &mut dyn Write, options: Options, io_options: IoOptions, limits: Limits, capacity: Capacity) -> io::Result<()> {
+	let capacity    = Capacity {shrink: Shrink::ToHints, ..capacity};
+	let cancel      = Cancel::default();
+	let mut session = Session {options, io_options, limits, capacity, run: None, checkpoint: None, cancel: cancel.clone()};
+
+	// Cancel as soon as a cancel is read, rather than once it's answered
+	let (sender, received) = mpsc::channel();
+	thread::spawn(move || for line in requests.lines() {
+		if let Ok(ref line) = line {
+			if json::parse(line).ok().and_then(|request| request.get("method").and_then(Value::as_str).map(|method| method == "cancel")) == Some(true) {
+				cancel.cancel();
+			}
+		}
+		if sender.send(line).is_err() {
+			break;
+		}
+	});
+
+	for line in received {
+		let line = line?;
+		if line.trim().is_empty() {
+			continue;
+		}
+
+		let response = match json::parse(&line) {
+			Ok(request) => session.respond(&request),
+			Err(error)  => format!("{{\"id\":null,\"ok\":false,\"error\":{}}}", json::string(&format!("the request isn't JSON: {}", error))),
+		};
+		writeln!(responses, "{}", response)?;
+		responses.flush()?;
+	}
+
+	Ok(())
+}
+
+
+/********************************************/
+// Private Functions
+/********************************************/
+// The program a request gives, as whitespace or in STL notation
+fn source(request: &Value) -> Result<String, String> {
+	match (request.get("program").and_then(Value::as_str), request.get("stl").and_then(Value::as_str)) {
+		(Some(program), None)  => Ok(program.to_string()),
+		(None, Some(notation)) => Ok(parser::from_stl(notation)),
+		_                      => Err("the request needs either a program or stl".to_string()),
+	}
+}
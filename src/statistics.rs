@@ -0,0 +1,75 @@
+/***********************************************************/
+//
+// Statistics
+// ==========
+// Counters the vm keeps while it runs, so the shape of a
+// run (what executed how often, how much I/O it did, how its
+// branches went) can be examined once it stops.
+//
+// struct Statistics <- The counters for a single run
+//
+/***********************************************************/
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use symbols::Action;
+
+
+/********************************************/
+// Structures
+/********************************************/
+// The counters for a single run
+#[derive(Debug, Clone, Default)]
+pub struct Statistics {
+	pub executions:         Vec<u64>, // How many times each action of the program was executed
+	pub bytes_read:         u64,
+	pub bytes_written:      u64,
+	pub heap_stores:        u64,      // Including the stores made by reads and GetEnv
+	pub heap_retrieves:     u64,      // Including the retrieves made by GetEnv
+	pub branches_taken:     u64,      // Conditional jumps that jumped
+	pub branches_not_taken: u64,      // Conditional jumps that fell through
+}
+
+impl Statistics {
+	// Constructor, empty counters for a program of the given length
+	pub fn new(length: usize) -> Statistics {
+		Statistics {executions: vec![0; length], ..Statistics::default()}
+	}
+
+	// The total number of actions executed
+	pub fn steps(&self) -> u64 {
+		self.executions.iter().sum()
+	}
+
+	// How many times each kind of action was executed, by name
+	pub fn by_action(&self, program: &[Action]) -> BTreeMap<&'static str, u64> {
+		let mut counts = BTreeMap::new();
+
+		for (action, &count) in program.iter().zip(&self.executions) {
+			if count > 0 {
+				*counts.entry(action.name()).or_insert(0) += count;
+			}
+		}
+
+		counts
+	}
+
+	// Describe the run in a few lines of text
+	pub fn render(&self, program: &[Action]) -> String {
+		let mut text = String::new();
+
+		let _ = writeln!(text, "Statistics:");
+		let _ = writeln!(text, "  {:<20} {}", "actions executed", self.steps());
+		let _ = writeln!(text, "  {:<20} {}", "bytes read", self.bytes_read);
+		let _ = writeln!(text, "  {:<20} {}", "bytes written", self.bytes_written);
+		let _ = writeln!(text, "  {:<20} {}", "heap stores", self.heap_stores);
+		let _ = writeln!(text, "  {:<20} {}", "heap retrieves", self.heap_retrieves);
+		let _ = writeln!(text, "  {:<20} {}", "branches taken", self.branches_taken);
+		let _ = writeln!(text, "  {:<20} {}", "branches not taken", self.branches_not_taken);
+
+		for (name, count) in self.by_action(program) {
+			let _ = writeln!(text, "    {:<18} {}", name, count);
+		}
+
+		text
+	}
+}
@@ -0,0 +1,99 @@
+/***********************************************************/
+//
+// Status Reports
+// ==============
+// Says how a long run is getting on every so often, for
+// run --status-interval: how many actions it has executed,
+// how fast it's going, and how big its stack and heap are.
+// A program stuck in a loop keeps its rate but goes nowhere,
+// and one that has stalled stops reporting.
+//
+// The clock is only checked every few thousand actions, so
+// a program waiting for input doesn't report until it has
+// read something.
+//
+// struct Status <- When the next report is due and where it goes
+//
+// parse_interval(text: &str) -> Option<Duration>
+// -- Parse an interval like 5s, 500ms or 2m
+//
+/***********************************************************/
+use std::io;
+use std::io::Write;
+use std::time::{Duration, Instant};
+
+
+/********************************************/
+// Structures
+/********************************************/
+// When the next report is due and where it goes
+pub struct Status {
+	interval: Duration,
+	started:  Instant,
+	reported: Instant,      // When the last report was written, or the run started
+	steps:    u64,          // The actions executed by then
+	check:    u64,          // The number of actions at which to look at the clock again
+	log:      Box<dyn Write>,
+}
+
+// How many actions to execute between looks at the clock
+const CHECK_EVERY: u64 = 4096;
+
+
+/********************************************/
+// Methods
+/********************************************/
+impl Status {
+	// Constructor, report to the log once every interval from now on
+	pub fn new(interval: Duration, log: Box<dyn Write>) -> Status {
+		let now = Instant::now();
+		Status {interval, started: now, reported: now, steps: 0, check: CHECK_EVERY, log}
+	}
+
+	// Whether the vm should hand over its state, which it does every few
+	// thousand actions so the clock isn't read all the time
+	#[inline(always)]
+	pub fn due(&self, steps: u64) -> bool {
+		steps >= self.check
+	}
+
+	// Write a report if the interval has passed since the last one
+	pub fn report(&mut self, steps: u64, pointer: usize, stack: usize, heap: usize) -> io::Result<()> {
+		self.check = steps + CHECK_EVERY;
+
+		let now = Instant::now();
+		let elapsed = now.duration_since(self.reported);
+		if elapsed < self.interval {
+			return Ok(());
+		}
+
+		let rate = (steps - self.steps) as f64 / elapsed.as_secs_f64();
+		self.reported = now;
+		self.steps    = steps;
+		writeln!(
+			self.log, "Status after {:.1}s: {} actions executed, {:.0} actions/s, stack depth {}, heap size {}, at action {}",
+			now.duration_since(self.started).as_secs_f64(), steps, rate, stack, heap, pointer,
+		)?;
+		self.log.flush()
+	}
+}
+
+
+/********************************************/
+// Public Functions
+/********************************************/
+// Parse an interval given in seconds, with an optional unit of ms, s or m
+pub fn parse_interval(text: &str) -> Option<Duration> {
+	let (number, scale) = if let Some(number) = text.strip_suffix("ms") {
+		(number, 0.001)
+	} else if let Some(number) = text.strip_suffix('s') {
+		(number, 1.0)
+	} else if let Some(number) = text.strip_suffix('m') {
+		(number, 60.0)
+	} else {
+		(text, 1.0)
+	};
+
+	let seconds = number.parse::<f64>().ok()? * scale;
+	Duration::try_from_secs_f64(seconds).ok().filter(|interval| !interval.is_zero())
+}
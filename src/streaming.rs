@@ -0,0 +1,213 @@
+/***********************************************************/
+//
+// Streaming
+// =========
+// Executes a program while it's still being parsed, for run
+// --stream, so a huge generated program starts straight away
+// rather than once the whole of it has been read. The parser
+// runs on its own thread, handing over actions a chunk at a
+// time, and each chunk's labels are resolved to pointers as
+// far as they can be before the vm is extended with it. The
+// chunks double in size, so the vm starts once the first few
+// actions are parsed but is only extended a few dozen times.
+//
+// The vm only waits for the parser when it reaches the end of
+// what's been loaded, or a call or jump to a label that
+// hasn't been found yet. A label defined more than once goes
+// to the first of them, which is the only one there is when
+// the first call to it is loaded. What the program would have
+// failed to parse or resolve is still reported once parsing
+// is done, even if the run already finished.
+//
+// execute(vm: &mut WhitespaceVM, program: String, options: Options, parse: &mut Option<Duration>, warnings: &mut Vec<ParseError>) -> Result<(), WhitespaceError>
+// -- Execute a program while parsing it
+//
+/***********************************************************/
+use std::collections::HashMap;
+use std::mem;
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use std::time::{Duration, Instant};
+use error::{ParseError, UnresolvedLabel, WhitespaceError};
+use labels::{LabelId, SymbolTable};
+use options::{LabelResolution, Options};
+use parser;
+use parser::UNRESOLVED;
+use symbols::Action;
+use symbols::Action::*;
+use virtual_machine::WhitespaceVM;
+
+
+/********************************************/
+// Structures
+/********************************************/
+// What the parser's thread hands over
+enum Parsed {
+	Actions(Vec<Action>),                                     // The next actions, still with their labels
+	Done(Result<(), WhitespaceError>, Duration, SymbolTable), // Whether the whole program parsed, how long it took, and its labels
+}
+
+// The labels found so far and the calls and jumps still waiting for theirs
+#[derive(Debug, Default)]
+struct Resolver {
+	labels:  HashMap<LabelId, usize>,                         // The pointer each label found so far leads to
+	pending: HashMap<LabelId, Vec<(usize, UnresolvedLabel)>>, // The calls and jumps to each label not found yet, with their pointers, named once parsing is done
+	parsed:  usize,                                           // The actions parsed so far, counting labels
+	loaded:  usize,                                           // The actions loaded into the vm so far
+}
+
+// Loads what the parser's thread hands over into a vm
+struct Loader<'a> {
+	receiver:   Receiver<Parsed>,
+	resolver:   Resolver,
+	resolution: LabelResolution,
+	done:       bool,                     // Whether the parser has handed over everything
+	parse:      &'a mut Option<Duration>, // How long parsing took, once it's done
+}
+
+// How many actions the parser hands over first
+const CHUNK: usize = 1024;
+
+
+/********************************************/
+// Methods
+/********************************************/
+impl Resolver {
+	// Replace the labels in the next actions parsed with pointers where
+	// they've been found, returning the actions along with the pointers
+	// found for calls and jumps loaded before, by the call or jump's pointer
+	fn reduce(&mut self, actions: Vec<Action>) -> (Vec<Action>, Vec<(usize, usize)>) {
+		let mut reduced  = Vec::with_capacity(actions.len());
+		let mut resolved = Vec::new();
+
+		for action in actions {
+			let index   = self.parsed;
+			let pointer = self.loaded + reduced.len();
+			self.parsed += 1;
+
+			let label = match action {
+				Label(label) => {
+					self.labels.entry(label).or_insert(pointer);
+					if let Some(waiting) = self.pending.remove(&label) {
+						resolved.extend(waiting.into_iter().map(|(waiting, _)| (waiting, pointer)));
+					}
+					continue;
+				},
+				Call(label) | Jump(label) | JumpIfZero(label) | JumpIfNegative(label) => label,
+				other_action => {reduced.push(other_action); continue},
+			};
+
+			let target = match self.labels.get(&label) {
+				Some(&target) => LabelId(target as u64),
+				None          => {
+					self.pending.entry(label).or_default().push((pointer, UnresolvedLabel {label, name: String::new(), index, action}));
+					LabelId(UNRESOLVED)
+				},
+			};
+
+			reduced.push(match action {
+				Call(_)       => Call(target),
+				Jump(_)       => Jump(target),
+				JumpIfZero(_) => JumpIfZero(target),
+				_             => JumpIfNegative(target),
+			});
+		}
+
+		self.loaded += reduced.len();
+		(reduced, resolved)
+	}
+}
+
+impl<'a> Loader<'a> {
+	// Load everything parsed so far into the vm, waiting for something if
+	// nothing has been yet, and failing if the program didn't parse or,
+	// resolving eagerly, referred to missing labels
+	fn load(&mut self, vm: &mut WhitespaceVM) -> Result<(), WhitespaceError> {
+		let mut actions = Vec::new();
+		let mut parsed  = None;
+		let mut labels  = SymbolTable::default();
+
+		let mut next = Some(self.receiver.recv().expect("the parser's thread panicked"));
+		while let Some(message) = next.take() {
+			match message {
+				Parsed::Actions(chunk)             => {actions.extend(chunk); next = self.receiver.try_recv().ok()},
+				Parsed::Done(result, taken, table) => {*self.parse = Some(self.parse.unwrap_or_default() + taken); parsed = Some(result); labels = table},
+			}
+		}
+
+		let (reduced, resolved) = self.resolver.reduce(actions);
+		vm.extend(&reduced, &resolved);
+
+		// Once it's all there the vm can carry on as though it always was
+		if let Some(parsed) = parsed {
+			self.done = true;
+			vm.set_loading(false);
+			parsed?;
+
+			let mut missing: Vec<UnresolvedLabel> = self.resolver.pending.drain().flat_map(|(_, waiting)| waiting).map(|(_, reference)| UnresolvedLabel {name: labels.name(reference.label), ..reference}).collect();
+			if !missing.is_empty() && self.resolution == LabelResolution::Eager {
+				missing.sort_by_key(|reference| reference.index);
+				return Err(WhitespaceError::Resolve(missing));
+			}
+		}
+		Ok(())
+	}
+}
+
+
+/********************************************/
+// Public Functions
+/********************************************/
+// Execute a program on a vm while parsing it, the vm starting with nothing
+// loaded. How long parsing took is added to parse, and what the parser
+// warned about to warnings.
+pub fn execute(vm: &mut WhitespaceVM, program: String, options: Options, parse: &mut Option<Duration>, warnings: &mut Vec<ParseError>) -> Result<(), WhitespaceError> {
+	let (sender, receiver) = mpsc::channel();
+	let parser = thread::Builder::new().spawn(move || {
+		let started   = Instant::now();
+		let mut chunk    = Vec::with_capacity(CHUNK);
+		let mut labels   = SymbolTable::default();
+		let mut warnings = Vec::new();
+		let parsed       = parser::parse_each(program, &options, &mut labels, &mut |action| {
+			chunk.push(action);
+			if chunk.len() == chunk.capacity() {
+				let next = Vec::with_capacity(2 * chunk.len());
+				let _ = sender.send(Parsed::Actions(mem::replace(&mut chunk, next)));
+			}
+		}, &mut warnings);
+		let _ = sender.send(Parsed::Actions(chunk));
+		let _ = sender.send(Parsed::Done(parsed, started.elapsed(), labels));
+		warnings
+	})?;
+
+	let mut loader = Loader {receiver, resolver: Resolver::default(), resolution: options.labels, done: false, parse};
+	vm.set_loading(true);
+	let ran = run(vm, &mut loader);
+
+	// The parser's warnings are wanted even when it failed partway through
+	warnings.extend(parser.join().expect("the parser's thread panicked"));
+	ran
+}
+
+
+/********************************************/
+// Private Functions
+/********************************************/
+// Run the program as it loads, then finish loading whatever of it wasn't
+// needed
+fn run(vm: &mut WhitespaceVM, loader: &mut Loader<'_>) -> Result<(), WhitespaceError> {
+	// Run until the vm needs more of the program than has been parsed
+	let ran = loop {
+		match vm.execute() {
+			Ok(()) if vm.needs_code() => {},
+			ran                       => break ran,
+		}
+		loader.load(vm)?;
+	};
+
+	// The rest of the program still has to parse, even if it wasn't needed
+	while !loader.done {
+		loader.load(vm)?;
+	}
+	ran
+}
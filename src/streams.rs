@@ -0,0 +1,204 @@
+/***********************************************************/
+//
+// Streams
+// =======
+// Input and output endpoints for running a program
+// somewhere other than a terminal.
+//
+// struct SharedBuffer <- An output stream whose contents can be
+//                        read back after the vm is done with it
+//
+// struct TeeOutput <- An output stream that keeps a copy of
+//                     everything written to it
+//
+// struct PendingInput <- An input stream fed a piece at a time,
+//                        which pauses the vm when it runs dry
+//
+// struct RawTerminal <- Hands keypresses on the terminal to the
+//                       program without waiting for Enter
+//
+/***********************************************************/
+use std::cell::RefCell;
+use std::io;
+use std::io::{BufRead, IsTerminal, Read, Write};
+#[cfg(unix)]
+use std::process::{Command, Stdio};
+use std::rc::Rc;
+
+
+/********************************************/
+// Structures
+/********************************************/
+// An in-memory output stream. Clones share the same buffer, so a
+// clone can be handed to the vm and the original read afterwards.
+#[derive(Debug, Clone, Default)]
+pub struct SharedBuffer {
+	buffer: Rc<RefCell<Vec<u8>>>,
+}
+
+impl SharedBuffer {
+	// Get a copy of everything written so far
+	pub fn contents(&self) -> Vec<u8> {
+		self.buffer.borrow().clone()
+	}
+
+	// Get everything written since the last time, emptying the buffer
+	pub fn take(&self) -> Vec<u8> {
+		std::mem::take(&mut *self.buffer.borrow_mut())
+	}
+}
+
+// An output stream that writes through to another while keeping a copy
+// of everything written in a SharedBuffer, so the output can be both
+// shown and looked at
+pub struct TeeOutput {
+	output: Box<dyn Write>,
+	copy:   SharedBuffer,
+}
+
+impl TeeOutput {
+	// Constructor, write to the output and copy into the buffer
+	pub fn new(output: Box<dyn Write>, copy: SharedBuffer) -> TeeOutput {
+		TeeOutput {output, copy}
+	}
+}
+
+// An input stream fed a piece at a time. Until it's closed, running out
+// of input fails with WouldBlock instead of ending, which pauses the vm
+// at the read until more is fed. Clones share what's fed to them.
+#[derive(Debug, Clone, Default)]
+pub struct PendingInput {
+	fed:   Rc<RefCell<Fed>>,
+	taken: Vec<u8>, // Input moved out of what was fed, not yet read
+}
+
+#[derive(Debug, Default)]
+struct Fed {
+	bytes:  Vec<u8>,
+	closed: bool,
+}
+
+impl PendingInput {
+	// Add to the input
+	pub fn feed(&self, bytes: &[u8]) {
+		self.fed.borrow_mut().bytes.extend_from_slice(bytes);
+	}
+
+	// End the input, so reads past what's been fed see the end of it
+	pub fn close(&self) {
+		self.fed.borrow_mut().closed = true;
+	}
+
+	// Move what's been fed into this stream's own buffer
+	fn take_fed(&mut self) -> bool {
+		let mut fed = self.fed.borrow_mut();
+		self.taken.append(&mut fed.bytes);
+		fed.closed
+	}
+}
+
+// Switches the terminal on stdin out of line editing mode, so every
+// keypress can be read as soon as it's made. Keypresses are still
+// echoed. The terminal is restored when this is dropped.
+#[derive(Debug)]
+pub struct RawTerminal {
+	saved: String, // The terminal settings to restore, as given by `stty -g`
+}
+
+impl RawTerminal {
+	// Switch the terminal into raw mode, failing if stdin isn't a terminal
+	pub fn enable() -> io::Result<RawTerminal> {
+		if !io::stdin().is_terminal() {
+			return Err(io::Error::other("raw input needs stdin to be a terminal"));
+		}
+
+		let saved = stty(&["-g"])?;
+		stty(&["-icanon", "min", "1"])?;
+		Ok(RawTerminal {saved: saved.trim().to_string()})
+	}
+}
+
+impl Drop for RawTerminal {
+	fn drop(&mut self) {
+		let _ = stty(&[&self.saved]);
+	}
+}
+
+
+/********************************************/
+// Trait Implementations
+/********************************************/
+impl Write for SharedBuffer {
+	fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+		self.buffer.borrow_mut().extend_from_slice(bytes);
+		Ok(bytes.len())
+	}
+
+	fn flush(&mut self) -> io::Result<()> {
+		Ok(())
+	}
+}
+
+impl Write for TeeOutput {
+	fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+		let written = self.output.write(bytes)?;
+		self.copy.write_all(&bytes[..written])?;
+		Ok(written)
+	}
+
+	fn flush(&mut self) -> io::Result<()> {
+		self.output.flush()
+	}
+}
+
+impl Read for PendingInput {
+	fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+		let closed = self.take_fed();
+		if self.taken.is_empty() && !closed {
+			return Err(io::Error::from(io::ErrorKind::WouldBlock));
+		}
+
+		let count = buffer.len().min(self.taken.len());
+		buffer[..count].copy_from_slice(&self.taken[..count]);
+		self.taken.drain(..count);
+		Ok(count)
+	}
+}
+
+// Reads through the buffer are reads of lines, so only hand out input once
+// a whole line is there. Otherwise the start of a line could be read and
+// then lost when the rest of it would block.
+impl BufRead for PendingInput {
+	fn fill_buf(&mut self) -> io::Result<&[u8]> {
+		let closed = self.take_fed();
+		if !self.taken.contains(&b'\n') && !closed {
+			return Err(io::Error::from(io::ErrorKind::WouldBlock));
+		}
+		Ok(&self.taken)
+	}
+
+	fn consume(&mut self, amount: usize) {
+		self.taken.drain(..amount);
+	}
+}
+
+
+/********************************************/
+// Private Functions
+/********************************************/
+// Run stty against the terminal on stdin, returning what it printed
+#[cfg(unix)]
+fn stty(arguments: &[&str]) -> io::Result<String> {
+	let output = Command::new("stty").args(arguments).stdin(Stdio::inherit()).output()?;
+
+	if output.status.success() {
+		Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+	} else {
+		Err(io::Error::other(format!("stty failed: {}", String::from_utf8_lossy(&output.stderr).trim())))
+	}
+}
+
+#[cfg(not(unix))]
+fn stty(_arguments: &[&str]) -> io::Result<String> {
+	Err(io::Error::new(io::ErrorKind::Unsupported, "raw input is only supported on unix terminals"))
+}
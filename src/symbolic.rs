@@ -0,0 +1,631 @@
+/***********************************************************/
+//
+// Symbolic Execution
+// ==================
+// Works out inputs that make a program fail or reach a
+// label, for explore --symbolic, without trying them all.
+// Each value the program reads is a symbol, and values
+// computed from them are kept as sums of symbols times
+// constants. Every conditional jump on such a value splits
+// the path in two, each with a constraint on the inputs
+// that take it, and paths whose constraints can't all hold
+// are dropped. A path that fails or reaches the label has
+// its constraints solved for inputs, which are then run on
+// the real vm to make sure they do what was found.
+//
+// Constraints are inequalities over the integers, solved by
+// Fourier-Motzkin elimination. That's exact over the
+// rationals but can miss integer solutions, and a path that
+// multiplies two inputs, divides one, or uses one as a heap
+// address is given up on, so not finding anything doesn't
+// mean there's nothing to find.
+//
+// Characters are taken to be printable ASCII, and the input
+// never to end.
+//
+// struct Exploration <- What executing a program symbolically found
+//
+// explore(program: Vec<Action>, options: Options, io_options: IoOptions, limits: Limits, depth: usize, target: Option<usize>) -> Exploration
+// -- Look for inputs that make a program fail or reach an action
+//
+// render(exploration: &Exploration) -> String
+// -- Describe what was found
+//
+/***********************************************************/
+use std::collections::{HashMap, VecDeque};
+use std::convert::TryFrom;
+use std::fmt::Write;
+use std::io;
+use std::io::Cursor;
+use error::WhitespaceError;
+use options::{EndOfProgram, IoOptions, Limits, Options, ReadCharDestination, UnsetHeap};
+use program::{Opcode, Program};
+use symbols::Action;
+use virtual_machine::WhitespaceVM;
+
+
+/********************************************/
+// Structures
+/********************************************/
+// What executing a program symbolically found
+#[derive(Debug, Clone, Default)]
+pub struct Exploration {
+	pub depth:     usize,
+	pub target:    Option<usize>,
+	pub paths:     u64,          // Paths followed to their end, however it came
+	pub halted:    u64,
+	pub reading:   u64,          // Paths still reading once they'd read depth inputs
+	pub limited:   u64,          // Paths stopped by the step limit, or dropped once there were too many
+	pub abandoned: u64,          // Paths that did something the solver can't follow
+	pub findings:  Vec<Finding>, // The failures found, and where the target was reached, in the order found
+}
+
+// Inputs that make the program fail or reach the target
+#[derive(Debug, Clone)]
+pub struct Finding {
+	pub error:     Option<String>, // The error the program fails with, or None for reaching the target
+	pub pointer:   usize,
+	pub steps:     u64,            // The actions executed up to the failure or the target
+	pub input:     String,
+	pub confirmed: bool,           // Whether running the program on the input did the same
+}
+
+// A value computed from the inputs, as a constant plus each input it
+// depends on times a coefficient. Terms are in input order, and none
+// has a coefficient of zero.
+#[derive(Debug, Clone, Default, PartialEq)]
+struct Linear {
+	constant: i64,
+	terms:    Vec<(usize, i64)>,
+}
+
+// What a path has read at each input
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Input {
+	Number,
+	Character,
+}
+
+// Where a path through the program has got to
+#[derive(Debug, Clone, Default)]
+struct Path {
+	pointer:     usize,
+	steps:       u64,
+	stack:       Vec<Linear>,
+	call_stack:  Vec<usize>,
+	heap:        HashMap<i64, Linear>,
+	constraints: Vec<Linear>, // Each is at least zero on this path
+	inputs:      Vec<Input>,
+}
+
+// How following a path for an action went
+enum Step {
+	Next,              // The path carries on at its pointer
+	Branch(Vec<Path>), // The path splits into these
+	Halted,
+	Failed(String),
+	Reading,           // The path has read as many inputs as it may
+	Ended,             // The path can't go on, and what it found has been noted
+	Abandoned,         // The path did something the solver can't follow
+}
+
+// A constraint as the solver works on it: the constant plus each
+// input times its coefficient is at least zero
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct Row {
+	coefficients: Vec<i128>,
+	constant:     i128,
+}
+
+// The most paths to follow, and the most constraints the solver keeps
+// while eliminating an input, before giving up
+const PATHS: u64 = 10_000;
+const ROWS:  usize = 2_000;
+
+// The steps each path gets when no limit is given
+const STEPS: u64 = 10_000;
+
+// The characters a read character is taken to be one of
+const PRINTABLE: (i64, i64) = (32, 126);
+
+
+/********************************************/
+// Methods
+/********************************************/
+impl Linear {
+	// Constructor, a value that doesn't depend on the input
+	fn constant(constant: i64) -> Linear {
+		Linear {constant, terms: Vec::new()}
+	}
+
+	// Constructor, the value of an input
+	fn input(input: usize) -> Linear {
+		Linear {constant: 0, terms: vec![(input, 1)]}
+	}
+
+	// The value, if it doesn't depend on the input
+	fn known(&self) -> Option<i64> {
+		if self.terms.is_empty() {Some(self.constant)} else {None}
+	}
+
+	// The sum of two values, or None if it overflows
+	fn add(&self, other: &Linear) -> Option<Linear> {
+		let mut terms = self.terms.clone();
+		for &(input, coefficient) in &other.terms {
+			match terms.iter().position(|&(existing, _)| existing == input) {
+				Some(index) => terms[index].1 = terms[index].1.checked_add(coefficient)?,
+				None        => terms.push((input, coefficient)),
+			}
+		}
+		terms.retain(|&(_, coefficient)| coefficient != 0);
+		terms.sort();
+		Some(Linear {constant: self.constant.checked_add(other.constant)?, terms})
+	}
+
+	// The value times a constant, or None if it overflows
+	fn scale(&self, factor: i64) -> Option<Linear> {
+		let terms = self.terms.iter().map(|&(input, coefficient)| coefficient.checked_mul(factor).map(|coefficient| (input, coefficient)));
+		let terms = terms.collect::<Option<Vec<(usize, i64)>>>()?.into_iter().filter(|&(_, coefficient)| coefficient != 0).collect();
+		Some(Linear {constant: self.constant.checked_mul(factor)?, terms})
+	}
+
+	// The value plus a constant, or None if it overflows
+	fn offset(&self, amount: i64) -> Option<Linear> {
+		self.add(&Linear::constant(amount))
+	}
+
+	// The value given values for the inputs
+	fn evaluate(&self, inputs: &[i64]) -> i64 {
+		self.terms.iter().fold(self.constant, |sum, &(input, coefficient)| sum.wrapping_add(coefficient.wrapping_mul(inputs[input])))
+	}
+}
+
+
+impl Path {
+	// Pop a value the stack is known to hold
+	fn pop(&mut self) -> Linear {
+		self.stack.pop().unwrap_or_default()
+	}
+
+	// The path with more constraints, if they can all hold
+	fn constrained(&self, constraints: &[Option<Linear>]) -> Option<Path> {
+		let mut path = self.clone();
+		for constraint in constraints {
+			path.constrain(constraint.clone()?);
+		}
+		solve(&path.constraints, path.inputs.len()).map(|_| path)
+	}
+
+	// Add a constraint, merging it with one on the same inputs. A loop
+	// counting an input down would otherwise add a constraint every time
+	// round, and the solver would slow to a crawl.
+	fn constrain(&mut self, constraint: Linear) {
+		let divisor = constraint.terms.iter().fold(0, |divisor, &(_, coefficient)| gcd(divisor, coefficient as i128)) as i64;
+		let constraint = match divisor {
+			0 | 1 => constraint,
+			_     => Linear {
+				constant: constraint.constant.div_euclid(divisor),
+				terms:    constraint.terms.iter().map(|&(input, coefficient)| (input, coefficient / divisor)).collect(),
+			},
+		};
+
+		match self.constraints.iter_mut().find(|existing| existing.terms == constraint.terms) {
+			Some(existing) => existing.constant = existing.constant.min(constraint.constant),
+			None           => self.constraints.push(constraint),
+		}
+	}
+
+	// Read an input of a kind, limiting characters to printable ones
+	fn read(&mut self, kind: Input) -> Linear {
+		let input = Linear::input(self.inputs.len());
+		self.inputs.push(kind);
+		if kind == Input::Character {
+			self.constrain(input.offset(-PRINTABLE.0).unwrap_or_default());
+			self.constrain(input.scale(-1).and_then(|negated| negated.offset(PRINTABLE.1)).unwrap_or_default());
+		}
+		input
+	}
+
+	// The input the path reads, as the text to give the program, given a
+	// value for each input
+	fn text(&self, values: &[i64]) -> String {
+		self.inputs.iter().zip(values).map(|(&kind, &value)| match kind {
+			Input::Number    => format!("{}\n", value),
+			Input::Character => ((value as u8) as char).to_string(),
+		}).collect()
+	}
+}
+
+
+struct Search<'a> {
+	program:     &'a Program,
+	actions:     &'a [Action],
+	options:     Options,
+	io_options:  IoOptions,
+	limits:      Limits,
+	exploration: Exploration,
+}
+
+impl<'a> Search<'a> {
+	// Follow every path from the start of the program, shortest first
+	fn run(&mut self) {
+		let mut paths = VecDeque::from(vec![Path::default()]);
+		while let Some(mut path) = paths.pop_front() {
+			if self.exploration.paths + paths.len() as u64 >= PATHS {
+				self.exploration.limited += 1;
+				continue;
+			}
+
+			loop {
+				if Some(path.pointer) == self.exploration.target {
+					self.find(&path, None);
+					self.exploration.paths += 1;
+					break;
+				}
+				if path.steps >= self.limits.steps.unwrap_or(STEPS) {
+					self.exploration.limited += 1;
+					self.exploration.paths   += 1;
+					break;
+				}
+
+				match self.step(&mut path) {
+					Step::Next             => continue,
+					Step::Branch(branches) => paths.extend(branches),
+					Step::Halted           => {self.exploration.halted += 1; self.exploration.paths += 1},
+					Step::Failed(message)  => {self.find(&path, Some(message)); self.exploration.paths += 1},
+					Step::Reading          => {self.exploration.reading += 1; self.exploration.paths += 1},
+					Step::Ended            => self.exploration.paths += 1,
+					Step::Abandoned        => {self.exploration.abandoned += 1; self.exploration.paths += 1},
+				}
+				break;
+			}
+		}
+	}
+
+	// Follow a path for the action at its pointer
+	fn step(&mut self, path: &mut Path) -> Step {
+		let opcode = match self.program.opcode(path.pointer) {
+			Some(opcode) => opcode,
+			None if self.options.end_of_program == EndOfProgram::Halt => return Step::Halted,
+			None => return Step::Failed("Reached the end of the program without halting.".to_string()),
+		};
+
+		let operand = self.program.operand(path.pointer);
+		path.steps += 1;
+		if path.stack.len() < opcode.pops() {
+			let action = self.program.action(path.pointer);
+			return Step::Failed(format!("{:?} needs {} value(s) on the stack, but it holds {}.", action, opcode.pops(), path.stack.len()));
+		}
+
+		let mut next = path.pointer + 1;
+		match opcode {
+			Opcode::StackPush      => path.stack.push(Linear::constant(operand)),
+			Opcode::StackDuplicate => path.stack.push(path.stack[path.stack.len() - 1].clone()),
+			Opcode::StackSwap      => {let length = path.stack.len(); path.stack.swap(length - 1, length - 2)},
+			Opcode::StackDiscard   => {path.pop();},
+
+			Opcode::Add | Opcode::Subtract | Opcode::Multiply => {
+				let right = path.pop();
+				let left  = path.pop();
+				let value = match opcode {
+					Opcode::Add      => left.add(&right),
+					Opcode::Subtract => right.scale(-1).and_then(|right| left.add(&right)),
+					_                => match (left.known(), right.known()) {
+						(Some(left), _)  => right.scale(left),
+						(_, Some(right)) => left.scale(right),
+						_                => None,
+					},
+				};
+				match value {
+					Some(value) => path.stack.push(value),
+					None        => return Step::Abandoned,
+				}
+			},
+
+			Opcode::Divide | Opcode::Modulo => {
+				let right = path.pop();
+				let left  = path.pop();
+				let message = if opcode == Opcode::Divide {"Tried to divide by zero."} else {"Tried to take the remainder of a division by zero."};
+				match (left.known(), right.known()) {
+					(_, Some(0)) => return Step::Failed(message.to_string()),
+					(Some(left), Some(right)) => {
+						let value = if opcode == Opcode::Divide {self.options.division.quotient(left, right)} else {self.options.division.remainder(left, right)};
+						match value {
+							Some(value) => path.stack.push(Linear::constant(value)),
+							None        => return Step::Abandoned,
+						}
+					},
+
+					// The quotient of an input isn't linear, but it may be a division by zero
+					(_, None) => {
+						return match path.constrained(&[Some(right.clone()), right.scale(-1)]) {
+							Some(zero) => {*path = zero; Step::Failed(message.to_string())},
+							None       => Step::Abandoned,
+						};
+					},
+					(None, Some(_)) => return Step::Abandoned,
+				}
+			},
+
+			Opcode::HeapStore => {
+				let value   = path.pop();
+				let address = path.pop();
+				match address.known() {
+					Some(address) => {path.heap.insert(address, value);},
+					None          => return Step::Abandoned,
+				}
+			},
+
+			Opcode::HeapRetrieve => {
+				let address = match path.pop().known() {
+					Some(address) => address,
+					None          => return Step::Abandoned,
+				};
+				match path.heap.get(&address) {
+					Some(value) => path.stack.push(value.clone()),
+					None if self.options.unset_heap == UnsetHeap::Zero => path.stack.push(Linear::constant(0)),
+					None => return Step::Failed(format!("Tried to get a value from the heap, but no value was found at address: {}", address)),
+				}
+			},
+
+			Opcode::Call => {
+				path.call_stack.push(next);
+				next = operand as usize;
+			},
+			Opcode::Jump => next = operand as usize,
+			Opcode::EndSubroutine => match path.call_stack.pop() {
+				Some(pointer) => next = pointer,
+				None          => return Step::Failed("Tried to return from a procedure, but no procedure call was made.".to_string()),
+			},
+			Opcode::Halt => return Step::Halted,
+
+			// Split the path on a condition that isn't known, into each way it can go
+			Opcode::JumpIfZero | Opcode::JumpIfNegative => {
+				let value = path.pop();
+				let taken = match value.known() {
+					Some(value) if opcode == Opcode::JumpIfZero => value == 0,
+					Some(value)                                 => value < 0,
+					None                                        => {
+						let negated  = value.scale(-1);
+						let positive = value.offset(-1);
+						let negative = negated.as_ref().and_then(|negated| negated.offset(-1));
+						let branches = if opcode == Opcode::JumpIfZero {
+							vec![(vec![Some(value.clone()), negated], true), (vec![positive], false), (vec![negative], false)]
+						} else {
+							vec![(vec![negative], true), (vec![Some(value.clone())], false)]
+						};
+
+						path.pointer = next;
+						let branches = branches.into_iter().filter_map(|(constraints, taken)| path.constrained(&constraints).map(|mut branch| {
+							if taken {
+								branch.pointer = operand as usize;
+							}
+							branch
+						})).collect();
+						return Step::Branch(branches);
+					},
+				};
+				if taken {
+					next = operand as usize;
+				}
+			},
+
+			Opcode::OutputChar | Opcode::OutputNumber => {path.pop();},
+			Opcode::OutputFormatted                   => {path.pop(); path.pop(); path.pop();},
+
+			Opcode::ReadChar | Opcode::ReadNumber => {
+				if path.inputs.len() == self.exploration.depth {
+					return Step::Reading;
+				}
+
+				let stored = opcode == Opcode::ReadNumber || self.options.read_char == ReadCharDestination::Heap;
+				let value  = path.read(if opcode == Opcode::ReadNumber {Input::Number} else {Input::Character});
+				if !stored {
+					path.stack.push(value);
+				} else {
+					match path.pop().known() {
+						Some(address) => {path.heap.insert(address, value);},
+						None          => return Step::Abandoned,
+					}
+				}
+			},
+
+			Opcode::DumpHeap  => {},
+			Opcode::CallDepth => path.stack.push(Linear::constant(path.call_stack.len() as i64)),
+			Opcode::PeekReturn => {
+				let depth = match path.pop().known() {
+					Some(depth) => depth,
+					None        => return Step::Abandoned,
+				};
+				let found = usize::try_from(depth).ok().and_then(|depth| path.call_stack.len().checked_sub(depth + 1)).map(|index| path.call_stack[index]);
+				path.stack.push(Linear::constant(found.map_or(-1, |pointer| pointer as i64)));
+			},
+
+			// A failing assertion is a failure like any other, and a passing one
+			// constrains the rest of the path
+			Opcode::Assert => {
+				let expected   = path.pop();
+				let actual     = path.pop();
+				let difference = match expected.scale(-1).and_then(|expected| actual.add(&expected)) {
+					Some(difference) => difference,
+					None             => return Step::Abandoned,
+				};
+				if let Some(difference) = difference.known() {
+					if difference != 0 {
+						return Step::Failed(format!("Assertion failed: expected {}, but got {}.", expected.constant, actual.constant));
+					}
+				} else {
+					let mut failing = vec![difference.offset(-1), difference.scale(-1).and_then(|negated| negated.offset(-1))].into_iter()
+						.filter_map(|constraint| path.constrained(&[constraint]));
+					if let Some(failing) = failing.next() {
+						let values = solve(&failing.constraints, failing.inputs.len()).unwrap_or_default();
+						self.find(&failing, Some(format!("Assertion failed: expected {}, but got {}.", expected.evaluate(&values), actual.evaluate(&values))));
+					}
+
+					match path.constrained(&[Some(difference.clone()), difference.scale(-1)]) {
+						Some(passing) => *path = passing,
+						None          => return Step::Ended,
+					}
+				}
+			},
+
+			// Nothing the program can tell from its input decides what these give
+			Opcode::GetEnv | Opcode::Clock | Opcode::Random | Opcode::Label | Opcode::OutputText => return Step::Abandoned,
+		}
+
+		path.pointer = next;
+		Step::Next
+	}
+
+	// Note inputs that make the program fail, or reach the target if there's
+	// no error, unless a way there has already been found
+	fn find(&mut self, path: &Path, error: Option<String>) {
+		let found = self.exploration.findings.iter().any(|finding| finding.pointer == path.pointer && finding.error.is_some() == error.is_some());
+		let values = match solve(&path.constraints, path.inputs.len()) {
+			Some(values) if !found => values,
+			_                      => return,
+		};
+
+		// Run the program on the input, up to the action found
+		let input = path.text(&values);
+		let mut vm = WhitespaceVM::with_io(self.actions.to_vec(), self.options, Box::new(Cursor::new(input.clone().into_bytes())), Box::new(io::sink()));
+		vm.set_io_options(self.io_options.clone());
+		vm.set_limits(Limits {steps: Some(path.steps), ..self.limits});
+
+		let (error, confirmed) = match (vm.execute(), error) {
+			(Err(WhitespaceError::Runtime(error)), Some(_)) if error.program_pointer == path.pointer => (Some(WhitespaceError::Runtime(error).to_string()), true),
+			(Err(WhitespaceError::Limit(_)), None) => (None, vm.program_pointer() == path.pointer),
+			(_, error)                             => (error, false),
+		};
+		self.exploration.findings.push(Finding {error, pointer: path.pointer, steps: path.steps, input, confirmed});
+	}
+}
+
+
+/********************************************/
+// Public Functions
+/********************************************/
+// Look for inputs, up to depth of them, that make a resolved program fail,
+// or that reach the action at the target
+pub fn explore(program: Vec<Action>, options: Options, io_options: IoOptions, limits: Limits, depth: usize, target: Option<usize>) -> Exploration {
+	let loaded = Program::load(&program);
+	let mut search = Search {
+		program:     &loaded,
+		actions:     &program,
+		options,
+		io_options,
+		limits,
+		exploration: Exploration {depth, target, ..Exploration::default()},
+	};
+	search.run();
+	search.exploration
+}
+
+
+// Describe what executing a program symbolically found
+pub fn render(exploration: &Exploration) -> String {
+	let mut text = format!(
+		"Followed {} path(s) with up to {} inputs: {} halted, {} still reading, {} stopped by the limits, {} beyond what the solver follows\n",
+		exploration.paths, exploration.depth, exploration.halted, exploration.reading, exploration.limited, exploration.abandoned,
+	);
+
+	for finding in &exploration.findings {
+		let unconfirmed = if finding.confirmed {""} else {" (running the program on it didn't do the same)"};
+		let _ = match finding.error {
+			Some(ref error) => writeln!(text, "\nError: {}\n    given {:?}{}", error, finding.input, unconfirmed),
+			None            => writeln!(text, "\nReached the label at action {} after {} steps\n    given {:?}{}", finding.pointer, finding.steps, finding.input, unconfirmed),
+		};
+	}
+
+	if exploration.target.is_some() && !exploration.findings.iter().any(|finding| finding.error.is_none()) {
+		text.push_str("\nFound no input that reaches the label\n");
+	}
+	text
+}
+
+
+/********************************************/
+// Private Functions
+/********************************************/
+// Find a value for each of a number of inputs that makes every constraint
+// at least zero, choosing each as close to zero as it can be, or None if
+// there's none the solver can find
+fn solve(constraints: &[Linear], inputs: usize) -> Option<Vec<i64>> {
+	let mut rows: Vec<Row> = constraints.iter().map(|constraint| {
+		let mut coefficients = vec![0; inputs];
+		for &(input, coefficient) in &constraint.terms {
+			coefficients[input] = coefficient as i128;
+		}
+		Row {coefficients, constant: constraint.constant as i128}
+	}).collect();
+
+	// Eliminate the inputs last first, keeping the constraints on each as it's eliminated
+	let mut stages = vec![Vec::new(); inputs];
+	for input in (0..inputs).rev() {
+		rows.sort();
+		rows.dedup();
+		stages[input] = rows.clone();
+
+		let (lower, rest): (Vec<Row>, Vec<Row>) = rows.into_iter().partition(|row| row.coefficients[input] > 0);
+		let (upper, rest): (Vec<Row>, Vec<Row>) = rest.into_iter().partition(|row| row.coefficients[input] < 0);
+		rows = rest;
+		for low in &lower {
+			for high in &upper {
+				rows.push(combine(low, high, input)?);
+			}
+		}
+		if rows.len() > ROWS {
+			return None;
+		}
+	}
+	if rows.iter().any(|row| row.constant < 0) {
+		return None;
+	}
+
+	// Then choose the inputs first first, within the bounds the earlier ones leave
+	let mut values = vec![0i64; inputs];
+	for input in 0..inputs {
+		let (mut low, mut high) = (i64::MIN as i128, i64::MAX as i128);
+		for row in &stages[input] {
+			let coefficient = row.coefficients[input];
+			let rest = (0..input).try_fold(row.constant, |sum, earlier| sum.checked_add(row.coefficients[earlier].checked_mul(values[earlier] as i128)?))?;
+			if coefficient > 0 {
+				low = low.max(ceiling(-rest, coefficient));
+			} else if coefficient < 0 {
+				high = high.min(rest.div_euclid(-coefficient));
+			}
+		}
+		if low > high {
+			return None;
+		}
+		values[input] = 0i128.clamp(low, high) as i64;
+	}
+	Some(values)
+}
+
+// Add two constraints, scaled so an input cancels out, with the result
+// divided through by what its coefficients have in common
+fn combine(low: &Row, high: &Row, input: usize) -> Option<Row> {
+	let (up, down) = (low.coefficients[input], -high.coefficients[input]);
+	let coefficients = low.coefficients.iter().zip(&high.coefficients)
+		.map(|(&low, &high)| low.checked_mul(down)?.checked_add(high.checked_mul(up)?))
+		.collect::<Option<Vec<i128>>>()?;
+	let constant = low.constant.checked_mul(down)?.checked_add(high.constant.checked_mul(up)?)?;
+
+	// The constant can round down, since the inputs are whole numbers
+	let divisor = coefficients.iter().fold(0, |divisor, &coefficient| gcd(divisor, coefficient));
+	match divisor {
+		0 | 1 => Some(Row {coefficients, constant}),
+		_     => Some(Row {coefficients: coefficients.iter().map(|coefficient| coefficient / divisor).collect(), constant: constant.div_euclid(divisor)}),
+	}
+}
+
+// The smallest whole number at least a fraction
+fn ceiling(numerator: i128, denominator: i128) -> i128 {
+	-((-numerator).div_euclid(denominator))
+}
+
+// The greatest common divisor of two numbers, ignoring their signs
+fn gcd(left: i128, right: i128) -> i128 {
+	if right == 0 {left.abs()} else {gcd(right, left % right)}
+}
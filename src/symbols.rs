@@ -8,10 +8,26 @@
 //
 // enum Action <- All of the operations in the language
 //
+// enum Category <- The groups the operations fall into
+//
+// Extensions are encoded as Flow Control followed by [LF][Space]
+// (a sequence the language leaves unused) and a number literal
+// naming the extension. They're only parsed when enabled:
+//    1 - GetEnv
+//    2 - DumpHeap
+//    3 - Assert
+//    4 - CallDepth
+//    5 - PeekReturn
+//    6 - OutputFormatted
+//    7 - Clock
+//    8 - Random
+//
 /***********************************************************/
+use labels::LabelId;
+use self::Action::*;
 
 // The three whitespace tokens
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub enum Token {
 	Return,
 	Space,
@@ -39,11 +55,11 @@ pub enum Action {
 	HeapRetrieve, // Retrieve the value at the address indicated by the top value on the stack
 
 	/** Flow Control **/
-	Label(u64),          // Create the label u64 at the current location
-	Call(u64),           // Call the subroutine indicated by u64
-	Jump(u64),           // Unconditionally jump to the label u64
-	JumpIfZero(u64),     // Jump to the label u64 if the top of the stack is zero
-	JumpIfNegative(u64), // Jump to the label u64 if the top of the stack is negative
+	Label(LabelId),          // Create the label at the current location
+	Call(LabelId),           // Call the subroutine at the label
+	Jump(LabelId),           // Unconditionally jump to the label
+	JumpIfZero(LabelId),     // Jump to the label if the top of the stack is zero
+	JumpIfNegative(LabelId), // Jump to the label if the top of the stack is negative
 	EndSubroutine,       // End the current subroutine
 	Halt,                // Halt the execution of the program
 
@@ -53,5 +69,76 @@ pub enum Action {
 	ReadChar,     // Read a character onto the stack
 	ReadNumber,   // Read a number onto the stack
 
-	Error(&'static str), // Unrecognized token
-}
\ No newline at end of file
+	/** Extensions **/
+	GetEnv,          // Replace the variable name stored in the heap at the address on the stack with its value, pushing its length or -1
+	DumpHeap,        // Write every address that holds a value, and the value, to stderr
+	Assert,          // Pop the value expected and then the actual value, failing if they differ
+	CallDepth,       // Push how many subroutines are being executed
+	PeekReturn,      // Replace a depth on the stack with where the subroutine that deep returns to, or -1
+	OutputFormatted, // Pop a width, a base, and then a number, and output the number in that base padded to the width
+	Clock,           // Push the milliseconds since the Unix epoch, or the time on a virtual clock
+	Random,          // Replace a bound on the stack with a random number from zero up to it
+}
+
+// The groups the operations fall into, matching the sections above
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Category {
+	Stack,
+	Arithmetic,
+	Heap,
+	Flow,
+	Io,
+	Extension,
+}
+
+impl Action {
+	// The group the action falls into
+	pub fn category(&self) -> Category {
+		match *self {
+			StackPush(_) | StackDuplicate | StackSwap | StackDiscard => Category::Stack,
+			Add | Subtract | Multiply | Divide | Modulo              => Category::Arithmetic,
+			HeapStore | HeapRetrieve                                 => Category::Heap,
+			Label(_) | Call(_) | Jump(_) | JumpIfZero(_)             => Category::Flow,
+			JumpIfNegative(_) | EndSubroutine | Halt                 => Category::Flow,
+			OutputChar | OutputNumber | ReadChar | ReadNumber        => Category::Io,
+			GetEnv | DumpHeap | Assert | CallDepth | PeekReturn      => Category::Extension,
+			OutputFormatted | Clock | Random                         => Category::Extension,
+		}
+	}
+
+	// The name of the action, without its operand
+	pub fn name(&self) -> &'static str {
+		match *self {
+			StackPush(_)      => "StackPush",
+			StackDuplicate    => "StackDuplicate",
+			StackSwap         => "StackSwap",
+			StackDiscard      => "StackDiscard",
+			Add               => "Add",
+			Subtract          => "Subtract",
+			Multiply          => "Multiply",
+			Divide            => "Divide",
+			Modulo            => "Modulo",
+			HeapStore         => "HeapStore",
+			HeapRetrieve      => "HeapRetrieve",
+			Label(_)          => "Label",
+			Call(_)           => "Call",
+			Jump(_)           => "Jump",
+			JumpIfZero(_)     => "JumpIfZero",
+			JumpIfNegative(_) => "JumpIfNegative",
+			EndSubroutine     => "EndSubroutine",
+			Halt              => "Halt",
+			OutputChar        => "OutputChar",
+			OutputNumber      => "OutputNumber",
+			ReadChar          => "ReadChar",
+			ReadNumber        => "ReadNumber",
+			GetEnv            => "GetEnv",
+			DumpHeap          => "DumpHeap",
+			Assert            => "Assert",
+			CallDepth         => "CallDepth",
+			PeekReturn        => "PeekReturn",
+			OutputFormatted   => "OutputFormatted",
+			Clock             => "Clock",
+			Random            => "Random",
+		}
+	}
+}
@@ -0,0 +1,197 @@
+/***********************************************************/
+//
+// Taint Tracking
+// ==============
+// Follows the values a run reads from its input, for run
+// --taint, to show what the input controls. Every value on
+// the stack and in the heap is marked as coming from the
+// input or not, and the mark is carried along by the actions
+// that copy and combine values. Wherever a marked value is
+// divided by, used as a heap address, or decides a jump, the
+// flow is recorded, which is where unexpected input can
+// make a program fail or go somewhere it shouldn't.
+//
+// Values GetEnv reads from the environment are marked too.
+// The marks are kept alongside the vm rather than in it, by
+// looking at each action and the stack before it's executed.
+//
+// struct Taint <- The marks on a run's values and where they've flowed
+//
+/***********************************************************/
+use std::collections::HashSet;
+use std::fmt::Write;
+use options::ReadCharDestination;
+use symbols::Action;
+use symbols::Action::*;
+
+
+/********************************************/
+// Structures
+/********************************************/
+// The marks on a run's values and where they've flowed
+#[derive(Debug, Clone)]
+pub struct Taint {
+	read_char: ReadCharDestination,
+	stack:     Vec<bool>,     // Whether each value on the stack came from the input, bottom first
+	heap:      HashSet<i64>,  // The heap addresses holding a value that came from the input
+	variable:  Option<i64>,   // Where GetEnv was told to put a variable, until its length is on the stack
+	last:      u64,           // The last step seen, since a read that pauses is seen again when it's repeated
+	flows:     Vec<Flow>,
+}
+
+// A place input flowed into, and how often it did
+#[derive(Debug, Clone)]
+struct Flow {
+	pointer: usize,
+	action:  Action,
+	used:    Use,
+	count:   u64,
+	step:    u64, // The step it first happened at
+}
+
+// What a value from the input was used for
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Use {
+	Divisor,
+	Address,
+	Condition,
+}
+
+
+/********************************************/
+// Methods
+/********************************************/
+impl Taint {
+	// Constructor, marking nothing yet. Where ReadChar puts what it reads
+	// changes what it pops.
+	pub fn new(read_char: ReadCharDestination) -> Taint {
+		Taint {read_char, stack: Vec::new(), heap: HashSet::new(), variable: None, last: 0, flows: Vec::new()}
+	}
+
+	// Carry the marks through an action about to be executed with the given
+	// stack, recording where marked values flow
+	pub fn before(&mut self, step: u64, pointer: usize, action: &Action, stack: &[i64]) {
+		if step <= self.last {
+			return;
+		}
+		self.last = step;
+
+		// Values put on the stack by anything but the program, like its arguments, aren't marked
+		if self.stack.len() < stack.len() {
+			let missing = stack.len() - self.stack.len();
+			self.stack.splice(0..0, vec![false; missing]);
+		}
+		self.stack.truncate(stack.len());
+
+		// The variable GetEnv wrote is as long as what it pushed said
+		if let Some(address) = self.variable.take() {
+			let length = stack.last().cloned().unwrap_or(-1);
+			self.heap.extend((0..length.saturating_add(1)).filter_map(|offset| address.checked_add(offset)));
+		}
+
+		let top   = |depth: usize| stack.len().checked_sub(depth + 1).map(|index| stack[index]);
+		let value = |taint: &Taint, depth: usize| taint.stack.len().checked_sub(depth + 1).is_some_and(|index| taint.stack[index]);
+
+		match *action {
+			StackPush(_) | CallDepth | Clock => self.stack.push(false),
+			StackDuplicate                   => {let marked = value(self, 0); self.stack.push(marked)},
+			StackSwap                        => {let length = self.stack.len(); if length >= 2 {self.stack.swap(length - 1, length - 2)}},
+			StackDiscard | OutputChar | OutputNumber => {self.stack.pop();},
+
+			Add | Subtract | Multiply | Divide | Modulo => {
+				if matches!(*action, Divide | Modulo) && value(self, 0) {
+					self.flow(step, pointer, *action, Use::Divisor);
+				}
+				let marked = value(self, 0) || value(self, 1);
+				self.pop(2);
+				self.stack.push(marked);
+			},
+
+			HeapStore => {
+				if value(self, 1) {
+					self.flow(step, pointer, *action, Use::Address);
+				}
+				if let Some(address) = top(1) {
+					if value(self, 0) {self.heap.insert(address);} else {self.heap.remove(&address);}
+				}
+				self.pop(2);
+			},
+
+			HeapRetrieve => {
+				let address = value(self, 0);
+				if address {
+					self.flow(step, pointer, *action, Use::Address);
+				}
+				let marked = address || top(0).is_some_and(|address| self.heap.contains(&address));
+				self.pop(1);
+				self.stack.push(marked);
+			},
+
+			JumpIfZero(_) | JumpIfNegative(_) => {
+				if value(self, 0) {
+					self.flow(step, pointer, *action, Use::Condition);
+				}
+				self.pop(1);
+			},
+
+			// Whatever's read is marked, wherever it goes
+			ReadChar if self.read_char == ReadCharDestination::Stack => self.stack.push(true),
+			ReadChar | ReadNumber => {
+				if value(self, 0) {
+					self.flow(step, pointer, *action, Use::Address);
+				}
+				if let Some(address) = top(0) {
+					self.heap.insert(address);
+				}
+				self.pop(1);
+			},
+
+			GetEnv => {
+				self.variable = top(0);
+				self.pop(1);
+				self.stack.push(true);
+			},
+
+			Assert              => self.pop(2),
+			OutputFormatted     => self.pop(3),
+			PeekReturn | Random => {self.pop(1); self.stack.push(false)},
+			DumpHeap | Label(_) | Call(_) | Jump(_) | EndSubroutine | Halt => {},
+		}
+	}
+
+	// Take values off the marks
+	fn pop(&mut self, count: usize) {
+		let length = self.stack.len().saturating_sub(count);
+		self.stack.truncate(length);
+	}
+
+	// Record that a marked value flowed into an action
+	fn flow(&mut self, step: u64, pointer: usize, action: Action, used: Use) {
+		match self.flows.iter_mut().find(|flow| flow.pointer == pointer && flow.used == used) {
+			Some(flow) => flow.count += 1,
+			None       => self.flows.push(Flow {pointer, action, used, count: 1, step}),
+		}
+	}
+
+	// Describe where values from the input flowed, in program order
+	pub fn render(&self) -> String {
+		if self.flows.is_empty() {
+			return "Taint report: no value from the input was divided by, used as a heap address, or decided a jump\n".to_string();
+		}
+
+		let mut flows: Vec<&Flow> = self.flows.iter().collect();
+		flows.sort_by_key(|flow| flow.pointer);
+
+		let mut text = format!("Taint report: values from the input flowed into {} place(s)\n", flows.len());
+		for flow in flows {
+			let used = match flow.used {
+				Use::Divisor   => "divides by a value from the input",
+				Use::Address   => "uses a value from the input as a heap address",
+				Use::Condition => "jumps on a value from the input",
+			};
+			let times = if flow.count == 1 {"once".to_string()} else {format!("{} times", flow.count)};
+			let _ = writeln!(text, "    action {} ({:?}) {}, {}, first at step {}", flow.pointer, flow.action, used, times, flow.step);
+		}
+		text
+	}
+}
@@ -0,0 +1,114 @@
+/***********************************************************/
+//
+// Tracing
+// =======
+// Logs each action as the vm executes it, along with the
+// top of the stack, optionally limited to the categories of
+// action being investigated.
+//
+// struct TraceFilter <- The categories of action to log
+//
+// struct Trace <- Where the log goes and what's written to it
+//
+/***********************************************************/
+use std::io;
+use std::io::Write;
+use symbols::{Action, Category};
+
+
+/********************************************/
+// Structures
+/********************************************/
+// The categories of action to log
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct TraceFilter {
+	pub stack:      bool,
+	pub arithmetic: bool,
+	pub heap:       bool,
+	pub flow:       bool,
+	pub io:         bool,
+	pub extension:  bool,
+}
+
+impl TraceFilter {
+	// A filter that logs every action
+	pub fn all() -> TraceFilter {
+		TraceFilter {stack: true, arithmetic: true, heap: true, flow: true, io: true, extension: true}
+	}
+
+	// Parse a comma separated list of categories: stack, arithmetic,
+	// heap, flow, io, and ext, or all
+	pub fn parse(list: &str) -> Option<TraceFilter> {
+		let mut filter = TraceFilter {stack: false, arithmetic: false, heap: false, flow: false, io: false, extension: false};
+
+		for category in list.split(',') {
+			match category {
+				"stack"      => filter.stack      = true,
+				"arithmetic" => filter.arithmetic = true,
+				"heap"       => filter.heap       = true,
+				"flow"       => filter.flow       = true,
+				"io"         => filter.io         = true,
+				"ext"        => filter.extension  = true,
+				"all"        => filter = TraceFilter::all(),
+				_            => return None,
+			}
+		}
+
+		Some(filter)
+	}
+
+	// Whether actions of a category are logged
+	pub fn includes(&self, category: Category) -> bool {
+		match category {
+			Category::Stack      => self.stack,
+			Category::Arithmetic => self.arithmetic,
+			Category::Heap       => self.heap,
+			Category::Flow       => self.flow,
+			Category::Io         => self.io,
+			Category::Extension  => self.extension,
+		}
+	}
+}
+
+
+// Where the log goes and which actions are written to it
+pub struct Trace {
+	filter: TraceFilter,
+	log:    io::BufWriter<Box<dyn Write>>,
+}
+
+impl Trace {
+	// Constructor, log the actions the filter includes
+	pub fn new(filter: TraceFilter, log: Box<dyn Write>) -> Trace {
+		Trace {filter, log: io::BufWriter::new(log)}
+	}
+
+	// Log an action about to be executed, if the filter includes it
+	pub fn action(&mut self, step: u64, pointer: usize, action: &Action, stack: &[i64]) -> io::Result<()> {
+		if !self.filter.includes(action.category()) {
+			return Ok(());
+		}
+
+		// Only the top of the stack is interesting, and the whole thing could be huge
+		let shown = &stack[stack.len().saturating_sub(STACK_SHOWN)..];
+		let more  = if shown.len() < stack.len() {"... "} else {""};
+		writeln!(self.log, "{:>8} {:>6}  {:<22} [{}{}]", step, pointer, format!("{:?}", action), more, join(shown))
+	}
+
+	// Write out anything still buffered
+	pub fn flush(&mut self) -> io::Result<()> {
+		self.log.flush()
+	}
+}
+
+// The number of values from the top of the stack shown with each action
+const STACK_SHOWN: usize = 8;
+
+
+/********************************************/
+// Private Functions
+/********************************************/
+// Join stack values with spaces, bottom first
+fn join(values: &[i64]) -> String {
+	values.iter().map(|value| value.to_string()).collect::<Vec<String>>().join(" ")
+}
@@ -0,0 +1,317 @@
+/***********************************************************/
+//
+// Transcripts
+// ===========
+// Records every byte a program reads and writes, with the
+// time it happened, so an interactive session can be
+// replayed later and checked against what it printed.
+//
+// A transcript is a text file with one event per line:
+//    <milliseconds> in <bytes>
+//    <milliseconds> out <bytes>
+//    <milliseconds> source seed <number>
+//    <milliseconds> source virtual-time
+// The bytes are escaped so that a whole event fits on one
+// line: \n, \t, \r, \\, and \xNN for any other byte that
+// isn't printable ASCII. Lines starting with # are comments.
+// A source event says where Random and Clock drew from, so a
+// replay draws the same numbers and reads the same times.
+//
+// struct Transcript <- A parsed transcript file
+//
+// struct Recorder <- Writes events to a transcript file as they happen
+//
+// struct RecordingInput <- An input stream that records what's read from it
+//
+// struct RecordingOutput <- An output stream that records what's written to it
+//
+// struct ReplayOutput <- An output stream that checks what's written
+//                        to it against a transcript
+//
+/***********************************************************/
+use std::cell::RefCell;
+use std::fs::File;
+use std::io;
+use std::io::{BufRead, BufWriter, Read, Write};
+use std::rc::Rc;
+use std::time::Instant;
+
+
+/********************************************/
+// Structures
+/********************************************/
+// A parsed transcript
+#[derive(Debug, Default)]
+pub struct Transcript {
+	input:        Vec<u8>,     // Every byte the program read, in order
+	output:       Vec<u8>,     // Every byte the program wrote, in order
+	seed:         Option<u64>, // The seed Random drew from, if it had one
+	virtual_time: bool,        // Whether Clock read a virtual clock
+}
+
+impl Transcript {
+	// Read and parse a transcript file
+	pub fn load(path: &str) -> io::Result<Transcript> {
+		let mut contents = String::new();
+		File::open(path)?.read_to_string(&mut contents)?;
+
+		let mut transcript = Transcript::default();
+		for (number, line) in contents.lines().enumerate() {
+			if line.is_empty() || line.starts_with('#') {
+				continue;
+			}
+
+			let invalid = || io::Error::new(io::ErrorKind::InvalidData, format!("{}: line {} isn't a transcript event", path, number + 1));
+			let mut fields = line.splitn(3, ' ');
+			let time       = fields.next().ok_or_else(invalid)?;
+			let direction  = fields.next().ok_or_else(invalid)?;
+			let bytes      = unescape(fields.next().ok_or_else(invalid)?).ok_or_else(invalid)?;
+			time.parse::<u128>().map_err(|_| invalid())?;
+
+			match direction {
+				"in"     => transcript.input.extend(bytes),
+				"out"    => transcript.output.extend(bytes),
+				"source" => transcript.source(&bytes).ok_or_else(invalid)?,
+				_        => return Err(invalid()),
+			}
+		}
+
+		Ok(transcript)
+	}
+
+	// Take in a source event, returning None if it isn't one
+	fn source(&mut self, source: &[u8]) -> Option<()> {
+		match std::str::from_utf8(source).ok()? {
+			"virtual-time" => self.virtual_time = true,
+			source         => self.seed = Some(source.strip_prefix("seed ")?.parse().ok()?),
+		}
+		Some(())
+	}
+
+	// Every byte the program read
+	pub fn input(&self) -> &[u8] {
+		&self.input
+	}
+
+	// Every byte the program wrote
+	pub fn output(&self) -> &[u8] {
+		&self.output
+	}
+
+	// The seed Random drew from, if it had one
+	pub fn seed(&self) -> Option<u64> {
+		self.seed
+	}
+
+	// Whether Clock read a virtual clock
+	pub fn virtual_time(&self) -> bool {
+		self.virtual_time
+	}
+}
+
+
+// Writes events to a transcript file. Clones share the same file, so
+// the input and output streams can both record to it.
+#[derive(Clone)]
+pub struct Recorder {
+	file:  Rc<RefCell<BufWriter<File>>>,
+	start: Instant,
+}
+
+impl Recorder {
+	// Create a transcript file, replacing any existing one
+	pub fn create(path: &str) -> io::Result<Recorder> {
+		let mut file = BufWriter::new(File::create(path)?);
+		writeln!(file, "# whitespace I/O transcript")?;
+		Ok(Recorder {file: Rc::new(RefCell::new(file)), start: Instant::now()})
+	}
+
+	// Record where Random or Clock draws from, like `seed 42`
+	pub fn source(&self, source: &str) -> io::Result<()> {
+		self.record("source", source.as_bytes())
+	}
+
+	// Record bytes going one way
+	fn record(&self, direction: &str, bytes: &[u8]) -> io::Result<()> {
+		if bytes.is_empty() {
+			return Ok(());
+		}
+
+		let mut file = self.file.borrow_mut();
+		writeln!(file, "{} {} {}", self.start.elapsed().as_millis(), direction, escape(bytes))?;
+		file.flush()
+	}
+}
+
+
+// An input stream that records every byte the program takes from it
+pub struct RecordingInput<R: BufRead> {
+	inner:    R,
+	recorder: Recorder,
+}
+
+impl<R: BufRead> RecordingInput<R> {
+	// Constructor, record what's read from a stream
+	pub fn new(inner: R, recorder: Recorder) -> RecordingInput<R> {
+		RecordingInput {inner, recorder}
+	}
+}
+
+
+// An output stream that records every byte the program writes to it
+pub struct RecordingOutput<W: Write> {
+	inner:    W,
+	recorder: Recorder,
+}
+
+impl<W: Write> RecordingOutput<W> {
+	// Constructor, record what's written to a stream
+	pub fn new(inner: W, recorder: Recorder) -> RecordingOutput<W> {
+		RecordingOutput {inner, recorder}
+	}
+}
+
+
+// An output stream that passes everything through while checking it
+// against the output of a transcript. Clones share the same progress,
+// so a clone can be handed to the vm and the original checked afterwards.
+#[derive(Clone)]
+pub struct ReplayOutput {
+	state: Rc<RefCell<ReplayState>>,
+}
+
+struct ReplayState {
+	expected: Vec<u8>,
+	written:  usize,
+	inner:    Box<dyn Write>,
+}
+
+impl ReplayOutput {
+	// Constructor, check what's written to a stream against the expected output
+	pub fn new(expected: Vec<u8>, inner: Box<dyn Write>) -> ReplayOutput {
+		ReplayOutput {state: Rc::new(RefCell::new(ReplayState {expected, written: 0, inner}))}
+	}
+
+	// Fail if the program stopped before writing all of the expected output
+	pub fn finish(&self) -> io::Result<()> {
+		let state = self.state.borrow();
+
+		if state.written < state.expected.len() {
+			Err(io::Error::other(format!(
+				"output ended after {} bytes but the transcript has {}", state.written, state.expected.len()
+			)))
+		} else {
+			Ok(())
+		}
+	}
+}
+
+
+/********************************************/
+// Trait Implementations
+/********************************************/
+impl<R: BufRead> Read for RecordingInput<R> {
+	fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+		let count = self.inner.read(buffer)?;
+		self.recorder.record("in", &buffer[..count])?;
+		Ok(count)
+	}
+}
+
+impl<R: BufRead> BufRead for RecordingInput<R> {
+	fn fill_buf(&mut self) -> io::Result<&[u8]> {
+		self.inner.fill_buf()
+	}
+
+	fn consume(&mut self, amount: usize) {
+		if let Ok(buffer) = self.inner.fill_buf() {
+			let _ = self.recorder.record("in", &buffer[..amount.min(buffer.len())]);
+		}
+		self.inner.consume(amount);
+	}
+}
+
+impl<W: Write> Write for RecordingOutput<W> {
+	fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+		let count = self.inner.write(bytes)?;
+		self.recorder.record("out", &bytes[..count])?;
+		Ok(count)
+	}
+
+	fn flush(&mut self) -> io::Result<()> {
+		self.inner.flush()
+	}
+}
+
+impl Write for ReplayOutput {
+	fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+		let mut state = self.state.borrow_mut();
+		let start     = state.written;
+		let expected  = &state.expected[start.min(state.expected.len())..];
+
+		if let Some(offset) = bytes.iter().zip(expected).position(|(actual, expected)| actual != expected) {
+			return Err(io::Error::other(format!("output differs from the transcript at byte {}", start + offset)));
+		}
+		if bytes.len() > expected.len() {
+			return Err(io::Error::other(format!("output continues past the end of the transcript at byte {}", start + expected.len())));
+		}
+
+		state.inner.write_all(bytes)?;
+		state.written += bytes.len();
+		Ok(bytes.len())
+	}
+
+	fn flush(&mut self) -> io::Result<()> {
+		self.state.borrow_mut().inner.flush()
+	}
+}
+
+
+/********************************************/
+// Private Functions
+/********************************************/
+// Escape bytes so they fit on a single line of a transcript
+fn escape(bytes: &[u8]) -> String {
+	let mut escaped = String::new();
+
+	for &byte in bytes {
+		match byte {
+			b'\n'         => escaped.push_str("\\n"),
+			b'\t'         => escaped.push_str("\\t"),
+			b'\r'         => escaped.push_str("\\r"),
+			b'\\'         => escaped.push_str("\\\\"),
+			b' ' ..= b'~' => escaped.push(byte as char),
+			_             => escaped.push_str(&format!("\\x{:02x}", byte)),
+		}
+	}
+
+	escaped
+}
+
+
+// Undo escape, returning None if the text isn't validly escaped
+fn unescape(text: &str) -> Option<Vec<u8>> {
+	let mut bytes = Vec::new();
+	let mut characters = text.bytes();
+
+	while let Some(byte) = characters.next() {
+		if byte != b'\\' {
+			bytes.push(byte);
+			continue;
+		}
+
+		match characters.next()? {
+			b'n'  => bytes.push(b'\n'),
+			b't'  => bytes.push(b'\t'),
+			b'r'  => bytes.push(b'\r'),
+			b'\\' => bytes.push(b'\\'),
+			b'x'  => {
+				let digits = [characters.next()?, characters.next()?];
+				bytes.push(u8::from_str_radix(std::str::from_utf8(&digits).ok()?, 16).ok()?);
+			},
+			_     => return None,
+		}
+	}
+
+	Some(bytes)
+}
@@ -3,26 +3,104 @@
 // WhitespaceVM
 // ============
 // Defines a virtual machine that executes a whitespace
-// program given as a vector of Actions
+// program given as a vector of Actions, which it loads as
+// a Program of parallel opcodes and operands
+//
+// An input stream that fails with WouldBlock pauses the
+// program at the read, and executing again carries on from
+// there once the stream has something to give. A vm still
+// loading its program pauses the same way where it needs
+// what isn't loaded yet, but executing gives Ok there, and
+// needs_code tells that apart from halting.
+//
+// Besides running until it stops, a vm can execute a single
+// action at a time and be looked at in between, which is how
+// the debugger steps through a program, or be given fuel, a
+// number of actions to execute before it stops, so a program
+// that loops forever only runs as long as a tool allows.
+//
+// A codec can take over how OutputChar and ReadChar turn
+// values into bytes and back, so a program written for an
+// encoding like EBCDIC runs unmodified.
+//
+// A cancellation token lets another thread stop a run. The
+// vm looks at it before reads and writes and when it goes
+// back to an earlier action, and a cancelled run stops there
+// able to carry on, like one that reached a limit.
+//
+// A vm can be forked wherever it's stopped, into a copy that
+// carries on independently with streams of its own. Their
+// heaps share storage until they change it, so tools can
+// branch a run at a read and try each input they like.
+//
+// A vm can take a snapshot of itself every so many actions,
+// and carry on from one taken by an earlier run.
+//
+// A program can be loaded while it's still being parsed. The
+// vm pauses like a read that would block when it reaches the
+// end of what's loaded, or a call or jump to a label that
+// hasn't been found yet, and carries on once it's extended.
+//
+// Clock and Random read the time and draw from entropy, unless
+// the io options make them virtual: then Clock reads a clock
+// that starts at zero and moves a millisecond each time it's
+// read, and Random draws from a generator with a fixed seed,
+// so a run does the same every time. Both are kept in
+// snapshots, so a resumed run carries on where they were.
+//
+// A vm can also be recycled to run another program, keeping
+// the storage the last one grew as far as its capacity's
+// shrink policy allows, and it counts how often its storage
+// still had to grow.
+//
+// When nothing has to see the actions one at a time, no
+// trace, hooks or limits, the straight-line stretches of
+// the program are run as batches, and the program pointer
+// and counters are only brought up to date at the action
+// that ends a stretch or fails.
 //
 /***********************************************************/
-use std::collections::HashMap;
+use std::collections::hash_map::RandomState;
+use std::convert::TryFrom;
+use std::env;
+use std::fmt;
+use std::hash::{BuildHasher, Hasher};
+use std::rc::Rc;
+use accesses::Accesses;
+use allocation::{Allocations, Capacity, Shrink};
+use audit::{Audit, Source};
+use budget::Budget;
+use cancel::Cancel;
+use checkpoint::{Checkpoint, Checkpoints};
+use codec::Codec;
+use heap::{ForkedHeap, HashHeap, Heap};
+#[cfg(feature = "hooks")]
+use hooks::Hooks;
+use error::{Fault, Limit, LimitError, RuntimeError, WhitespaceError};
+use options::{CharEncoding, EndOfProgram, EofPolicy, FlushPolicy, InvalidChar, InvalidNumber, IoOptions, Limits, Options, ReadCharDestination, UnsetHeap};
+use parser::UNRESOLVED;
+use program::{Opcode, Program};
+use program::Opcode::*;
+use statistics::Statistics;
+use status::Status;
+use taint::Taint;
+use trace::Trace;
 use symbols::Action;
-use symbols::Action::*;
 use std::io;
-use std::io::{Read, Write};
+use std::io::{BufRead, BufWriter, Write};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
 
 
 /********************************/
 // Macros
 /********************************/
-// Pop a value off a vector, returning the value
-// or panicking with an error if the stack is empty
+// Pop a value off the vm's stack, returning the value
+// or returning an error if the stack is empty
 macro_rules! pop {
-    ($stack:expr) => (
-    	match $stack.pop() {
-    		Some(value) => value as i64,
-    		None => panic!("Runtime Error: Tried to pop the stack, but it was empty."),
+    ($vm:expr) => (
+    	match $vm.stack.pop() {
+    		Some(value) => value,
+    		None => return Err($vm.error(Fault::StackUnderflow, "Tried to pop the stack, but it was empty.")),
     	}
     )
 }
@@ -32,225 +110,1438 @@ macro_rules! pop {
 // Public Structure
 /********************************/
 // A virtual machine that executes whitespace programs
-#[derive(Debug)]
 pub struct WhitespaceVM {
-	heap:            HashMap<i64, i64>,
+	heap:            Box<dyn Heap>,
 	stack:           Vec<i64>,
 	call_stack:      Vec<usize>,
-	program:         Vec<Action>,
+	program:         Rc<Program>,
 	program_pointer: usize,
+	options:         Options,
+	io_options:      IoOptions,
+	codec:           Option<Rc<dyn Codec>>, // How characters are written and read, when not as the options say
+	limits:          Limits,
+	steps:           u64,
+	statistics:      Statistics,
+	capacity:        Capacity,
+	allocations:     Allocations,    // Counted so far, leaving out what the heap's storage has counted since it was set
+	heap_counted:    u64,            // What the heap's storage had counted when it was set
+	batches:         Vec<i64>,       // Batches run from each action, less those ending there, until they're counted
+	trace:           Option<Trace>,
+	status:          Option<Status>,
+	checkpoints:     Option<Checkpoints>,
+	audit:           Option<Audit>,
+	taint:           Option<Taint>,
+	budget:          Option<Budget>,
+	accesses:        Option<Accesses>,
+	cancel:          Option<Cancel>,
+	#[cfg(feature = "hooks")]
+	hooks:           Option<Hooks>,
+	loading:         bool,           // Whether more of the program is still to come
+	clock:           i64,            // The milliseconds the virtual clock has reached
+	random:          Option<u64>,    // Random's generator, once it's been seeded
+	input:           Box<dyn BufRead>,
+	output:          BufWriter<Box<dyn Write>>,
+}
+
+impl fmt::Debug for WhitespaceVM {
+	fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+		formatter.debug_struct("WhitespaceVM")
+			.field("heap",            &self.heap)
+			.field("stack",           &self.stack)
+			.field("call_stack",      &self.call_stack)
+			.field("program",         &self.program)
+			.field("program_pointer", &self.program_pointer)
+			.field("options",         &self.options)
+			.field("io_options",      &self.io_options)
+			.field("limits",          &self.limits)
+			.field("steps",           &self.steps)
+			.field("statistics",      &self.statistics)
+			.field("capacity",        &self.capacity)
+			.field("tracing",         &self.trace.is_some())
+			.finish()
+	}
 }
 
 impl WhitespaceVM {
-	// Constructor, create a WhitespaceVM
-	pub fn new(program: Vec<Action>) -> WhitespaceVM {
+	// Constructor, create a WhitespaceVM that reads from and writes to the given streams
+	pub fn with_io(program: Vec<Action>, options: Options, input: Box<dyn BufRead>, output: Box<dyn Write>) -> WhitespaceVM {
 		WhitespaceVM {
-			heap:            HashMap::new(),
+			statistics:      Statistics::new(program.len()),
+			capacity:        Capacity::default(),
+			allocations:     Allocations::default(),
+			heap_counted:    0,
+			batches:         vec![0; program.len() + 1],
+			heap:            Box::new(HashHeap::default()),
 			stack:           Vec::new(),
 			call_stack:      Vec::new(),
-			program:         program,
+			program:         Rc::new(Program::load(&program)),
 			program_pointer: 0,
+			options,
+			io_options:      IoOptions::default(),
+			codec:           None,
+			limits:          Limits::default(),
+			steps:           0,
+			trace:           None,
+			status:          None,
+			checkpoints:     None,
+			audit:           None,
+			taint:           None,
+			budget:          None,
+			accesses:        None,
+			cancel:          None,
+			#[cfg(feature = "hooks")]
+			hooks:           None,
+			loading:         false,
+			clock:           0,
+			random:          None,
+			input,
+			output:          BufWriter::new(output),
 		}
 	}
 
-	// Execute the program
-	pub fn execute(&mut self) {
-		// Loop processing actions until a Halt is encountered
-		loop {
-			// Get the index of the highest element on the stack
-			let stack_end = if self.stack.len() > 0 {self.stack.len() - 1} else {0};
+	// Set how the vm's input and output behave
+	pub fn set_io_options(&mut self, io_options: IoOptions) {
+		self.io_options = io_options;
+		self.random     = None;
+	}
 
-			// Execute the current action
-			match self.program[self.program_pointer] {
-				/**************************/
-				// Stack Operations
-				/**************************/
-				// Push the i64 value onto the stack
-				StackPush(value) => self.stack.push(value),
-	
-				// Duplicate the top value of the stack
-				StackDuplicate => {
-					let value = self.stack[stack_end];
-					self.stack.push(value);
-				}
-				
-				// Swap the top two values on the stack
-				StackSwap => {
-					let temporary_value       = self.stack[stack_end];
-					self.stack[stack_end]     = self.stack[stack_end - 1];
-					self.stack[stack_end - 1] = temporary_value;
-				}
-				
-				// Discard the top value of the stack
-				StackDiscard => {
-					pop!(self.stack);
-				},
+	// Write and read characters through a codec, in place of the char
+	// encoding the options give and reading a byte at a time
+	pub fn set_codec(&mut self, codec: Rc<dyn Codec>) {
+		self.codec = Some(codec);
+	}
 
+	// Hand command line arguments to the program on the stack. From the top
+	// down it holds the argument count, then each argument's characters in
+	// order followed by a zero, starting with the first argument.
+	pub fn push_arguments(&mut self, arguments: &[String]) {
+		for argument in arguments.iter().rev() {
+			self.push(0);
+			for character in argument.chars().rev() {
+				self.push(character as i64);
+			}
+		}
+		self.push(arguments.len() as i64);
+	}
 
-				/**************************/
-				// Arithmetic Operations
-				/**************************/
-				// Add the top two values on the stack
-				Add => {
-					let right = pop!(self.stack);
-					let left  = pop!(self.stack);
-					let sum   = left + right;
-					self.stack.push(sum);
-				},
+	// Replace the heap's storage, keeping whatever the new storage holds
+	pub fn set_heap(&mut self, heap: Box<dyn Heap>) {
+		self.allocations = self.allocations();
+		self.heap         = heap;
+		self.heap_counted = self.heap.allocations();
+	}
 
-				// Subtract the top two values of the stack
-				Subtract => {
-					let right      = pop!(self.stack);
-					let left       = pop!(self.stack);
-					let difference = left - right;
-					self.stack.push(difference);
-				},
+	// The heap, as the program has left it
+	pub fn heap(&self) -> &dyn Heap {
+		&*self.heap
+	}
 
-				// Multiply the top two values of the stack
-				Multiply => {
-					let right   = pop!(self.stack);
-					let left    = pop!(self.stack);
-					let product = left * right;
-					self.stack.push(product);
-				},
+	// Make room in the vm's storage ahead of time, and set what it keeps
+	// when it's recycled
+	pub fn set_capacity(&mut self, capacity: Capacity) {
+		self.capacity = capacity;
+		self.reserve();
+	}
 
-				// Divide the top two values of the stack
-				Divide => {
-					let right    = pop!(self.stack);
-					let left     = pop!(self.stack);
-					let quotient = left / right;
-					self.stack.push(quotient);
-				},
+	// How often the vm's storage has allocated or grown
+	pub fn allocations(&self) -> Allocations {
+		Allocations {heap: self.allocations.heap + (self.heap.allocations() - self.heap_counted), ..self.allocations}
+	}
 
-				// Get the remainder after dividing the top two values on the stack
-				Modulo => {
-					let right     = pop!(self.stack);
-					let left      = pop!(self.stack);
-					let remainder = left % right;
-					self.stack.push(remainder);
-				},
+	// Start another program on the vm with the given streams, as though it
+	// were new but for its io options, codec and capacity. The stack, call stack
+	// and heap are emptied but keep their room as the shrink policy allows,
+	// unless the heap's storage can't be emptied, like a shared heap, which
+	// is replaced with a heap of its own.
+	pub fn recycle(&mut self, program: Vec<Action>, options: Options, input: Box<dyn BufRead>, output: Box<dyn Write>) {
+		self.stack.clear();
+		self.call_stack.clear();
+		if !self.heap.clear() {
+			self.heap = Box::new(HashHeap::default());
+		}
 
-				
-				/**************************/
-				// Heap Operations
-				/**************************/
-				// Store the second value on the stack at the address indicated by the first value on the stack
-				HeapStore => {
-					let value   = pop!(self.stack);
-					let address = pop!(self.stack);
-					self.heap.insert(address, value);
-				}
+		if self.capacity.shrink == Shrink::ToHints {
+			self.stack.shrink_to(self.capacity.stack);
+			self.call_stack.shrink_to(self.capacity.call_stack);
+			self.heap.shrink_to(self.capacity.heap);
+		}
+		self.reserve();
 
-				// Retrieve the value at the address indicated by the top value on the stack
-				HeapRetrieve => {
-					let address = pop!(self.stack);
-					let value = match self.heap.get(&address) {
-						Some(value) => value,
-						None => panic!("Tried to get a value from the heap, but no value was found at address: {}", address),
-					};
-					self.stack.push(*value);
+		self.statistics      = Statistics::new(program.len());
+		self.allocations     = Allocations::default();
+		self.heap_counted    = self.heap.allocations();
+		self.batches         = vec![0; program.len() + 1];
+		self.program         = Rc::new(Program::load(&program));
+		self.program_pointer = 0;
+		self.options         = options;
+		self.limits          = Limits::default();
+		self.steps           = 0;
+		self.trace           = None;
+		self.status          = None;
+		self.checkpoints     = None;
+		self.audit           = None;
+		self.taint           = None;
+		self.budget          = None;
+		self.accesses        = None;
+		self.cancel          = None;
+		#[cfg(feature = "hooks")]
+		{
+			self.hooks = None;
+		}
+		self.loading = false;
+		self.clock   = 0;
+		self.random  = None;
+		self.input   = input;
+		self.output  = BufWriter::new(output);
+	}
+
+	// Set how far the vm lets the program go
+	pub fn set_limits(&mut self, limits: Limits) {
+		self.limits = limits;
+	}
+
+	// The number of actions executed so far
+	pub fn steps(&self) -> u64 {
+		self.steps
+	}
+
+	// The index of the next action to execute
+	pub fn program_pointer(&self) -> usize {
+		self.program_pointer
+	}
+
+	// The action at a program pointer, if the program reaches that far
+	pub fn action(&self, pointer: usize) -> Option<Action> {
+		self.program.opcode(pointer).map(|_| self.program.action(pointer))
+	}
+
+	// The stack, bottom first
+	pub fn stack(&self) -> &[i64] {
+		&self.stack
+	}
+
+	// Where each subroutine being executed returns to, outermost first
+	pub fn call_stack(&self) -> &[usize] {
+		&self.call_stack
+	}
+
+	// How many values the stack has room for, which grows to fit the most
+	// it's held
+	pub fn stack_capacity(&self) -> usize {
+		self.stack.capacity()
+	}
+
+	// How many return pointers the call stack has room for
+	pub fn call_stack_capacity(&self) -> usize {
+		self.call_stack.capacity()
+	}
+
+	// Log each action to a trace as it's executed
+	pub fn set_trace(&mut self, trace: Trace) {
+		self.trace = Some(trace);
+	}
+
+	// Report how the run is getting on every so often
+	pub fn set_status(&mut self, status: Status) {
+		self.status = Some(status);
+	}
+
+	// Take a snapshot of the run every so often
+	pub fn set_checkpoints(&mut self, checkpoints: Checkpoints) {
+		self.checkpoints = Some(checkpoints);
+	}
+
+	// A snapshot of where the run has got to
+	pub fn snapshot(&self) -> Checkpoint {
+		Checkpoint {
+			pointer:    self.program_pointer,
+			steps:      self.steps,
+			read:       self.statistics.bytes_read,
+			stack:      self.stack.clone(),
+			call_stack: self.call_stack.clone(),
+			heap:       self.heap.iter().collect(),
+			clock:      self.clock,
+			random:     self.random,
+		}
+	}
+
+	// Carry on from a snapshot of a run of the same program, adding its
+	// heap to the heap's storage
+	pub fn restore(&mut self, checkpoint: &Checkpoint) {
+		self.program_pointer       = checkpoint.pointer;
+		self.steps                 = checkpoint.steps;
+		self.statistics.bytes_read = checkpoint.read;
+		self.stack                 = checkpoint.stack.clone();
+		self.call_stack            = checkpoint.call_stack.clone();
+		self.clock                 = checkpoint.clock;
+		self.random                = checkpoint.random;
+		for &(address, value) in &checkpoint.heap {
+			self.heap.set(address, value);
+		}
+	}
+
+	// Keep track of what the run depends on besides the program
+	pub fn set_audit(&mut self, audit: Audit) {
+		self.audit = Some(audit);
+	}
+
+	// What the run has depended on so far, if it's audited
+	pub fn audit(&self) -> Option<&Audit> {
+		self.audit.as_ref()
+	}
+
+	// Follow the values read from the input to where they're used
+	pub fn set_taint(&mut self, taint: Taint) {
+		self.taint = Some(taint);
+	}
+
+	// Where values read from the input have been used so far, if they're followed
+	pub fn taint(&self) -> Option<&Taint> {
+		self.taint.as_ref()
+	}
+
+	// Count the actions executed against the subroutines they run in
+	pub fn set_budget(&mut self, budget: Budget) {
+		self.budget = Some(budget);
+	}
+
+	// Where the actions have been executed so far, if they're counted
+	pub fn budget(&self) -> Option<&Budget> {
+		self.budget.as_ref()
+	}
+
+	// Record every heap address read and written, and when
+	pub fn set_accesses(&mut self, accesses: Accesses) {
+		self.accesses = Some(accesses);
+	}
+
+	// The heap accesses made so far, if they're recorded
+	pub fn accesses(&self) -> Option<&Accesses> {
+		self.accesses.as_ref()
+	}
+
+	// Stop the run at its next safe point once the token is cancelled
+	pub fn set_cancel(&mut self, cancel: Cancel) {
+		self.cancel = Some(cancel);
+	}
+
+	// Run hooks before the actions they're attached to
+	#[cfg(feature = "hooks")]
+	pub fn set_hooks(&mut self, hooks: Hooks) {
+		self.hooks = Some(hooks);
+	}
+
+	// Make a copy of the vm, stopped where it is, that reads from and writes
+	// to the given streams. The heap moves into storage the copy shares
+	// until either changes it, so a heap shared with other vms isn't shared
+	// with them anymore. Traces, status reports and hooks aren't copied.
+	pub fn fork(&mut self, input: Box<dyn BufRead>, output: Box<dyn Write>) -> WhitespaceVM {
+		let heap = match self.heap.fork() {
+			Some(heap) => heap,
+			None       => {
+				let mut forked = ForkedHeap::new(&*self.heap);
+				let heap = Box::new(forked.split());
+				self.set_heap(Box::new(forked));
+				heap
+			},
+		};
+		let heap_counted = heap.allocations();
+
+		WhitespaceVM {
+			heap,
+			stack:           self.stack.clone(),
+			call_stack:      self.call_stack.clone(),
+			program:         Rc::clone(&self.program),
+			program_pointer: self.program_pointer,
+			options:         self.options,
+			io_options:      self.io_options.clone(),
+			codec:           self.codec.clone(),
+			limits:          self.limits,
+			steps:           self.steps,
+			statistics:      self.statistics.clone(),
+			capacity:        self.capacity,
+			allocations:     self.allocations(),
+			heap_counted,
+			batches:         vec![0; self.batches.len()],
+			trace:           None,
+			status:          None,
+			checkpoints:     None,
+			audit:           self.audit.clone(),
+			taint:           self.taint.clone(),
+			budget:          self.budget.clone(),
+			accesses:        self.accesses.clone(),
+			cancel:          self.cancel.clone(),
+			#[cfg(feature = "hooks")]
+			hooks:           None,
+			loading:         self.loading,
+			clock:           self.clock,
+			random:          self.random,
+			input,
+			output:          BufWriter::new(output),
+		}
+	}
+
+	// What the program has done so far
+	pub fn statistics(&self) -> &Statistics {
+		&self.statistics
+	}
+
+	// Switch to a new version of the program, keeping the heap. Positions
+	// in the old program, the current one and those on the call stack, are
+	// moved by remap. The stack is cleared unless it's kept.
+	pub fn reload(&mut self, program: Vec<Action>, remap: &dyn Fn(usize) -> usize, keep_stack: bool) {
+		self.program_pointer = remap(self.program_pointer);
+		for pointer in &mut self.call_stack {
+			*pointer = remap(*pointer);
+		}
+		if !keep_stack {
+			self.stack.clear();
+		}
+
+		// Counts for the old code don't mean anything for the new
+		self.statistics.executions = vec![0; program.len()];
+		self.batches = vec![0; program.len() + 1];
+		self.program = Rc::new(Program::load(&program));
+	}
+
+	// Say whether more of the program is still to be loaded, so the vm
+	// waits where it needs what isn't there yet rather than failing
+	pub fn set_loading(&mut self, loading: bool) {
+		self.loading = loading;
+	}
+
+	// Load more of a program still being parsed: actions to add to its end,
+	// and the pointers of the labels found for calls and jumps loaded before
+	// them, by the index of the call or jump
+	pub fn extend(&mut self, actions: &[Action], resolved: &[(usize, usize)]) {
+		let program = Rc::make_mut(&mut self.program);
+		program.extend(actions);
+		for &(index, pointer) in resolved {
+			program.resolve(index, pointer);
+		}
+
+		let length = self.statistics.executions.len() + actions.len();
+		self.statistics.executions.resize(length, 0);
+		self.batches.resize(length + 1, 0);
+	}
+
+	// Whether the vm is stopped where it needs more of the program than is
+	// loaded, the end of what's there or a label that hasn't been found yet
+	pub fn needs_code(&self) -> bool {
+		self.loading && match self.program.opcode(self.program_pointer) {
+			Some(Call) | Some(Jump) | Some(JumpIfZero) | Some(JumpIfNegative) => self.program.operand(self.program_pointer) as u64 == UNRESOLVED,
+			Some(_)                                                           => false,
+			None                                                              => true,
+		}
+	}
+
+	// Execute the program, flushing any buffered output once it stops.
+	// Stopping where more of the program is needed gives Ok, like halting.
+	pub fn execute(&mut self) -> Result<(), WhitespaceError> {
+		let result = self.run();
+		if self.batching() {
+			self.count_batches();
+		}
+
+		// A read that would block hasn't happened yet, so don't count it
+		if let Err(WhitespaceError::Io(ref error)) = result {
+			if error.kind() == io::ErrorKind::WouldBlock {
+				self.steps -= 1;
+				self.statistics.executions[self.program_pointer] -= 1;
+			}
+		}
+
+		let flushed = self.output.flush();
+		let traced  = self.trace.as_mut().map_or(Ok(()), Trace::flush);
+		result.and(flushed.and(traced).map_err(WhitespaceError::from))
+	}
+
+	// Execute at most fuel more actions, giving how the run stopped and how
+	// many actions it executed. Running out of fuel stops it with the steps
+	// limit error, able to carry on given more. The limits still apply,
+	// counted from the start of the run.
+	pub fn execute_with_fuel(&mut self, fuel: u64) -> (Result<(), WhitespaceError>, u64) {
+		let (limits, started) = (self.limits, self.steps);
+		let most = started.saturating_add(fuel);
+		self.limits.steps = Some(limits.steps.map_or(most, |steps| steps.min(most)));
+		let result = self.execute();
+		self.limits = limits;
+		(result, self.steps - started)
+	}
+
+	// Execute the next action and no more, giving whether the program has
+	// halted. The limits still apply, counted from the start of the run.
+	pub fn step(&mut self) -> Result<bool, WhitespaceError> {
+		// Running out of fuel after the action is how a single step ends
+		match self.execute_with_fuel(1) {
+			(Ok(()), _)                                                                  => Ok(true),
+			(Err(WhitespaceError::Limit(LimitError {limit: Limit::Steps(_), ..})), 1) => Ok(false),
+			(Err(error), _)                                                              => Err(error),
+		}
+	}
+
+	// Execute actions until the program halts, fails or needs more of
+	// itself than is loaded
+	fn run(&mut self) -> Result<(), WhitespaceError> {
+		let started = Instant::now();
+		let batched = self.batching();
+
+		// Loop processing actions until a Halt is encountered
+		loop {
+			if batched {
+				self.run_straight()?;
+			}
+
+			// Wait for the rest of a program still being loaded
+			if self.needs_code() {
+				return Ok(());
+			}
+
+			let opcode = match self.program.opcode(self.program_pointer) {
+				Some(opcode) => opcode,
+				None if self.options.end_of_program == EndOfProgram::Halt => return Ok(()),
+				None => return Err(self.error(Fault::EndOfProgram, "Reached the end of the program without halting.")),
+			};
+
+			if let Some(steps) = self.limits.steps {
+				if self.steps >= steps {
+					return Err(self.limit(Limit::Steps(steps)));
 				}
+			}
+			if let Some(time) = self.limits.time {
+				// Checking the clock is slow next to most actions, so only do it now and then
+				if self.steps.is_multiple_of(1024) && started.elapsed() > time {
+					if let Some(ref mut audit) = self.audit {
+						audit.record(Source::Clock, self.steps, self.program_pointer);
+					}
+					return Err(self.limit(Limit::Time(time)));
+				}
+			}
+			if let Some(memory) = self.limits.memory {
+				// A shared heap has to be locked to count, so only count when it matters
+				if self.stack.len() + self.call_stack.len() + self.heap.len() > memory {
+					return Err(self.limit(Limit::Memory(memory)));
+				}
+			}
+			if let Some(ref mut status) = self.status {
+				if status.due(self.steps) {
+					status.report(self.steps, self.program_pointer, self.stack.len(), self.heap.len())?;
+				}
+			}
+			if self.checkpoints.as_ref().is_some_and(|checkpoints| checkpoints.due(self.steps)) {
+				self.save_checkpoint()?;
+			}
+			if opcode.performs_io() && self.cancelled() {
+				return Err(self.limit(Limit::Cancelled));
+			}
+			self.steps += 1;
+			self.statistics.executions[self.program_pointer] += 1;
+
+			if let Some(ref mut trace) = self.trace {
+				trace.action(self.steps, self.program_pointer, &self.program.action(self.program_pointer), &self.stack)?;
+			}
+			if let Some(ref mut taint) = self.taint {
+				taint.before(self.steps, self.program_pointer, &self.program.action(self.program_pointer), &self.stack);
+			}
+			if let Some(ref mut budget) = self.budget {
+				budget.before(self.steps, &self.program.action(self.program_pointer));
+			}
 
+			#[cfg(feature = "hooks")]
+			{
+				let hooked = match self.hooks {
+					Some(ref mut hooks) => hooks.before(self.program_pointer, self.steps, &self.program.action(self.program_pointer), &mut self.stack, &mut self.heap),
+					None                => Ok(()),
+				};
+				hooked.map_err(|message| self.error(Fault::Hook, &message))?;
+			}
+
+			// The action to execute after this one, unless control is transferred
+			let mut next_pointer = self.program_pointer + 1;
 
+			// Make sure the stack holds everything the action pops
+			let needed = opcode.pops();
+			if self.stack.len() < needed {
+				return Err(self.underflow(needed));
+			}
+
+			// Execute the current action
+			match opcode {
 				/**************************/
 				// Flow Control Operations
 				/**************************/
-				// Call the subroutine indicated by u64
-				Call(location) => {
-					self.call_stack.push(self.program_pointer);
-					self.program_pointer = (location - 1u64) as usize; // Program Pointer will still be incremented this loop
+				// Call the subroutine at the operand
+				Call => {
+					if self.call_stack.len() == self.call_stack.capacity() {
+						self.allocations.call_stack += 1;
+					}
+					self.call_stack.push(next_pointer);
+					next_pointer = self.target()?;
 				},
 
-				// Unconditionally jump to the label u64
-				Jump(location) => {
-					self.program_pointer = (location - 1u64) as usize; // Program pointer will still be incremented this loop
+				// Unconditionally jump to the operand
+				Jump => {
+					next_pointer = self.target()?;
 				},
 
-				// Jump to the label u64 if the top of the stack is zero
-				JumpIfZero(location) => {
-					if pop!(self.stack) == 0 {
-						self.program_pointer = (location - 1u64) as usize;
+				// Jump to the operand if the top of the stack is zero
+				JumpIfZero => {
+					if self.branch(|value| value == 0)? {
+						next_pointer = self.target()?;
 					}
 				}, 
 
-				// Jump to the label u64 if the top of the stack is negative
-				JumpIfNegative(location) => {
-					if pop!(self.stack) < 0 {
-						self.program_pointer = (location - 1u64) as usize;
+				// Jump to the operand if the top of the stack is negative
+				JumpIfNegative => {
+					if self.branch(|value| value < 0)? {
+						next_pointer = self.target()?;
 					}
 				},
 
 				// End the current subroutine
 				EndSubroutine => {
-					self.program_pointer = self.call_stack.pop().expect("Tried to return from a procedure, but no procedure call was made.");
+					next_pointer = match self.call_stack.pop() {
+						Some(pointer) => pointer,
+						None          => return Err(self.error(Fault::ReturnWithoutCall, "Tried to return from a procedure, but no procedure call was made.")),
+					};
 				},
 
 				// Halt the execution of the program
 				Halt => {
-					break;
+					return Ok(());
 				},
 
 
-				/**************************/
-				// Flow Control Operations
-				/**************************/
-				// Output the top value of the stack as a character
-				OutputChar => {
-					let character = (pop!(self.stack) as u8) as char;
-					print!("{}", character);
-					io::stdout().flush().expect("Unable to flush standard output.");
-				},
+				/*****************************************************************/
+				// This shouldn't happen since labels are removed by reduce_labels
+				/*****************************************************************/
+				Label => return Err(self.error(Fault::UnknownLabel, &format!("Found a label instruction. This should have been reduced! Label was: {}", self.program.operand(self.program_pointer)))),
 
-				// Output the top value of the stack as a number
-				OutputNumber => {
-					let number = pop!(self.stack);
-					print!("{}", number);
-					io::stdout().flush().expect("Runtime Error: Unable to flush standard output.");
+
+				// Everything else carries straight on to the next action
+				_ => {
+					let operand = self.program.operand(self.program_pointer);
+					self.straight(opcode, operand)?;
 				},
+			}
 
-				// Read a character onto the stack
-				ReadChar => {
-					let destination = pop!(self.stack);
-					let mut buffer = [0u8; 1];
-					io::stdin().read_exact(&mut buffer).expect("Unable to read a character.");
-					self.heap.insert(destination, buffer[0] as i64);
+			// Move on to the next action, unless going back round is cancelled
+			let back = next_pointer <= self.program_pointer;
+			self.program_pointer = next_pointer;
+			if back && self.cancelled() {
+				return Err(self.limit(Limit::Cancelled));
+			}
+		}
+	}
+
+	// Execute the straight-line stretch of actions starting at the program
+	// pointer as a batch, leaving the program pointer on the action that
+	// ends it. The pointer, steps and counts are only updated afterwards,
+	// or when an action fails, which moves the pointer to that action.
+	fn run_straight(&mut self) -> Result<(), WhitespaceError> {
+		let program = Rc::clone(&self.program);
+		let start   = self.program_pointer;
+		let (opcodes, operands) = program.straight(start);
+
+		let mut offset = 0;
+		while let Some(&opcode) = opcodes.get(offset) {
+			// Fused output covers several actions, which can't fail but to write.
+			// A codec writes each character itself, so the push is taken alone.
+			let opcode = if opcode == OutputText && self.codec.is_some() {StackPush} else {opcode};
+			let (result, length) = match opcode {
+				OutputText => {
+					let (text, length) = program.text(start + offset);
+					(self.write(text).map_err(|error| Some(WhitespaceError::from(error))), length)
+				},
+				_ if self.stack.len() < opcode.pops() => (Err(None), 1),
+				_ if opcode.performs_io() && self.cancelled() => {
+					self.finish_straight(start, offset);
+					self.program_pointer = start + offset;
+					return Err(self.limit(Limit::Cancelled));
 				},
+				_                                     => (self.straight(opcode, operands[offset]).map_err(Some), 1),
+			};
+
+			if let Err(error) = result {
+				self.finish_straight(start, offset + 1);
+				self.program_pointer = start + offset;
+				return Err(match error {
+					Some(WhitespaceError::Runtime(error)) => WhitespaceError::Runtime(RuntimeError {program_pointer: self.program_pointer, ..error}),
+					Some(error)                           => error,
+					None                                  => self.underflow(opcode.pops()),
+				});
+			}
+			offset += length;
+		}
+
+		self.finish_straight(start, opcodes.len());
+		self.program_pointer = start + opcodes.len();
+		Ok(())
+	}
+
+	// Count a batch of actions run by run_straight. Counting each action
+	// would cost as much as running it, so the batch is only marked where
+	// it starts and ends until count_batches adds it to the statistics.
+	fn finish_straight(&mut self, start: usize, length: usize) {
+		self.steps += length as u64;
+		self.batches[start]          += 1;
+		self.batches[start + length] -= 1;
+	}
+
+	// Add the batches run since the last count to the statistics
+	fn count_batches(&mut self) {
+		let mut running = 0;
+		for (count, batches) in self.statistics.executions.iter_mut().zip(&mut self.batches) {
+			running += std::mem::take(batches);
+			*count  += running as u64;
+		}
+		if let Some(last) = self.batches.last_mut() {
+			*last = 0;
+		}
+	}
+
+	// Whether straight-line stretches run as batches, which they can when
+	// nothing has to see each action. Counting batches takes a pass over
+	// the whole program, so execute only does it after running some.
+	fn batching(&self) -> bool {
+		self.trace.is_none() && self.audit.is_none() && self.taint.is_none() && self.budget.is_none() && self.accesses.is_none() && self.limits == Limits::default() && !self.hooked()
+	}
+
+	// Whether the run's cancellation token has been cancelled
+	fn cancelled(&self) -> bool {
+		self.cancel.as_ref().is_some_and(Cancel::is_cancelled)
+	}
+
+	// Whether any hooks are attached, which have to see every action
+	#[cfg(feature = "hooks")]
+	fn hooked(&self) -> bool {
+		self.hooks.is_some()
+	}
+
+	#[cfg(not(feature = "hooks"))]
+	fn hooked(&self) -> bool {
+		false
+	}
+
+	// Execute an action that doesn't transfer control, given its operand.
+	// The stack already holds everything it pops. Inlined so the batches
+	// in run_straight dispatch without a call per action, which otherwise
+	// costs more than the batching saves.
+	#[inline(always)]
+	fn straight(&mut self, opcode: Opcode, operand: i64) -> Result<(), WhitespaceError> {
+		match opcode {
+			/**************************/
+			// Stack Operations
+			/**************************/
+			// Push the i64 value onto the stack
+			StackPush => self.push(operand),
+
+			// Duplicate the top value of the stack
+			StackDuplicate => {
+				let value = match self.stack.last() {
+					Some(&value) => value,
+					None         => return Err(self.underflow(1)),
+				};
+				self.push(value);
+			}
+			
+			// Swap the top two values on the stack
+			StackSwap => {
+				let length = self.stack.len();
+				if length < 2 {
+					return Err(self.underflow(2));
+				}
+				self.stack.swap(length - 1, length - 2);
+			}
+			
+			// Discard the top value of the stack
+			StackDiscard => {
+				pop!(self);
+			},
+
+
+			/**************************/
+			// Arithmetic Operations
+			/**************************/
+			// Add the top two values on the stack
+			Add => {
+				let right = pop!(self);
+				let left  = pop!(self);
+				let sum   = left.checked_add(right).ok_or_else(|| self.error(Fault::Overflow, "Addition overflowed."))?;
+				self.push(sum);
+			},
+
+			// Subtract the top two values of the stack
+			Subtract => {
+				let right      = pop!(self);
+				let left       = pop!(self);
+				let difference = left.checked_sub(right).ok_or_else(|| self.error(Fault::Overflow, "Subtraction overflowed."))?;
+				self.push(difference);
+			},
+
+			// Multiply the top two values of the stack
+			Multiply => {
+				let right   = pop!(self);
+				let left    = pop!(self);
+				let product = left.checked_mul(right).ok_or_else(|| self.error(Fault::Overflow, "Multiplication overflowed."))?;
+				self.push(product);
+			},
+
+			// Divide the top two values of the stack
+			Divide => {
+				let right    = pop!(self);
+				let left     = pop!(self);
+				if right == 0 {
+					return Err(self.error(Fault::DivisionByZero, "Tried to divide by zero."));
+				}
+				let quotient = self.options.division.quotient(left, right).ok_or_else(|| self.error(Fault::Overflow, "Division overflowed."))?;
+				self.push(quotient);
+			},
+
+			// Get the remainder after dividing the top two values on the stack
+			Modulo => {
+				let right     = pop!(self);
+				let left      = pop!(self);
+				if right == 0 {
+					return Err(self.error(Fault::DivisionByZero, "Tried to take the remainder of a division by zero."));
+				}
+				let remainder = self.options.division.remainder(left, right).ok_or_else(|| self.error(Fault::Overflow, "Modulo overflowed."))?;
+				self.push(remainder);
+			},
+
+			
+			/**************************/
+			// Heap Operations
+			/**************************/
+			// Store the second value on the stack at the address indicated by the first value on the stack
+			HeapStore => {
+				let value   = pop!(self);
+				let address = pop!(self);
+				self.store(address, value);
+			}
+
+			// Retrieve the value at the address indicated by the top value on the stack
+			HeapRetrieve => {
+				let address = pop!(self);
+				let value   = self.retrieve(address)?;
+				self.push(value);
+			}
+
+
+			/**************************/
+			// I/O Operations
+			/**************************/
+			// Output the top value of the stack as a character
+			OutputChar => {
+				let value = pop!(self);
+				match self.codec.clone() {
+					Some(codec) => {
+						let bytes = self.encoded(&*codec, value)?;
+						self.write_bytes(&bytes)?;
+					},
+					None => {
+						let character = self.character(value)?;
+						self.write(character.encode_utf8(&mut [0; 4]))?;
+					},
+				}
+			},
+
+			// Output the top value of the stack as a number
+			OutputNumber => {
+				let number = pop!(self);
+				self.write(&number.to_string())?;
+			},
 
-				// Read a number onto the stack
-				ReadNumber => {
-					let destination = pop!(self.stack);
-					let number;
-					
-					loop {
-						let mut buffer = String::new();
-						io::stdin().read_line(&mut buffer).expect("Unable to read from standard input.");
-
-						match buffer.trim().parse() {
-							Ok(val) => {
-								number = val;
-								break;
-							},
-							Err(error) => {
-								println!("Unable to parse number: {}", error);
-								continue;
+			// Read a character onto the stack
+			ReadChar => {
+				self.touch(Source::Input)?;
+				self.reading()?;
+				let read = match self.codec {
+					Some(ref codec) => codec.decode(&mut *self.input)?,
+					None            => {
+						let mut buffer = [0u8; 1];
+						match self.input.read(&mut buffer)? {
+							0 => None,
+							_ => Some(buffer[0] as i64),
+						}
+					},
+				};
+				let character = match read {
+					Some(character) => Some(character),
+					None            => self.eof_value("a character")?,
+				};
+				self.statistics.bytes_read += character.is_some() as u64;
+
+				match self.options.read_char {
+					ReadCharDestination::Heap => {
+						let destination = pop!(self);
+						if let Some(character) = character {
+							self.store(destination, character);
+						}
+					},
+					ReadCharDestination::Stack => if let Some(character) = character {self.push(character)},
+				}
+			},
+
+			// Read a number onto the stack
+			ReadNumber => {
+				self.touch(Source::Input)?;
+				let number;
+				
+				loop {
+					self.reading()?;
+					let mut buffer = String::new();
+					let read = self.input.read_line(&mut buffer)?;
+					self.statistics.bytes_read += read as u64;
+					if read == 0 {
+						number = self.eof_value("a number")?;
+						break;
+					}
+
+					match self.options.number_input.parse(buffer.trim_end_matches(['\n', '\r'])) {
+						Ok(Some(val)) => {
+							number = Some(val);
+							break;
+						},
+						Ok(None) => continue,
+						Err(error) => {
+							if self.options.invalid_number == InvalidNumber::Error {
+								return Err(self.error(Fault::InvalidInput, &format!("Unable to parse number: {}", error)));
 							}
+
+							if self.io_options.input_messages {
+								writeln!(self.output, "Unable to parse number: {}", error)?;
+							}
+							continue;
 						}
 					}
+				}
 
-					self.heap.insert(destination, number);
-				},
+				// Only take the address once the read can't block, so a paused read can be repeated
+				let destination = pop!(self);
+				if let Some(number) = number {
+					self.store(destination, number);
+				}
+			},
 
 
-				/*****************************************************************/
-				// These shouldn't happen since they are processed during parsing
-				/*****************************************************************/
-				Label(label) => panic!("Found a label instruction. This should have been parsed! Label was: {}", label), // Can't happen
-				Error(error) => panic!("Found a parsing error while executing the program. This should have generated a Parse-Error! Error was: {}", error),
+			/**************************/
+			// Extensions
+			/**************************/
+			// Replace the zero terminated variable name in the heap at the address
+			// on the stack with the variable's value, also zero terminated, and
+			// push its length, or -1 if it isn't set or the program may not read it
+			GetEnv => {
+				let address = pop!(self);
+				let mut name = String::new();
+
+				for offset in 0.. {
+					let code = self.retrieve(self.offset(address, offset)?)?;
+					if code == 0 {
+						break;
+					}
+
+					match u32::try_from(code).ok().and_then(char::from_u32) {
+						Some(character) => name.push(character),
+						None            => return Err(self.error(Fault::InvalidOperand, &format!("Tried to read an environment variable name, but {} isn't a character.", code))),
+					}
+				}
+
+				// Names the program may not read are never set, whatever the environment holds
+				let allowed = self.io_options.allowed_env.contains(&name);
+				if allowed {
+					self.touch(Source::Environment(name.clone()))?;
+				}
+				let value = if allowed {env::var(&name).ok()} else {None};
+
+				match value {
+					Some(value) => {
+						for (offset, character) in value.chars().chain(Some('\0')).enumerate() {
+							let destination = self.offset(address, offset as i64)?;
+							self.store(destination, character as i64);
+						}
+						self.push(value.chars().count() as i64);
+					},
+					None        => self.push(-1),
+				}
+			},
+
+			// Write every address that holds a value to stderr, after the
+			// program's own output so far so the two read in order
+			DumpHeap => {
+				self.output.flush()?;
+				let mut dump = String::new();
+				for (address, value) in self.heap.iter() {
+					dump.push_str(&format!("{}: {}\n", address, value));
+				}
+				io::stderr().write_all(dump.as_bytes())?;
+			},
+
+			// Fail unless the value on top of the stack, the one expected, is
+			// the same as the actual value below it
+			Assert => {
+				let expected = pop!(self);
+				let actual   = pop!(self);
+				if actual != expected {
+					return Err(self.error(Fault::AssertionFailed, &format!("Assertion failed: expected {}, but got {}.", expected, actual)));
+				}
+			},
+
+			// Push how many subroutines are being executed, which is zero outside them all
+			CallDepth => {
+				self.push(self.call_stack.len() as i64);
+			},
+
+			// Replace a depth with the program pointer the subroutine that deep
+			// returns to, counting from zero for the innermost, or with -1 when
+			// fewer subroutines are being executed
+			PeekReturn => {
+				let depth = pop!(self);
+				let pointer = usize::try_from(depth).ok()
+					.and_then(|depth| self.call_stack.len().checked_sub(depth + 1))
+					.map_or(-1, |index| self.call_stack[index] as i64);
+				self.push(pointer);
+			},
+
+			// Output a number in a base from 2 to 36, padded to a width: with spaces
+			// in front when the width is positive, or zeros after any sign when
+			// it's negative
+			OutputFormatted => {
+				let width  = pop!(self);
+				let base   = pop!(self);
+				let number = pop!(self);
+				let text   = self.format_number(number, base, width)?;
+				self.write(&text)?;
+			},
+
+			// Push the milliseconds since the Unix epoch, or since the run
+			// started with a virtual clock
+			Clock => {
+				let now = if self.io_options.virtual_time {
+					self.clock += 1;
+					self.clock - 1
+				} else {
+					self.touch(Source::Time)?;
+					SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |time| time.as_millis() as i64)
+				};
+				self.push(now);
+			},
+
+			// Replace a bound with a random number from zero up to but not including it
+			Random => {
+				let bound = pop!(self);
+				if bound <= 0 {
+					return Err(self.error(Fault::InvalidOperand, &format!("Tried to draw a random number below {}, but the bound isn't positive.", bound)));
+				}
+				let drawn = self.draw()?;
+				self.push(((drawn as u128 * bound as u128) >> 64) as i64);
+			},
+
+			Label | Call | Jump | JumpIfZero | JumpIfNegative | EndSubroutine | Halt => unreachable!("control is transferred by run"),
+			OutputText => unreachable!("fused output is written by run_straight"),
+		}
+
+		Ok(())
+	}
+
+
+	// Push a value onto the stack, counting the times it has to grow
+	#[inline(always)]
+	fn push(&mut self, value: i64) {
+		if self.stack.len() == self.stack.capacity() {
+			self.allocations.stack += 1;
+		}
+		self.stack.push(value);
+	}
+
+	// Draw the next number from Random's generator, a splitmix64 seeded
+	// with the seed or, without one, from entropy the first time
+	fn draw(&mut self) -> Result<u64, WhitespaceError> {
+		let state = match (self.random, self.io_options.seed) {
+			(Some(state), _)   => state,
+			(None, Some(seed)) => seed,
+			(None, None)       => {
+				self.touch(Source::Entropy)?;
+				RandomState::new().build_hasher().finish()
+			},
+		};
+		let state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+		self.random = Some(state);
+
+		let mut drawn = state;
+		drawn = (drawn ^ (drawn >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+		drawn = (drawn ^ (drawn >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+		Ok(drawn ^ (drawn >> 31))
+	}
+
+	// Make the room the capacity hints ask for
+	fn reserve(&mut self) {
+		self.stack.reserve(self.capacity.stack.saturating_sub(self.stack.len()));
+		self.call_stack.reserve(self.capacity.call_stack.saturating_sub(self.call_stack.len()));
+		let held = self.heap.len();
+		self.heap.reserve(self.capacity.heap.saturating_sub(held));
+	}
+
+	// Write a snapshot over the last one, once what was written before it
+	// is out, so carrying on from it doesn't write anything twice or lose it
+	fn save_checkpoint(&mut self) -> io::Result<()> {
+		self.output.flush()?;
+		let checkpoint = self.snapshot();
+		match self.checkpoints {
+			Some(ref mut checkpoints) => checkpoints.save(&checkpoint),
+			None                      => Ok(()),
+		}
+	}
+
+	// Create an error for an action that needs more values than the stack holds
+	fn underflow(&self, needed: usize) -> WhitespaceError {
+		let action = self.program.action(self.program_pointer);
+		self.error(Fault::StackUnderflow, &format!("{:?} needs {} value(s) on the stack, but it holds {}.", action, needed, self.stack.len()))
+	}
+
+	// Create an error for going past a limit at the current action
+	fn limit(&self, limit: Limit) -> WhitespaceError {
+		WhitespaceError::from(LimitError {limit, program_pointer: self.program_pointer})
+	}
+
+	// The character OutputChar writes for a value, in the encoding the
+	// options choose
+	fn character(&self, value: i64) -> Result<char, WhitespaceError> {
+		let character = match self.options.char_encoding {
+			CharEncoding::Byte    => u8::try_from(value).ok().map(char::from),
+			CharEncoding::Unicode => u32::try_from(value).ok().and_then(char::from_u32),
+		};
+
+		match (character, self.options.invalid_char, self.options.char_encoding) {
+			(Some(character), _, _)                          => Ok(character),
+			(None, InvalidChar::Lossy, CharEncoding::Byte)    => Ok(char::from(value as u8)),
+			(None, InvalidChar::Lossy, CharEncoding::Unicode) => Ok(char::REPLACEMENT_CHARACTER),
+			(None, InvalidChar::Error, CharEncoding::Byte)    => Err(self.error(Fault::InvalidOperand, &format!("Tried to write {} as a character, but it's outside the range of a byte.", value))),
+			(None, InvalidChar::Error, CharEncoding::Unicode) => Err(self.error(Fault::InvalidOperand, &format!("Tried to write {} as a character, but it isn't a Unicode code point.", value))),
+		}
+	}
+
+	// The bytes OutputChar writes for a value, encoded by a codec
+	fn encoded(&self, codec: &dyn Codec, value: i64) -> Result<Vec<u8>, WhitespaceError> {
+		let mut bytes = Vec::new();
+		match (codec.encode(value, &mut bytes), self.options.invalid_char) {
+			(true, _)                   => Ok(bytes),
+			(false, InvalidChar::Lossy) => Ok(char::REPLACEMENT_CHARACTER.to_string().into_bytes()),
+			(false, InvalidChar::Error) => Err(self.error(Fault::InvalidOperand, &format!("Tried to write {} as a character, but the encoding has no character for it.", value))),
+		}
+	}
+
+	// Get the value stored at a heap address
+	fn retrieve(&mut self, address: i64) -> Result<i64, WhitespaceError> {
+		self.statistics.heap_retrieves += 1;
+		if let Some(ref mut accesses) = self.accesses {
+			accesses.read(self.steps, address);
+		}
+		match self.heap.get(address) {
+			Some(value) => Ok(value),
+			None if self.options.unset_heap == UnsetHeap::Zero => Ok(0),
+			None => Err(self.error(Fault::UnsetHeap, &format!("Tried to get a value from the heap, but no value was found at address: {}", address))),
+		}
+	}
+
+	// Get the heap address a number of cells past another
+	fn offset(&self, address: i64, offset: i64) -> Result<i64, WhitespaceError> {
+		address.checked_add(offset).ok_or_else(|| self.error(Fault::Overflow, "Tried to access the heap past the largest address."))
+	}
+
+	// Store a value in the heap
+	fn store(&mut self, address: i64, value: i64) {
+		self.statistics.heap_stores += 1;
+		if let Some(ref mut accesses) = self.accesses {
+			accesses.write(self.steps, address);
+		}
+		self.heap.set(address, value);
+	}
+
+	// Write a number for OutputFormatted, failing on a base it can't be
+	// written in or a width too wide to be meant
+	fn format_number(&self, number: i64, base: i64, width: i64) -> Result<String, WhitespaceError> {
+		const WIDEST: i64 = 1024;
+
+		let radix = match u32::try_from(base) {
+			Ok(radix) if (2..=36).contains(&radix) => radix,
+			_ => return Err(self.error(Fault::InvalidOperand, &format!("Tried to output a number in base {}, but the base has to be from 2 to 36.", base))),
+		};
+		if width.checked_abs().is_none_or(|width| width > WIDEST) {
+			return Err(self.error(Fault::InvalidOperand, &format!("Tried to output a number padded to {} characters, but the most is {}.", width, WIDEST)));
+		}
+
+		// Work out the digits from the least significant up
+		let mut magnitude = number.unsigned_abs();
+		let mut digits    = Vec::new();
+		loop {
+			digits.push(char::from_digit((magnitude % radix as u64) as u32, radix).unwrap());
+			magnitude /= radix as u64;
+			if magnitude == 0 {
+				break;
 			}
+		}
+
+		let sign    = if number < 0 {"-"} else {""};
+		let digits  = digits.iter().rev().collect::<String>();
+		let padding = (width.unsigned_abs() as usize).saturating_sub(sign.len() + digits.len());
+		Ok(match width {
+			0.. => format!("{}{}{}", " ".repeat(padding), sign, digits),
+			_   => format!("{}{}{}", sign, "0".repeat(padding), digits),
+		})
+	}
+
+	// Pop the value a conditional jump tests, returning whether the jump is taken
+	fn branch(&mut self, condition: fn(i64) -> bool) -> Result<bool, WhitespaceError> {
+		let taken = condition(pop!(self));
+		if taken {
+			self.statistics.branches_taken += 1;
+		} else {
+			self.statistics.branches_not_taken += 1;
+		}
+		Ok(taken)
+	}
+
+	// Write the program's output, then flush it if the flush policy asks for it
+	fn write(&mut self, text: &str) -> io::Result<()> {
+		self.write_bytes(text.as_bytes())
+	}
 
-			// Increment the program counter
-			self.program_pointer += 1;
+	// Write bytes to the output, flushing it as the io options say
+	fn write_bytes(&mut self, bytes: &[u8]) -> io::Result<()> {
+		self.output.write_all(bytes)?;
+		self.statistics.bytes_written += bytes.len() as u64;
+
+		match self.io_options.flush {
+			FlushPolicy::Always                           => self.output.flush(),
+			FlushPolicy::Newline if bytes.ends_with(b"\n") => self.output.flush(),
+			_                                             => Ok(()),
+		}
+	}
+
+	// Tell the audit the action depends on a source besides the program,
+	// failing if the audit is strict
+	fn touch(&mut self, source: Source) -> Result<(), WhitespaceError> {
+		let touched = match self.audit {
+			Some(ref mut audit) => audit.touch(source, self.steps, self.program_pointer),
+			None                => Ok(()),
+		};
+		touched.map_err(|message| self.error(Fault::Denied, &message))
+	}
+
+	// Write the prompt before reading input, then flush output if the
+	// flush policy asks for it
+	fn reading(&mut self) -> io::Result<()> {
+		if !self.io_options.prompt.is_empty() {
+			write!(self.output, "{}", self.io_options.prompt)?;
+		}
+
+		match self.io_options.flush {
+			FlushPolicy::Newline | FlushPolicy::Input => self.output.flush(),
+			_                                         => Ok(()),
+		}
+	}
+
+	// Get the program pointer the current call or jump transfers control
+	// to, failing if its label was never resolved
+	fn target(&self) -> Result<usize, WhitespaceError> {
+		let location = self.program.operand(self.program_pointer) as u64;
+		if location == UNRESOLVED {
+			Err(self.error(Fault::UnknownLabel, "Tried to jump to a label that doesn't exist."))
+		} else {
+			Ok(location as usize)
 		}
 	}
+
+	// Get the value a read produces at the end of the input, according
+	// to the eof policy. None means the destination is left untouched.
+	fn eof_value(&self, reading: &str) -> Result<Option<i64>, WhitespaceError> {
+		match self.options.eof {
+			EofPolicy::Error       => Err(self.error(Fault::EndOfInput, &format!("Reached the end of the input while reading {}.", reading))),
+			EofPolicy::NegativeOne => Ok(Some(-1)),
+			EofPolicy::Zero        => Ok(Some(0)),
+			EofPolicy::Unchanged   => Ok(None),
+		}
+	}
+
+	// Create a runtime error of some kind at the current action
+	fn error(&self, fault: Fault, message: &str) -> WhitespaceError {
+		WhitespaceError::Runtime(RuntimeError {
+			fault,
+			message:         message.to_string(),
+			program_pointer: self.program_pointer,
+		})
+	}
 }
 
+
+
+/********************************************/
+// Tests
+/********************************************/
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::io::Cursor;
+	use assembler;
+	use codec::Table;
+	use parser;
+	use streams::SharedBuffer;
+
+	// Everything about a run that batching mustn't change
+	#[derive(Debug, PartialEq)]
+	struct Ran {
+		output:          Vec<u8>,
+		result:          String, // The error's debug form, which includes where it happened
+		program_pointer: usize,
+		steps:           u64,
+		executions:      Vec<u64>,
+		stack:           Vec<i64>,
+	}
+
+	// Assemble a program and run it against the input, either in batches
+	// or one action at a time, which a steps limit that's never reached forces
+	fn run(source: &str, input: &str, batched: bool, codec: Option<Rc<dyn Codec>>) -> Ran {
+		let options  = Options::default();
+		let assembly = assembler::assemble(source).unwrap();
+		let program  = parser::reduce_labels(assembly.program, &assembly.labels, options.labels).unwrap();
+		let output   = SharedBuffer::default();
+
+		let mut vm = WhitespaceVM::with_io(program, options, Box::new(Cursor::new(input.as_bytes().to_vec())), Box::new(output.clone()));
+		if !batched {
+			vm.set_limits(Limits {steps: Some(u64::MAX), ..Limits::default()});
+		}
+		if let Some(codec) = codec {
+			vm.set_codec(codec);
+		}
+		assert_eq!(vm.batching(), batched);
+
+		let result = vm.execute();
+		Ran {
+			output:          output.contents(),
+			result:          format!("{:?}", result),
+			program_pointer: vm.program_pointer(),
+			steps:           vm.steps(),
+			executions:      vm.statistics().executions.clone(),
+			stack:           vm.stack().to_vec(),
+		}
+	}
+
+	// Run a program both ways, giving how it ran after checking they agree
+	fn agree(source: &str, input: &str) -> Ran {
+		let batched = run(source, input, true, None);
+		assert_eq!(batched, run(source, input, false, None), "running\n{}", source);
+		batched
+	}
+
+	#[test]
+	fn batching_keeps_loops_and_calls_the_same() {
+		let ran = agree("
+			read:
+				push 0
+				readn
+				push 0
+				retrieve
+			loop:
+				dup
+				outn
+				push 10
+				outc
+				call decrement
+				dup
+				jz end
+				jump loop
+			end:
+				drop
+				halt
+			decrement:
+				push 1
+				sub
+				ret
+		", "3\n");
+		assert_eq!(ran.output, b"3\n2\n1\n");
+		assert_eq!(ran.result, "Ok(())");
+	}
+
+	#[test]
+	fn batching_keeps_fused_output_the_same() {
+		let ran = agree("
+			push 'H'
+			outc
+			push 'i'
+			outc
+			push 200
+			outc
+			push 255
+			outc
+			push '!'
+			outc
+			push 256
+			outc
+			push 'x'
+			outc
+			halt
+		", "");
+		assert_eq!(ran.steps, 15);
+		assert!(ran.output.starts_with(b"Hi"));
+	}
+
+	#[test]
+	fn batching_keeps_fused_output_the_same_with_a_codec() {
+		let source = "
+			push 200
+			outc
+			push 'A'
+			outc
+			push 'B'
+			outc
+			halt
+		";
+		let batched = run(source, "", true, Some(Rc::new(Table::ebcdic())));
+		assert_eq!(batched, run(source, "", false, Some(Rc::new(Table::ebcdic()))));
+		assert_eq!(batched.output, "H\u{a0}â".as_bytes());
+	}
+
+	#[test]
+	fn batching_keeps_where_runtime_errors_happen() {
+		let ran = agree("
+			push 'o'
+			outc
+			push 'k'
+			outc
+			push 1
+			push 0
+			div
+			halt
+		", "");
+		assert_eq!(ran.output, b"ok");
+		assert_eq!(ran.program_pointer, 6);
+		assert!(ran.result.contains("program_pointer: 6"));
+	}
+
+	#[test]
+	fn batching_keeps_where_the_stack_runs_out() {
+		let ran = agree("
+			push 'a'
+			outc
+			push 'b'
+			outc
+			push 1
+			add
+			halt
+		", "");
+		assert_eq!(ran.output, b"ab");
+		assert_eq!(ran.program_pointer, 5);
+	}
+
+	#[test]
+	fn batching_keeps_running_off_the_end_the_same() {
+		let ran = agree("
+			push 5
+			push 6
+			swap
+		", "");
+		assert_eq!(ran.stack, vec![6, 5]);
+		assert_eq!(ran.steps, 3);
+	}
+}